@@ -0,0 +1,248 @@
+use actix_web::{get, web, HttpResponse};
+use chrono::{Duration, NaiveDateTime};
+use log::info;
+use sqlx::{PgPool, Row};
+use std::collections::HashMap;
+use std::ops::Range;
+use utoipa::OpenApi;
+
+use crate::errors::{ApiError, ErrorBody};
+use crate::{fit, tcx};
+
+// A logged workout as handed to the FIT and TCX encoders
+pub(crate) struct Activity {
+    pub(crate) name: Option<String>,
+    pub(crate) start: NaiveDateTime,
+    pub(crate) end: NaiveDateTime,
+    pub(crate) sets: Vec<ActivitySet>, // In the order they were logged
+}
+
+pub(crate) struct ActivitySet {
+    pub(crate) exercise_name: String,
+    pub(crate) weight: i16, // kg
+    pub(crate) reps: i16,
+}
+
+impl Activity {
+    pub(crate) fn duration(&self) -> Duration {
+        (self.end - self.start).max(Duration::zero())
+    }
+
+    // Sets carry no timestamps of their own, so the workout is divided evenly
+    // between them
+    pub(crate) fn set_times(&self) -> Vec<(NaiveDateTime, Duration)> {
+        let count = self.sets.len() as i32;
+        if count == 0 {
+            return Vec::new();
+        }
+        let slot = self.duration() / count;
+        (0..count).map(|i| (self.start + slot * i, slot)).collect()
+    }
+
+    // Consecutive sets of the same exercise form one lap
+    pub(crate) fn laps(&self) -> Vec<Range<usize>> {
+        let mut laps: Vec<Range<usize>> = Vec::new();
+        for (i, set) in self.sets.iter().enumerate() {
+            match laps.last_mut() {
+                Some(lap) if self.sets[lap.start].exercise_name == set.exercise_name => {
+                    lap.end = i + 1
+                }
+                _ => laps.push(i..i + 1),
+            }
+        }
+        laps
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(paths(export_workout))]
+pub(crate) struct ApiDoc;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(export_workout);
+}
+
+async fn fetch_activity(pool: &PgPool, workout_id: i32) -> Result<Option<Activity>, sqlx::Error> {
+    let Some(workout) = sqlx::query(
+        r#"SELECT w.start, w."end", r.routinename
+           FROM Workout w
+           LEFT JOIN Routines r ON w.routineid = r.routineid
+           WHERE w.workoutid = $1"#,
+    )
+    .bind(workout_id)
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let sets = sqlx::query(
+        r#"SELECT e.exercisename, s.weight, s.reps
+           FROM Workout_Exercises_Sets wes
+           JOIN ExerciseList e ON wes.exerciseid = e.exerciseid
+           JOIN "Set" s ON wes.setid = s.setid
+           WHERE wes.workoutid = $1
+           ORDER BY s.setid"#,
+    )
+    .bind(workout_id)
+    .fetch_all(pool)
+    .await?
+    .iter()
+    .map(|row| ActivitySet {
+        exercise_name: row.get("exercisename"),
+        weight: row.get("weight"),
+        reps: row.get("reps"),
+    })
+    .collect();
+
+    Ok(Some(Activity {
+        name: workout.get("routinename"),
+        start: workout.get("start"),
+        end: workout.get("end"),
+        sets,
+    }))
+}
+
+#[utoipa::path(
+    tag = "workouts",
+    summary = "Download a workout as a FIT or TCX activity file",
+    params(("format" = String, Query, description = "fit or tcx")),
+    responses(
+        (status = 200, content(
+            (Vec<u8> = "application/vnd.ant.fit"),
+            (String = "application/vnd.garmin.tcx+xml")
+        )),
+        (status = 400, description = "Unknown format", body = ErrorBody),
+        (status = 404, description = "Workout not found", body = ErrorBody)
+    )
+)]
+#[get("/workouts/{workout_id}/export")]
+async fn export_workout(
+    pool: web::Data<PgPool>,
+    workout_id: web::Path<i32>,
+    request: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let workout_id = workout_id.into_inner();
+
+    let format = match request.get("format").map(|f| f.to_lowercase()) {
+        Some(format) if format == "fit" || format == "tcx" => format,
+        _ => {
+            return Err(ApiError::bad_request(
+                "Invalid format. Must be 'fit' or 'tcx'",
+            ))
+        }
+    };
+
+    let activity = fetch_activity(pool.get_ref(), workout_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(format!("Workout with ID {} not found", workout_id)))?;
+
+    let (body, content_type) = if format == "fit" {
+        (fit::encode(&activity), "application/vnd.ant.fit")
+    } else {
+        (
+            tcx::encode(&activity).into_bytes(),
+            "application/vnd.garmin.tcx+xml",
+        )
+    };
+
+    info!("Exported workout {} as {}", workout_id, format);
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"workout-{}.{}\"", workout_id, format),
+        ))
+        .body(body))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use std::path::PathBuf;
+
+    // The fixture behind the golden files in tests/golden
+    pub(crate) fn sample_activity() -> Activity {
+        let day = NaiveDate::from_ymd_opt(2024, 3, 5).unwrap();
+        let set = |exercise_name: &str, weight, reps| ActivitySet {
+            exercise_name: exercise_name.to_string(),
+            weight,
+            reps,
+        };
+        Activity {
+            name: Some("Push & Pull".to_string()),
+            start: day.and_hms_opt(18, 0, 0).unwrap(),
+            end: day.and_hms_opt(18, 45, 0).unwrap(),
+            sets: vec![
+                set("Bench Press", 60, 10),
+                set("Bench Press", 70, 8),
+                set("Bench Press", 70, 7),
+                set("Lateral Raises (Dumbbell)", 10, 12),
+                set("Lateral Raises (Dumbbell)", 10, 12),
+                set("Tricep Extensions (Cable)", 25, 15),
+            ],
+        }
+    }
+
+    // Compares against a golden file; UPDATE_GOLDEN=1 rewrites it instead
+    pub(crate) fn assert_golden(name: &str, actual: &[u8]) {
+        let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "golden", name]
+            .iter()
+            .collect();
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::write(&path, actual).unwrap();
+            return;
+        }
+        let expected = std::fs::read(&path)
+            .unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e));
+        assert!(
+            expected == actual,
+            "{} does not match the encoder output; rerun with UPDATE_GOLDEN=1 if the change is intended",
+            path.display()
+        );
+    }
+
+    #[test]
+    fn sets_share_the_workout_duration() {
+        let activity = sample_activity();
+        let times = activity.set_times();
+        assert_eq!(times.len(), 6);
+        assert_eq!(times[0].0, activity.start);
+        assert_eq!(times[1].1, Duration::seconds(450));
+        assert_eq!(times[5].0 + times[5].1, activity.end);
+    }
+
+    #[test]
+    fn laps_group_consecutive_sets() {
+        assert_eq!(sample_activity().laps(), vec![0..3, 3..5, 5..6]);
+    }
+
+    #[actix_web::test]
+    async fn export_route_serves_fit_and_tcx() {
+        let app = crate::test_support::TestApp::new().await;
+        let squat = app.exercise("Squat").create().await;
+        let workout = app
+            .workout()
+            .at("2026-01-05T18:00:00")
+            .set(squat, 100, 5)
+            .create()
+            .await;
+        let uri = format!("/api/v1/workouts/{}/export", workout);
+
+        let fit = app.get(&format!("{}?format=fit", uri)).await.expect(200);
+        assert_eq!(fit.header("content-type"), Some("application/vnd.ant.fit"));
+        assert_eq!(&fit.body[8..12], b".FIT");
+        let tcx = app.get(&format!("{}?format=TCX", uri)).await.expect(200);
+        assert_eq!(
+            tcx.header("content-disposition"),
+            Some(format!("attachment; filename=\"workout-{}.tcx\"", workout).as_str())
+        );
+        assert!(tcx.text().contains("<Id>2026-01-05T18:00:00Z</Id>"));
+
+        app.get(&uri).await.expect(400);
+        app.get("/api/v1/workouts/999/export?format=fit")
+            .await
+            .expect(404);
+    }
+}
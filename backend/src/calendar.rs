@@ -0,0 +1,430 @@
+use actix_web::{delete, get, post, web, HttpResponse};
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{PgPool, Row};
+use std::collections::HashMap;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::errors::{ApiError, ErrorBody};
+use crate::{programs, versioning};
+
+#[derive(Deserialize, ToSchema)]
+struct FeedCreate {
+    #[serde(default)]
+    label: Option<String>, // e.g. "Work laptop", to tell subscriptions apart
+}
+
+#[derive(Serialize, ToSchema)]
+struct Feed {
+    token: String,
+    label: Option<String>,
+    url: String,
+    created_at: NaiveDateTime,
+}
+
+struct CalendarEvent {
+    uid: String,
+    when: EventTime,
+    summary: String,
+    description: String,
+}
+
+// Logged workouts have times, planned sessions only a day
+enum EventTime {
+    Timed(NaiveDateTime, NaiveDateTime),
+    AllDay(NaiveDate),
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(create_feed, list_feeds, delete_feed, calendar_feed),
+    tags((name = "calendar", description = "iCalendar feeds of logged and planned workouts"))
+)]
+pub(crate) struct ApiDoc;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_feed)
+        .service(list_feeds)
+        .service(delete_feed)
+        .service(calendar_feed);
+}
+
+fn feed_url(token: &str) -> String {
+    format!("{}/calendar/{}.ics", versioning::PREFIX, token)
+}
+
+// Escapes TEXT values as described in RFC 5545 section 3.3.11
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+// Lines longer than 75 octets are folded onto continuation lines
+fn push_line(ics: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            ics.push_str("\r\n ");
+            width = 1;
+        }
+        ics.push(c);
+        width += c.len_utf8();
+    }
+    ics.push_str("\r\n");
+}
+
+fn format_duration(duration: Duration) -> String {
+    let minutes = duration.num_minutes().max(0);
+    match (minutes / 60, minutes % 60) {
+        (0, m) => format!("{}m", m),
+        (h, 0) => format!("{}h", h),
+        (h, m) => format!("{}h {}m", h, m),
+    }
+}
+
+fn render(name: &str, events: &[CalendarEvent]) -> String {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut ics = String::new();
+    push_line(&mut ics, "BEGIN:VCALENDAR");
+    push_line(&mut ics, "VERSION:2.0");
+    push_line(&mut ics, "PRODID:-//StrongerYou//Workouts//EN");
+    push_line(&mut ics, "CALSCALE:GREGORIAN");
+    push_line(&mut ics, &format!("X-WR-CALNAME:{}", escape(name)));
+
+    for event in events {
+        push_line(&mut ics, "BEGIN:VEVENT");
+        push_line(&mut ics, &format!("UID:{}", event.uid));
+        push_line(&mut ics, &format!("DTSTAMP:{}", stamp));
+        // Times are stored without a zone, so they are written as floating times
+        match event.when {
+            EventTime::Timed(start, end) => {
+                push_line(
+                    &mut ics,
+                    &format!("DTSTART:{}", start.format("%Y%m%dT%H%M%S")),
+                );
+                push_line(&mut ics, &format!("DTEND:{}", end.format("%Y%m%dT%H%M%S")));
+            }
+            EventTime::AllDay(date) => {
+                push_line(
+                    &mut ics,
+                    &format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d")),
+                );
+                push_line(&mut ics, "TRANSP:TRANSPARENT");
+            }
+        }
+        push_line(&mut ics, &format!("SUMMARY:{}", escape(&event.summary)));
+        if !event.description.is_empty() {
+            push_line(
+                &mut ics,
+                &format!("DESCRIPTION:{}", escape(&event.description)),
+            );
+        }
+        push_line(&mut ics, "END:VEVENT");
+    }
+
+    push_line(&mut ics, "END:VCALENDAR");
+    ics
+}
+
+async fn workout_events(pool: &PgPool) -> Result<Vec<CalendarEvent>, sqlx::Error> {
+    // Per workout and exercise: number of sets and the top set (heaviest, then most reps)
+    let rows = sqlx::query(
+        r#"SELECT wes.workoutid::INTEGER AS workoutid, e.exercisename,
+                  COUNT(*) AS sets,
+                  (ARRAY_AGG(s.weight ORDER BY s.weight DESC, s.reps DESC))[1] AS top_weight,
+                  (ARRAY_AGG(s.reps ORDER BY s.weight DESC, s.reps DESC))[1] AS top_reps,
+                  MIN(s.setid) AS first_set
+           FROM Workout_Exercises_Sets wes
+           JOIN ExerciseList e ON wes.exerciseid = e.exerciseid
+           JOIN "Set" s ON wes.setid = s.setid
+           GROUP BY wes.workoutid, e.exercisename
+           ORDER BY wes.workoutid, first_set"#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut exercises: HashMap<i32, Vec<String>> = HashMap::new();
+    for row in &rows {
+        let sets: i64 = row.get("sets");
+        exercises
+            .entry(row.get("workoutid"))
+            .or_default()
+            .push(format!(
+                "{}: {} set{}, top set {} kg x {}",
+                row.get::<String, _>("exercisename"),
+                sets,
+                if sets == 1 { "" } else { "s" },
+                row.get::<i16, _>("top_weight"),
+                row.get::<i16, _>("top_reps"),
+            ));
+    }
+
+    let workouts = sqlx::query(
+        r#"SELECT w.workoutid, w.start, w."end", r.routinename
+           FROM Workout w
+           LEFT JOIN Routines r ON w.routineid = r.routineid
+           ORDER BY w.start"#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(workouts
+        .iter()
+        .map(|row| {
+            let workout_id: i32 = row.get("workoutid");
+            let start: NaiveDateTime = row.get("start");
+            let end: NaiveDateTime = row.get::<NaiveDateTime, _>("end").max(start);
+            let mut description = vec![format!("Duration: {}", format_duration(end - start))];
+            description.extend(exercises.remove(&workout_id).unwrap_or_default());
+
+            CalendarEvent {
+                uid: format!("workout-{}@strongeryou", workout_id),
+                when: EventTime::Timed(start, end),
+                summary: row
+                    .get::<Option<String>, _>("routinename")
+                    .unwrap_or_else(|| "Workout".to_string()),
+                description: description.join("\n"),
+            }
+        })
+        .collect())
+}
+
+// Upcoming sessions of every program that have not been done yet
+async fn planned_events(pool: &PgPool) -> Result<Vec<CalendarEvent>, sqlx::Error> {
+    let today = Local::now().date_naive();
+    let mut events = Vec::new();
+    for program in programs::fetch_programs(pool, None).await? {
+        let sessions = programs::planned_sessions(pool, &program, today, program.end_date).await?;
+        events.extend(
+            sessions
+                .into_iter()
+                .filter(|session| session.workout_id.is_none())
+                .map(|session| CalendarEvent {
+                    uid: format!(
+                        "program-{}-{}@strongeryou",
+                        program.program_id,
+                        session.date.format("%Y%m%d")
+                    ),
+                    when: EventTime::AllDay(session.date),
+                    summary: if session.deload {
+                        format!("{} (planned, deload)", session.routine_name)
+                    } else {
+                        format!("{} (planned)", session.routine_name)
+                    },
+                    description: format!(
+                        "{}: week {} of {}",
+                        program.name, session.week, program.weeks
+                    ),
+                }),
+        );
+    }
+    Ok(events)
+}
+
+#[utoipa::path(
+    tag = "calendar",
+    summary = "Create a feed token",
+    request_body(content = Option<FeedCreate>),
+    responses((status = 201, body = Feed))
+)]
+#[post("/calendar/feeds")]
+async fn create_feed(
+    pool: web::Data<PgPool>,
+    feed: Option<web::Json<FeedCreate>>,
+) -> Result<HttpResponse, ApiError> {
+    let label = feed.and_then(|feed| feed.into_inner().label);
+    let row = sqlx::query(
+        "INSERT INTO CalendarFeeds (Label) VALUES ($1) RETURNING Token, Label, CreatedAt",
+    )
+    .bind(&label)
+    .fetch_one(pool.get_ref())
+    .await?;
+    let token: String = row.get("token");
+    info!("Created calendar feed {:?}", label);
+    Ok(HttpResponse::Created().json(Feed {
+        url: feed_url(&token),
+        token,
+        label: row.get("label"),
+        created_at: row.get("createdat"),
+    }))
+}
+
+#[utoipa::path(
+    tag = "calendar",
+    summary = "List feed tokens",
+    responses((status = 200, body = Vec<Feed>))
+)]
+#[get("/calendar/feeds")]
+async fn list_feeds(pool: web::Data<PgPool>) -> Result<HttpResponse, ApiError> {
+    let rows = sqlx::query("SELECT Token, Label, CreatedAt FROM CalendarFeeds ORDER BY CreatedAt")
+        .fetch_all(pool.get_ref())
+        .await?;
+    let feeds: Vec<Feed> = rows
+        .iter()
+        .map(|row| {
+            let token: String = row.get("token");
+            Feed {
+                url: feed_url(&token),
+                token,
+                label: row.get("label"),
+                created_at: row.get("createdat"),
+            }
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(feeds))
+}
+
+fn feed_not_found() -> ApiError {
+    ApiError::not_found("Calendar feed not found")
+}
+
+// Revoking a token stops every calendar subscribed with it
+#[utoipa::path(
+    tag = "calendar",
+    summary = "Revoke a feed token",
+    responses(
+        (status = 200, body = serde_json::Value, example = json!({ "status": "deleted" })),
+        (status = 404, description = "Feed not found", body = ErrorBody)
+    )
+)]
+#[delete("/calendar/feeds/{token}")]
+async fn delete_feed(
+    pool: web::Data<PgPool>,
+    token: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let result = sqlx::query("DELETE FROM CalendarFeeds WHERE Token = $1")
+        .bind(token.as_str())
+        .execute(pool.get_ref())
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(feed_not_found());
+    }
+    info!("Revoked calendar feed");
+    Ok(HttpResponse::Ok().json(json!({ "status": "deleted" })))
+}
+
+#[utoipa::path(
+    tag = "calendar",
+    summary = "The iCalendar feed for a token",
+    params(("token" = String, Path)),
+    responses(
+        (status = 200, content_type = "text/calendar", body = String),
+        (status = 404, description = "Feed not found", body = ErrorBody)
+    )
+)]
+#[get("/calendar/{token}.ics")]
+async fn calendar_feed(
+    pool: web::Data<PgPool>,
+    token: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    sqlx::query("SELECT 1 FROM CalendarFeeds WHERE Token = $1")
+        .bind(token.as_str())
+        .fetch_optional(pool.get_ref())
+        .await?
+        .ok_or_else(feed_not_found)?;
+
+    let events = workout_events(pool.get_ref()).await?;
+    let planned = planned_events(pool.get_ref()).await?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .body(render(
+            "StrongerYou workouts",
+            &events.into_iter().chain(planned).collect::<Vec<_>>(),
+        )))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+    use serde_json::json;
+
+    use crate::test_support::TestApp;
+
+    #[actix_web::test]
+    async fn create_list_and_revoke_feeds() {
+        let app = TestApp::new().await;
+
+        let feed = app
+            .post("/api/v1/calendar/feeds", json!({ "label": "Phone" }))
+            .await
+            .expect(201)
+            .json();
+        let token = feed["token"].as_str().unwrap();
+        assert_eq!(feed["url"], format!("/api/v1/calendar/{}.ics", token));
+        app.post("/api/v1/calendar/feeds", json!({}))
+            .await
+            .expect(201);
+
+        let feeds = app.get("/api/v1/calendar/feeds").await.expect(200).json();
+        assert_eq!(feeds.as_array().unwrap().len(), 2);
+        assert_eq!(feeds[0]["label"], "Phone");
+
+        app.delete(&format!("/api/v1/calendar/feeds/{}", token))
+            .await
+            .expect(200);
+        app.get(&format!("/api/v1/calendar/{}.ics", token))
+            .await
+            .expect(404);
+        app.delete(&format!("/api/v1/calendar/feeds/{}", token))
+            .await
+            .expect(404);
+    }
+
+    #[actix_web::test]
+    async fn feed_lists_workouts_and_planned_sessions() {
+        let app = TestApp::new().await;
+        let squat = app.exercise("Squat").create().await;
+        let legs = app.routine("Legs").exercise(squat, 2).create().await;
+        let workout = app
+            .workout()
+            .routine(legs)
+            .at("2026-01-05T18:00:00")
+            .minutes(75)
+            .set(squat, 100, 5)
+            .set(squat, 100, 4)
+            .create()
+            .await;
+        let program = app
+            .post(
+                "/api/v1/programs",
+                json!({
+                    "name": "Daily",
+                    "schedule_type": "rotation",
+                    "start_date": Local::now().date_naive(),
+                    "weeks": 1,
+                    "slots": [{ "routine_id": legs }],
+                }),
+            )
+            .await
+            .expect(201)
+            .json();
+        let feed = app
+            .post("/api/v1/calendar/feeds", json!(null))
+            .await
+            .expect(201)
+            .json();
+
+        let response = app.get(feed["url"].as_str().unwrap()).await.expect(200);
+        assert_eq!(
+            response.header("content-type"),
+            Some("text/calendar; charset=utf-8")
+        );
+        let ics = response.text().replace("\r\n ", "");
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.contains(&format!("UID:workout-{}@strongeryou\r\n", workout)));
+        assert!(ics.contains("DTSTART:20260105T180000\r\nDTEND:20260105T191500\r\n"));
+        assert!(
+            ics.contains("DESCRIPTION:Duration: 1h 15m\\nSquat: 2 sets\\, top set 100 kg x 5\r\n")
+        );
+        assert!(ics.contains(&format!(
+            "UID:program-{}-{}@strongeryou\r\n",
+            program["program_id"],
+            Local::now().date_naive().format("%Y%m%d")
+        )));
+        assert!(ics.contains("SUMMARY:Legs (planned)\r\n"));
+    }
+}
@@ -0,0 +1,379 @@
+use clap::{Subcommand, ValueEnum};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::db::{self, SCHEMA_VERSION};
+use crate::export;
+use crate::openapi;
+use crate::repository::postgres::rebuild_prs;
+
+// Maintenance tasks that run against the configured database and exit. There
+// are no user accounts, so import and export cover the whole server.
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(about = "Serve the HTTP API (the default)")]
+    Serve,
+    #[command(about = "Create or update the database schema")]
+    Migrate {
+        #[command(subcommand)]
+        action: MigrateAction,
+    },
+    #[command(about = "Add exercises from a catalog file, or the built-in starter catalog")]
+    Seed {
+        #[arg(
+            long,
+            value_name = "FILE",
+            help = "JSON array or CSV of name, muscles_trained, exercise_type"
+        )]
+        catalog: Option<PathBuf>,
+    },
+    #[command(about = "Write an export of all data, as served by GET /export")]
+    Export {
+        #[arg(long, short, value_name = "FILE", help = "Defaults to standard output")]
+        output: Option<PathBuf>,
+        #[arg(long, value_enum, default_value = "json")]
+        format: ExportFormat,
+    },
+    #[command(about = "Merge a JSON export into this database, as POST /import does")]
+    Import {
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+    },
+    #[command(about = "Rebuild PRs and best reps per weight from the workout history")]
+    RecomputePrs,
+    #[command(about = "Delete all data, keeping the schema")]
+    ResetDb {
+        #[arg(long, help = "Required, as this cannot be undone")]
+        confirm: bool,
+    },
+    #[command(about = "Write the OpenAPI document, as served at /openapi.json")]
+    Openapi {
+        #[arg(long, short, value_name = "FILE", help = "Defaults to standard output")]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum MigrateAction {
+    #[command(about = "Apply the schema")]
+    Up,
+    #[command(about = "Show the applied and expected schema versions")]
+    Status,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+// One exercise of a catalog file; CSV files separate muscles with ';'
+#[derive(Deserialize)]
+struct CatalogEntry {
+    name: String,
+    #[serde(default)]
+    muscles_trained: Vec<String>,
+    exercise_type: String,
+}
+
+#[derive(Deserialize)]
+struct CsvCatalogEntry {
+    name: String,
+    #[serde(default)]
+    muscles_trained: String,
+    exercise_type: String,
+}
+
+type CliResult = Result<(), Box<dyn Error>>;
+
+pub async fn run(command: Command, config: &Config) -> CliResult {
+    let pool = db::connect(config).await?;
+
+    match command {
+        Command::Serve => unreachable!("serve is handled by main"),
+        Command::Openapi { .. } => unreachable!("openapi is handled by main"),
+        Command::Migrate {
+            action: MigrateAction::Up,
+        } => {
+            db::migrate(&pool).await?;
+            println!("Schema is at version {}", SCHEMA_VERSION);
+            Ok(())
+        }
+        Command::Migrate {
+            action: MigrateAction::Status,
+        } => migrate_status(&pool).await,
+        Command::Seed { catalog } => {
+            require_schema(&pool).await?;
+            seed(&pool, catalog.as_deref()).await
+        }
+        Command::Export { output, format } => {
+            require_schema(&pool).await?;
+            let contents = export::export_file(&pool, matches!(format, ExportFormat::Csv)).await?;
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, contents)?;
+                    eprintln!("Wrote {}", path.display());
+                }
+                None => std::io::Write::write_all(&mut std::io::stdout(), &contents)?,
+            }
+            Ok(())
+        }
+        Command::Import { file } => {
+            require_schema(&pool).await?;
+            let contents = std::fs::read(&file)?;
+            let summary = export::import_file(&pool, &contents).await?;
+            println!("{}", serde_json::to_string_pretty(&summary)?);
+            Ok(())
+        }
+        Command::RecomputePrs => {
+            require_schema(&pool).await?;
+            recompute_prs(&pool).await
+        }
+        Command::ResetDb { confirm } => reset_db(&pool, confirm).await,
+    }
+}
+
+// Needs neither a configuration nor a database, so main runs it before either
+pub fn write_openapi(output: Option<&Path>) -> CliResult {
+    let document = openapi::document().to_pretty_json()? + "\n";
+    match output {
+        Some(path) => {
+            std::fs::write(path, document)?;
+            eprintln!("Wrote {}", path.display());
+        }
+        None => print!("{}", document),
+    }
+    Ok(())
+}
+
+async fn migrate_status(pool: &PgPool) -> CliResult {
+    match db::schema_version(pool).await? {
+        None => println!("Not migrated; expected version {}", SCHEMA_VERSION),
+        Some(version) if version == SCHEMA_VERSION => {
+            println!("Up to date at version {}", version)
+        }
+        Some(version) if version < SCHEMA_VERSION => println!(
+            "Behind: at version {}, expected version {}; run `migrate up`",
+            version, SCHEMA_VERSION
+        ),
+        Some(version) => println!(
+            "Ahead: at version {}, newer than this binary's version {}",
+            version, SCHEMA_VERSION
+        ),
+    }
+    Ok(())
+}
+
+async fn require_schema(pool: &PgPool) -> CliResult {
+    match db::schema_version(pool).await? {
+        Some(version) if version == SCHEMA_VERSION => Ok(()),
+        Some(version) => Err(format!(
+            "the schema is at version {} but this binary expects {}; run `migrate up`",
+            version, SCHEMA_VERSION
+        )
+        .into()),
+        None => Err("the database has not been migrated; run `migrate up`".into()),
+    }
+}
+
+fn read_catalog(path: &Path) -> Result<Vec<CatalogEntry>, Box<dyn Error>> {
+    let is_csv = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+    if !is_csv {
+        let contents = std::fs::read(path)?;
+        return Ok(serde_json::from_slice(&contents)?);
+    }
+
+    let mut reader = csv::Reader::from_path(path)?;
+    let mut entries = Vec::new();
+    for row in reader.deserialize::<CsvCatalogEntry>() {
+        let row = row?;
+        entries.push(CatalogEntry {
+            name: row.name,
+            muscles_trained: row
+                .muscles_trained
+                .split(';')
+                .map(|muscle| muscle.trim().to_string())
+                .filter(|muscle| !muscle.is_empty())
+                .collect(),
+            exercise_type: row.exercise_type,
+        });
+    }
+    Ok(entries)
+}
+
+async fn seed(pool: &PgPool, catalog: Option<&Path>) -> CliResult {
+    let Some(path) = catalog else {
+        let added = db::insert_initial_exercises(pool).await?;
+        println!("Added {} starter exercises", added);
+        return Ok(());
+    };
+
+    let entries = read_catalog(path)?;
+    if let Some(entry) = entries
+        .iter()
+        .find(|e| e.name.trim().is_empty() || e.exercise_type.trim().is_empty())
+    {
+        return Err(format!(
+            "every catalog entry needs a name and an exercise_type (got '{}')",
+            entry.name
+        )
+        .into());
+    }
+
+    let mut added = 0;
+    for entry in &entries {
+        if db::insert_exercise(
+            pool,
+            entry.name.trim(),
+            &entry.muscles_trained,
+            entry.exercise_type.trim(),
+        )
+        .await?
+        {
+            added += 1;
+        }
+    }
+    println!(
+        "Added {} exercises, {} already present",
+        added,
+        entries.len() - added
+    );
+    Ok(())
+}
+
+async fn recompute_prs(pool: &PgPool) -> CliResult {
+    let exercise_ids: Vec<i32> = sqlx::query_scalar(
+        "SELECT DISTINCT ExerciseID::INTEGER FROM Workout_Exercises_Sets
+         UNION
+         SELECT DISTINCT ExerciseID::INTEGER FROM PRs",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut tx = pool.begin().await?;
+    rebuild_prs(&mut tx, &exercise_ids).await?;
+    tx.commit().await?;
+
+    println!("Recomputed PRs for {} exercises", exercise_ids.len());
+    Ok(())
+}
+
+// Replaces truncate_database.py. SchemaMigrations is kept, so the emptied
+// database still counts as migrated.
+async fn reset_db(pool: &PgPool, confirm: bool) -> CliResult {
+    let tables: Vec<String> = sqlx::query_scalar(
+        "SELECT tablename::TEXT FROM pg_tables
+         WHERE schemaname = current_schema() AND tablename <> 'schemamigrations'
+         ORDER BY tablename",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    if !confirm {
+        return Err(format!(
+            "this deletes every row of {} tables ({}); pass --confirm to proceed",
+            tables.len(),
+            tables.join(", ")
+        )
+        .into());
+    }
+    if tables.is_empty() {
+        println!("No tables to reset");
+        return Ok(());
+    }
+
+    let quoted: Vec<String> = tables
+        .iter()
+        .map(|table| format!("\"{}\"", table.replace('"', "\"\"")))
+        .collect();
+    sqlx::query(&format!(
+        "TRUNCATE TABLE {} RESTART IDENTITY CASCADE",
+        quoted.join(", ")
+    ))
+    .execute(pool)
+    .await?;
+
+    for table in &tables {
+        println!("Truncated table: {}", table);
+    }
+    println!("All tables truncated. Run `seed` to restore the starter exercises.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestApp;
+
+    #[actix_web::test]
+    async fn reset_db_needs_confirmation() {
+        let app = TestApp::new().await;
+        let squat = app.exercise("Squat").create().await;
+        app.workout().set(squat, 100, 5).create().await;
+
+        let error = reset_db(&app.pool, false).await.unwrap_err();
+        assert!(error.to_string().contains("pass --confirm"));
+        assert_eq!(app.count("Workout").await, 1);
+
+        reset_db(&app.pool, true).await.unwrap();
+        assert_eq!(app.count("Workout").await, 0);
+        assert_eq!(app.count("ExerciseList").await, 0);
+        // Still migrated, so the other commands keep working
+        require_schema(&app.pool).await.unwrap();
+    }
+
+    #[actix_web::test]
+    async fn recompute_prs_rebuilds_the_records() {
+        let app = TestApp::new().await;
+        let squat = app.exercise("Squat").create().await;
+        app.workout().set(squat, 100, 5).create().await;
+        app.workout().set(squat, 110, 3).create().await;
+        let records: Vec<(i16, f32)> = sqlx::query_as(
+            "SELECT heaviestweight, ROUND(onerm::NUMERIC, 2)::REAL FROM PRs ORDER BY prid",
+        )
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+
+        sqlx::query("DELETE FROM HighestRepsPerWeight")
+            .execute(&app.pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM PRs")
+            .execute(&app.pool)
+            .await
+            .unwrap();
+
+        recompute_prs(&app.pool).await.unwrap();
+        let rebuilt: Vec<(i16, f32)> = sqlx::query_as(
+            "SELECT heaviestweight, ROUND(onerm::NUMERIC, 2)::REAL FROM PRs ORDER BY prid",
+        )
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+        assert_eq!(rebuilt, records);
+        assert_eq!(app.count("HighestRepsPerWeight").await, 2);
+    }
+
+    #[actix_web::test]
+    async fn import_merges_an_export_file() {
+        let source = TestApp::new().await;
+        let squat = source.exercise("Squat").create().await;
+        source.workout().set(squat, 100, 5).create().await;
+        let file = source.get("/api/v1/export").await.expect(200).body;
+
+        let target = TestApp::new().await;
+        export::import_file(&target.pool, &file).await.unwrap();
+        assert_eq!(target.count("Workout").await, 1);
+        assert_eq!(target.count("PRs").await, 1);
+
+        assert!(export::import_file(&target.pool, b"not json")
+            .await
+            .is_err());
+    }
+}
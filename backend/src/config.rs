@@ -0,0 +1,620 @@
+use clap::{Args, ValueEnum};
+use serde::Deserialize;
+use std::env;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Server settings, resolved with this precedence (later wins):
+//   built-in defaults < config file < environment < command-line flags
+// The file is the one given by --config or STRONGERYOU_CONFIG, otherwise
+// strongeryou.toml in the working directory if it exists.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub log: LogConfig,
+    pub seed: SeedConfig,
+    pub limits: LimitsConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    pub cors_origins: Vec<String>, // "*" allows any origin
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: Option<String>, // Required, usually through DATABASE_URL
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub acquire_timeout_secs: u64,
+    pub idle_timeout_secs: Option<u64>,
+    pub slow_statement_ms: u64, // Statements taking longer are logged as warnings
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub level: String, // Log filter, e.g. "info" or "info,backend=debug"
+    pub format: LogFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SeedConfig {
+    pub exercises: bool, // Insert the starter exercise catalog on startup
+}
+
+// Request size caps and rate limits; exceeding them gets a 413 or 429
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub json_body_bytes: usize,   // JSON request bodies
+    pub import_body_bytes: usize, // Uploaded files and export documents
+    pub exercises_per_workout: usize,
+    pub sets_per_exercise: usize,
+    pub requests_per_minute_per_ip: u32, // 0 disables
+    // Per Authorization credential, for requests that carry one; 0 disables
+    pub requests_per_minute_per_user: u32,
+    pub burst: u32, // Requests allowed at once before the per-minute rate applies
+    // Reverse proxies whose X-Forwarded-For or Forwarded header names the
+    // client for the per-IP limit; when empty the connecting address is used
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            host: "127.0.0.1".to_string(),
+            port: 8081,
+            cors_origins: vec!["http://localhost:8101".to_string()],
+        }
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: None,
+            max_connections: 10,
+            min_connections: 0,
+            acquire_timeout_secs: 30,
+            idle_timeout_secs: Some(600),
+            slow_statement_ms: 500,
+        }
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+impl Default for SeedConfig {
+    fn default() -> Self {
+        SeedConfig { exercises: true }
+    }
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            json_body_bytes: 256 * 1024,
+            import_body_bytes: 16 * 1024 * 1024,
+            exercises_per_workout: 50,
+            sets_per_exercise: 100,
+            requests_per_minute_per_ip: 600,
+            requests_per_minute_per_user: 300,
+            burst: 60,
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+// Command-line overrides; every flag is optional so that unset flags leave
+// the file and environment values alone
+#[derive(Debug, Default, Args)]
+pub struct ConfigArgs {
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "Path to a TOML config file"
+    )]
+    pub config: Option<PathBuf>,
+    #[arg(long, global = true, help = "Address to bind to")]
+    pub host: Option<String>,
+    #[arg(long, global = true, help = "Port to listen on")]
+    pub port: Option<u16>,
+    #[arg(
+        long = "cors-origin",
+        global = true,
+        value_name = "ORIGIN",
+        help = "Allowed CORS origin; repeat for several, \"*\" for any"
+    )]
+    pub cors_origins: Vec<String>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "URL",
+        help = "Postgres connection URL"
+    )]
+    pub database_url: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        help = "Maximum number of pooled connections"
+    )]
+    pub db_max_connections: Option<u32>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECS",
+        help = "Seconds to wait for a pooled connection"
+    )]
+    pub db_acquire_timeout: Option<u64>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILTER",
+        help = "Log filter, e.g. \"info\" or \"info,backend=debug\""
+    )]
+    pub log_level: Option<String>,
+    #[arg(long, global = true, help = "Log line format")]
+    pub log_format: Option<LogFormat>,
+    #[arg(
+        long,
+        global = true,
+        help = "Skip inserting the starter exercise catalog"
+    )]
+    pub no_seed: bool,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    File(PathBuf, String),
+    Env(&'static str, String),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::File(path, message) => {
+                write!(f, "config file {}: {}", path.display(), message)
+            }
+            ConfigError::Env(name, message) => {
+                write!(f, "environment variable {}: {}", name, message)
+            }
+            ConfigError::Invalid(message) => write!(f, "invalid configuration: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+const DEFAULT_FILE: &str = "strongeryou.toml";
+
+impl Config {
+    pub fn load(args: &ConfigArgs) -> Result<Config, ConfigError> {
+        let file = match config_file(args) {
+            Some(path) => Some(Config::from_file(&path)?),
+            None => None,
+        };
+        Config::resolve(file, &env_var, args)
+    }
+
+    // Layers the environment and flags over the file, or the defaults without
+    // one. Variables are looked up through `var` so that tests need not touch
+    // the process environment.
+    fn resolve(
+        file: Option<Config>,
+        var: &dyn Fn(&str) -> Option<String>,
+        args: &ConfigArgs,
+    ) -> Result<Config, ConfigError> {
+        let mut config = file.unwrap_or_default();
+        config.apply_env(var)?;
+        config.apply_args(args);
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Config, ConfigError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::File(path.to_path_buf(), e.to_string()))?;
+        toml::from_str(&text).map_err(|e| ConfigError::File(path.to_path_buf(), e.to_string()))
+    }
+
+    fn apply_env(&mut self, var: &dyn Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        if let Some(host) = var("STRONGERYOU_HOST") {
+            self.server.host = host;
+        }
+        if let Some(port) = env_parse(var, "STRONGERYOU_PORT")? {
+            self.server.port = port;
+        }
+        if let Some(origins) = var("STRONGERYOU_CORS_ORIGINS") {
+            self.server.cors_origins = origins
+                .split(',')
+                .map(|origin| origin.trim().to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect();
+        }
+        if let Some(url) = var("DATABASE_URL") {
+            self.database.url = Some(url);
+        }
+        if let Some(max) = env_parse(var, "STRONGERYOU_DB_MAX_CONNECTIONS")? {
+            self.database.max_connections = max;
+        }
+        if let Some(min) = env_parse(var, "STRONGERYOU_DB_MIN_CONNECTIONS")? {
+            self.database.min_connections = min;
+        }
+        if let Some(secs) = env_parse(var, "STRONGERYOU_DB_ACQUIRE_TIMEOUT")? {
+            self.database.acquire_timeout_secs = secs;
+        }
+        if let Some(secs) = env_parse(var, "STRONGERYOU_DB_IDLE_TIMEOUT")? {
+            // 0 keeps idle connections open indefinitely
+            self.database.idle_timeout_secs = (secs > 0).then_some(secs);
+        }
+        if let Some(ms) = env_parse(var, "STRONGERYOU_DB_SLOW_STATEMENT_MS")? {
+            self.database.slow_statement_ms = ms;
+        }
+        if let Some(level) = var("STRONGERYOU_LOG_LEVEL").or_else(|| var("RUST_LOG")) {
+            self.log.level = level;
+        }
+        if let Some(format) = var("STRONGERYOU_LOG_FORMAT") {
+            self.log.format = LogFormat::from_str(&format, true).map_err(|_| {
+                ConfigError::Env(
+                    "STRONGERYOU_LOG_FORMAT",
+                    "expected 'text' or 'json'".to_string(),
+                )
+            })?;
+        }
+        if let Some(seed) = env_parse(var, "STRONGERYOU_SEED_EXERCISES")? {
+            self.seed.exercises = seed;
+        }
+        if let Some(bytes) = env_parse(var, "STRONGERYOU_JSON_BODY_BYTES")? {
+            self.limits.json_body_bytes = bytes;
+        }
+        if let Some(bytes) = env_parse(var, "STRONGERYOU_IMPORT_BODY_BYTES")? {
+            self.limits.import_body_bytes = bytes;
+        }
+        if let Some(count) = env_parse(var, "STRONGERYOU_EXERCISES_PER_WORKOUT")? {
+            self.limits.exercises_per_workout = count;
+        }
+        if let Some(count) = env_parse(var, "STRONGERYOU_SETS_PER_EXERCISE")? {
+            self.limits.sets_per_exercise = count;
+        }
+        if let Some(rate) = env_parse(var, "STRONGERYOU_RATE_LIMIT_PER_IP")? {
+            self.limits.requests_per_minute_per_ip = rate;
+        }
+        if let Some(rate) = env_parse(var, "STRONGERYOU_RATE_LIMIT_PER_USER")? {
+            self.limits.requests_per_minute_per_user = rate;
+        }
+        if let Some(burst) = env_parse(var, "STRONGERYOU_RATE_LIMIT_BURST")? {
+            self.limits.burst = burst;
+        }
+        if let Some(proxies) = var("STRONGERYOU_TRUSTED_PROXIES") {
+            self.limits.trusted_proxies = proxies
+                .split(',')
+                .map(str::trim)
+                .filter(|proxy| !proxy.is_empty())
+                .map(|proxy| {
+                    proxy.parse().map_err(|e| {
+                        ConfigError::Env(
+                            "STRONGERYOU_TRUSTED_PROXIES",
+                            format!("'{}': {}", proxy, e),
+                        )
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        Ok(())
+    }
+
+    fn apply_args(&mut self, args: &ConfigArgs) {
+        if let Some(host) = &args.host {
+            self.server.host = host.clone();
+        }
+        if let Some(port) = args.port {
+            self.server.port = port;
+        }
+        if !args.cors_origins.is_empty() {
+            self.server.cors_origins = args.cors_origins.clone();
+        }
+        if let Some(url) = &args.database_url {
+            self.database.url = Some(url.clone());
+        }
+        if let Some(max) = args.db_max_connections {
+            self.database.max_connections = max;
+        }
+        if let Some(secs) = args.db_acquire_timeout {
+            self.database.acquire_timeout_secs = secs;
+        }
+        if let Some(level) = &args.log_level {
+            self.log.level = level.clone();
+        }
+        if let Some(format) = args.log_format {
+            self.log.format = format;
+        }
+        if args.no_seed {
+            self.seed.exercises = false;
+        }
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |message: String| Err(ConfigError::Invalid(message));
+
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.log.level) {
+            return invalid(format!("log level '{}': {}", self.log.level, e));
+        }
+        if self.server.host.trim().is_empty() {
+            return invalid("server.host must not be empty".to_string());
+        }
+        for origin in &self.server.cors_origins {
+            if origin != "*" && !origin.starts_with("http://") && !origin.starts_with("https://") {
+                return invalid(format!(
+                    "CORS origin '{}' must start with http:// or https://, or be \"*\"",
+                    origin
+                ));
+            }
+        }
+
+        match &self.database.url {
+            None => {
+                return invalid(
+                    "no database URL; set DATABASE_URL, database.url or --database-url".to_string(),
+                )
+            }
+            Some(url) if !url.starts_with("postgres://") && !url.starts_with("postgresql://") => {
+                return invalid(
+                    "the database URL must start with postgres:// or postgresql://".to_string(),
+                )
+            }
+            Some(_) => {}
+        }
+        if self.database.max_connections == 0 {
+            return invalid("database.max_connections must be at least 1".to_string());
+        }
+        if self.database.min_connections > self.database.max_connections {
+            return invalid(format!(
+                "database.min_connections ({}) exceeds database.max_connections ({})",
+                self.database.min_connections, self.database.max_connections
+            ));
+        }
+
+        let limits = &self.limits;
+        for (name, value) in [
+            ("limits.json_body_bytes", limits.json_body_bytes),
+            ("limits.import_body_bytes", limits.import_body_bytes),
+            ("limits.exercises_per_workout", limits.exercises_per_workout),
+            ("limits.sets_per_exercise", limits.sets_per_exercise),
+        ] {
+            if value == 0 {
+                return invalid(format!("{} must be at least 1", name));
+            }
+        }
+        let rate_limited =
+            limits.requests_per_minute_per_ip > 0 || limits.requests_per_minute_per_user > 0;
+        if rate_limited && limits.burst == 0 {
+            return invalid("limits.burst must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn database_url(&self) -> &str {
+        self.database.url.as_deref().unwrap_or_default()
+    }
+}
+
+impl DatabaseConfig {
+    pub fn acquire_timeout(&self) -> Duration {
+        Duration::from_secs(self.acquire_timeout_secs)
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout_secs.map(Duration::from_secs)
+    }
+
+    pub fn slow_statement_threshold(&self) -> Duration {
+        Duration::from_millis(self.slow_statement_ms)
+    }
+}
+
+fn config_file(args: &ConfigArgs) -> Option<PathBuf> {
+    args.config
+        .clone()
+        .or_else(|| env_var("STRONGERYOU_CONFIG").map(PathBuf::from))
+        .or_else(|| {
+            let default = PathBuf::from(DEFAULT_FILE);
+            default.exists().then_some(default)
+        })
+}
+
+// Unset and empty variables are treated the same
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn env_parse<T: std::str::FromStr>(
+    var: &dyn Fn(&str) -> Option<String>,
+    name: &'static str,
+) -> Result<Option<T>, ConfigError>
+where
+    T::Err: fmt::Display,
+{
+    var(name)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|e: T::Err| ConfigError::Env(name, format!("'{}': {}", value, e)))
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    const URL: &str = "postgres://localhost/strongeryou";
+
+    fn resolve(
+        file: Option<&str>,
+        vars: &[(&str, &str)],
+        args: &ConfigArgs,
+    ) -> Result<Config, ConfigError> {
+        let file = file.map(|text| toml::from_str(text).unwrap());
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        Config::resolve(file, &|name| vars.get(name).cloned(), args)
+    }
+
+    #[test]
+    fn later_sources_win() {
+        let file = r#"
+            [server]
+            host = "0.0.0.0"
+            port = 9000
+            [database]
+            url = "postgres://file/db"
+            max_connections = 20
+            [limits]
+            sets_per_exercise = 40
+            trusted_proxies = ["10.0.0.1"]
+        "#;
+        let vars = [
+            ("STRONGERYOU_PORT", "9001"),
+            ("DATABASE_URL", "postgres://env/db"),
+            ("STRONGERYOU_SETS_PER_EXERCISE", "30"),
+            ("STRONGERYOU_EXERCISES_PER_WORKOUT", "12"),
+            ("STRONGERYOU_TRUSTED_PROXIES", "10.0.0.2, ::1"),
+        ];
+        let args = ConfigArgs {
+            database_url: Some("postgres://flag/db".to_string()),
+            no_seed: true,
+            ..ConfigArgs::default()
+        };
+
+        let config = resolve(Some(file), &vars, &args).unwrap();
+        assert_eq!(config.server.host, "0.0.0.0"); // File over default
+        assert_eq!(config.database.max_connections, 20);
+        assert_eq!(config.server.port, 9001); // Environment over file
+        assert_eq!(config.limits.sets_per_exercise, 30);
+        assert_eq!(config.limits.exercises_per_workout, 12);
+        assert_eq!(
+            config.limits.trusted_proxies,
+            ["10.0.0.2", "::1"].map(|ip| ip.parse::<IpAddr>().unwrap())
+        );
+        assert_eq!(config.database_url(), "postgres://flag/db"); // Flag over both
+        assert!(!config.seed.exercises);
+        // Untouched by any source
+        assert_eq!(config.database.min_connections, 0);
+        assert_eq!(config.limits.json_body_bytes, 256 * 1024);
+
+        let config = resolve(None, &[("DATABASE_URL", URL)], &ConfigArgs::default()).unwrap();
+        assert_eq!(config.server.port, ServerConfig::default().port);
+        assert!(config.seed.exercises);
+        assert!(config.limits.trusted_proxies.is_empty());
+    }
+
+    #[test]
+    fn unparseable_variables_are_reported() {
+        for (name, value) in [
+            ("STRONGERYOU_PORT", "http"),
+            ("STRONGERYOU_TRUSTED_PROXIES", "10.0.0.1,proxy.local"),
+        ] {
+            let vars = [("DATABASE_URL", URL), (name, value)];
+            match resolve(None, &vars, &ConfigArgs::default()) {
+                Err(ConfigError::Env(reported, _)) => assert_eq!(reported, name),
+                other => panic!("expected an environment error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        // A change that breaks a valid config, and part of the message
+        type Case = (fn(&mut Config), &'static str);
+        let cases: Vec<Case> = vec![
+            (|c| c.log.level = "info,=[".to_string(), "log level"),
+            (|c| c.server.host = " ".to_string(), "server.host"),
+            (
+                |c| c.server.cors_origins = vec!["example.com".to_string()],
+                "CORS origin 'example.com'",
+            ),
+            (|c| c.database.url = None, "no database URL"),
+            (
+                |c| c.database.url = Some("mysql://localhost/db".to_string()),
+                "must start with postgres://",
+            ),
+            (
+                |c| c.database.max_connections = 0,
+                "database.max_connections must be at least 1",
+            ),
+            (
+                |c| c.database.min_connections = 20,
+                "database.min_connections (20) exceeds",
+            ),
+            (
+                |c| c.limits.json_body_bytes = 0,
+                "limits.json_body_bytes must be at least 1",
+            ),
+            (
+                |c| c.limits.import_body_bytes = 0,
+                "limits.import_body_bytes must be at least 1",
+            ),
+            (
+                |c| c.limits.exercises_per_workout = 0,
+                "limits.exercises_per_workout must be at least 1",
+            ),
+            (
+                |c| c.limits.sets_per_exercise = 0,
+                "limits.sets_per_exercise must be at least 1",
+            ),
+            (|c| c.limits.burst = 0, "limits.burst must be at least 1"),
+        ];
+
+        for (change, expected) in cases {
+            let mut config = Config::default();
+            config.database.url = Some(URL.to_string());
+            assert!(config.validate().is_ok());
+            change(&mut config);
+            match config.validate() {
+                Err(ConfigError::Invalid(message)) => {
+                    assert!(message.contains(expected), "{}", message)
+                }
+                other => panic!("expected '{}', got {:?}", expected, other),
+            }
+        }
+
+        // A burst of 0 is fine once rate limiting is off
+        let mut config = Config::default();
+        config.database.url = Some(URL.to_string());
+        config.limits.requests_per_minute_per_ip = 0;
+        config.limits.requests_per_minute_per_user = 0;
+        config.limits.burst = 0;
+        assert!(config.validate().is_ok());
+    }
+}
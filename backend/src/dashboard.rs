@@ -0,0 +1,122 @@
+// Everything the app's home screen shows, so it can load with one request
+use actix_web::{get, web, HttpResponse};
+use chrono::NaiveDate;
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::errors::{ApiError, ErrorBody};
+use crate::markers::{self, MarkerSummary};
+use crate::repository::{RecordSet, Repositories};
+use crate::workouts::WorkoutSummary;
+
+const RECENT_WORKOUTS: i64 = 5;
+const RECENT_RECORDS: i64 = 5;
+
+#[derive(Serialize, ToSchema)]
+struct Dashboard {
+    markers: Vec<MarkerSummary>,          // By name
+    recent_workouts: Vec<WorkoutSummary>, // Newest first
+    recent_prs: Vec<RecordSet>,           // Newest first
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(get_dashboard),
+    tags((name = "dashboard", description = "Overview for the app's home screen"))
+)]
+pub(crate) struct ApiDoc;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_dashboard);
+}
+
+#[utoipa::path(
+    tag = "dashboard",
+    summary = "Markers, recent workouts and recent PRs",
+    params(("date" = Option<NaiveDate>, Query, description = "Summarize markers as of this day instead of today")),
+    responses(
+        (status = 200, body = Dashboard),
+        (status = 400, description = "Invalid date", body = ErrorBody)
+    )
+)]
+#[get("/dashboard")]
+async fn get_dashboard(
+    pool: web::Data<PgPool>,
+    repos: web::Data<Repositories>,
+    request: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let today = markers::as_of_date(&request)?;
+    let marker_list = repos.markers.list().await?;
+
+    Ok(HttpResponse::Ok().json(Dashboard {
+        markers: markers::summarize(pool.get_ref(), &repos, marker_list, today).await?,
+        recent_workouts: repos.workouts.list(Some(RECENT_WORKOUTS)).await?,
+        recent_prs: repos.prs.recent_records(RECENT_RECORDS).await?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::test_support::TestApp;
+
+    #[actix_web::test]
+    async fn aggregates_markers_workouts_and_prs() {
+        let app = TestApp::new().await;
+        let squat = app.exercise("Squat").create().await;
+        let bench = app.exercise("Bench Press").create().await;
+        app.marker("Weight")
+            .entry("2026-01-10", 80.0)
+            .create()
+            .await;
+
+        let first = app
+            .workout()
+            .at("2026-01-05T18:00:00")
+            .set(squat, 100, 5)
+            .create()
+            .await;
+        app.workout()
+            .at("2026-01-07T18:00:00")
+            .set(squat, 90, 5)
+            .create()
+            .await;
+        let mut latest = 0;
+        for day in 8..=12 {
+            latest = app
+                .workout()
+                .at(&format!("2026-01-{:02}T18:00:00", day))
+                .set(bench, 60, 5)
+                .create()
+                .await;
+        }
+
+        let dashboard = app
+            .get("/api/v1/dashboard?date=2026-01-12")
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(dashboard["markers"][0]["name"], "Weight");
+        assert_eq!(dashboard["markers"][0]["latest_value"], 80.0);
+        app.get("/api/v1/dashboard?date=-262143-01-05")
+            .await
+            .expect(400);
+
+        let workouts = dashboard["recent_workouts"].as_array().unwrap();
+        assert_eq!(workouts.len(), 5);
+        assert_eq!(workouts[0]["workout_id"], latest);
+
+        // 90x5 and the repeated 60x5 beat nothing
+        let prs = dashboard["recent_prs"].as_array().unwrap();
+        assert_eq!(prs.len(), 2);
+        assert_eq!(prs[0]["exercise_name"], "Bench Press");
+        assert_eq!(prs[0]["start_time"], "2026-01-08T18:00:00");
+        assert_eq!(
+            (&prs[1]["workout_id"], &prs[1]["heaviest_weight"]),
+            (&json!(first), &json!(100))
+        );
+    }
+}
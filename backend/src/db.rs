@@ -1,26 +1,89 @@
-use sqlx::{Error, PgPool};
-use std::env;
+use log::{debug, info, LevelFilter};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{ConnectOptions, Error, PgPool};
+use tracing::instrument;
 
-pub async fn initialize_database() -> Result<PgPool, Error> {
-    dotenv::dotenv().ok();
-    let database_url = env::var("DATABASE_URL").expect("Failed to obtain database url");
+use crate::config::Config;
 
-    println!("Initializing database connection...");
-    let pool = PgPool::connect(&database_url).await?;
-    println!("Database connection established.");
+// Bumped whenever create_tables changes the schema; `migrate status` and the
+// readiness check compare it with the version recorded in SchemaMigrations
+pub const SCHEMA_VERSION: i32 = 2;
 
-    println!("Creating tables if they don't exist...");
-    create_tables(&pool).await?;
-    println!("Tables initialized.");
+// Every statement is logged at debug level with its duration, inside the
+// span of the request and function that ran it; slow ones at warn level
+pub async fn connect(config: &Config) -> Result<PgPool, Error> {
+    let options: PgConnectOptions = config.database_url().parse()?;
+    let options = options
+        // Silences the "already exists, skipping" notices of create_tables
+        .options([("client_min_messages", "warning")])
+        .log_statements(LevelFilter::Debug)
+        .log_slow_statements(LevelFilter::Warn, config.database.slow_statement_threshold());
 
-    println!("Inserting initial exercise data...");
-    insert_initial_exercises(&pool).await?;
-    println!("Initial exercise data inserted.");
+    PgPoolOptions::new()
+        .max_connections(config.database.max_connections)
+        .min_connections(config.database.min_connections)
+        .acquire_timeout(config.database.acquire_timeout())
+        .idle_timeout(config.database.idle_timeout())
+        .connect_with(options)
+        .await
+}
+
+// Connects, brings the schema up to date and seeds the starter exercises
+pub async fn initialize_database(config: &Config) -> Result<PgPool, Error> {
+    info!("Connecting to the database");
+    let pool = connect(config).await?;
+
+    migrate(&pool).await?;
+    info!("Database schema is at version {}", SCHEMA_VERSION);
+
+    if config.seed.exercises {
+        let added = insert_initial_exercises(&pool).await?;
+        info!("Inserted {} starter exercises", added);
+    }
 
     Ok(pool)
 }
 
+// create_tables is idempotent, so migrating is running it and recording the
+// version it brings the schema to
+#[instrument(skip_all)]
+pub async fn migrate(pool: &PgPool) -> Result<(), Error> {
+    create_tables(pool).await?;
+    sqlx::query(
+        "INSERT INTO SchemaMigrations (Version) VALUES ($1) ON CONFLICT (Version) DO NOTHING",
+    )
+    .bind(SCHEMA_VERSION)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// Latest recorded schema version, None for a database never migrated
+pub async fn schema_version(pool: &PgPool) -> Result<Option<i32>, Error> {
+    let tracked: bool = sqlx::query_scalar("SELECT to_regclass('schemamigrations') IS NOT NULL")
+        .fetch_one(pool)
+        .await?;
+    if !tracked {
+        return Ok(None);
+    }
+    sqlx::query_scalar("SELECT MAX(Version) FROM SchemaMigrations")
+        .fetch_one(pool)
+        .await
+}
+
 async fn create_tables(pool: &PgPool) -> Result<(), Error> {
+    // Schema versions applied by migrate()
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS SchemaMigrations (
+            Version INTEGER PRIMARY KEY,
+            AppliedAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
     // Users table
     sqlx::query(
         r#"
@@ -67,7 +130,7 @@ async fn create_tables(pool: &PgPool) -> Result<(), Error> {
         CREATE TABLE IF NOT EXISTS Workout (
             WorkoutID SERIAL PRIMARY KEY,
             Start TIMESTAMP NOT NULL,
-            "End" TIMESTAMP NOT NULL,
+            "end" TIMESTAMP NOT NULL,
             RoutineID INTEGER REFERENCES Routines(RoutineID)
         );
         "#,
@@ -75,6 +138,23 @@ async fn create_tables(pool: &PgPool) -> Result<(), Error> {
     .execute(pool)
     .await?;
 
+    // Older databases created here have a case-sensitive "End" column, while
+    // every query uses "end"
+    sqlx::query(
+        r#"
+        DO $$
+        BEGIN
+            IF EXISTS (SELECT 1 FROM information_schema.columns
+                       WHERE table_schema = current_schema()
+                         AND table_name = 'workout' AND column_name = 'End') THEN
+                ALTER TABLE Workout RENAME COLUMN "End" TO "end";
+            END IF;
+        END $$;
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
     // PRs table
     sqlx::query(
         r#"
@@ -126,7 +206,7 @@ async fn create_tables(pool: &PgPool) -> Result<(), Error> {
         CREATE TABLE IF NOT EXISTS Workout_Exercises_Sets (
             WorkoutID INTEGER REFERENCES Workout(WorkoutID),
             ExerciseID INTEGER REFERENCES ExerciseList(ExerciseID),
-            SetID SMALLINT NOT NULL,
+            SetID INTEGER NOT NULL,
             PRIMARY KEY (WorkoutID, ExerciseID, SetID)
         );
         "#,
@@ -161,6 +241,22 @@ async fn create_tables(pool: &PgPool) -> Result<(), Error> {
     .execute(pool)
     .await?;
 
+    // Marker display settings and goal
+    sqlx::query(
+        r#"
+        ALTER TABLE MarkerList
+            ADD COLUMN IF NOT EXISTS Unit VARCHAR(32),
+            ADD COLUMN IF NOT EXISTS DecimalPlaces SMALLINT NOT NULL DEFAULT 1,
+            ADD COLUMN IF NOT EXISTS Direction VARCHAR(10) NOT NULL DEFAULT 'higher',
+            ADD COLUMN IF NOT EXISTS GoalValue REAL,
+            ADD COLUMN IF NOT EXISTS GoalDate DATE,
+            ADD COLUMN IF NOT EXISTS ReadingPolicy VARCHAR(10) NOT NULL DEFAULT 'keep',
+            ADD COLUMN IF NOT EXISTS Formula TEXT;
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
     // Markers table
     sqlx::query(
         r#"
@@ -175,6 +271,220 @@ async fn create_tables(pool: &PgPool) -> Result<(), Error> {
     .execute(pool)
     .await?;
 
+    // Addressable marker entries with an optional timestamp and note
+    sqlx::query(
+        r#"
+        ALTER TABLE Markers
+            ADD COLUMN IF NOT EXISTS EntryID SERIAL PRIMARY KEY,
+            ADD COLUMN IF NOT EXISTS LoggedAt TIMESTAMP,
+            ADD COLUMN IF NOT EXISTS Note TEXT;
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Settings table
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS Settings (
+            Name VARCHAR(64) PRIMARY KEY,
+            Value DOUBLE PRECISION NOT NULL,
+            UserID INTEGER REFERENCES Users(UserID)
+        );
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // ExerciseAliases table: names used by other trackers mapped onto ExerciseList
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS ExerciseAliases (
+            Alias VARCHAR(255) PRIMARY KEY,
+            ExerciseID INTEGER NOT NULL REFERENCES ExerciseList(ExerciseID) ON DELETE CASCADE
+        );
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // CalendarFeeds table: secret tokens for subscribing to the workout calendar
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS CalendarFeeds (
+            Token VARCHAR(64) PRIMARY KEY DEFAULT replace(gen_random_uuid()::text, '-', ''),
+            Label VARCHAR(255),
+            CreatedAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // RoutineVersions table: every definition a routine has had
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS RoutineVersions (
+            RoutineID INTEGER REFERENCES Routines(RoutineID) ON DELETE CASCADE,
+            Version INTEGER NOT NULL,
+            RoutineName VARCHAR(255) NOT NULL,
+            CreatedAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (RoutineID, Version)
+        );
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // RoutineVersionExercises table: exercises and sets of each version, in order
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS RoutineVersionExercises (
+            RoutineID INTEGER NOT NULL,
+            Version INTEGER NOT NULL,
+            Position SMALLINT NOT NULL,
+            ExerciseID INTEGER REFERENCES ExerciseList(ExerciseID),
+            NumberOfSets SMALLINT NOT NULL,
+            PRIMARY KEY (RoutineID, Version, Position),
+            FOREIGN KEY (RoutineID, Version)
+                REFERENCES RoutineVersions(RoutineID, Version) ON DELETE CASCADE
+        );
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Optional per-exercise targets of a routine
+    for table in ["Routines_Exercises_Sets", "RoutineVersionExercises"] {
+        sqlx::query(&format!(
+            "ALTER TABLE {} ADD COLUMN IF NOT EXISTS TargetReps SMALLINT,
+                            ADD COLUMN IF NOT EXISTS TargetWeight SMALLINT;",
+            table
+        ))
+        .execute(pool)
+        .await?;
+    }
+
+    // The routine version a workout was performed from
+    sqlx::query(r#"ALTER TABLE Workout ADD COLUMN IF NOT EXISTS RoutineVersion INTEGER;"#)
+        .execute(pool)
+        .await?;
+
+    // Routines created before versioning start out at version 1
+    sqlx::query(
+        r#"
+        INSERT INTO RoutineVersions (RoutineID, Version, RoutineName, CreatedAt)
+        SELECT r.RoutineID, 1, r.RoutineName, r.Timestamp FROM Routines r
+        WHERE NOT EXISTS (SELECT 1 FROM RoutineVersions v WHERE v.RoutineID = r.RoutineID);
+        "#,
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query(
+        r#"
+        INSERT INTO RoutineVersionExercises (RoutineID, Version, Position, ExerciseID, NumberOfSets)
+        SELECT res.RoutineID, 1,
+               ROW_NUMBER() OVER (PARTITION BY res.RoutineID ORDER BY res.ExerciseID) - 1,
+               res.ExerciseID, res.NumberOfSets
+        FROM Routines_Exercises_Sets res
+        WHERE NOT EXISTS (
+            SELECT 1 FROM RoutineVersionExercises e WHERE e.RoutineID = res.RoutineID
+        );
+        "#,
+    )
+    .execute(pool)
+    .await?;
+    // Workouts logged before versioning were performed from that version
+    sqlx::query(
+        r#"UPDATE Workout SET RoutineVersion = 1
+           WHERE RoutineID IS NOT NULL AND RoutineVersion IS NULL;"#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Order of the exercises in a routine, taken from its latest version
+    sqlx::query(
+        r#"ALTER TABLE Routines_Exercises_Sets ADD COLUMN IF NOT EXISTS Position SMALLINT;"#,
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query(
+        r#"
+        UPDATE Routines_Exercises_Sets res SET Position = ve.Position
+        FROM RoutineVersionExercises ve
+        WHERE res.Position IS NULL
+          AND ve.RoutineID = res.RoutineID AND ve.ExerciseID = res.ExerciseID
+          AND ve.Version = (SELECT MAX(Version) FROM RoutineVersions v WHERE v.RoutineID = res.RoutineID);
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Programs table: multi-week plans built from routines
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS Programs (
+            ProgramID SERIAL PRIMARY KEY,
+            ProgramName VARCHAR(255) NOT NULL,
+            ScheduleType VARCHAR(10) NOT NULL,
+            StartDate DATE NOT NULL,
+            Weeks SMALLINT NOT NULL,
+            DeloadWeeks SMALLINT[] NOT NULL DEFAULT '{}'
+        );
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // ProgramSlots table: a weekday or a position in the rotation; no routine means rest
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS ProgramSlots (
+            ProgramID INTEGER REFERENCES Programs(ProgramID) ON DELETE CASCADE,
+            Position SMALLINT NOT NULL,
+            Weekday SMALLINT,
+            RoutineID INTEGER REFERENCES Routines(RoutineID) ON DELETE SET NULL,
+            PRIMARY KEY (ProgramID, Position)
+        );
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Templates table: the library of published routine and program templates
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS Templates (
+            TemplateID SERIAL PRIMARY KEY,
+            Kind VARCHAR(10) NOT NULL,
+            TemplateName VARCHAR(255) NOT NULL,
+            Description TEXT,
+            Document JSONB NOT NULL,
+            PublishedAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Version 2: set links hold "Set".SetID, a SERIAL, so they outgrow
+    // SMALLINT after 32767 sets. Checked first to skip the table rewrite.
+    sqlx::query(
+        r#"
+        DO $$
+        BEGIN
+            IF (SELECT data_type FROM information_schema.columns
+                WHERE table_schema = current_schema()
+                  AND table_name = 'workout_exercises_sets' AND column_name = 'setid') = 'smallint'
+            THEN
+                ALTER TABLE Workout_Exercises_Sets ALTER COLUMN SetID TYPE INTEGER;
+            END IF;
+        END $$;
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
     // Indices
     sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_users_date_joined ON Users(DateJoined);"#)
         .execute(pool)
@@ -185,17 +495,27 @@ async fn create_tables(pool: &PgPool) -> Result<(), Error> {
     sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_workout_routine ON Workout(RoutineID);"#)
         .execute(pool)
         .await?;
+    sqlx::query(
+        r#"CREATE UNIQUE INDEX IF NOT EXISTS idx_highest_reps_exercise_weight ON HighestRepsPerWeight(ExerciseID, Weight);"#,
+    )
+    .execute(pool)
+    .await?;
     sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_markers_user ON Markers(UserID);"#)
         .execute(pool)
         .await?;
     sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_markers_date ON Markers(Date);"#)
         .execute(pool)
         .await?;
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_markers_marker_date ON Markers(MarkerID, Date);"#)
+        .execute(pool)
+        .await?;
 
     Ok(())
 }
 
-async fn insert_initial_exercises(pool: &PgPool) -> Result<(), Error> {
+// Returns how many were added; ones already present by name are skipped
+#[instrument(skip_all)]
+pub async fn insert_initial_exercises(pool: &PgPool) -> Result<usize, Error> {
     let exercises = vec![
         (
             "Bench Press",
@@ -267,21 +587,36 @@ async fn insert_initial_exercises(pool: &PgPool) -> Result<(), Error> {
         ("Front Raises (Dumbbell)", vec!["Shoulders"], "Single limb"),
     ];
 
+    let mut added = 0;
     for (name, muscles, type_) in exercises {
-        sqlx::query(
-            r#"
-            INSERT INTO ExerciseList (ExerciseName, MusclesTrained, ExerciseType)
-            VALUES ($1, $2, $3)
-            ON CONFLICT (ExerciseName) DO NOTHING
-            "#,
-        )
-        .bind(name)
-        .bind(&muscles)
-        .bind(type_)
-        .execute(pool)
-        .await?;
-        println!("Inserted exercise: {}", name);
+        let muscles: Vec<String> = muscles.into_iter().map(String::from).collect();
+        if insert_exercise(pool, name, &muscles, type_).await? {
+            debug!("Inserted exercise: {}", name);
+            added += 1;
+        }
     }
 
-    Ok(())
+    Ok(added)
+}
+
+// Adds an exercise unless one with the same name exists; true if added
+pub async fn insert_exercise(
+    pool: &PgPool,
+    name: &str,
+    muscles: &[String],
+    exercise_type: &str,
+) -> Result<bool, Error> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO ExerciseList (ExerciseName, MusclesTrained, ExerciseType)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (ExerciseName) DO NOTHING
+        "#,
+    )
+    .bind(name)
+    .bind(muscles)
+    .bind(exercise_type)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
 }
@@ -0,0 +1,605 @@
+// Derived markers: a marker whose values are computed from a formula over
+// other markers, exercise metrics and user settings instead of being logged.
+//
+// Supported syntax:
+//   marker("Bodyweight") or marker(3)   latest value on or before the date
+//   avg(marker("Bodyweight"), 7)        mean of the entries in the last 7 days
+//   e1rm("Bench Press") or e1rm(1)      best e1RM of the latest session
+//   setting("height")                   value from the Settings table
+//   numbers, + - * /, unary minus and parentheses
+use chrono::{Duration, NaiveDate};
+use sqlx::{Executor, PgConnection, PgPool, Postgres, Row};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::ops::Range;
+
+use crate::markers::ReadingPolicy;
+use crate::repository::postgres::fetch_readings;
+
+// Longest window avg() accepts, about ten years
+const MAX_AVERAGE_DAYS: f64 = 3650.0;
+
+// Parentheses and unary minus nested deeper than this are rejected, as the
+// parser and the evaluation recurse on them
+const MAX_NESTING: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Reference {
+    Id(i32),
+    Name(String),
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reference::Id(id) => write!(f, "{}", id),
+            Reference::Name(name) => write!(f, "\"{}\"", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug)]
+enum Expr {
+    Number(f64),
+    Marker(Reference),
+    Average(Reference, i64),
+    E1rm(Reference),
+    Setting(String),
+    Neg(Box<Expr>),
+    Binary(Box<Expr>, Op, Box<Expr>),
+}
+
+#[derive(Debug)]
+pub enum FormulaError {
+    Parse(String),
+    UnknownMarker(String),
+    UnknownExercise(String),
+    UnknownSetting(String),
+    DerivedReference(String),
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for FormulaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormulaError::Parse(message) => write!(f, "Invalid formula: {}", message),
+            FormulaError::UnknownMarker(marker) => write!(f, "Unknown marker {}", marker),
+            FormulaError::UnknownExercise(exercise) => write!(f, "Unknown exercise {}", exercise),
+            FormulaError::UnknownSetting(name) => write!(f, "Unknown setting \"{}\"", name),
+            FormulaError::DerivedReference(marker) => write!(
+                f,
+                "Marker {} is itself derived and cannot be referenced",
+                marker
+            ),
+            FormulaError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl From<sqlx::Error> for FormulaError {
+    fn from(e: sqlx::Error) -> Self {
+        FormulaError::Database(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Str(String),
+    Symbol(char),
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, FormulaError> {
+    Ok(tokenize_spanned(input)?
+        .into_iter()
+        .map(|(token, _)| token)
+        .collect())
+}
+
+// Tokens with the byte range of the input each came from
+fn tokenize_spanned(input: &str) -> Result<Vec<(Token, Range<usize>)>, FormulaError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    let offset = |chars: &mut std::iter::Peekable<std::str::CharIndices>| {
+        chars.peek().map_or(input.len(), |(i, _)| *i)
+    };
+
+    while let Some(&(start, c)) = chars.peek() {
+        let token = if c.is_whitespace() {
+            chars.next();
+            continue;
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut number = String::new();
+            while let Some(&(_, c)) = chars
+                .peek()
+                .filter(|(_, c)| c.is_ascii_digit() || *c == '.')
+            {
+                number.push(c);
+                chars.next();
+            }
+            let value = number
+                .parse()
+                .map_err(|_| FormulaError::Parse(format!("bad number '{}'", number)))?;
+            Token::Number(value)
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut ident = String::new();
+            while let Some(&(_, c)) = chars
+                .peek()
+                .filter(|(_, c)| c.is_ascii_alphanumeric() || *c == '_')
+            {
+                ident.push(c);
+                chars.next();
+            }
+            Token::Ident(ident.to_lowercase())
+        } else if c == '"' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, c)) => text.push(c),
+                    None => return Err(FormulaError::Parse("unterminated string".to_string())),
+                }
+            }
+            Token::Str(text)
+        } else if "+-*/(),".contains(c) {
+            chars.next();
+            Token::Symbol(c)
+        } else {
+            return Err(FormulaError::Parse(format!("unexpected character '{}'", c)));
+        };
+        tokens.push((token, start..offset(&mut chars)));
+    }
+
+    Ok(tokens)
+}
+
+// Rewrites the numeric IDs in marker(), avg() and e1rm() references using the
+// given old-to-new maps, for formulas copied into another database. IDs
+// missing from the maps are left as they are.
+pub fn remap_ids(
+    formula: &str,
+    marker_ids: &HashMap<i32, i32>,
+    exercise_ids: &HashMap<i32, i32>,
+) -> Result<String, FormulaError> {
+    let tokens = tokenize_spanned(formula)?;
+    let mut remapped = formula.to_string();
+    // From the end, so that earlier ranges stay valid
+    for window in tokens.windows(3).rev() {
+        let ids = match &window[0].0 {
+            Token::Ident(name) if name == "marker" => marker_ids,
+            Token::Ident(name) if name == "e1rm" => exercise_ids,
+            _ => continue,
+        };
+        if let (Token::Symbol('('), (Token::Number(id), range)) = (&window[1].0, &window[2]) {
+            if let Some(new_id) = ids.get(&(*id as i32)).filter(|_| id.fract() == 0.0) {
+                remapped.replace_range(range.clone(), &new_id.to_string());
+            }
+        }
+    }
+    Ok(remapped)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), FormulaError> {
+        match self.next() {
+            Some(Token::Symbol(c)) if c == symbol => Ok(()),
+            _ => Err(FormulaError::Parse(format!("expected '{}'", symbol))),
+        }
+    }
+
+    fn nested(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<Expr, FormulaError>,
+    ) -> Result<Expr, FormulaError> {
+        if self.depth == MAX_NESTING {
+            return Err(FormulaError::Parse(
+                "formula is nested too deeply".to_string(),
+            ));
+        }
+        self.depth += 1;
+        let expr = parse(self)?;
+        self.depth -= 1;
+        Ok(expr)
+    }
+
+    fn expression(&mut self) -> Result<Expr, FormulaError> {
+        let mut left = self.term()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol('+')) => Op::Add,
+                Some(Token::Symbol('-')) => Op::Sub,
+                _ => return Ok(left),
+            };
+            self.next();
+            left = Expr::Binary(Box::new(left), op, Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, FormulaError> {
+        let mut left = self.factor()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol('*')) => Op::Mul,
+                Some(Token::Symbol('/')) => Op::Div,
+                _ => return Ok(left),
+            };
+            self.next();
+            left = Expr::Binary(Box::new(left), op, Box::new(self.factor()?));
+        }
+    }
+
+    fn factor(&mut self) -> Result<Expr, FormulaError> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Expr::Number(value)),
+            Some(Token::Symbol('-')) => {
+                let inner = self.nested(Self::factor)?;
+                Ok(Expr::Neg(Box::new(inner)))
+            }
+            Some(Token::Symbol('(')) => {
+                let expr = self.nested(Self::expression)?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => self.call(&name),
+            Some(token) => Err(FormulaError::Parse(format!("unexpected {:?}", token))),
+            None => Err(FormulaError::Parse("unexpected end of formula".to_string())),
+        }
+    }
+
+    fn call(&mut self, name: &str) -> Result<Expr, FormulaError> {
+        self.expect('(')?;
+        let expr = match name {
+            "marker" => Expr::Marker(self.reference()?),
+            "e1rm" => Expr::E1rm(self.reference()?),
+            "setting" => match self.next() {
+                Some(Token::Str(name)) => Expr::Setting(name),
+                _ => return Err(FormulaError::Parse("setting() takes a name".to_string())),
+            },
+            "avg" => {
+                match self.next() {
+                    Some(Token::Ident(inner)) if inner == "marker" => self.expect('(')?,
+                    _ => {
+                        return Err(FormulaError::Parse(
+                            "avg() takes a marker(...) as its first argument".to_string(),
+                        ))
+                    }
+                }
+                let reference = self.reference()?;
+                self.expect(')')?;
+                self.expect(',')?;
+                match self.next() {
+                    Some(Token::Number(days))
+                        if (1.0..=MAX_AVERAGE_DAYS).contains(&days) && days.fract() == 0.0 =>
+                    {
+                        Expr::Average(reference, days as i64)
+                    }
+                    _ => {
+                        return Err(FormulaError::Parse(format!(
+                            "avg() takes a whole number of days from 1 to {}",
+                            MAX_AVERAGE_DAYS
+                        )))
+                    }
+                }
+            }
+            _ => return Err(FormulaError::Parse(format!("unknown function '{}'", name))),
+        };
+        self.expect(')')?;
+        Ok(expr)
+    }
+
+    fn reference(&mut self) -> Result<Reference, FormulaError> {
+        match self.next() {
+            Some(Token::Str(name)) => Ok(Reference::Name(name)),
+            Some(Token::Number(id)) if id.fract() == 0.0 => Ok(Reference::Id(id as i32)),
+            _ => Err(FormulaError::Parse(
+                "expected a quoted name or a numeric ID".to_string(),
+            )),
+        }
+    }
+}
+
+fn parse(formula: &str) -> Result<Expr, FormulaError> {
+    let mut parser = Parser {
+        tokens: tokenize(formula)?,
+        position: 0,
+        depth: 0,
+    };
+    let expr = parser.expression()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(token) => Err(FormulaError::Parse(format!("unexpected {:?}", token))),
+    }
+}
+
+#[derive(Default)]
+struct References {
+    markers: Vec<Reference>,
+    exercises: Vec<Reference>,
+    settings: Vec<String>,
+}
+
+fn collect(expr: &Expr, refs: &mut References) {
+    match expr {
+        Expr::Number(_) => {}
+        Expr::Marker(r) | Expr::Average(r, _) => refs.markers.push(r.clone()),
+        Expr::E1rm(r) => refs.exercises.push(r.clone()),
+        Expr::Setting(name) => refs.settings.push(name.clone()),
+        Expr::Neg(inner) => collect(inner, refs),
+        Expr::Binary(left, _, right) => {
+            collect(left, refs);
+            collect(right, refs);
+        }
+    }
+}
+
+type Series = Vec<(NaiveDate, f64)>;
+
+// Everything a formula needs, loaded up front
+struct Inputs {
+    marker_ids: HashMap<Reference, i32>,
+    exercise_ids: HashMap<Reference, i32>,
+    markers: HashMap<i32, Series>,
+    exercises: HashMap<i32, Series>,
+    settings: HashMap<String, f64>,
+}
+
+// Resolvers take any executor so that validate can run inside an import's
+// transaction, where the markers it refers to are not committed yet
+async fn resolve_marker<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    reference: &Reference,
+) -> Result<(i32, ReadingPolicy), FormulaError> {
+    let row = match reference {
+        Reference::Id(id) => {
+            sqlx::query(
+                "SELECT MarkerID, ReadingPolicy, Formula FROM MarkerList WHERE MarkerID = $1",
+            )
+            .bind(id)
+            .fetch_optional(executor)
+            .await?
+        }
+        Reference::Name(name) => {
+            sqlx::query(
+                "SELECT MarkerID, ReadingPolicy, Formula FROM MarkerList WHERE MarkerName = $1",
+            )
+            .bind(name)
+            .fetch_optional(executor)
+            .await?
+        }
+    };
+
+    let row = row.ok_or_else(|| FormulaError::UnknownMarker(reference.to_string()))?;
+    if row.get::<Option<String>, _>("formula").is_some() {
+        return Err(FormulaError::DerivedReference(reference.to_string()));
+    }
+    Ok((
+        row.get("markerid"),
+        row.get::<String, _>("readingpolicy")
+            .parse()
+            .unwrap_or_default(),
+    ))
+}
+
+async fn resolve_exercise<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    reference: &Reference,
+) -> Result<i32, FormulaError> {
+    let row = match reference {
+        Reference::Id(id) => {
+            sqlx::query("SELECT ExerciseID FROM ExerciseList WHERE ExerciseID = $1")
+                .bind(id)
+                .fetch_optional(executor)
+                .await?
+        }
+        Reference::Name(name) => {
+            sqlx::query("SELECT ExerciseID FROM ExerciseList WHERE ExerciseName ILIKE $1")
+                .bind(name)
+                .fetch_optional(executor)
+                .await?
+        }
+    };
+
+    row.map(|row| row.get("exerciseid"))
+        .ok_or_else(|| FormulaError::UnknownExercise(reference.to_string()))
+}
+
+async fn fetch_setting<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    name: &str,
+) -> Result<f64, FormulaError> {
+    sqlx::query("SELECT Value FROM Settings WHERE Name = $1")
+        .bind(name)
+        .fetch_optional(executor)
+        .await?
+        .map(|row| row.get::<f64, _>("value"))
+        .ok_or_else(|| FormulaError::UnknownSetting(name.to_string()))
+}
+
+// Best e1RM of each session for the exercise, one point per day
+async fn fetch_e1rm_series(
+    pool: &PgPool,
+    exercise_id: i32,
+    end_date: NaiveDate,
+) -> Result<Series, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT w.Start::date AS date, MAX(p.OneRM) AS onerm
+         FROM PRs p
+         JOIN Workout w ON p.WorkoutID = w.WorkoutID
+         WHERE p.ExerciseID = $1 AND w.Start::date <= $2
+         GROUP BY w.Start::date
+         ORDER BY date ASC",
+    )
+    .bind(exercise_id)
+    .bind(end_date)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| {
+            (
+                row.get::<NaiveDate, _>("date"),
+                f64::from(row.get::<f32, _>("onerm")),
+            )
+        })
+        .collect())
+}
+
+async fn load_inputs(
+    pool: &PgPool,
+    refs: &References,
+    end_date: NaiveDate,
+) -> Result<Inputs, FormulaError> {
+    let mut inputs = Inputs {
+        marker_ids: HashMap::new(),
+        exercise_ids: HashMap::new(),
+        markers: HashMap::new(),
+        exercises: HashMap::new(),
+        settings: HashMap::new(),
+    };
+
+    for reference in &refs.markers {
+        if inputs.marker_ids.contains_key(reference) {
+            continue;
+        }
+        let (marker_id, policy) = resolve_marker(pool, reference).await?;
+        // History before the window is needed to carry values forward
+        let series = fetch_readings(pool, marker_id, policy, None, end_date).await?;
+        inputs.marker_ids.insert(reference.clone(), marker_id);
+        inputs.markers.insert(marker_id, series);
+    }
+
+    for reference in &refs.exercises {
+        if inputs.exercise_ids.contains_key(reference) {
+            continue;
+        }
+        let exercise_id = resolve_exercise(pool, reference).await?;
+        let series = fetch_e1rm_series(pool, exercise_id, end_date).await?;
+        inputs.exercise_ids.insert(reference.clone(), exercise_id);
+        inputs.exercises.insert(exercise_id, series);
+    }
+
+    for name in &refs.settings {
+        if !inputs.settings.contains_key(name) {
+            let value = fetch_setting(pool, name).await?;
+            inputs.settings.insert(name.clone(), value);
+        }
+    }
+
+    Ok(inputs)
+}
+
+// Most recent value on or before the date
+fn latest(series: &Series, date: NaiveDate) -> Option<f64> {
+    let index = series.partition_point(|(d, _)| *d <= date);
+    index.checked_sub(1).map(|i| series[i].1)
+}
+
+fn window_average(series: &Series, date: NaiveDate, days: i64) -> Option<f64> {
+    let start = date.checked_sub_signed(Duration::days(days - 1))?;
+    let values: Vec<f64> = series
+        .iter()
+        .filter(|(d, _)| *d >= start && *d <= date)
+        .map(|(_, value)| *value)
+        .collect();
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.iter().sum::<f64>() / values.len() as f64)
+    }
+}
+
+fn evaluate(expr: &Expr, inputs: &Inputs, date: NaiveDate) -> Option<f64> {
+    match expr {
+        Expr::Number(value) => Some(*value),
+        Expr::Marker(r) => latest(&inputs.markers[&inputs.marker_ids[r]], date),
+        Expr::Average(r, days) => {
+            window_average(&inputs.markers[&inputs.marker_ids[r]], date, *days)
+        }
+        Expr::E1rm(r) => latest(&inputs.exercises[&inputs.exercise_ids[r]], date),
+        Expr::Setting(name) => inputs.settings.get(name).copied(),
+        Expr::Neg(inner) => evaluate(inner, inputs, date).map(|value| -value),
+        Expr::Binary(left, op, right) => {
+            let left = evaluate(left, inputs, date)?;
+            let right = evaluate(right, inputs, date)?;
+            let value = match op {
+                Op::Add => left + right,
+                Op::Sub => left - right,
+                Op::Mul => left * right,
+                Op::Div => left / right,
+            };
+            Some(value).filter(|value| value.is_finite())
+        }
+    }
+}
+
+// Checks that a formula parses and that everything it references exists.
+pub async fn validate(conn: &mut PgConnection, formula: &str) -> Result<(), FormulaError> {
+    let expr = parse(formula)?;
+    let mut refs = References::default();
+    collect(&expr, &mut refs);
+
+    for reference in &refs.markers {
+        resolve_marker(&mut *conn, reference).await?;
+    }
+    for reference in &refs.exercises {
+        resolve_exercise(&mut *conn, reference).await?;
+    }
+    for name in &refs.settings {
+        fetch_setting(&mut *conn, name).await?;
+    }
+    Ok(())
+}
+
+// Evaluates a formula on every day in the range where one of its inputs changed.
+pub async fn evaluate_series(
+    pool: &PgPool,
+    formula: &str,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Result<Vec<(NaiveDate, f64)>, FormulaError> {
+    let expr = parse(formula)?;
+    let mut refs = References::default();
+    collect(&expr, &mut refs);
+    let inputs = load_inputs(pool, &refs, end_date).await?;
+
+    let dates: BTreeSet<NaiveDate> = inputs
+        .markers
+        .values()
+        .chain(inputs.exercises.values())
+        .flatten()
+        .map(|(date, _)| *date)
+        .filter(|date| *date >= start_date && *date <= end_date)
+        .collect();
+
+    Ok(dates
+        .into_iter()
+        .filter_map(|date| evaluate(&expr, &inputs, date).map(|value| (date, value)))
+        .collect())
+}
@@ -0,0 +1,195 @@
+use actix_web::error::JsonPayloadError;
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, HttpResponse, ResponseError};
+use log::error;
+use serde::Serialize;
+use std::fmt;
+use utoipa::ToSchema;
+
+use crate::derived::FormulaError;
+
+// Error returned by handlers. Each variant has one status code and a stable
+// machine-readable code; database errors are logged but never shown to the
+// client.
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    NotFound(String),
+    Conflict(String),
+    Unprocessable(String),
+    Validation(Vec<FieldError>),
+    PayloadTooLarge(String),
+    RateLimited(u64), // Seconds until the next request is allowed
+    Database(sqlx::Error),
+    Internal(String), // Any other server-side failure, logged like Database
+}
+
+// One invalid field of a request body, e.g. "exercises[0].sets"
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub code: &'static str,
+    pub message: String,
+}
+
+// Body of every error response
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody<'a> {
+    error: String,
+    code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fields: Option<&'a [FieldError]>, // Only for validation_failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>, // Filled in by request_id::correlate
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, code: &'static str, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.into(),
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl ApiError {
+    pub fn not_found(message: impl Into<String>) -> Self {
+        ApiError::NotFound(message.into())
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        ApiError::BadRequest(message.into())
+    }
+
+    pub fn field(field: impl Into<String>, code: &'static str, message: impl Into<String>) -> Self {
+        ApiError::Validation(vec![FieldError::new(field, code, message)])
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Unprocessable(_) => "unprocessable",
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::PayloadTooLarge(_) => "payload_too_large",
+            ApiError::RateLimited(_) => "rate_limited",
+            ApiError::Database(_) | ApiError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ApiError::BadRequest(message)
+            | ApiError::NotFound(message)
+            | ApiError::Conflict(message)
+            | ApiError::Unprocessable(message)
+            | ApiError::PayloadTooLarge(message) => write!(f, "{}", message),
+            ApiError::Validation(_) => write!(f, "The request has invalid fields"),
+            ApiError::RateLimited(secs) => {
+                write!(f, "Too many requests, retry in {} seconds", secs)
+            }
+            ApiError::Database(_) | ApiError::Internal(_) => {
+                write!(f, "An internal error occurred")
+            }
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Unprocessable(_) | ApiError::Validation(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Database(_) | ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut body = ErrorBody {
+            error: self.to_string(),
+            code: self.code(),
+            fields: None,
+            request_id: None,
+        };
+        let mut response = HttpResponse::build(self.status_code());
+        match self {
+            ApiError::Validation(fields) => body.fields = Some(fields),
+            ApiError::RateLimited(secs) => {
+                response.insert_header((header::RETRY_AFTER, secs.to_string()));
+            }
+            ApiError::Database(e) => error!("Database error: {}", e),
+            ApiError::Internal(message) => error!("Internal error: {}", message),
+            _ => {}
+        }
+        response.json(body)
+    }
+}
+
+// Constraint violations are the client's fault and keep their status; anything
+// else from the database is an internal error
+impl From<sqlx::Error> for ApiError {
+    fn from(e: sqlx::Error) -> Self {
+        use sqlx::error::ErrorKind;
+
+        match &e {
+            sqlx::Error::RowNotFound => ApiError::not_found("Resource not found"),
+            sqlx::Error::Database(db) => match db.kind() {
+                ErrorKind::UniqueViolation => {
+                    ApiError::Conflict("A record with the same value already exists".to_string())
+                }
+                ErrorKind::ForeignKeyViolation => ApiError::Conflict(
+                    "The change conflicts with records that reference or are referenced by it"
+                        .to_string(),
+                ),
+                ErrorKind::NotNullViolation | ErrorKind::CheckViolation => {
+                    ApiError::Unprocessable("A value violates a data constraint".to_string())
+                }
+                _ => ApiError::Database(e),
+            },
+            _ => ApiError::Database(e),
+        }
+    }
+}
+
+impl From<FormulaError> for ApiError {
+    fn from(e: FormulaError) -> Self {
+        match e {
+            FormulaError::Database(e) => ApiError::Database(e),
+            e => ApiError::Unprocessable(e.to_string()),
+        }
+    }
+}
+
+// Rejected bodies, query strings and paths get the same error shape as
+// handler errors instead of actix's plain-text responses
+pub fn json_config(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(limit)
+        .error_handler(move |e, _| match e {
+            JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => {
+                ApiError::PayloadTooLarge(format!("JSON bodies are limited to {} bytes", limit))
+                    .into()
+            }
+            e => ApiError::bad_request(format!("Invalid JSON body: {}", e)).into(),
+        })
+}
+
+pub fn query_config() -> web::QueryConfig {
+    web::QueryConfig::default()
+        .error_handler(|e, _| ApiError::bad_request(format!("Invalid query string: {}", e)).into())
+}
+
+pub fn path_config() -> web::PathConfig {
+    web::PathConfig::default()
+        .error_handler(|e, _| ApiError::bad_request(format!("Invalid path: {}", e)).into())
+}
@@ -1,47 +1,60 @@
-use actix_web::{delete, get, post, web, HttpResponse, Responder};
-// Make sure NaiveDateTime is imported if you use it directly
-use chrono::{DateTime, NaiveDateTime, Utc};
-use log::error;
+use std::collections::HashMap;
+
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
-use sqlx::PgPool;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::errors::{ApiError, ErrorBody};
+use crate::repository::{ExerciseRecord, Removal, Repositories};
+use crate::validation::{validate, Validate, Validator};
 
 // Data structures for request/response handling
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 struct ExerciseInput {
     exercise_name: String,
     muscles_trained: Vec<String>,
     exercise_type: String,
 }
 
-#[derive(Serialize, Deserialize)]
-struct ExerciseStats {
-    date: DateTime<Utc>,
-    value: f64,
+impl Validate for ExerciseInput {
+    fn rules(&self, v: &mut Validator) {
+        v.required("exercise_name", &self.exercise_name, 255);
+        v.required("exercise_type", &self.exercise_type, 255);
+        for (i, muscle) in self.muscles_trained.iter().enumerate() {
+            v.required(&format!("muscles_trained[{}]", i), muscle, 255);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub(crate) struct ExerciseStats {
+    pub(crate) date: DateTime<Utc>,
+    pub(crate) value: f64,
 }
 
-#[derive(Serialize, Deserialize)]
-struct PersonalRecord {
-    workout_date: DateTime<Utc>,
-    weight: i16,
-    reps: i16,
-    one_rm: f32,
-    set_volume: i32,
+#[derive(Serialize, Deserialize, ToSchema)]
+pub(crate) struct PersonalRecord {
+    pub(crate) workout_date: DateTime<Utc>,
+    pub(crate) weight: i16,
+    pub(crate) reps: i16,
+    pub(crate) one_rm: f32,
+    pub(crate) set_volume: i32,
 }
 
-#[derive(sqlx::FromRow, Serialize)]
+#[derive(Serialize)]
 struct ExerciseSearchResult {
     exerciseid: i32,
     exercisename: String,
     muscles_trained: Vec<String>,
 }
 
-#[derive(sqlx::FromRow, Serialize)]
+#[derive(Serialize)]
 struct ExerciseIdResult {
     exerciseid: i32,
 }
 
-#[derive(sqlx::FromRow, Serialize)]
+#[derive(Serialize, ToSchema)]
 struct ExerciseDetails {
     exerciseid: i32,
     exercisename: String,
@@ -49,376 +62,562 @@ struct ExerciseDetails {
     exercisetype: String,
 }
 
-#[derive(sqlx::FromRow, Serialize)]
+impl From<ExerciseRecord> for ExerciseDetails {
+    fn from(e: ExerciseRecord) -> Self {
+        ExerciseDetails {
+            exerciseid: e.exercise_id,
+            exercisename: e.name,
+            muscles_trained: e.muscles_trained,
+            exercisetype: e.exercise_type,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
 struct DeletedExercise {
     exerciseid: i32,
 }
 
-// Search for exercises by partial name (Unchanged)
+// Search for exercises by partial name; superseded by GET /exercises?search=
 #[get("/exercises/search/{partial_name}")]
 async fn search_exercises_by_name(
-    pool: web::Data<PgPool>,
+    repos: web::Data<Repositories>,
     partial_name: web::Path<String>,
-) -> impl Responder {
-    let search_term = format!("%{}%", partial_name.as_ref());
-    let exercises = sqlx::query_as!(
-        ExerciseSearchResult,
-        r#"
-        SELECT
-            ExerciseID as exerciseid,
-            ExerciseName as exercisename,
-            MusclesTrained as muscles_trained
-        FROM ExerciseList
-        WHERE ExerciseName ILIKE $1
-        ORDER BY ExerciseName
-        LIMIT 20
-        "#,
-        search_term
-    )
-    .fetch_all(pool.get_ref())
-    .await;
-
-    match exercises {
-        Ok(results) => HttpResponse::Ok().json(results),
-        Err(e) => {
-            error!("Database error in search_exercises_by_name: {:?}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to search exercises",
-                "details": e.to_string()
-            }))
-        }
-    }
+) -> Result<HttpResponse, ApiError> {
+    let exercises: Vec<ExerciseSearchResult> = repos
+        .exercises
+        .search(partial_name.as_ref(), Some(20))
+        .await?
+        .into_iter()
+        .map(|e| ExerciseSearchResult {
+            exerciseid: e.exercise_id,
+            exercisename: e.name,
+            muscles_trained: e.muscles_trained,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(exercises))
 }
 
-// Get exercise ID by exact name (Unchanged)
+// Get exercise ID by exact name; superseded by GET /exercises?name=
 #[get("/exercises/id/{exercise_name}")]
 async fn get_exercise_id_by_name(
-    pool: web::Data<PgPool>,
+    repos: web::Data<Repositories>,
     exercise_name: web::Path<String>,
-) -> impl Responder {
+) -> Result<HttpResponse, ApiError> {
     let name = exercise_name.into_inner();
-    let exercise_id_result = sqlx::query_as!(
-        ExerciseIdResult,
-        r#"
-        SELECT ExerciseID as exerciseid
-        FROM ExerciseList
-        WHERE ExerciseName ILIKE $1
-        "#,
-        name
-    )
-    .fetch_optional(pool.get_ref())
-    .await;
-
-    match exercise_id_result {
-        Ok(Some(result)) => HttpResponse::Ok().json(result),
-        Ok(None) => HttpResponse::NotFound().json(json!({
-            "error": "Exercise not found",
-            "details": format!("No exercise found with the exact name: {}", name)
-        })),
-        Err(e) => {
-            error!("Database error in get_exercise_id_by_name: {:?}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to fetch exercise ID by name",
-                "details": e.to_string()
-            }))
+    let exercise = repos.exercises.find_by_name(&name).await?.ok_or_else(|| {
+        ApiError::not_found(format!("No exercise found with the exact name: {}", name))
+    })?;
+
+    Ok(HttpResponse::Ok().json(ExerciseIdResult {
+        exerciseid: exercise.exercise_id,
+    }))
+}
+
+#[utoipa::path(
+    tag = "exercises",
+    params(
+        ("search" = Option<String>, Query, description = "Only exercises whose name contains this text"),
+        ("name" = Option<String>, Query, description = "Only the exercise with exactly this name (case-insensitive)")
+    ),
+    responses((status = 200, body = Vec<ExerciseDetails>))
+)]
+#[get("/exercises")]
+async fn list_exercises(
+    repos: web::Data<Repositories>,
+    request: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let exercises = match request.get("name") {
+        Some(name) => repos
+            .exercises
+            .find_by_name(name)
+            .await?
+            .into_iter()
+            .collect(),
+        None => {
+            let partial_name = request.get("search").map_or("", String::as_str);
+            repos.exercises.search(partial_name, None).await?
         }
-    }
+    };
+
+    let exercises: Vec<ExerciseDetails> = exercises.into_iter().map(Into::into).collect();
+    Ok(HttpResponse::Ok().json(exercises))
+}
+
+#[utoipa::path(
+    tag = "exercises",
+    responses(
+        (status = 200, body = ExerciseDetails),
+        (status = 404, description = "Exercise not found", body = ErrorBody)
+    )
+)]
+#[get("/exercises/{exercise_id}")]
+async fn get_exercise(
+    repos: web::Data<Repositories>,
+    exercise_id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let id = exercise_id.into_inner();
+    let exercise = repos
+        .exercises
+        .get(id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(format!("Exercise with ID {} not found", id)))?;
+
+    Ok(HttpResponse::Ok().json(ExerciseDetails::from(exercise)))
 }
 
-// Create a new exercise (Unchanged)
+// Create a new exercise
+#[utoipa::path(
+    tag = "exercises",
+    responses(
+        (status = 201, body = ExerciseDetails),
+        (status = 409, description = "Name already taken", body = ErrorBody),
+        (status = 422, description = "Invalid exercise", body = ErrorBody)
+    )
+)]
 #[post("/exercises")]
 async fn create_exercise(
-    pool: web::Data<PgPool>,
+    repos: web::Data<Repositories>,
     exercise_input: web::Json<ExerciseInput>,
-) -> impl Responder {
-    match sqlx::query!(
-        "SELECT ExerciseID FROM ExerciseList WHERE ExerciseName ILIKE $1",
-        exercise_input.exercise_name
+) -> Result<HttpResponse, ApiError> {
+    validate(&*exercise_input).finish()?;
+
+    let existing = repos
+        .exercises
+        .find_by_name(&exercise_input.exercise_name)
+        .await?;
+
+    if existing.is_some() {
+        return Err(ApiError::Conflict(format!(
+            "An exercise with the name '{}' already exists (case-insensitive).",
+            exercise_input.exercise_name
+        )));
+    }
+
+    let created = repos
+        .exercises
+        .create(
+            &exercise_input.exercise_name,
+            &exercise_input.muscles_trained,
+            &exercise_input.exercise_type,
+        )
+        .await?;
+
+    Ok(HttpResponse::Created().json(ExerciseDetails::from(created)))
+}
+
+#[utoipa::path(
+    tag = "exercises",
+    responses(
+        (status = 200, body = ExerciseDetails),
+        (status = 404, description = "Exercise not found", body = ErrorBody),
+        (status = 409, description = "Name taken by another exercise", body = ErrorBody),
+        (status = 422, description = "Invalid exercise", body = ErrorBody)
     )
-    .fetch_optional(pool.get_ref())
-    .await
-    {
-        Ok(Some(_)) => {
-            HttpResponse::Conflict().json(json!({
-                "error": "Exercise already exists",
-                "details": format!("An exercise with the name '{}' already exists (case-insensitive).", exercise_input.exercise_name)
-            }))
-        }
-        Ok(None) => {
-            let result = sqlx::query_as!(
-                ExerciseDetails,
-                r#"
-                INSERT INTO ExerciseList (ExerciseName, MusclesTrained, ExerciseType)
-                VALUES ($1, $2, $3)
-                RETURNING
-                    ExerciseID as exerciseid,
-                    ExerciseName as exercisename,
-                    MusclesTrained as muscles_trained,
-                    ExerciseType as exercisetype
-                "#,
-                exercise_input.exercise_name,
-                &exercise_input.muscles_trained,
-                exercise_input.exercise_type
-            )
-            .fetch_one(pool.get_ref())
-            .await;
+)]
+#[put("/exercises/{exercise_id}")]
+async fn update_exercise(
+    repos: web::Data<Repositories>,
+    exercise_id: web::Path<i32>,
+    exercise_input: web::Json<ExerciseInput>,
+) -> Result<HttpResponse, ApiError> {
+    let id = exercise_id.into_inner();
+    validate(&*exercise_input).finish()?;
 
-            match result {
-                Ok(created_exercise) => HttpResponse::Created().json(created_exercise),
-                Err(e) => {
-                    error!("Database error in create_exercise during INSERT: {:?}", e);
-                    HttpResponse::InternalServerError().json(json!({
-                        "error": "Failed to create exercise",
-                        "details": e.to_string()
-                    }))
-                }
-            }
-        }
-        Err(e) => {
-            error!("Database error in create_exercise checking existence: {:?}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": "Database error while checking for existing exercise",
-                "details": e.to_string()
-            }))
-        }
+    let existing = repos
+        .exercises
+        .find_by_name(&exercise_input.exercise_name)
+        .await?;
+    if existing.is_some_and(|e| e.exercise_id != id) {
+        return Err(ApiError::Conflict(format!(
+            "An exercise with the name '{}' already exists (case-insensitive).",
+            exercise_input.exercise_name
+        )));
     }
+
+    let updated = repos
+        .exercises
+        .update(
+            id,
+            &exercise_input.exercise_name,
+            &exercise_input.muscles_trained,
+            &exercise_input.exercise_type,
+        )
+        .await?
+        .ok_or_else(|| ApiError::not_found(format!("Exercise with ID {} not found", id)))?;
+
+    Ok(HttpResponse::Ok().json(ExerciseDetails::from(updated)))
 }
 
 // Delete an exercise by ID
+#[utoipa::path(
+    tag = "exercises",
+    responses(
+        (status = 200, body = DeletedExercise),
+        (status = 404, description = "Exercise not found", body = ErrorBody),
+        (status = 409, description = "Still used by routines or workouts", body = ErrorBody)
+    )
+)]
 #[delete("/exercises/{exercise_id}")]
 async fn delete_exercise(
-    pool: web::Data<PgPool>,
-    exercise_id: web::Path<i32>, // Keep i32 if ExerciseList.ExerciseID is INTEGER
-) -> impl Responder {
-    // Note: Assuming ExerciseList.ExerciseID is i32. If it's SMALLINT, change Path to i16.
+    repos: web::Data<Repositories>,
+    exercise_id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
     let id = exercise_id.into_inner();
-    let result = sqlx::query_as!(
-        DeletedExercise,
-        r#"
-        DELETE FROM ExerciseList
-        WHERE ExerciseID = $1
-        RETURNING ExerciseID as exerciseid
-        "#,
-        id // Pass i32 directly
-    )
-    .fetch_optional(pool.get_ref())
-    .await;
-
-    match result {
-        Ok(Some(deleted_exercise)) => HttpResponse::Ok().json(deleted_exercise),
-        Ok(None) => HttpResponse::NotFound().json(json!({
-            "error": "Exercise not found",
-            "details": format!("Exercise with ID {} could not be deleted because it was not found.", id)
-        })),
-        Err(e) => {
-            error!("Database error in delete_exercise: {:?}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to delete exercise",
-                "details": e.to_string()
-            }))
-        }
+    match repos.exercises.delete(id).await? {
+        Removal::Deleted => Ok(HttpResponse::Ok().json(DeletedExercise { exerciseid: id })),
+        Removal::NotFound => Err(ApiError::not_found(format!(
+            "Exercise with ID {} not found",
+            id
+        ))),
+        Removal::InUse => Err(ApiError::Conflict(format!(
+            "Exercise with ID {} is still used by routines or workouts",
+            id
+        ))),
     }
 }
 
-// Get set volume history for an exercise by ID
-#[get("/exercises/volume/{exercise_id}")]
+#[utoipa::path(
+    tag = "exercises",
+    summary = "Total volume per workout, oldest first",
+    responses((status = 200, body = Vec<ExerciseStats>))
+)]
+#[get("/exercises/{exercise_id}/stats/volume")]
 async fn get_exercise_volume(
-    pool: web::Data<PgPool>,
-    exercise_id: web::Path<i16>, // Keep i16 based on previous error
-) -> impl Responder {
-    // Define the expected return types - expecting i64 due to SQL CAST
-    struct VolumeRow {
-        workout_date_naive: Option<NaiveDateTime>,
-        total_volume: Option<i64>, // Expecting BIGINT from SQL CAST
-    }
-
-    let volumes_raw = sqlx::query_as!(
-        VolumeRow,
-        r#"
-        SELECT w.Start as workout_date_naive,
-               -- Cast the final SUM result to BIGINT (i64) in SQL
-               CAST( SUM( s.Weight * s.Reps ) AS BIGINT ) as total_volume
-        FROM Workout w
-        JOIN Workout_Exercises_Sets wes ON w.WorkoutID = wes.WorkoutID
-        JOIN "Set" s ON wes.SetID = s.SetID
-        WHERE wes.ExerciseID = $1 -- Expects i16 here
-        GROUP BY w.Start
-        ORDER BY w.Start
-        "#,
-        exercise_id.into_inner() // Provides i16
-    )
-    .fetch_all(pool.get_ref())
-    .await;
-
-    match volumes_raw {
-        Ok(results) => {
-            let stats: Vec<ExerciseStats> = results
-                .into_iter()
-                .filter_map(|row| {
-                    // Match on the Option fields
-                    match (row.workout_date_naive, row.total_volume) {
-                        (Some(date_naive), Some(volume_numeric)) => {
-                            // volume_numeric is i64
-                            let date_utc = DateTime::from_naive_utc_and_offset(date_naive, Utc);
-                            // Convert the i64 volume to f64
-                            let value = volume_numeric as f64;
-                            Some(ExerciseStats {
-                                date: date_utc,
-                                value,
-                            })
-                        }
-                        _ => None, // Skip if date or volume is None
-                    }
-                })
-                .collect();
-            HttpResponse::Ok().json(stats)
-        }
-        Err(e) => {
-            error!("Database error in get_exercise_volume: {:?}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to fetch volume data",
-                "details": e.to_string()
-            }))
-        }
-    }
+    repos: web::Data<Repositories>,
+    exercise_id: web::Path<i16>,
+) -> Result<HttpResponse, ApiError> {
+    let stats = repos
+        .exercises
+        .volume_history(exercise_id.into_inner().into())
+        .await?;
+    Ok(HttpResponse::Ok().json(stats))
 }
 
-// Get max weight history for an exercise by ID
-#[get("/exercises/max-weight/{exercise_id}")]
+#[utoipa::path(
+    tag = "exercises",
+    summary = "Heaviest weight per workout, oldest first",
+    responses((status = 200, body = Vec<ExerciseStats>))
+)]
+#[get("/exercises/{exercise_id}/stats/max-weight")]
 async fn get_exercise_max_weight(
-    pool: web::Data<PgPool>,
-    exercise_id: web::Path<i16>, // <<<<<<<< CHANGED to i16 based on error E0308
-) -> impl Responder {
-    // Define expected return types
-    struct MaxWeightRow {
-        workout_date_naive: Option<NaiveDateTime>,
-        max_weight_val: Option<i16>, // Assuming MAX(s.Weight) returns SMALLINT
-    }
-
-    let max_weights_raw = sqlx::query_as!(
-        MaxWeightRow,
-        r#"
-        SELECT w.Start as workout_date_naive, MAX(s.Weight) as max_weight_val
-        FROM Workout w
-        JOIN Workout_Exercises_Sets wes ON w.WorkoutID = wes.WorkoutID
-        JOIN "Set" s ON wes.SetID = s.SetID
-        WHERE wes.ExerciseID = $1 AND s.Weight IS NOT NULL -- Expects i16 here based on error E0308
-        GROUP BY w.Start
-        ORDER BY w.Start
-        "#,
-        exercise_id.into_inner() // Provides i16
-    )
-    .fetch_all(pool.get_ref())
-    .await;
-
-    match max_weights_raw {
-        Ok(results) => {
-            let stats: Vec<ExerciseStats> = results
-                .into_iter()
-                .filter_map(|row| {
-                    // Match on the Option fields
-                    match (row.workout_date_naive, row.max_weight_val) {
-                        (Some(date_naive), Some(max_w)) => {
-                            let date_utc = DateTime::from_naive_utc_and_offset(date_naive, Utc);
-                            Some(ExerciseStats {
-                                date: date_utc,
-                                value: max_w as f64,
-                            })
-                        }
-                        _ => None, // Skip if date or max_weight is None
-                    }
-                })
-                .collect();
-            HttpResponse::Ok().json(stats)
-        }
-        Err(e) => {
-            error!("Database error in get_exercise_max_weight: {:?}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to fetch max weight data",
-                "details": e.to_string()
-            }))
-        }
-    }
+    repos: web::Data<Repositories>,
+    exercise_id: web::Path<i16>,
+) -> Result<HttpResponse, ApiError> {
+    let stats = repos
+        .exercises
+        .max_weight_history(exercise_id.into_inner().into())
+        .await?;
+    Ok(HttpResponse::Ok().json(stats))
 }
 
-// Get PRs for an exercise by ID
-#[get("/exercises/prs/{exercise_id}")]
+#[utoipa::path(
+    tag = "exercises",
+    summary = "Personal records, best first",
+    responses((status = 200, body = Vec<PersonalRecord>))
+)]
+#[get("/exercises/{exercise_id}/prs")]
 async fn get_exercise_prs(
-    pool: web::Data<PgPool>,
-    exercise_id: web::Path<i16>, // <<<<<<<< CHANGED to i16 based on error E0308
-) -> impl Responder {
-    // Define expected return types, assuming columns in PRs can be NULL
-    struct PrRow {
-        workout_date_naive: Option<NaiveDateTime>,
-        heaviest_weight: Option<i16>,
-        one_rm: Option<f32>,
-        set_volume: Option<i32>,
-    }
+    repos: web::Data<Repositories>,
+    exercise_id: web::Path<i16>,
+) -> Result<HttpResponse, ApiError> {
+    let records = repos.prs.history(exercise_id.into_inner().into()).await?;
+    Ok(HttpResponse::Ok().json(records))
+}
 
-    let prs_raw = sqlx::query_as!(
-        PrRow,
-        r#"
-        SELECT
-            w.Start as workout_date_naive,
-            p.HeaviestWeight as heaviest_weight,
-            p.OneRM as one_rm,
-            p.SetVolume as set_volume
-        FROM PRs p
-        JOIN Workout w ON p.WorkoutID = w.WorkoutID
-        WHERE p.ExerciseID = $1 -- Expects i16 here based on error E0308
-        ORDER BY p.OneRM DESC, p.HeaviestWeight DESC, p.SetVolume DESC
-        "#,
-        exercise_id.into_inner() // Provides i16
-    )
-    .fetch_all(pool.get_ref())
-    .await;
-
-    match prs_raw {
-        Ok(results) => {
-            let pr_records: Vec<PersonalRecord> = results
-                .into_iter()
-                .filter_map(|row| {
-                    // Match on all required Option fields from the row
-                    match (
-                        row.workout_date_naive,
-                        row.heaviest_weight,
-                        row.one_rm,
-                        row.set_volume,
-                    ) {
-                        (Some(date_naive), Some(weight), Some(one_rm), Some(set_volume)) => {
-                            let date_utc = DateTime::from_naive_utc_and_offset(date_naive, Utc);
-                            Some(PersonalRecord {
-                                workout_date: date_utc,
-                                weight,     // Use the unwrapped value
-                                one_rm,     // Use the unwrapped value
-                                set_volume, // Use the unwrapped value
-                                reps: 0,
-                            })
-                        }
-                        // If any of the required fields are None, skip this record
-                        _ => None,
-                    }
-                })
-                .collect();
-            HttpResponse::Ok().json(pr_records)
-        }
-        Err(e) => {
-            error!("Database error in get_exercise_prs: {:?}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to fetch PRs",
-                "details": e.to_string()
-            }))
-        }
-    }
+#[get("/exercises/volume/{exercise_id}")]
+async fn legacy_exercise_volume(
+    repos: web::Data<Repositories>,
+    exercise_id: web::Path<i16>,
+) -> Result<HttpResponse, ApiError> {
+    let stats = repos
+        .exercises
+        .volume_history(exercise_id.into_inner().into())
+        .await?;
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+#[get("/exercises/max-weight/{exercise_id}")]
+async fn legacy_exercise_max_weight(
+    repos: web::Data<Repositories>,
+    exercise_id: web::Path<i16>,
+) -> Result<HttpResponse, ApiError> {
+    let stats = repos
+        .exercises
+        .max_weight_history(exercise_id.into_inner().into())
+        .await?;
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+#[get("/exercises/prs/{exercise_id}")]
+async fn legacy_exercise_prs(
+    repos: web::Data<Repositories>,
+    exercise_id: web::Path<i16>,
+) -> Result<HttpResponse, ApiError> {
+    let records = repos.prs.history(exercise_id.into_inner().into()).await?;
+    Ok(HttpResponse::Ok().json(records))
 }
 
-// Initialize all routes (Unchanged)
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        list_exercises,
+        get_exercise,
+        create_exercise,
+        update_exercise,
+        delete_exercise,
+        get_exercise_volume,
+        get_exercise_max_weight,
+        get_exercise_prs
+    ),
+    tags((name = "exercises", description = "The exercise catalog and per-exercise history"))
+)]
+pub(crate) struct ApiDoc;
+
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
-    cfg.service(search_exercises_by_name)
-        .service(get_exercise_id_by_name)
+    cfg.service(list_exercises)
+        .service(get_exercise)
         .service(create_exercise)
+        .service(update_exercise)
         .service(delete_exercise)
         .service(get_exercise_volume)
         .service(get_exercise_max_weight)
         .service(get_exercise_prs);
 }
+
+// The unversioned routes, kept as deprecated aliases
+pub fn init_legacy_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(search_exercises_by_name)
+        .service(get_exercise_id_by_name)
+        .service(create_exercise)
+        .service(delete_exercise)
+        .service(legacy_exercise_volume)
+        .service(legacy_exercise_max_weight)
+        .service(legacy_exercise_prs);
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::test_support::TestApp;
+
+    #[actix_web::test]
+    async fn create_search_and_find_by_name() {
+        let app = TestApp::new().await;
+
+        let created = app
+            .post(
+                "/exercises",
+                json!({
+                    "exercise_name": "Bench Press",
+                    "muscles_trained": ["Chest", "Triceps"],
+                    "exercise_type": "Regular",
+                }),
+            )
+            .await
+            .expect(201)
+            .json();
+        assert_eq!(created["exercisename"], "Bench Press");
+        assert_eq!(created["muscles_trained"], json!(["Chest", "Triceps"]));
+        app.exercise("Incline Bench Press").create().await;
+        app.exercise("Squat").create().await;
+
+        let found = app.get("/exercises/search/bench").await.expect(200).json();
+        let names: Vec<&str> = found
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["exercisename"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["Bench Press", "Incline Bench Press"]);
+
+        let id = app
+            .get("/exercises/id/bench%20press")
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(id["exerciseid"], created["exerciseid"]);
+        app.get("/exercises/id/Deadlift").await.expect(404);
+    }
+
+    #[actix_web::test]
+    async fn create_rejects_duplicates_and_blank_fields() {
+        let app = TestApp::new().await;
+        app.exercise("Squat").create().await;
+
+        let body = json!({
+            "exercise_name": "SQUAT",
+            "muscles_trained": ["Quads"],
+            "exercise_type": "Regular",
+        });
+        app.post("/exercises", body).await.expect(409);
+
+        let body = app
+            .post(
+                "/exercises",
+                json!({ "exercise_name": "", "muscles_trained": [""], "exercise_type": "" }),
+            )
+            .await
+            .expect(422)
+            .json();
+        let fields: Vec<&str> = body["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["field"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            fields,
+            vec!["exercise_name", "exercise_type", "muscles_trained[0]"]
+        );
+    }
+
+    #[actix_web::test]
+    async fn delete_refuses_exercises_in_use() {
+        let app = TestApp::new().await;
+        let squat = app.exercise("Squat").create().await;
+        let curl = app.exercise("Curl").create().await;
+        app.workout().set(squat, 100, 5).create().await;
+
+        app.delete(&format!("/exercises/{}", squat))
+            .await
+            .expect(409);
+        let deleted = app
+            .delete(&format!("/exercises/{}", curl))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(deleted, json!({ "exerciseid": curl }));
+        app.delete(&format!("/exercises/{}", curl))
+            .await
+            .expect(404);
+    }
+
+    #[actix_web::test]
+    async fn volume_max_weight_and_prs_history() {
+        let app = TestApp::new().await;
+        let squat = app.exercise("Squat").create().await;
+        app.workout()
+            .at("2026-01-05T18:00:00")
+            .set(squat, 100, 5)
+            .set(squat, 80, 10)
+            .create()
+            .await;
+        app.workout()
+            .at("2026-01-12T18:00:00")
+            .set(squat, 110, 3)
+            .create()
+            .await;
+
+        let volume = app
+            .get(&format!("/exercises/volume/{}", squat))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(volume[0]["value"], 1300.0);
+        assert_eq!(volume[1]["value"], 330.0);
+        assert_eq!(volume[0]["date"], "2026-01-05T18:00:00Z");
+
+        let max = app
+            .get(&format!("/exercises/max-weight/{}", squat))
+            .await
+            .expect(200)
+            .json();
+        let weights: Vec<f64> = max
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["value"].as_f64().unwrap())
+            .collect();
+        assert_eq!(weights, vec![100.0, 110.0]);
+
+        let prs = app
+            .get(&format!("/exercises/prs/{}", squat))
+            .await
+            .expect(200)
+            .json();
+        let prs = prs.as_array().unwrap();
+        assert_eq!(prs.len(), 2);
+        assert_eq!(prs[0]["weight"], 110);
+        assert_eq!(prs[1]["set_volume"], 1300);
+
+        app.get("/exercises/volume/abc").await.expect(400);
+
+        let volume_v1 = app
+            .get(&format!("/api/v1/exercises/{}/stats/volume", squat))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(volume_v1, volume);
+        let prs_v1 = app
+            .get(&format!("/api/v1/exercises/{}/prs", squat))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(prs_v1.as_array().unwrap().len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn list_get_and_update_under_v1() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
+        app.exercise("Incline Bench Press").create().await;
+        let squat = app.exercise("Squat").create().await;
+
+        let all = app.get("/api/v1/exercises").await.expect(200).json();
+        assert_eq!(all.as_array().unwrap().len(), 3);
+        let found = app
+            .get("/api/v1/exercises?search=bench")
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(found.as_array().unwrap().len(), 2);
+        let exact = app
+            .get("/api/v1/exercises?name=bench%20press")
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(exact[0]["exerciseid"], bench);
+        assert_eq!(exact.as_array().unwrap().len(), 1);
+
+        let body = json!({
+            "exercise_name": "Back Squat",
+            "muscles_trained": ["Quads", "Glutes"],
+            "exercise_type": "Regular",
+        });
+        let updated = app
+            .put(&format!("/api/v1/exercises/{}", squat), body.clone())
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(updated["exercisename"], "Back Squat");
+        let fetched = app
+            .get(&format!("/api/v1/exercises/{}", squat))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(fetched, updated);
+
+        // Keeping its own name is fine, taking another exercise's is not
+        app.put(&format!("/api/v1/exercises/{}", squat), body.clone())
+            .await
+            .expect(200);
+        let taken = json!({
+            "exercise_name": "BENCH PRESS",
+            "muscles_trained": [],
+            "exercise_type": "Regular",
+        });
+        app.put(&format!("/api/v1/exercises/{}", squat), taken)
+            .await
+            .expect(409);
+        let missing = json!({
+            "exercise_name": "Deadlift",
+            "muscles_trained": [],
+            "exercise_type": "Regular",
+        });
+        app.put("/api/v1/exercises/9999", missing).await.expect(404);
+        app.get("/api/v1/exercises/9999").await.expect(404);
+    }
+}
@@ -0,0 +1,1227 @@
+use actix_web::{get, post, web, HttpResponse};
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{PgPool, Postgres, Row, Transaction};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use tracing::instrument;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::derived::{self, FormulaError};
+use crate::errors::{ApiError, ErrorBody};
+use crate::markers::{Direction, MarkerSettings, ReadingPolicy};
+use crate::metrics::{Metrics, WorkoutSource};
+use crate::repository::postgres::{insert_routine, rebuild_prs};
+use crate::routines::{definition_rules, RoutineExercise};
+use crate::validation::{validate, Validate, Validator};
+use crate::workouts::Set;
+
+// Identifies export documents; bump the version when the layout changes
+const EXPORT_FORMAT: &str = "strongeryou-export";
+const EXPORT_VERSION: u32 = 1;
+
+// IDs in an export are only references within the document. Importing maps
+// them onto the target server's own IDs.
+#[derive(Serialize, Deserialize, ToSchema)]
+struct ExportDocument {
+    format: String,
+    version: u32,
+    exported_at: NaiveDateTime,
+    exercises: Vec<ExportedExercise>,
+    routines: Vec<ExportedRoutine>,
+    workouts: Vec<ExportedWorkout>,
+    #[serde(default)]
+    prs: Vec<ExportedPr>, // Informational, PRs are rebuilt from workouts on import
+    markers: Vec<ExportedMarker>,
+    #[serde(default)]
+    settings: BTreeMap<String, f64>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct ExportedExercise {
+    exercise_id: i32,
+    name: String,
+    muscles_trained: Vec<String>,
+    exercise_type: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct ExportedRoutine {
+    routine_id: i32,
+    name: String,
+    created_at: NaiveDateTime,
+    exercises: Vec<ExportedRoutineExercise>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct ExportedRoutineExercise {
+    exercise_id: i32,
+    sets: i16,
+    #[serde(default)]
+    target_reps: Option<i16>,
+    #[serde(default)]
+    target_weight: Option<i16>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct ExportedWorkout {
+    workout_id: i32,
+    routine_id: Option<i32>,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    sets: Vec<ExportedSet>, // In the order they were logged
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct ExportedSet {
+    exercise_id: i32,
+    weight: i16,
+    reps: i16,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct ExportedPr {
+    exercise_id: i32,
+    workout_id: i32,
+    heaviest_weight: i16,
+    one_rm: f32,
+    set_volume: i32,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct ExportedMarker {
+    marker_id: i32,
+    name: String,
+    color: Option<String>,
+    unit: Option<String>,
+    precision: i16,
+    direction: String,
+    goal_value: Option<f32>,
+    goal_date: Option<NaiveDate>,
+    reading_policy: String,
+    formula: Option<String>,
+    logs: Vec<ExportedMarkerLog>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct ExportedMarkerLog {
+    date: NaiveDate,
+    value: f32,
+    logged_at: Option<NaiveDateTime>,
+    note: Option<String>,
+}
+
+// The rules the endpoints apply to the same entities, checked before anything
+// is written so that a hand-edited document gets a 400 rather than failing on
+// the columns' limits
+impl Validate for ExportDocument {
+    fn rules(&self, v: &mut Validator) {
+        v.each("exercises", &self.exercises);
+        v.each("routines", &self.routines);
+        v.each("workouts", &self.workouts);
+        v.each("markers", &self.markers);
+    }
+}
+
+impl Validate for ExportedExercise {
+    fn rules(&self, v: &mut Validator) {
+        v.required("name", &self.name, 255);
+        v.required("exercise_type", &self.exercise_type, 255);
+        for (i, muscle) in self.muscles_trained.iter().enumerate() {
+            v.required(&format!("muscles_trained[{}]", i), muscle, 255);
+        }
+    }
+}
+
+impl Validate for ExportedRoutine {
+    fn rules(&self, v: &mut Validator) {
+        let exercises: Vec<RoutineExercise> = self
+            .exercises
+            .iter()
+            .map(|exercise| RoutineExercise {
+                exercise_id: exercise.exercise_id,
+                sets: i32::from(exercise.sets),
+                target_reps: exercise.target_reps,
+                target_weight: exercise.target_weight,
+            })
+            .collect();
+        definition_rules(v, &self.name, &exercises);
+    }
+}
+
+impl Validate for ExportedWorkout {
+    fn rules(&self, v: &mut Validator) {
+        v.each("sets", &self.sets);
+    }
+}
+
+impl Validate for ExportedSet {
+    fn rules(&self, v: &mut Validator) {
+        Set {
+            weight: self.weight,
+            reps: self.reps,
+        }
+        .rules(v);
+    }
+}
+
+impl Validate for ExportedMarker {
+    fn rules(&self, v: &mut Validator) {
+        v.required("name", &self.name, 255);
+        if let Some(color) = &self.color {
+            v.hex_color("color", color);
+        }
+        let direction = self.direction.parse().unwrap_or_else(|message: String| {
+            v.error("direction", "invalid", message);
+            Direction::default()
+        });
+        let reading_policy = self
+            .reading_policy
+            .parse()
+            .unwrap_or_else(|message: String| {
+                v.error("reading_policy", "invalid", message);
+                ReadingPolicy::default()
+            });
+        // Whether the formula's references resolve is only known once the
+        // document's markers are in place, see import_document
+        MarkerSettings {
+            unit: self.unit.clone(),
+            precision: self.precision,
+            direction,
+            goal_value: self.goal_value.map(f64::from),
+            goal_date: self.goal_date,
+            reading_policy,
+            formula: self.formula.clone(),
+        }
+        .rules(v);
+    }
+}
+
+#[derive(Serialize, Default, ToSchema)]
+struct ImportCounts {
+    created: usize,
+    existing: usize,
+}
+
+#[derive(Serialize, Default, ToSchema)]
+pub(crate) struct ImportSummary {
+    exercises: ImportCounts,
+    routines: ImportCounts,
+    workouts: ImportCounts,
+    markers: ImportCounts,
+    marker_logs: ImportCounts,
+    settings: ImportCounts,
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(export_account, import_account),
+    tags((name = "export", description = "Whole-account backup and restore"))
+)]
+pub(crate) struct ApiDoc;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(export_account).service(import_account);
+}
+
+#[instrument(skip_all)]
+async fn build_document(pool: &PgPool) -> Result<ExportDocument, sqlx::Error> {
+    let exercises = sqlx::query(
+        "SELECT ExerciseID, ExerciseName, MusclesTrained, ExerciseType
+         FROM ExerciseList ORDER BY ExerciseID",
+    )
+    .fetch_all(pool)
+    .await?
+    .iter()
+    .map(|row| ExportedExercise {
+        exercise_id: row.get("exerciseid"),
+        name: row.get("exercisename"),
+        muscles_trained: row.get("musclestrained"),
+        exercise_type: row.get("exercisetype"),
+    })
+    .collect();
+
+    let mut routine_exercises: HashMap<i32, Vec<ExportedRoutineExercise>> = HashMap::new();
+    for row in sqlx::query(
+        "SELECT RoutineID::INTEGER, ExerciseID::INTEGER, NumberOfSets, TargetReps, TargetWeight
+         FROM Routines_Exercises_Sets
+         ORDER BY RoutineID, Position NULLS LAST, ExerciseID",
+    )
+    .fetch_all(pool)
+    .await?
+    {
+        routine_exercises
+            .entry(row.get("routineid"))
+            .or_default()
+            .push(ExportedRoutineExercise {
+                exercise_id: row.get("exerciseid"),
+                sets: row.get("numberofsets"),
+                target_reps: row.get("targetreps"),
+                target_weight: row.get("targetweight"),
+            });
+    }
+
+    let routines =
+        sqlx::query("SELECT RoutineID, RoutineName, Timestamp FROM Routines ORDER BY RoutineID")
+            .fetch_all(pool)
+            .await?
+            .iter()
+            .map(|row| {
+                let routine_id: i32 = row.get("routineid");
+                ExportedRoutine {
+                    routine_id,
+                    name: row.get("routinename"),
+                    created_at: row.get("timestamp"),
+                    exercises: routine_exercises.remove(&routine_id).unwrap_or_default(),
+                }
+            })
+            .collect();
+
+    let mut workout_sets: HashMap<i32, Vec<ExportedSet>> = HashMap::new();
+    for row in sqlx::query(
+        r#"SELECT wes.workoutid::INTEGER, wes.exerciseid::INTEGER, s.weight, s.reps
+           FROM Workout_Exercises_Sets wes
+           JOIN "Set" s ON wes.setid = s.setid
+           ORDER BY wes.workoutid, s.setid"#,
+    )
+    .fetch_all(pool)
+    .await?
+    {
+        workout_sets
+            .entry(row.get("workoutid"))
+            .or_default()
+            .push(ExportedSet {
+                exercise_id: row.get("exerciseid"),
+                weight: row.get("weight"),
+                reps: row.get("reps"),
+            });
+    }
+
+    let workouts = sqlx::query(
+        r#"SELECT WorkoutID, RoutineID, Start, "end" FROM Workout ORDER BY Start, WorkoutID"#,
+    )
+    .fetch_all(pool)
+    .await?
+    .iter()
+    .map(|row| {
+        let workout_id: i32 = row.get("workoutid");
+        ExportedWorkout {
+            workout_id,
+            routine_id: row.get("routineid"),
+            start: row.get("start"),
+            end: row.get("end"),
+            sets: workout_sets.remove(&workout_id).unwrap_or_default(),
+        }
+    })
+    .collect();
+
+    let prs = sqlx::query(
+        "SELECT ExerciseID::INTEGER, WorkoutID::INTEGER, HeaviestWeight, OneRM, SetVolume
+         FROM PRs ORDER BY PRID",
+    )
+    .fetch_all(pool)
+    .await?
+    .iter()
+    .map(|row| ExportedPr {
+        exercise_id: row.get("exerciseid"),
+        workout_id: row.get("workoutid"),
+        heaviest_weight: row.get("heaviestweight"),
+        one_rm: row.get("onerm"),
+        set_volume: row.get("setvolume"),
+    })
+    .collect();
+
+    let mut marker_logs: HashMap<i32, Vec<ExportedMarkerLog>> = HashMap::new();
+    for row in sqlx::query(
+        "SELECT MarkerID, Date, Value, LoggedAt, Note FROM Markers ORDER BY MarkerID, Date, EntryID",
+    )
+    .fetch_all(pool)
+    .await?
+    {
+        marker_logs
+            .entry(row.get("markerid"))
+            .or_default()
+            .push(ExportedMarkerLog {
+                date: row.get("date"),
+                value: row.get("value"),
+                logged_at: row.get("loggedat"),
+                note: row.get("note"),
+            });
+    }
+
+    let markers = sqlx::query(
+        "SELECT MarkerID, MarkerName, Clr, Unit, DecimalPlaces, Direction, GoalValue, GoalDate,
+                ReadingPolicy, Formula
+         FROM MarkerList ORDER BY MarkerID",
+    )
+    .fetch_all(pool)
+    .await?
+    .iter()
+    .map(|row| {
+        let marker_id: i32 = row.get("markerid");
+        ExportedMarker {
+            marker_id,
+            name: row.get("markername"),
+            color: row.get("clr"),
+            unit: row.get("unit"),
+            precision: row.get("decimalplaces"),
+            direction: row.get("direction"),
+            goal_value: row.get("goalvalue"),
+            goal_date: row.get("goaldate"),
+            reading_policy: row.get("readingpolicy"),
+            formula: row.get("formula"),
+            logs: marker_logs.remove(&marker_id).unwrap_or_default(),
+        }
+    })
+    .collect();
+
+    let settings = sqlx::query("SELECT Name, Value FROM Settings")
+        .fetch_all(pool)
+        .await?
+        .iter()
+        .map(|row| (row.get("name"), row.get("value")))
+        .collect();
+
+    Ok(ExportDocument {
+        format: EXPORT_FORMAT.to_string(),
+        version: EXPORT_VERSION,
+        exported_at: Utc::now().naive_utc(),
+        exercises,
+        routines,
+        workouts,
+        prs,
+        markers,
+        settings,
+    })
+}
+
+fn csv_file<R: Serialize>(
+    header: &[&str],
+    records: impl IntoIterator<Item = R>,
+) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(Vec::new());
+    writer.write_record(header)?;
+    for record in records {
+        writer.serialize(record)?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()))
+}
+
+// One CSV file per entity plus a manifest carrying the format version
+fn csv_archive(document: &ExportDocument) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut files: Vec<(&str, Vec<u8>)> = vec![(
+        "manifest.json",
+        serde_json::to_vec_pretty(&json!({
+            "format": document.format,
+            "version": document.version,
+            "exported_at": document.exported_at,
+        }))?,
+    )];
+
+    files.push((
+        "exercises.csv",
+        csv_file(
+            &["exercise_id", "name", "muscles_trained", "exercise_type"],
+            document.exercises.iter().map(|e| {
+                (
+                    e.exercise_id,
+                    &e.name,
+                    e.muscles_trained.join(";"),
+                    &e.exercise_type,
+                )
+            }),
+        )?,
+    ));
+    files.push((
+        "routines.csv",
+        csv_file(
+            &["routine_id", "name", "created_at"],
+            document
+                .routines
+                .iter()
+                .map(|r| (r.routine_id, &r.name, r.created_at)),
+        )?,
+    ));
+    files.push((
+        "routine_exercises.csv",
+        csv_file(
+            &[
+                "routine_id",
+                "exercise_id",
+                "sets",
+                "target_reps",
+                "target_weight",
+            ],
+            document.routines.iter().flat_map(|r| {
+                r.exercises.iter().map(move |e| {
+                    (
+                        r.routine_id,
+                        e.exercise_id,
+                        e.sets,
+                        e.target_reps,
+                        e.target_weight,
+                    )
+                })
+            }),
+        )?,
+    ));
+    files.push((
+        "workouts.csv",
+        csv_file(
+            &["workout_id", "routine_id", "start", "end"],
+            document
+                .workouts
+                .iter()
+                .map(|w| (w.workout_id, w.routine_id, w.start, w.end)),
+        )?,
+    ));
+    files.push((
+        "workout_sets.csv",
+        csv_file(
+            &["workout_id", "set_number", "exercise_id", "weight", "reps"],
+            document.workouts.iter().flat_map(|w| {
+                w.sets
+                    .iter()
+                    .enumerate()
+                    .map(move |(i, s)| (w.workout_id, i + 1, s.exercise_id, s.weight, s.reps))
+            }),
+        )?,
+    ));
+    files.push((
+        "prs.csv",
+        csv_file(
+            &[
+                "exercise_id",
+                "workout_id",
+                "heaviest_weight",
+                "one_rm",
+                "set_volume",
+            ],
+            document.prs.iter().map(|p| {
+                (
+                    p.exercise_id,
+                    p.workout_id,
+                    p.heaviest_weight,
+                    p.one_rm,
+                    p.set_volume,
+                )
+            }),
+        )?,
+    ));
+    files.push((
+        "markers.csv",
+        csv_file(
+            &[
+                "marker_id",
+                "name",
+                "color",
+                "unit",
+                "precision",
+                "direction",
+                "goal_value",
+                "goal_date",
+                "reading_policy",
+                "formula",
+            ],
+            document.markers.iter().map(|m| {
+                (
+                    m.marker_id,
+                    &m.name,
+                    &m.color,
+                    &m.unit,
+                    m.precision,
+                    &m.direction,
+                    m.goal_value,
+                    m.goal_date,
+                    &m.reading_policy,
+                    &m.formula,
+                )
+            }),
+        )?,
+    ));
+    files.push((
+        "marker_logs.csv",
+        csv_file(
+            &["marker_id", "date", "value", "logged_at", "note"],
+            document.markers.iter().flat_map(|m| {
+                m.logs
+                    .iter()
+                    .map(move |l| (m.marker_id, l.date, l.value, l.logged_at, &l.note))
+            }),
+        )?,
+    ));
+    files.push((
+        "settings.csv",
+        csv_file(&["name", "value"], document.settings.iter())?,
+    ));
+
+    let mut archive = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    for (name, contents) in files {
+        archive.start_file(name, options)?;
+        archive.write_all(&contents)?;
+    }
+    Ok(archive.finish()?.into_inner())
+}
+
+#[utoipa::path(
+    tag = "export",
+    summary = "Download everything as JSON or a zip of CSV files",
+    params(("format" = Option<String>, Query, description = "json (default) or csv")),
+    responses(
+        (status = 200, content(
+            (ExportDocument = "application/json"),
+            (Vec<u8> = "application/zip")
+        )),
+        (status = 400, description = "Unknown format", body = ErrorBody)
+    )
+)]
+#[get("/export")]
+async fn export_account(
+    pool: web::Data<PgPool>,
+    request: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let format = request
+        .get("format")
+        .map(|f| f.to_lowercase())
+        .unwrap_or_else(|| "json".to_string());
+    if format != "json" && format != "csv" {
+        return Err(ApiError::bad_request(
+            "Invalid format. Must be 'json' or 'csv'",
+        ));
+    }
+
+    let document = build_document(pool.get_ref()).await?;
+
+    let filename = format!(
+        "{}-{}",
+        EXPORT_FORMAT,
+        document.exported_at.format("%Y%m%d-%H%M%S")
+    );
+    info!(
+        "Exported {} workouts and {} markers as {}",
+        document.workouts.len(),
+        document.markers.len(),
+        format
+    );
+
+    if format == "json" {
+        return Ok(HttpResponse::Ok()
+            .insert_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"{}.json\"", filename),
+            ))
+            .json(document));
+    }
+
+    let archive = csv_archive(&document)
+        .map_err(|e| ApiError::Internal(format!("Failed to build CSV archive: {}", e)))?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}.zip\"", filename),
+        ))
+        .body(archive))
+}
+
+// Merges an export into this server. Entities that already exist (exercises,
+// routines and markers by name, workouts by start time) are reused rather than
+// duplicated, so importing the same document twice is harmless.
+#[instrument(skip_all, fields(workouts = document.workouts.len()))]
+async fn import_document(
+    tx: &mut Transaction<'_, Postgres>,
+    document: &ExportDocument,
+) -> Result<ImportSummary, ApiError> {
+    let mut summary = ImportSummary::default();
+
+    let mut exercise_ids: HashMap<i32, i32> = HashMap::new();
+    for exercise in &document.exercises {
+        let existing = sqlx::query("SELECT ExerciseID FROM ExerciseList WHERE ExerciseName = $1")
+            .bind(&exercise.name)
+            .fetch_optional(&mut **tx)
+            .await?;
+        let id = match existing {
+            Some(row) => {
+                summary.exercises.existing += 1;
+                row.get("exerciseid")
+            }
+            None => {
+                summary.exercises.created += 1;
+                sqlx::query(
+                    "INSERT INTO ExerciseList (ExerciseName, MusclesTrained, ExerciseType)
+                     VALUES ($1, $2, $3) RETURNING ExerciseID",
+                )
+                .bind(&exercise.name)
+                .bind(&exercise.muscles_trained)
+                .bind(&exercise.exercise_type)
+                .fetch_one(&mut **tx)
+                .await?
+                .get("exerciseid")
+            }
+        };
+        exercise_ids.insert(exercise.exercise_id, id);
+    }
+
+    let mut routine_ids: HashMap<i32, i32> = HashMap::new();
+    for routine in &document.routines {
+        let existing = sqlx::query("SELECT RoutineID FROM Routines WHERE RoutineName = $1")
+            .bind(&routine.name)
+            .fetch_optional(&mut **tx)
+            .await?;
+        let id = match existing {
+            Some(row) => {
+                summary.routines.existing += 1;
+                row.get("routineid")
+            }
+            None => {
+                summary.routines.created += 1;
+                // check_document made sure every exercise is in the document
+                let exercises: Vec<RoutineExercise> = routine
+                    .exercises
+                    .iter()
+                    .map(|exercise| RoutineExercise {
+                        exercise_id: exercise_ids[&exercise.exercise_id],
+                        sets: i32::from(exercise.sets),
+                        target_reps: exercise.target_reps,
+                        target_weight: exercise.target_weight,
+                    })
+                    .collect();
+                insert_routine(tx, &routine.name, routine.created_at, &exercises).await?
+            }
+        };
+        routine_ids.insert(routine.routine_id, id);
+    }
+
+    let mut affected_exercises: Vec<i32> = Vec::new();
+    for workout in &document.workouts {
+        let exists = sqlx::query("SELECT 1 FROM Workout WHERE Start = $1")
+            .bind(workout.start)
+            .fetch_optional(&mut **tx)
+            .await?
+            .is_some();
+        if exists {
+            summary.workouts.existing += 1;
+            continue;
+        }
+        summary.workouts.created += 1;
+
+        let workout_id: i32 = sqlx::query(
+            r#"INSERT INTO Workout (start, "end", routineid, routineversion)
+               VALUES ($1, $2, $3,
+                       (SELECT MAX(version) FROM RoutineVersions WHERE routineid = $3))
+               RETURNING workoutid"#,
+        )
+        .bind(workout.start)
+        .bind(workout.end)
+        .bind(workout.routine_id.map(|id| routine_ids[&id]))
+        .fetch_one(&mut **tx)
+        .await?
+        .get("workoutid");
+
+        let sets: Vec<(i32, &ExportedSet)> = workout
+            .sets
+            .iter()
+            .map(|set| (exercise_ids[&set.exercise_id], set))
+            .collect();
+        let ids: Vec<i32> = sets.iter().map(|(id, _)| *id).collect();
+        let weights: Vec<i16> = sets.iter().map(|(_, set)| set.weight).collect();
+        let reps: Vec<i16> = sets.iter().map(|(_, set)| set.reps).collect();
+
+        let set_ids: Vec<i32> = sqlx::query(
+            r#"INSERT INTO "Set" (weight, reps)
+               SELECT * FROM UNNEST($1::SMALLINT[], $2::SMALLINT[])
+               RETURNING setid"#,
+        )
+        .bind(&weights)
+        .bind(&reps)
+        .fetch_all(&mut **tx)
+        .await?
+        .iter()
+        .map(|row| row.get("setid"))
+        .collect();
+
+        sqlx::query(
+            "INSERT INTO Workout_Exercises_Sets (workoutid, exerciseid, setid)
+             SELECT $1, * FROM UNNEST($2::INTEGER[], $3::INTEGER[])",
+        )
+        .bind(workout_id)
+        .bind(&ids)
+        .bind(&set_ids)
+        .execute(&mut **tx)
+        .await?;
+
+        affected_exercises.extend(ids);
+    }
+
+    affected_exercises.sort();
+    affected_exercises.dedup();
+    rebuild_prs(tx, &affected_exercises).await?;
+
+    let mut marker_ids: HashMap<i32, i32> = HashMap::new();
+    let mut created_formulas: Vec<(usize, i32, &str)> = Vec::new();
+    for (i, marker) in document.markers.iter().enumerate() {
+        let existing = sqlx::query("SELECT MarkerID FROM MarkerList WHERE MarkerName = $1")
+            .bind(&marker.name)
+            .fetch_optional(&mut **tx)
+            .await?;
+        let marker_id: i32 = match existing {
+            Some(row) => {
+                summary.markers.existing += 1;
+                row.get("markerid")
+            }
+            None => {
+                summary.markers.created += 1;
+                let marker_id = sqlx::query(
+                    "INSERT INTO MarkerList
+                        (MarkerName, Clr, Unit, DecimalPlaces, Direction, GoalValue, GoalDate,
+                         ReadingPolicy, Formula)
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING MarkerID",
+                )
+                .bind(&marker.name)
+                .bind(&marker.color)
+                .bind(&marker.unit)
+                .bind(marker.precision)
+                .bind(&marker.direction)
+                .bind(marker.goal_value)
+                .bind(marker.goal_date)
+                .bind(&marker.reading_policy)
+                .bind(&marker.formula)
+                .fetch_one(&mut **tx)
+                .await?
+                .get("markerid");
+                if let Some(formula) = &marker.formula {
+                    created_formulas.push((i, marker_id, formula));
+                }
+                marker_id
+            }
+        };
+        marker_ids.insert(marker.marker_id, marker_id);
+
+        // Entries already present with the same date, value and time are skipped
+        for log in &marker.logs {
+            let inserted = sqlx::query(
+                "INSERT INTO Markers (MarkerID, Value, Date, LoggedAt, Note)
+                 SELECT $1, $2, $3, $4, $5
+                 WHERE NOT EXISTS (
+                     SELECT 1 FROM Markers
+                     WHERE MarkerID = $1 AND Value = $2 AND Date = $3
+                       AND LoggedAt IS NOT DISTINCT FROM $4
+                 )",
+            )
+            .bind(marker_id)
+            .bind(log.value)
+            .bind(log.date)
+            .bind(log.logged_at)
+            .bind(&log.note)
+            .execute(&mut **tx)
+            .await?
+            .rows_affected();
+            if inserted == 0 {
+                summary.marker_logs.existing += 1;
+            } else {
+                summary.marker_logs.created += 1;
+            }
+        }
+    }
+
+    // Formulas refer to markers and exercises by ID, which were only known
+    // once every marker was in place. Checked like the markers endpoints do,
+    // against this server's markers as they stand mid-import.
+    for (i, marker_id, formula) in created_formulas {
+        // A formula that does not tokenize fails the check below as it is
+        let remapped = derived::remap_ids(formula, &marker_ids, &exercise_ids)
+            .unwrap_or_else(|_| formula.to_string());
+        derived::validate(tx, &remapped)
+            .await
+            .map_err(|e| match e {
+                FormulaError::Database(e) => ApiError::Database(e),
+                e => ApiError::BadRequest(format!("markers[{}].formula: {}", i, e)),
+            })?;
+        sqlx::query("UPDATE MarkerList SET Formula = $1 WHERE MarkerID = $2")
+            .bind(remapped)
+            .bind(marker_id)
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    for (name, value) in &document.settings {
+        let inserted = sqlx::query(
+            "INSERT INTO Settings (Name, Value) VALUES ($1, $2) ON CONFLICT (Name) DO NOTHING",
+        )
+        .bind(name)
+        .bind(value)
+        .execute(&mut **tx)
+        .await?
+        .rows_affected();
+        if inserted == 0 {
+            summary.settings.existing += 1;
+        } else {
+            summary.settings.created += 1;
+        }
+    }
+
+    Ok(summary)
+}
+
+fn check_document(document: &ExportDocument) -> Result<(), String> {
+    if document.format != EXPORT_FORMAT {
+        return Err(format!("Not a {} document", EXPORT_FORMAT));
+    }
+    if document.version > EXPORT_VERSION {
+        return Err(format!(
+            "Unsupported export version {}. This server reads up to version {}",
+            document.version, EXPORT_VERSION
+        ));
+    }
+
+    // Anything referring outside the document would be lost on import
+    let exercises: HashSet<i32> = document.exercises.iter().map(|e| e.exercise_id).collect();
+    let routines: HashSet<i32> = document.routines.iter().map(|r| r.routine_id).collect();
+    let missing_exercise = |field: String, exercise_id: i32| {
+        Err(format!(
+            "{} refers to exercise {}, which is not in the document",
+            field, exercise_id
+        ))
+    };
+    for (i, routine) in document.routines.iter().enumerate() {
+        for (j, exercise) in routine.exercises.iter().enumerate() {
+            if !exercises.contains(&exercise.exercise_id) {
+                return missing_exercise(
+                    format!("routines[{}].exercises[{}]", i, j),
+                    exercise.exercise_id,
+                );
+            }
+        }
+    }
+    for (i, workout) in document.workouts.iter().enumerate() {
+        if let Some(routine_id) = workout.routine_id.filter(|id| !routines.contains(id)) {
+            return Err(format!(
+                "workouts[{}] refers to routine {}, which is not in the document",
+                i, routine_id
+            ));
+        }
+        for (j, set) in workout.sets.iter().enumerate() {
+            if !exercises.contains(&set.exercise_id) {
+                return missing_exercise(format!("workouts[{}].sets[{}]", i, j), set.exercise_id);
+            }
+        }
+    }
+
+    if let Err(ApiError::Validation(errors)) = validate(document).finish() {
+        let error = &errors[0];
+        return Err(format!("{}: {}", error.field, error.message));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    tag = "export",
+    summary = "Merge an export document into this server",
+    request_body = ExportDocument,
+    responses(
+        (status = 200, body = ImportSummary),
+        (status = 400, description = "Not an export document, an unsupported version, dangling references or invalid values", body = ErrorBody),
+        (status = 413, description = "Larger than the import size limit", body = ErrorBody)
+    )
+)]
+#[post("/import")]
+async fn import_account(
+    pool: web::Data<PgPool>,
+    metrics: web::Data<Metrics>,
+    body: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    // Read as bytes so that whole exports fall under the import size limit
+    // rather than the one for JSON bodies
+    let document: ExportDocument = serde_json::from_slice(&body)
+        .map_err(|e| ApiError::bad_request(format!("Invalid JSON body: {}", e)))?;
+    check_document(&document).map_err(ApiError::BadRequest)?;
+
+    // Rolled back on drop if the import fails
+    let mut tx = pool.begin().await?;
+    let summary = import_document(&mut tx, &document).await?;
+    tx.commit().await?;
+
+    metrics.workouts_logged(WorkoutSource::Restore, summary.workouts.created as u64);
+    info!(
+        "Imported export from {}: {} workouts, {} markers created",
+        document.exported_at, summary.workouts.created, summary.markers.created
+    );
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+// The same export and import as the endpoints, for the command line
+pub(crate) async fn export_file(
+    pool: &PgPool,
+    csv: bool,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let document = build_document(pool).await?;
+    if csv {
+        csv_archive(&document)
+    } else {
+        Ok(serde_json::to_vec_pretty(&document)?)
+    }
+}
+
+pub(crate) async fn import_file(
+    pool: &PgPool,
+    contents: &[u8],
+) -> Result<ImportSummary, Box<dyn std::error::Error>> {
+    let document: ExportDocument = serde_json::from_slice(contents)?;
+    check_document(&document)?;
+
+    let mut tx = pool.begin().await?;
+    let summary = match import_document(&mut tx, &document).await {
+        Ok(summary) => summary,
+        Err(ApiError::Database(e)) => return Err(e.into()),
+        Err(e) => return Err(e.to_string().into()),
+    };
+    tx.commit().await?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::test_support::TestApp;
+
+    #[actix_web::test]
+    async fn export_and_restore_into_another_account() {
+        let source = TestApp::new().await;
+        let squat = source.exercise("Squat").muscles(&["Quads"]).create().await;
+        let legs = source.routine("Legs").exercise(squat, 2).create().await;
+        source
+            .workout()
+            .routine(legs)
+            .at("2026-01-05T18:00:00")
+            .set(squat, 100, 5)
+            .set(squat, 90, 8)
+            .create()
+            .await;
+        source
+            .marker("Bodyweight")
+            .unit("kg")
+            .entry("2026-01-05", 80.0)
+            .create()
+            .await;
+        source
+            .put("/api/v1/settings/height", json!({ "value": 1.8 }))
+            .await
+            .expect(200);
+
+        let export = source.get("/api/v1/export").await.expect(200);
+        assert!(export
+            .header("content-disposition")
+            .unwrap()
+            .starts_with("attachment; filename=\"strongeryou-export-"));
+        let document = export.json();
+        assert_eq!(document["format"], "strongeryou-export");
+        assert_eq!(document["workouts"][0]["sets"].as_array().unwrap().len(), 2);
+        assert_eq!(document["prs"].as_array().unwrap().len(), 1);
+
+        let target = TestApp::new().await;
+        let summary = target
+            .post("/api/v1/import", document.clone())
+            .await
+            .expect(200)
+            .json();
+        for kind in [
+            "exercises",
+            "routines",
+            "workouts",
+            "markers",
+            "marker_logs",
+            "settings",
+        ] {
+            assert_eq!(
+                summary[kind],
+                json!({ "created": 1, "existing": 0 }),
+                "{}",
+                kind
+            );
+        }
+        assert_eq!(target.count("PRs").await, 1);
+        assert_eq!(target.count("\"Set\"").await, 2);
+        let routines = target.get("/api/v1/routines").await.expect(200).json();
+        let versions = target
+            .get(&format!(
+                "/api/v1/routines/{}/versions",
+                routines[0]["routine_id"]
+            ))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(versions[0]["workouts"], 1);
+
+        // Importing the same document again changes nothing
+        let summary = target
+            .post("/api/v1/import", document)
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(summary["workouts"], json!({ "created": 0, "existing": 1 }));
+        assert_eq!(target.count("Workout").await, 1);
+
+        let metrics = target.get("/metrics").await.expect(200).text();
+        assert!(metrics.contains("strongeryou_workouts_logged_total{source=\"restore\"} 1\n"));
+    }
+
+    #[actix_web::test]
+    async fn csv_archive_and_bad_documents() {
+        let app = TestApp::new().await;
+
+        let export = app.get("/api/v1/export?format=CSV").await.expect(200);
+        assert_eq!(export.header("content-type"), Some("application/zip"));
+        assert!(export.body.starts_with(b"PK"));
+        app.get("/api/v1/export?format=xml").await.expect(400);
+
+        let mut document = app.get("/api/v1/export").await.expect(200).json();
+        document["version"] = json!(99);
+        app.post("/api/v1/import", document.clone())
+            .await
+            .expect(400);
+        document["version"] = json!(1);
+        document["format"] = json!("other");
+        app.post("/api/v1/import", document).await.expect(400);
+    }
+
+    #[actix_web::test]
+    async fn dangling_references_are_rejected() {
+        let source = TestApp::new().await;
+        let squat = source.exercise("Squat").create().await;
+        source.routine("Legs").exercise(squat, 2).create().await;
+        source.workout().set(squat, 100, 5).create().await;
+        let document = source.get("/api/v1/export").await.expect(200).json();
+
+        let target = TestApp::new().await;
+        let mut orphaned = document.clone();
+        orphaned["exercises"] = json!([]);
+        let body = target
+            .post("/api/v1/import", orphaned)
+            .await
+            .expect(400)
+            .json();
+        assert_eq!(body["code"], "bad_request");
+        assert_eq!(
+            body["error"],
+            format!(
+                "routines[0].exercises[0] refers to exercise {}, which is not in the document",
+                squat
+            )
+        );
+        let mut orphaned = document;
+        orphaned["routines"][0]["exercises"] = json!([]);
+        orphaned["workouts"][0]["sets"][0]["exercise_id"] = json!(999);
+        let body = target
+            .post("/api/v1/import", orphaned)
+            .await
+            .expect(400)
+            .json();
+        assert!(body["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("workouts[0].sets[0] refers to exercise 999"));
+        assert_eq!(target.count("ExerciseList").await, 0);
+    }
+
+    #[actix_web::test]
+    async fn invalid_values_are_rejected() {
+        let source = TestApp::new().await;
+        let squat = source.exercise("Squat").create().await;
+        source.routine("Legs").exercise(squat, 2).create().await;
+        source.workout().set(squat, 100, 5).create().await;
+        source.marker("Bodyweight").create().await;
+        let document = source.get("/api/v1/export").await.expect(200).json();
+
+        let target = TestApp::new().await;
+        let nested = format!("{}1{}", "(".repeat(40), ")".repeat(40));
+        let edits = [
+            ("/exercises/0/name", json!(""), "exercises[0].name"),
+            (
+                "/routines/0/name",
+                json!("x".repeat(256)),
+                "routines[0].name",
+            ),
+            (
+                "/routines/0/exercises/0/sets",
+                json!(0),
+                "routines[0].exercises[0].sets",
+            ),
+            (
+                "/workouts/0/sets/0/weight",
+                json!(-5),
+                "workouts[0].sets[0].weight",
+            ),
+            ("/markers/0/name", json!("x".repeat(256)), "markers[0].name"),
+            ("/markers/0/direction", json!("up"), "markers[0].direction"),
+            (
+                "/markers/0/reading_policy",
+                json!("sum"),
+                "markers[0].reading_policy",
+            ),
+            ("/markers/0/formula", json!(nested), "markers[0].formula"),
+            (
+                "/markers/0/formula",
+                json!("marker(999)"),
+                "markers[0].formula",
+            ),
+        ];
+        for (pointer, value, field) in edits {
+            let mut edited = document.clone();
+            *edited.pointer_mut(pointer).unwrap() = value;
+            let body = target
+                .post("/api/v1/import", edited)
+                .await
+                .expect(400)
+                .json();
+            let error = body["error"].as_str().unwrap();
+            assert!(error.starts_with(&format!("{}: ", field)), "{}", error);
+        }
+        // The formula checks run mid-import; the transaction is rolled back
+        assert_eq!(target.count("ExerciseList").await, 0);
+        assert_eq!(target.count("MarkerList").await, 0);
+    }
+
+    #[actix_web::test]
+    async fn formula_ids_follow_the_imported_markers() {
+        let source = TestApp::new().await;
+        let squat = source.exercise("Squat").create().await;
+        let weight = source.marker("Bodyweight").create().await;
+        source
+            .marker("Strength")
+            .formula(&format!("e1rm({}) / marker({})", squat, weight))
+            .create()
+            .await;
+        let document = source.get("/api/v1/export").await.expect(200).json();
+
+        // The target already has rows, so the IDs differ
+        let target = TestApp::new().await;
+        target.exercise("Bench Press").create().await;
+        target.exercise("Deadlift").create().await;
+        target.marker("Waist").create().await;
+        target.post("/api/v1/import", document).await.expect(200);
+
+        let ids: (i32, i32) = sqlx::query_as(
+            "SELECT (SELECT ExerciseID FROM ExerciseList WHERE ExerciseName = 'Squat'),
+                    (SELECT MarkerID FROM MarkerList WHERE MarkerName = 'Bodyweight')",
+        )
+        .fetch_one(&target.pool)
+        .await
+        .unwrap();
+        assert_ne!(ids, (squat, weight));
+        let formula: String =
+            sqlx::query_scalar("SELECT Formula FROM MarkerList WHERE MarkerName = 'Strength'")
+                .fetch_one(&target.pool)
+                .await
+                .unwrap();
+        assert_eq!(formula, format!("e1rm({}) / marker({})", ids.0, ids.1));
+    }
+}
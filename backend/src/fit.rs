@@ -0,0 +1,355 @@
+// Encoder for Garmin FIT activity files. Only what a strength-training activity
+// needs is implemented: file_id, event, set, lap, session and activity
+// messages, all little-endian and without compressed timestamps.
+
+use chrono::{Duration, NaiveDateTime};
+
+use crate::activity_export::Activity;
+
+const PROTOCOL_VERSION: u8 = 0x20; // 2.0
+const PROFILE_VERSION: u16 = 2132; // 21.32
+const HEADER_SIZE: u8 = 14;
+
+// FIT timestamps count seconds from 1989-12-31T00:00:00Z
+const FIT_EPOCH: i64 = 631_065_600;
+
+// Global message numbers
+const MESG_FILE_ID: u16 = 0;
+const MESG_SESSION: u16 = 18;
+const MESG_LAP: u16 = 19;
+const MESG_EVENT: u16 = 21;
+const MESG_ACTIVITY: u16 = 34;
+const MESG_SET: u16 = 225;
+
+// Local message types, one per kind of message written
+const LOCAL_FILE_ID: u8 = 0;
+const LOCAL_EVENT: u8 = 1;
+const LOCAL_SET: u8 = 2;
+const LOCAL_LAP: u8 = 3;
+const LOCAL_SESSION: u8 = 4;
+const LOCAL_ACTIVITY: u8 = 5;
+
+// Profile enum values
+const FILE_ACTIVITY: u8 = 4;
+const MANUFACTURER_DEVELOPMENT: u16 = 255;
+const SPORT_TRAINING: u8 = 10;
+const SUB_SPORT_STRENGTH_TRAINING: u8 = 20;
+const EVENT_TIMER: u8 = 0;
+const EVENT_SESSION: u8 = 8;
+const EVENT_LAP: u8 = 9;
+const EVENT_ACTIVITY: u8 = 26;
+const EVENT_TYPE_START: u8 = 0;
+const EVENT_TYPE_STOP: u8 = 1;
+const EVENT_TYPE_STOP_ALL: u8 = 4;
+const SET_TYPE_ACTIVE: u8 = 1;
+const DISPLAY_UNIT_KILOGRAM: u16 = 1;
+const ACTIVITY_MANUAL: u8 = 0;
+
+// exercise_category values from the FIT profile
+const CATEGORY_BENCH_PRESS: u16 = 0;
+const CATEGORY_CALF_RAISE: u16 = 1;
+const CATEGORY_CURL: u16 = 7;
+const CATEGORY_DEADLIFT: u16 = 8;
+const CATEGORY_FLYE: u16 = 9;
+const CATEGORY_LATERAL_RAISE: u16 = 14;
+const CATEGORY_LEG_CURL: u16 = 15;
+const CATEGORY_LUNGE: u16 = 17;
+const CATEGORY_PULL_UP: u16 = 21;
+const CATEGORY_PUSH_UP: u16 = 22;
+const CATEGORY_ROW: u16 = 23;
+const CATEGORY_SHOULDER_PRESS: u16 = 24;
+const CATEGORY_SHRUG: u16 = 26;
+const CATEGORY_SQUAT: u16 = 28;
+const CATEGORY_TRICEPS_EXTENSION: u16 = 30;
+const CATEGORY_UNKNOWN: u16 = 65534;
+
+#[derive(Clone, Copy)]
+enum Value {
+    Enum(u8),
+    Uint8(u8),
+    Uint16(u16),
+    Uint32(u32),
+}
+
+impl Value {
+    // (size in bytes, base type)
+    fn base_type(self) -> (u8, u8) {
+        match self {
+            Value::Enum(_) => (1, 0x00),
+            Value::Uint8(_) => (1, 0x02),
+            Value::Uint16(_) => (2, 0x84),
+            Value::Uint32(_) => (4, 0x86),
+        }
+    }
+
+    fn write(self, out: &mut Vec<u8>) {
+        match self {
+            Value::Enum(v) | Value::Uint8(v) => out.push(v),
+            Value::Uint16(v) => out.extend_from_slice(&v.to_le_bytes()),
+            Value::Uint32(v) => out.extend_from_slice(&v.to_le_bytes()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    records: Vec<u8>,
+    defined: [bool; 16],
+}
+
+impl Writer {
+    // Writes a data message, preceded by its definition the first time the
+    // local type is used. Every message of a local type has the same fields.
+    fn message(&mut self, local: u8, global: u16, fields: &[(u8, Value)]) {
+        if !self.defined[local as usize] {
+            self.defined[local as usize] = true;
+            self.records.push(0x40 | local);
+            self.records.push(0); // Reserved
+            self.records.push(0); // Little-endian
+            self.records.extend_from_slice(&global.to_le_bytes());
+            self.records.push(fields.len() as u8);
+            for (number, value) in fields {
+                let (size, base_type) = value.base_type();
+                self.records.extend_from_slice(&[*number, size, base_type]);
+            }
+        }
+
+        self.records.push(local);
+        for (_, value) in fields {
+            value.write(&mut self.records);
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let mut file = Vec::with_capacity(self.records.len() + HEADER_SIZE as usize + 2);
+        file.push(HEADER_SIZE);
+        file.push(PROTOCOL_VERSION);
+        file.extend_from_slice(&PROFILE_VERSION.to_le_bytes());
+        file.extend_from_slice(&(self.records.len() as u32).to_le_bytes());
+        file.extend_from_slice(b".FIT");
+        let header_crc = crc(&file);
+        file.extend_from_slice(&header_crc.to_le_bytes());
+
+        file.extend_from_slice(&self.records);
+        let file_crc = crc(&file);
+        file.extend_from_slice(&file_crc.to_le_bytes());
+        file
+    }
+}
+
+// CRC-16 as specified by the FIT protocol
+pub(crate) fn crc(bytes: &[u8]) -> u16 {
+    const TABLE: [u16; 16] = [
+        0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401, 0xA001, 0x6C00, 0x7800,
+        0xB401, 0x5000, 0x9C01, 0x8801, 0x4400,
+    ];
+    bytes.iter().fold(0u16, |crc, byte| {
+        let crc = (crc >> 4) ^ TABLE[(crc & 0xF) as usize] ^ TABLE[(byte & 0xF) as usize];
+        (crc >> 4) ^ TABLE[(crc & 0xF) as usize] ^ TABLE[(byte >> 4) as usize]
+    })
+}
+
+fn timestamp(time: NaiveDateTime) -> Value {
+    Value::Uint32((time.and_utc().timestamp() - FIT_EPOCH).max(0) as u32)
+}
+
+// Durations are stored in milliseconds (scale 1000)
+fn milliseconds(duration: Duration) -> Value {
+    Value::Uint32(duration.num_milliseconds().max(0) as u32)
+}
+
+// Maps catalog names onto the closest FIT exercise category
+pub(crate) fn exercise_category(exercise_name: &str) -> u16 {
+    let name = exercise_name.to_lowercase();
+    let has = |word: &str| name.contains(word);
+
+    if has("leg curl") {
+        CATEGORY_LEG_CURL
+    } else if has("curl") {
+        CATEGORY_CURL
+    } else if has("calf") {
+        CATEGORY_CALF_RAISE
+    } else if has("deadlift") {
+        CATEGORY_DEADLIFT
+    } else if has("fly") || has("flye") {
+        CATEGORY_FLYE
+    } else if has("lateral raise") || has("front raise") {
+        CATEGORY_LATERAL_RAISE
+    } else if has("lunge") || has("split squat") {
+        CATEGORY_LUNGE
+    } else if has("squat") || has("leg press") {
+        CATEGORY_SQUAT
+    } else if has("pull-up") || has("pull up") || has("chin") || has("pulldown") {
+        CATEGORY_PULL_UP
+    } else if has("push-up") || has("push up") {
+        CATEGORY_PUSH_UP
+    } else if has("row") {
+        CATEGORY_ROW
+    } else if has("shrug") {
+        CATEGORY_SHRUG
+    } else if has("overhead press") || has("shoulder press") || has("military press") {
+        CATEGORY_SHOULDER_PRESS
+    } else if has("tricep") || has("dip") || has("skull") {
+        CATEGORY_TRICEPS_EXTENSION
+    } else if has("bench") || has("press") {
+        CATEGORY_BENCH_PRESS
+    } else {
+        CATEGORY_UNKNOWN
+    }
+}
+
+pub(crate) fn encode(activity: &Activity) -> Vec<u8> {
+    let mut writer = Writer::default();
+    let duration = milliseconds(activity.duration());
+
+    writer.message(
+        LOCAL_FILE_ID,
+        MESG_FILE_ID,
+        &[
+            (0, Value::Enum(FILE_ACTIVITY)),
+            (1, Value::Uint16(MANUFACTURER_DEVELOPMENT)),
+            (2, Value::Uint16(0)), // product
+            (4, timestamp(activity.start)),
+        ],
+    );
+
+    let event = |time, event, event_type| {
+        [
+            (253, timestamp(time)),
+            (0, Value::Enum(event)),
+            (1, Value::Enum(event_type)),
+        ]
+    };
+    writer.message(
+        LOCAL_EVENT,
+        MESG_EVENT,
+        &event(activity.start, EVENT_TIMER, EVENT_TYPE_START),
+    );
+
+    for (index, (set, (start, length))) in
+        activity.sets.iter().zip(activity.set_times()).enumerate()
+    {
+        // Weight is stored in 1/16 kg
+        let weight = (i32::from(set.weight.max(0)) * 16).min(i32::from(u16::MAX - 1)) as u16;
+        writer.message(
+            LOCAL_SET,
+            MESG_SET,
+            &[
+                (254, timestamp(start + length)),
+                (0, milliseconds(length)),
+                (3, Value::Uint16(set.reps.max(0) as u16)),
+                (4, Value::Uint16(weight)),
+                (5, Value::Uint8(SET_TYPE_ACTIVE)),
+                (6, timestamp(start)),
+                (7, Value::Uint16(exercise_category(&set.exercise_name))),
+                (9, Value::Uint16(DISPLAY_UNIT_KILOGRAM)),
+                (10, Value::Uint16(index as u16)),
+            ],
+        );
+    }
+
+    let set_times = activity.set_times();
+    let laps = activity.laps();
+    for (index, lap) in laps.iter().enumerate() {
+        let start = set_times[lap.start].0;
+        let end = set_times[lap.end - 1].0 + set_times[lap.end - 1].1;
+        writer.message(
+            LOCAL_LAP,
+            MESG_LAP,
+            &[
+                (253, timestamp(end)),
+                (0, Value::Enum(EVENT_LAP)),
+                (1, Value::Enum(EVENT_TYPE_STOP)),
+                (2, timestamp(start)),
+                (7, milliseconds(end - start)),
+                (8, milliseconds(end - start)),
+                (254, Value::Uint16(index as u16)),
+                (25, Value::Enum(SPORT_TRAINING)),
+                (39, Value::Enum(SUB_SPORT_STRENGTH_TRAINING)),
+            ],
+        );
+    }
+
+    writer.message(
+        LOCAL_EVENT,
+        MESG_EVENT,
+        &event(activity.end, EVENT_TIMER, EVENT_TYPE_STOP_ALL),
+    );
+
+    writer.message(
+        LOCAL_SESSION,
+        MESG_SESSION,
+        &[
+            (253, timestamp(activity.end)),
+            (0, Value::Enum(EVENT_SESSION)),
+            (1, Value::Enum(EVENT_TYPE_STOP)),
+            (2, timestamp(activity.start)),
+            (7, duration),
+            (8, duration),
+            (5, Value::Enum(SPORT_TRAINING)),
+            (6, Value::Enum(SUB_SPORT_STRENGTH_TRAINING)),
+            (25, Value::Uint16(0)), // first_lap_index
+            (26, Value::Uint16(laps.len() as u16)),
+            (254, Value::Uint16(0)),
+        ],
+    );
+
+    writer.message(
+        LOCAL_ACTIVITY,
+        MESG_ACTIVITY,
+        &[
+            (253, timestamp(activity.end)),
+            (0, duration),
+            (1, Value::Uint16(1)), // num_sessions
+            (2, Value::Enum(ACTIVITY_MANUAL)),
+            (3, Value::Enum(EVENT_ACTIVITY)),
+            (4, Value::Enum(EVENT_TYPE_STOP)),
+            (5, timestamp(activity.end)), // local_timestamp, times are stored as local
+        ],
+    );
+
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activity_export::tests::{assert_golden, sample_activity};
+
+    #[test]
+    fn matches_golden_file() {
+        assert_golden("strength_workout.fit", &encode(&sample_activity()));
+    }
+
+    #[test]
+    fn header_and_file_crc_are_valid() {
+        let file = encode(&sample_activity());
+        let data_size = u32::from_le_bytes(file[4..8].try_into().unwrap()) as usize;
+        assert_eq!(file[0], HEADER_SIZE);
+        assert_eq!(&file[8..12], b".FIT");
+        assert_eq!(file.len(), HEADER_SIZE as usize + data_size + 2);
+        // A CRC over data followed by its own CRC is zero
+        assert_eq!(crc(&file[..HEADER_SIZE as usize]), 0);
+        assert_eq!(crc(&file), 0);
+    }
+
+    #[test]
+    fn maps_catalog_exercises_to_categories() {
+        assert_eq!(exercise_category("Bench Press"), CATEGORY_BENCH_PRESS);
+        assert_eq!(
+            exercise_category("Incline Press (Dumbbell)"),
+            CATEGORY_BENCH_PRESS
+        );
+        assert_eq!(
+            exercise_category("Overhead Press (Barbell)"),
+            CATEGORY_SHOULDER_PRESS
+        );
+        assert_eq!(exercise_category("Leg Press"), CATEGORY_SQUAT);
+        assert_eq!(exercise_category("Romanian Deadlift"), CATEGORY_DEADLIFT);
+        assert_eq!(exercise_category("Lat Pulldown"), CATEGORY_PULL_UP);
+        assert_eq!(exercise_category("Hammer Curls (Dumbbell)"), CATEGORY_CURL);
+        assert_eq!(exercise_category("Seated Dips"), CATEGORY_TRICEPS_EXTENSION);
+        assert_eq!(exercise_category("Standing Cable Chest Fly"), CATEGORY_FLYE);
+        assert_eq!(exercise_category("Farmer's Walk"), CATEGORY_UNKNOWN);
+    }
+}
@@ -0,0 +1,101 @@
+use actix_web::{get, web, HttpResponse};
+use log::warn;
+use serde_json::json;
+use sqlx::PgPool;
+use std::time::Duration;
+use utoipa::OpenApi;
+
+use crate::db::{self, SCHEMA_VERSION};
+
+// Readiness answers quickly even when the pool would wait for its full
+// acquire timeout
+const READY_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(healthz, readyz),
+    tags((name = "health", description = "Liveness and readiness probes"))
+)]
+pub(crate) struct ApiDoc;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(healthz).service(readyz);
+}
+
+// Liveness: the process is up and serving requests. Deliberately does not
+// touch the database, so an outage there does not get the server restarted.
+#[utoipa::path(
+    tag = "health",
+    responses((status = 200, body = serde_json::Value, example = json!({ "status": "ok" })))
+)]
+#[get("/healthz")]
+async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "status": "ok" }))
+}
+
+// Readiness: the database is reachable and its schema matches this binary
+#[utoipa::path(
+    tag = "health",
+    responses(
+        (status = 200, body = serde_json::Value, example = json!({
+            "status": "ready",
+            "checks": { "database": "ok", "schema": { "version": 1, "expected": 1 } }
+        })),
+        (status = 503, description = "Database unreachable or schema out of date", body = serde_json::Value)
+    )
+)]
+#[get("/readyz")]
+async fn readyz(pool: web::Data<PgPool>) -> HttpResponse {
+    let (database, schema) =
+        match tokio::time::timeout(READY_TIMEOUT, db::schema_version(pool.get_ref())).await {
+            Err(_) => (json!("timed out"), json!(null)),
+            Ok(Err(e)) => {
+                warn!("Readiness check failed to reach the database: {}", e);
+                (json!("unreachable"), json!(null))
+            }
+            Ok(Ok(version)) => (json!("ok"), json!(version)),
+        };
+
+    let ready = database == "ok" && schema == json!(SCHEMA_VERSION);
+    let body = json!({
+        "status": if ready { "ready" } else { "unavailable" },
+        "checks": {
+            "database": database,
+            "schema": { "version": schema, "expected": SCHEMA_VERSION },
+        },
+    });
+    if ready {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::db::SCHEMA_VERSION;
+    use crate::test_support::TestApp;
+
+    #[actix_web::test]
+    async fn liveness_and_readiness() {
+        let app = TestApp::new().await;
+
+        let body = app.get("/healthz").await.expect(200).json();
+        assert_eq!(body, json!({ "status": "ok" }));
+
+        let body = app.get("/readyz").await.expect(200).json();
+        assert_eq!(body["status"], "ready");
+        assert_eq!(body["checks"]["schema"]["version"], SCHEMA_VERSION);
+
+        // A schema left behind by an older binary is not ready
+        sqlx::query("UPDATE SchemaMigrations SET Version = Version - 1")
+            .execute(&app.pool)
+            .await
+            .unwrap();
+        let body = app.get("/readyz").await.expect(503).json();
+        assert_eq!(body["status"], "unavailable");
+        assert_eq!(body["checks"]["database"], "ok");
+    }
+}
@@ -0,0 +1,326 @@
+// Request size caps and rate limiting, configured by the [limits] section.
+// The JSON and payload limits are extractor configs registered in
+// configure_app; the workout caps are checked by the handlers that store
+// workouts; throttle wraps the API scopes so probes, metrics and the docs
+// are never limited.
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderMap, AUTHORIZATION, FORWARDED};
+use actix_web::middleware::Next;
+use actix_web::{web, ResponseError};
+use governor::clock::{Clock, DefaultClock};
+use governor::{DefaultKeyedRateLimiter, Quota, RateLimiter};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::net::{IpAddr, SocketAddr};
+use std::num::NonZeroU32;
+
+use crate::config::LimitsConfig;
+use crate::errors::{self, ApiError};
+use crate::workouts::WorkoutData;
+
+// Keys not seen for a while are dropped once a limiter tracks this many
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+pub struct Limits {
+    json_body_bytes: usize,
+    import_body_bytes: usize,
+    exercises_per_workout: usize,
+    sets_per_exercise: usize,
+    per_ip: Option<DefaultKeyedRateLimiter<IpAddr>>,
+    per_user: Option<DefaultKeyedRateLimiter<u64>>, // Hash of the credential
+    trusted_proxies: Vec<IpAddr>,
+}
+
+// None when the rate is 0, which disables the limit
+fn limiter<K: Clone + Hash + Eq>(
+    per_minute: u32,
+    burst: u32,
+) -> Option<DefaultKeyedRateLimiter<K>> {
+    let per_minute = NonZeroU32::new(per_minute)?;
+    let burst = NonZeroU32::new(burst).unwrap_or(NonZeroU32::MIN);
+    Some(RateLimiter::keyed(
+        Quota::per_minute(per_minute).allow_burst(burst),
+    ))
+}
+
+impl Limits {
+    pub fn new(config: &LimitsConfig) -> Limits {
+        Limits {
+            json_body_bytes: config.json_body_bytes,
+            import_body_bytes: config.import_body_bytes,
+            exercises_per_workout: config.exercises_per_workout,
+            sets_per_exercise: config.sets_per_exercise,
+            per_ip: limiter(config.requests_per_minute_per_ip, config.burst),
+            per_user: limiter(config.requests_per_minute_per_user, config.burst),
+            trusted_proxies: config.trusted_proxies.clone(),
+        }
+    }
+
+    pub fn json_config(&self) -> web::JsonConfig {
+        errors::json_config(self.json_body_bytes)
+    }
+
+    // For the String bodies of the file imports
+    pub fn payload_config(&self) -> web::PayloadConfig {
+        web::PayloadConfig::new(self.import_body_bytes)
+    }
+
+    // Checked before validation so that oversized workouts are not walked
+    // set by set
+    pub(crate) fn check_workout(&self, workout: &WorkoutData) -> Result<(), ApiError> {
+        if workout.exercises.len() > self.exercises_per_workout {
+            return Err(ApiError::PayloadTooLarge(format!(
+                "A workout can have at most {} exercises",
+                self.exercises_per_workout
+            )));
+        }
+        match workout
+            .exercises
+            .iter()
+            .position(|exercise| exercise.sets.len() > self.sets_per_exercise)
+        {
+            Some(i) => Err(ApiError::PayloadTooLarge(format!(
+                "exercises[{}] has more than {} sets",
+                i, self.sets_per_exercise
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+// Seconds until the key may make another request, if it is over its limit
+fn over_limit<K: Clone + Hash + Eq>(limiter: &DefaultKeyedRateLimiter<K>, key: &K) -> Option<u64> {
+    let result = limiter.check_key(key);
+    if limiter.len() > MAX_TRACKED_KEYS {
+        limiter.retain_recent();
+    }
+    result.err().map(|not_until| {
+        let wait = not_until.wait_time_from(DefaultClock::default().now());
+        wait.as_secs() + u64::from(wait.subsec_nanos() > 0)
+    })
+}
+
+// A Forwarded node is an address with an optional port, IPv6 in brackets
+fn forwarded_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    node.parse()
+        .or_else(|_| node.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .or_else(|_| node.trim_start_matches('[').trim_end_matches(']').parse())
+        .ok()
+}
+
+// The hops a request passed through, the client first; None for a hop that
+// is not an address (obfuscated or "unknown")
+fn forwarded_hops(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let x_forwarded_for: Vec<_> = headers
+        .get_all("x-forwarded-for")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(forwarded_node)
+        .collect();
+    if !x_forwarded_for.is_empty() {
+        return x_forwarded_for;
+    }
+    headers
+        .get_all(FORWARDED)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|element| {
+            element.split(';').find_map(|pair| {
+                let (name, node) = pair.split_once('=')?;
+                name.trim()
+                    .eq_ignore_ascii_case("for")
+                    .then(|| forwarded_node(node))?
+            })
+        })
+        .collect()
+}
+
+// The connecting address, unless it is a trusted proxy. Then the client is
+// the nearest hop the proxies did not add themselves; anything further along
+// the header came from the client and may be made up.
+fn client_ip(req: &ServiceRequest, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    if !trusted_proxies.contains(&peer) {
+        return Some(peer);
+    }
+    let mut client = peer;
+    for hop in forwarded_hops(req.headers()).into_iter().rev() {
+        match hop {
+            Some(ip) => client = ip,
+            None => break,
+        }
+        if !trusted_proxies.contains(&client) {
+            break;
+        }
+    }
+    Some(client)
+}
+
+// Limits by the connecting address; X-Forwarded-For and Forwarded are only
+// believed from the configured trusted proxies, as the client controls them
+fn retry_after(req: &ServiceRequest) -> Option<u64> {
+    let limits = req.app_data::<web::Data<Limits>>()?;
+
+    let ip = client_ip(req, &limits.trusted_proxies);
+    if let (Some(limiter), Some(ip)) = (&limits.per_ip, ip) {
+        if let Some(secs) = over_limit(limiter, &ip) {
+            return Some(secs);
+        }
+    }
+
+    let credential = req.headers().get(AUTHORIZATION)?;
+    let mut hasher = DefaultHasher::new();
+    credential.as_bytes().hash(&mut hasher);
+    over_limit(limits.per_user.as_ref()?, &hasher.finish())
+}
+
+// Answers with the 429 itself rather than failing the request, so that the
+// outer middleware still adds the request ID and CORS headers
+pub async fn throttle(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    if let Some(secs) = retry_after(&req) {
+        let response = ApiError::RateLimited(secs).error_response();
+        return Ok(req.into_response(response));
+    }
+    Ok(next.call(req).await?.map_into_boxed_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, web};
+    use serde_json::json;
+    use std::net::SocketAddr;
+
+    use super::Limits;
+    use crate::config::LimitsConfig;
+    use crate::test_support::TestApp;
+
+    fn from(ip: &str) -> test::TestRequest {
+        let addr: SocketAddr = format!("{}:40000", ip).parse().unwrap();
+        test::TestRequest::get()
+            .uri("/api/v1/exercises")
+            .peer_addr(addr)
+    }
+
+    #[actix_web::test]
+    async fn each_address_gets_its_own_burst() {
+        let mut app = TestApp::new().await;
+        app.limits = web::Data::new(Limits::new(&LimitsConfig {
+            requests_per_minute_per_ip: 1,
+            burst: 2,
+            ..LimitsConfig::default()
+        }));
+
+        app.send(from("10.0.0.1")).await.expect(200);
+        app.send(from("10.0.0.1")).await.expect(200);
+        let limited = app.send(from("10.0.0.1")).await.expect(429);
+        assert_eq!(limited.json()["code"], "rate_limited");
+        let secs: u64 = limited.header("retry-after").unwrap().parse().unwrap();
+        assert!((1..=60).contains(&secs));
+        // The deprecated aliases share the limit
+        app.send(from("10.0.0.1").uri("/exercises"))
+            .await
+            .expect(429);
+
+        app.send(from("10.0.0.2")).await.expect(200);
+        // Probes are never limited
+        app.send(from("10.0.0.1").uri("/healthz")).await.expect(200);
+    }
+
+    #[actix_web::test]
+    async fn credentials_are_limited_across_addresses() {
+        let mut app = TestApp::new().await;
+        app.limits = web::Data::new(Limits::new(&LimitsConfig {
+            requests_per_minute_per_ip: 0,
+            requests_per_minute_per_user: 1,
+            burst: 1,
+            ..LimitsConfig::default()
+        }));
+        let with = |ip, token| from(ip).insert_header(("Authorization", token));
+
+        app.send(with("10.0.0.1", "Bearer a")).await.expect(200);
+        app.send(with("10.0.0.2", "Bearer a")).await.expect(429);
+        app.send(with("10.0.0.2", "Bearer b")).await.expect(200);
+        app.send(from("10.0.0.2")).await.expect(200);
+    }
+
+    #[actix_web::test]
+    async fn forwarded_clients_are_believed_from_trusted_proxies() {
+        let mut app = TestApp::new().await;
+        app.limits = web::Data::new(Limits::new(&LimitsConfig {
+            requests_per_minute_per_ip: 1,
+            burst: 1,
+            trusted_proxies: vec!["10.0.0.9".parse().unwrap()],
+            ..LimitsConfig::default()
+        }));
+        let via = |ip, header, value| from(ip).insert_header((header, value));
+
+        app.send(via("10.0.0.9", "X-Forwarded-For", "203.0.113.1"))
+            .await
+            .expect(200);
+        app.send(via("10.0.0.9", "X-Forwarded-For", "203.0.113.1"))
+            .await
+            .expect(429);
+        // Hops before the one the proxy added are the client's own claims
+        app.send(via(
+            "10.0.0.9",
+            "X-Forwarded-For",
+            "198.51.100.7, 203.0.113.1",
+        ))
+        .await
+        .expect(429);
+        app.send(via("10.0.0.9", "X-Forwarded-For", "203.0.113.2, 10.0.0.9"))
+            .await
+            .expect(200);
+        app.send(via(
+            "10.0.0.9",
+            "Forwarded",
+            "for=\"[2001:db8::1]:4711\";proto=https",
+        ))
+        .await
+        .expect(200);
+        app.send(via("10.0.0.9", "Forwarded", "for=2001:db8::1"))
+            .await
+            .expect(429);
+
+        // Other peers cannot pick their address
+        app.send(via("10.0.0.1", "X-Forwarded-For", "203.0.113.3"))
+            .await
+            .expect(200);
+        app.send(via("10.0.0.1", "X-Forwarded-For", "203.0.113.4"))
+            .await
+            .expect(429);
+    }
+
+    #[actix_web::test]
+    async fn oversized_bodies_are_rejected() {
+        let mut app = TestApp::new().await;
+        app.limits = web::Data::new(Limits::new(&LimitsConfig {
+            json_body_bytes: 64,
+            import_body_bytes: 128,
+            ..LimitsConfig::default()
+        }));
+
+        let body = app
+            .post(
+                "/api/v1/exercises",
+                json!({ "name": "x".repeat(100), "muscles": [], "exercise_type": "Strength" }),
+            )
+            .await
+            .expect(413)
+            .json();
+        assert_eq!(body["code"], "payload_too_large");
+
+        // Export documents fall under the larger import limit
+        app.post("/api/v1/import", json!({ "format": "x".repeat(50) }))
+            .await
+            .expect(400);
+        app.post("/api/v1/import", json!({ "format": "x".repeat(150) }))
+            .await
+            .expect(413);
+    }
+}
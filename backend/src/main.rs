@@ -1,64 +1,230 @@
 #![allow(unused_imports)]
 use actix_cors::Cors;
 use actix_web::cookie::time::error;
-use actix_web::middleware::Logger;
 use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
 use actix_web::{http, middleware};
+use clap::Parser;
 use dotenv::dotenv;
-use env_logger::Env;
 use log::{error, info, warn};
 use serde::{de::value::Error, Deserialize, Serialize};
 use sqlx::{PgConnection, PgPool};
 use std::env;
 use std::fs;
+use std::io::IsTerminal;
 
+use cli::Command;
+use config::{Config, ConfigArgs, LogConfig, LogFormat};
+use limits::Limits;
+use metrics::Metrics;
+use repository::Repositories;
+use request_id::REQUEST_ID;
+use tracing_subscriber::EnvFilter;
+
+mod activity_export;
+mod calendar;
+mod cli;
+mod config;
+mod dashboard;
 mod db;
+mod derived;
+mod errors;
 mod exercises;
+mod export;
+mod fit;
+mod health;
+mod limits;
+mod marker_import;
 mod markers;
+mod metrics;
+mod openapi;
+mod programs;
+mod repository;
+mod request_id;
+mod routine_comparison;
 mod routines;
+mod service;
+mod settings;
+mod tcx;
+mod templates;
+#[cfg(test)]
+mod test_support;
+mod validation;
+mod versioning;
+mod workout_import;
 mod workouts;
 
+#[derive(Parser)]
+#[command(about = "StrongerYou backend server")]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let port = 8081;
     dotenv::dotenv().ok();
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    let cli = Cli::parse();
+
+    if let Some(Command::Openapi { output }) = &cli.command {
+        if let Err(e) = cli::write_openapi(output.as_deref()) {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // Fail fast on a bad configuration, before anything is started
+    let config = match Config::load(&cli.config) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        }
+    };
+    init_logging(&config.log);
+
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(config).await,
+        command => {
+            if let Err(e) = cli::run(command, &config).await {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+    }
+}
 
+async fn serve(config: Config) -> std::io::Result<()> {
     // Initialize the database
-    let pool = match db::initialize_database().await {
+    let pool = match db::initialize_database(&config).await {
         Ok(p) => p,
         Err(e) => {
-            eprintln!("Failed to initialize database: {}", e);
-            return Ok(()); // Or handle the error more severely
+            eprintln!("error: failed to initialize database: {}", e);
+            std::process::exit(1);
         }
     };
 
+    let server = config.server.clone();
+    let metrics = web::Data::new(Metrics::default());
+    let limits = web::Data::new(Limits::new(&config.limits));
+    info!("Listening on {}:{}", server.host, server.port);
+
     // Start HTTP server
     HttpServer::new(move || {
-        let cors = Cors::default()
-            .allowed_origin("http://localhost:8101") // Your frontend's origin
-            .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
-            .allowed_headers(vec![
-                http::header::AUTHORIZATION,
-                http::header::ACCEPT,
-                http::header::CONTENT_TYPE,
-            ])
-            .max_age(3600);
         App::new()
-            .wrap(middleware::Logger::default())
-            .wrap(cors)
-            .wrap(Logger::default()) // Enable logging
-            .app_data(web::Data::new(pool.clone()))
-            .configure(configure_routes)
+            .wrap(middleware::from_fn(metrics::track_requests))
+            .wrap(cors(&server.cors_origins))
+            .wrap(middleware::from_fn(request_id::correlate))
+            .configure(|cfg| configure_app(cfg, &pool, &metrics, &limits))
     })
-    .bind(("localhost", port))?
+    .bind((config.server.host.as_str(), config.server.port))?
     .run()
     .await
 }
 
+fn cors(origins: &[String]) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
+        .allowed_headers(vec![
+            http::header::AUTHORIZATION,
+            http::header::ACCEPT,
+            http::header::CONTENT_TYPE,
+            REQUEST_ID,
+        ])
+        .expose_headers(vec![REQUEST_ID, versioning::DEPRECATION, http::header::LINK])
+        .max_age(3600);
+    for origin in origins {
+        cors = if origin == "*" {
+            cors.allow_any_origin()
+        } else {
+            cors.allowed_origin(origin)
+        };
+    }
+    cors
+}
+
+// log:: records from the handlers and libraries are forwarded to tracing, so
+// they carry the request span too. JSON lines list the spans an event
+// happened in, outermost (the request, with its request_id) first.
+fn init_logging(log: &LogConfig) {
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::new(&log.level))
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal());
+    match log.format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(false)
+            .with_span_list(true)
+            .init(),
+    }
+}
+
+// Shared state and routes, also used by the test harness in test_support.rs
+fn configure_app(
+    cfg: &mut web::ServiceConfig,
+    pool: &PgPool,
+    metrics: &web::Data<Metrics>,
+    limits: &web::Data<Limits>,
+) {
+    cfg.app_data(web::Data::new(pool.clone()))
+        .app_data(web::Data::new(Repositories::postgres(pool)))
+        .app_data(metrics.clone())
+        .app_data(limits.clone())
+        .app_data(limits.json_config())
+        .app_data(limits.payload_config())
+        .app_data(errors::query_config())
+        .app_data(errors::path_config())
+        .configure(configure_routes);
+}
+
+// Probes, metrics and docs stay at the root; the API itself is versioned and
+// rate limited. The unversioned aliases come last since their scope matches
+// every path.
 fn configure_routes(cfg: &mut web::ServiceConfig) {
+    health::init_routes(cfg);
+    metrics::init_routes(cfg);
+    openapi::init_routes(cfg);
+    cfg.service(
+        web::scope(versioning::PREFIX)
+            .wrap(middleware::from_fn(limits::throttle))
+            .configure(api_routes),
+    );
+    cfg.service(
+        web::scope("")
+            .wrap(middleware::from_fn(versioning::deprecated))
+            .wrap(middleware::from_fn(limits::throttle))
+            .configure(legacy_routes),
+    );
+}
+
+fn api_routes(cfg: &mut web::ServiceConfig) {
+    calendar::init_routes(cfg);
+    dashboard::init_routes(cfg);
     exercises::init_routes(cfg);
+    export::init_routes(cfg);
     markers::init_routes(cfg);
+    programs::init_routes(cfg);
+    marker_import::init_routes(cfg);
+    routine_comparison::init_routes(cfg);
     routines::init_routes(cfg);
+    settings::init_routes(cfg);
+    templates::init_routes(cfg);
     workouts::init_routes(cfg);
+    activity_export::init_routes(cfg);
+    workout_import::init_routes(cfg);
+}
+
+// Only the routes the API had before versioning; everything added since is
+// under /api/v1 alone
+fn legacy_routes(cfg: &mut web::ServiceConfig) {
+    exercises::init_legacy_routes(cfg);
+    markers::init_legacy_routes(cfg);
+    routines::init_legacy_routes(cfg);
+    workouts::init_legacy_routes(cfg);
 }
@@ -0,0 +1,590 @@
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use log::info;
+use serde::Serialize;
+use sqlx::{PgPool, Row};
+use std::collections::{HashMap, HashSet};
+use utoipa::{OpenApi, ToSchema};
+
+use crate::errors::{ApiError, ErrorBody};
+use crate::markers::MarkerValue;
+use crate::repository::postgres::fetch_marker_settings;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ImportFormat {
+    Csv,
+    Json,
+}
+
+// What to do with a row whose date already has an entry
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OnDuplicate {
+    Skip,
+    Replace,
+    Keep,
+}
+
+impl std::str::FromStr for OnDuplicate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "skip" => Ok(OnDuplicate::Skip),
+            "replace" => Ok(OnDuplicate::Replace),
+            "keep" => Ok(OnDuplicate::Keep),
+            _ => Err("Invalid 'on_duplicate'. Must be 'skip', 'replace' or 'keep'".to_string()),
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct RowError {
+    row: usize,
+    error: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SkippedRow {
+    row: usize,
+    date: NaiveDate,
+    reason: &'static str,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ImportReport {
+    dry_run: bool,
+    total_rows: usize,
+    imported: usize,
+    replaced_dates: usize,
+    skipped: Vec<SkippedRow>,
+    errors: Vec<RowError>,
+}
+
+// A row that passed validation, numbered as in the source file
+struct ParsedRow {
+    row: usize,
+    date: NaiveDate,
+    value: MarkerValue,
+}
+
+#[derive(OpenApi)]
+#[openapi(paths(import_marker_values))]
+pub(crate) struct ApiDoc;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(import_marker_values);
+}
+
+const DATE_FORMATS: [&str; 2] = ["%Y-%m-%d", "%Y/%m/%d"];
+const DATETIME_FORMATS: [&str; 4] = [
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+];
+// Offsets as written by Apple Health and similar exports
+const OFFSET_FORMATS: [&str; 2] = ["%Y-%m-%d %H:%M:%S %z", "%Y-%m-%dT%H:%M:%S%z"];
+
+// Returns the day and, when the field carries a time, the timestamp
+fn parse_date_field(field: &str) -> Result<(NaiveDate, Option<NaiveDateTime>), String> {
+    let field = field.trim();
+    for format in DATE_FORMATS {
+        if let Ok(date) = NaiveDate::parse_from_str(field, format) {
+            return Ok((date, None));
+        }
+    }
+    for format in DATETIME_FORMATS {
+        if let Ok(timestamp) = NaiveDateTime::parse_from_str(field, format) {
+            return Ok((timestamp.date(), Some(timestamp)));
+        }
+    }
+    // Keep the local wall-clock time the reading was taken at
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(field) {
+        return Ok((timestamp.date_naive(), Some(timestamp.naive_local())));
+    }
+    for format in OFFSET_FORMATS {
+        if let Ok(timestamp) = DateTime::parse_from_str(field, format) {
+            return Ok((timestamp.date_naive(), Some(timestamp.naive_local())));
+        }
+    }
+    Err(format!("Unrecognised date '{}'", field))
+}
+
+fn parse_value_field(field: &str) -> Result<f64, String> {
+    let field = field.trim();
+    // Accept a decimal comma, as used by European locale exports
+    let normalised = if field.contains('.') {
+        field.to_string()
+    } else {
+        field.replace(',', ".")
+    };
+    normalised
+        .parse::<f64>()
+        .map_err(|_| format!("Invalid value '{}'", field))
+}
+
+fn is_date_header(header: &str) -> bool {
+    header.contains("date") || ["day", "time", "timestamp", "datetime"].contains(&header)
+}
+
+fn is_value_header(header: &str) -> bool {
+    header == "value"
+        || ["weight", "steps", "quantity", "qty", "amount", "count"]
+            .iter()
+            .any(|name| header.contains(name))
+}
+
+struct CsvColumns {
+    date: usize,
+    value: usize,
+    note: Option<usize>,
+}
+
+// Picks the date/value/note columns from a header row, honouring overrides
+fn detect_columns(
+    headers: &[String],
+    date_column: Option<&String>,
+    value_column: Option<&String>,
+) -> Result<CsvColumns, String> {
+    let find = |wanted: Option<&String>, matches: fn(&str) -> bool, kind: &str| {
+        let position = match wanted {
+            Some(name) => headers.iter().position(|h| *h == name.trim().to_lowercase()),
+            None => headers.iter().position(|h| matches(h)),
+        };
+        position.ok_or_else(|| format!("Could not find a {} column in the CSV header", kind))
+    };
+
+    Ok(CsvColumns {
+        date: find(date_column, is_date_header, "date")?,
+        value: find(value_column, is_value_header, "value")?,
+        note: headers.iter().position(|h| h == "note" || h == "notes"),
+    })
+}
+
+fn parse_csv(
+    body: &str,
+    request: &HashMap<String, String>,
+) -> Result<(usize, Vec<ParsedRow>, Vec<RowError>), String> {
+    let first_line = body.lines().next().unwrap_or_default();
+    // Semicolon-separated exports use the comma as the decimal separator
+    let delimiter = if first_line.contains(';') {
+        b';'
+    } else {
+        b','
+    };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .delimiter(delimiter)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(body.as_bytes());
+
+    let mut records = reader.records().peekable();
+
+    // A first row without a numeric second field is a header
+    let has_header = match records.peek() {
+        Some(Ok(record)) => record
+            .get(1)
+            .map(|field| parse_value_field(field).is_err())
+            .unwrap_or(true),
+        _ => false,
+    };
+
+    let columns = if has_header {
+        let header = match records.next() {
+            Some(Ok(record)) => record,
+            _ => return Err("Failed to read CSV header".to_string()),
+        };
+        let headers: Vec<String> = header.iter().map(|h| h.to_lowercase()).collect();
+        detect_columns(&headers, request.get("date_column"), request.get("value_column"))?
+    } else {
+        CsvColumns {
+            date: 0,
+            value: 1,
+            note: None,
+        }
+    };
+
+    let mut total = 0;
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for (index, record) in records.enumerate() {
+        total += 1;
+        // Row numbers are 1-based and count the header line
+        let row = index + 1 + usize::from(has_header);
+
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                errors.push(RowError {
+                    row,
+                    error: format!("Malformed CSV row: {}", e),
+                });
+                continue;
+            }
+        };
+
+        let parsed = (|| {
+            let date_field = record.get(columns.date).ok_or("Missing date field")?;
+            let value_field = record.get(columns.value).ok_or("Missing value field")?;
+            let (date, logged_at) = parse_date_field(date_field)?;
+            let value = parse_value_field(value_field)?;
+            let note = columns
+                .note
+                .and_then(|i| record.get(i))
+                .filter(|note| !note.is_empty())
+                .map(str::to_string);
+            Ok::<_, String>(ParsedRow {
+                row,
+                date,
+                value: MarkerValue {
+                    value,
+                    date: Some(date),
+                    logged_at,
+                    note,
+                },
+            })
+        })();
+
+        match parsed {
+            Ok(parsed) => rows.push(parsed),
+            Err(error) => errors.push(RowError { row, error }),
+        }
+    }
+
+    Ok((total, rows, errors))
+}
+
+fn parse_json(body: &str) -> Result<(usize, Vec<ParsedRow>, Vec<RowError>), String> {
+    let items: Vec<serde_json::Value> = serde_json::from_str(body)
+        .map_err(|e| format!("Body must be a JSON array of entries: {}", e))?;
+
+    let total = items.len();
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for (index, item) in items.into_iter().enumerate() {
+        let row = index + 1;
+        match serde_json::from_value::<MarkerValue>(item) {
+            Ok(value) => match value.entry_date() {
+                Ok(date) => rows.push(ParsedRow { row, date, value }),
+                Err(error) => errors.push(RowError { row, error }),
+            },
+            Err(e) => errors.push(RowError {
+                row,
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    Ok((total, rows, errors))
+}
+
+#[utoipa::path(
+    tag = "markers",
+    summary = "Import marker values from CSV or JSON",
+    params(
+        ("format" = Option<String>, Query, description = "csv or json, by default taken from Content-Type"),
+        ("dry_run" = Option<bool>, Query, description = "Validate without saving"),
+        ("on_duplicate" = Option<String>, Query, description = "skip, replace or keep for dates that already have a value"),
+        ("date_column" = Option<String>, Query, description = "CSV column holding the date"),
+        ("value_column" = Option<String>, Query, description = "CSV column holding the value")
+    ),
+    request_body(content(
+        (String = "text/csv"),
+        (serde_json::Value = "application/json")
+    )),
+    responses(
+        (status = 200, body = ImportReport),
+        (status = 400, description = "Unreadable file or invalid parameters", body = ErrorBody),
+        (status = 404, description = "Marker not found", body = ErrorBody),
+        (status = 422, description = "Some rows are invalid; nothing was imported", body = ImportReport)
+    )
+)]
+#[post("/markers/{marker_id}/import")]
+async fn import_marker_values(
+    pool: web::Data<PgPool>,
+    marker_id: web::Path<i32>,
+    request: web::Query<HashMap<String, String>>,
+    http_request: HttpRequest,
+    body: String,
+) -> Result<HttpResponse, ApiError> {
+    let marker_id = marker_id.into_inner();
+
+    // Explicit ?format= wins, otherwise go by Content-Type
+    let format = match request.get("format").map(|f| f.to_lowercase()) {
+        Some(f) if f == "csv" => ImportFormat::Csv,
+        Some(f) if f == "json" => ImportFormat::Json,
+        Some(_) => {
+            return Err(ApiError::bad_request(
+                "Invalid 'format' parameter. Must be 'csv' or 'json'",
+            ))
+        }
+        None => {
+            let content_type = http_request
+                .headers()
+                .get(actix_web::http::header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default();
+            if content_type.contains("json") {
+                ImportFormat::Json
+            } else {
+                ImportFormat::Csv
+            }
+        }
+    };
+
+    let dry_run = request.get("dry_run").map(|v| v == "true").unwrap_or(false);
+
+    let on_duplicate = request
+        .get("on_duplicate")
+        .map(|v| v.parse::<OnDuplicate>())
+        .unwrap_or(Ok(OnDuplicate::Skip))
+        .map_err(ApiError::bad_request)?;
+
+    let settings = fetch_marker_settings(pool.get_ref(), marker_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(format!("Marker with ID {} not found", marker_id)))?;
+    if settings.formula.is_some() {
+        return Err(ApiError::bad_request(
+            "Values of a derived marker are computed and cannot be imported",
+        ));
+    }
+
+    let parsed = match format {
+        ImportFormat::Csv => parse_csv(&body, &request),
+        ImportFormat::Json => parse_json(&body),
+    };
+    let (total_rows, rows, mut errors) = parsed.map_err(ApiError::bad_request)?;
+
+    for parsed in &rows {
+        let value = parsed.value.value;
+        if !value.is_finite() {
+            errors.push(RowError {
+                row: parsed.row,
+                error: "Value must be a finite number".to_string(),
+            });
+        } else if !(value as f32).is_finite() {
+            // Values are stored as REAL, where the cast would make this infinite
+            errors.push(RowError {
+                row: parsed.row,
+                error: format!("Value must be at most {:e} in magnitude", f32::MAX),
+            });
+        }
+    }
+    errors.sort_by_key(|e| e.row);
+
+    // Rolled back on drop unless everything is written
+    let mut tx = pool.begin().await?;
+
+    let existing_dates: HashSet<NaiveDate> =
+        sqlx::query("SELECT DISTINCT Date FROM Markers WHERE MarkerID = $1")
+            .bind(marker_id)
+            .fetch_all(&mut *tx)
+            .await?
+            .iter()
+            .map(|row| row.get("date"))
+            .collect();
+
+    let mut skipped = Vec::new();
+    let mut seen_dates = HashSet::new();
+    let mut replace_dates = Vec::new();
+    let mut to_insert = Vec::new();
+    for parsed in rows {
+        if errors.iter().any(|e| e.row == parsed.row) {
+            continue;
+        }
+        let in_database = existing_dates.contains(&parsed.date);
+        let in_import = !seen_dates.insert(parsed.date);
+        match on_duplicate {
+            OnDuplicate::Skip if in_database => skipped.push(SkippedRow {
+                row: parsed.row,
+                date: parsed.date,
+                reason: "an entry already exists for this date",
+            }),
+            OnDuplicate::Skip | OnDuplicate::Replace if in_import => skipped.push(SkippedRow {
+                row: parsed.row,
+                date: parsed.date,
+                reason: "duplicate date within the import",
+            }),
+            OnDuplicate::Replace if in_database => {
+                replace_dates.push(parsed.date);
+                to_insert.push(parsed);
+            }
+            _ => to_insert.push(parsed),
+        }
+    }
+
+    let mut report = ImportReport {
+        dry_run,
+        total_rows,
+        imported: to_insert.len(),
+        replaced_dates: replace_dates.len(),
+        skipped,
+        errors,
+    };
+
+    // Nothing is written unless every row is valid
+    if !report.errors.is_empty() {
+        report.imported = 0;
+        report.replaced_dates = 0;
+        return Ok(HttpResponse::UnprocessableEntity().json(report));
+    }
+
+    if dry_run {
+        return Ok(HttpResponse::Ok().json(report));
+    }
+
+    if !replace_dates.is_empty() {
+        sqlx::query("DELETE FROM Markers WHERE MarkerID = $1 AND Date = ANY($2)")
+            .bind(marker_id)
+            .bind(&replace_dates)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let values: Vec<f32> = to_insert.iter().map(|r| r.value.value as f32).collect();
+    let dates: Vec<NaiveDate> = to_insert.iter().map(|r| r.date).collect();
+    let logged_at: Vec<Option<NaiveDateTime>> =
+        to_insert.iter().map(|r| r.value.logged_at).collect();
+    let notes: Vec<Option<String>> = to_insert.iter().map(|r| r.value.note.clone()).collect();
+
+    // A single statement for the whole batch
+    sqlx::query(
+        "INSERT INTO Markers (MarkerID, Value, Date, LoggedAt, Note)
+         SELECT $1, * FROM UNNEST($2::REAL[], $3::DATE[], $4::TIMESTAMP[], $5::TEXT[])",
+    )
+    .bind(marker_id)
+    .bind(&values)
+    .bind(&dates)
+    .bind(&logged_at)
+    .bind(&notes)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    info!(
+        "Imported {} entries for marker {}",
+        report.imported, marker_id
+    );
+    Ok(HttpResponse::Ok().json(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+    use serde_json::json;
+
+    use crate::test_support::TestApp;
+
+    fn csv_import(uri: &str, body: &'static str) -> TestRequest {
+        TestRequest::post()
+            .uri(uri)
+            .insert_header(("content-type", "text/csv"))
+            .set_payload(body)
+    }
+
+    #[actix_web::test]
+    async fn imports_csv_with_detected_columns() {
+        let app = TestApp::new().await;
+        let marker = app.marker("Bodyweight").create().await;
+
+        let report = app
+            .send(csv_import(
+                &format!("/api/v1/markers/{}/import", marker),
+                "Date;Weight (kg);Notes\n2026-01-05;80,5;morning\n2026/01/06;80,1;\n",
+            ))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(report["total_rows"], 2);
+        assert_eq!(report["imported"], 2);
+
+        let notes: Vec<(f32, Option<String>)> =
+            sqlx::query_as("SELECT Value, Note FROM Markers ORDER BY Date")
+                .fetch_all(&app.pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            notes,
+            vec![(80.5, Some("morning".to_string())), (80.1, None)]
+        );
+    }
+
+    #[actix_web::test]
+    async fn duplicates_are_skipped_or_replaced() {
+        let app = TestApp::new().await;
+        let marker = app
+            .marker("Bodyweight")
+            .entry("2026-01-05", 81.0)
+            .create()
+            .await;
+        let entries = json!([
+            { "value": 80.0, "date": "2026-01-05" },
+            { "value": 79.5, "date": "2026-01-06" },
+        ]);
+
+        let report = app
+            .post(
+                &format!("/api/v1/markers/{}/import?dry_run=true", marker),
+                entries.clone(),
+            )
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(report["imported"], 1);
+        assert_eq!(report["skipped"][0]["row"], 1);
+        assert_eq!(app.count("Markers").await, 1);
+
+        let report = app
+            .post(
+                &format!("/api/v1/markers/{}/import?on_duplicate=replace", marker),
+                entries,
+            )
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(report["imported"], 2);
+        assert_eq!(report["replaced_dates"], 1);
+        let values: Vec<f32> = sqlx::query_scalar("SELECT Value FROM Markers ORDER BY Date")
+            .fetch_all(&app.pool)
+            .await
+            .unwrap();
+        assert_eq!(values, vec![80.0, 79.5]);
+    }
+
+    #[actix_web::test]
+    async fn invalid_rows_abort_the_import() {
+        let app = TestApp::new().await;
+        let marker = app.marker("Bodyweight").create().await;
+        let uri = format!("/api/v1/markers/{}/import", marker);
+
+        let report = app
+            .send(csv_import(
+                &uri,
+                "2026-01-05,80\nyesterday,81\n2026-01-07,abc\n2026-01-08,-1e39\n",
+            ))
+            .await
+            .expect(422)
+            .json();
+        let rows: Vec<i64> = report["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["row"].as_i64().unwrap())
+            .collect();
+        assert_eq!(rows, vec![2, 3, 4]);
+        assert_eq!(report["imported"], 0);
+        assert_eq!(app.count("Markers").await, 0);
+
+        app.send(csv_import(&format!("{}?format=xml", uri), ""))
+            .await
+            .expect(400);
+        app.send(csv_import(&format!("{}?on_duplicate=merge", uri), ""))
+            .await
+            .expect(400);
+        app.send(csv_import("/api/v1/markers/999/import", "2026-01-05,80\n"))
+            .await
+            .expect(404);
+    }
+}
@@ -1,36 +1,210 @@
 use actix_web::{delete, get, post, put, web, HttpResponse, Scope};
-use chrono::{NaiveDate, NaiveDateTime};
-use log::{error, info};
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime};
+use log::info;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use sqlx::{PgPool, Postgres, Row};
 use std::collections::HashMap;
 use std::fmt;
+use utoipa::{OpenApi, ToSchema};
 
-#[derive(Serialize, Deserialize)]
+use crate::derived::{self, FormulaError};
+use crate::errors::{ApiError, ErrorBody};
+use crate::repository::Repositories;
+use crate::service;
+use crate::validation::{plausible_date, validate, Validate, Validator, MAX_YEARS_AWAY};
+
+#[derive(Serialize, Deserialize, ToSchema)]
 struct MarkerCreate {
     name: String,
     color: String, // Hex color
+    #[serde(flatten)]
+    settings: MarkerSettings,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 struct MarkerUpdate {
     name: String,
     color: String,
+    #[serde(flatten)]
+    settings: MarkerSettings,
 }
 
-#[derive(Serialize, Deserialize)]
-struct MarkerValue {
-    value: f64,
-    date: NaiveDate,
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub(crate) struct MarkerSettings {
+    #[serde(default)]
+    pub(crate) unit: Option<String>,
+    #[serde(default = "default_precision")]
+    pub(crate) precision: i16, // Decimal places used when reporting values
+    #[serde(default)]
+    pub(crate) direction: Direction,
+    #[serde(default)]
+    pub(crate) goal_value: Option<f64>,
+    #[serde(default)]
+    pub(crate) goal_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub(crate) reading_policy: ReadingPolicy,
+    #[serde(default)]
+    pub(crate) formula: Option<String>, // Set for derived markers, see derived.rs
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub(crate) struct MarkerInfo {
+    pub(crate) marker_id: i32,
+    pub(crate) name: String,
+    pub(crate) color: Option<String>,
+    #[serde(flatten)]
+    pub(crate) settings: MarkerSettings,
+}
+
+fn default_precision() -> i16 {
+    1
+}
+
+// Whether a rising or a falling value counts as progress
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Direction {
+    #[default]
+    Higher,
+    Lower,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Direction::Higher => write!(f, "higher"),
+            Direction::Lower => write!(f, "lower"),
+        }
+    }
+}
+
+impl std::str::FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "higher" => Ok(Direction::Higher),
+            "lower" => Ok(Direction::Lower),
+            _ => Err("Invalid direction. Must be 'higher' or 'lower'".to_string()),
+        }
+    }
+}
+
+// How several readings logged on the same day are treated
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ReadingPolicy {
+    #[default]
+    Keep,
+    Replace,
+    Average,
+}
+
+impl fmt::Display for ReadingPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadingPolicy::Keep => write!(f, "keep"),
+            ReadingPolicy::Replace => write!(f, "replace"),
+            ReadingPolicy::Average => write!(f, "average"),
+        }
+    }
+}
+
+impl std::str::FromStr for ReadingPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "keep" => Ok(ReadingPolicy::Keep),
+            "replace" => Ok(ReadingPolicy::Replace),
+            "average" => Ok(ReadingPolicy::Average),
+            _ => Err("Invalid reading policy. Must be 'keep', 'replace' or 'average'".to_string()),
+        }
+    }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
+struct GoalProgress {
+    goal_value: f64,
+    goal_date: Option<NaiveDate>,
+    start_value: f64,
+    current_value: f64,
+    remaining: f64,
+    percent_complete: f64,
+    achieved: bool,
+    trend_per_day: Option<f64>,
+    projected_date: Option<NaiveDate>,
+    on_track: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub(crate) struct MarkerValue {
+    pub(crate) value: f64,
+    #[serde(default)]
+    pub(crate) date: Option<NaiveDate>, // Defaults to the day of logged_at
+    #[serde(default)]
+    pub(crate) logged_at: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub(crate) note: Option<String>,
+}
+
+impl MarkerValue {
+    pub(crate) fn entry_date(&self) -> Result<NaiveDate, String> {
+        match (self.date, self.logged_at) {
+            (Some(date), Some(logged_at)) if date != logged_at.date() => {
+                Err("'date' does not match the day of 'logged_at'".to_string())
+            }
+            (Some(date), _) => Ok(date),
+            (None, Some(logged_at)) => Ok(logged_at.date()),
+            (None, None) => Err("Either 'date' or 'logged_at' is required".to_string()),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub(crate) struct LogEntry {
+    pub(crate) entry_id: i32,
+    pub(crate) value: f64,
+    pub(crate) date: NaiveDate,
+    pub(crate) logged_at: Option<NaiveDateTime>,
+    pub(crate) note: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
 struct TimelineEntry {
     value: f64,
     date: String,
 }
 
+#[derive(Serialize, ToSchema)]
+struct TimelineResponse {
+    unit: Option<String>,
+    precision: i16,
+    direction: Direction,
+    entries: Vec<TimelineEntry>,
+    goal: Option<GoalProgress>,
+}
+
+// A marker with where it currently stands, as listed and on the dashboard.
+// Changes compare the latest value with the last one on or before 7 and 30
+// days ago.
+#[derive(Serialize, ToSchema)]
+pub(crate) struct MarkerSummary {
+    #[serde(flatten)]
+    marker: MarkerInfo,
+    latest_value: Option<f64>,
+    last_entry_date: Option<NaiveDate>,
+    change_7d: Option<f64>,
+    change_30d: Option<f64>,
+    sparkline: Vec<f64>, // Values of the last 30 days, oldest first
+}
+
+const SPARKLINE_DAYS: i64 = 30;
+
+// Formulas are parsed and evaluated recursively, so their size is capped
+const MAX_FORMULA_LEN: usize = 500;
+
 #[derive(Debug)]
 enum MetricType {
     Average,
@@ -58,315 +232,1239 @@ impl std::str::FromStr for MetricType {
     }
 }
 
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        list_markers,
+        get_marker,
+        create_marker,
+        update_marker,
+        delete_marker,
+        log_marker_value,
+        list_marker_logs,
+        get_marker_log,
+        update_marker_log,
+        delete_marker_log,
+        get_marker_analytics,
+        get_marker_timeline
+    ),
+    tags((name = "markers", description = "Body markers such as bodyweight and their logged values"))
+)]
+pub(crate) struct ApiDoc;
+
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
-    cfg.service(get_marker_by_name)
+    cfg.service(list_markers)
+        .service(get_marker)
         .service(create_marker)
         .service(update_marker)
         .service(delete_marker)
         .service(log_marker_value)
+        .service(list_marker_logs)
+        .service(get_marker_log)
+        .service(update_marker_log)
+        .service(delete_marker_log)
         .service(get_marker_analytics)
         .service(get_marker_timeline);
 }
 
+// The unversioned routes, kept as deprecated aliases
+pub fn init_legacy_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_marker_by_name)
+        .service(create_marker)
+        .service(update_marker)
+        .service(delete_marker)
+        .service(log_marker_value)
+        .service(get_marker_analytics)
+        .service(legacy_marker_timeline);
+}
+
+#[utoipa::path(
+    tag = "markers",
+    summary = "List markers with their latest values",
+    params(
+        ("name" = Option<String>, Query, description = "Only the marker with this name"),
+        ("date" = Option<NaiveDate>, Query, description = "Summarize as of this day instead of today")
+    ),
+    responses(
+        (status = 200, body = Vec<MarkerSummary>),
+        (status = 400, description = "Invalid date", body = ErrorBody)
+    )
+)]
 #[get("/markers")]
-async fn get_marker_by_name(
+async fn list_markers(
     pool: web::Data<PgPool>,
+    repos: web::Data<Repositories>,
     request: web::Query<HashMap<String, String>>,
-) -> HttpResponse {
-    let marker_name = match request.get("name") {
-        Some(name) => name,
-        None => {
-            return HttpResponse::BadRequest().json(json!({
-                "error": "marker_name parameter is required"
-            }))
-        }
+) -> Result<HttpResponse, ApiError> {
+    let today = as_of_date(&request)?;
+    let markers = match request.get("name") {
+        Some(name) => match repos.markers.find_by_name(name).await? {
+            Some(marker_id) => repos.markers.get(marker_id).await?.into_iter().collect(),
+            None => Vec::new(),
+        },
+        None => repos.markers.list().await?,
     };
+    let summaries = summarize(pool.get_ref(), &repos, markers, today).await?;
 
-    match sqlx::query("SELECT MarkerID FROM MarkerList WHERE MarkerName = $1")
-        .bind(marker_name)
-        .fetch_one(pool.get_ref())
-        .await
-    {
-        Ok(row) => {
-            let marker_id: i32 = row.get("markerid");
-            info!("Retrieved MarkerID {} for name {}", marker_id, marker_name);
-            HttpResponse::Ok().json(json!({ "marker_id": marker_id }))
-        }
-        Err(e) => {
-            error!("Failed to fetch marker ID: {}", e);
-            HttpResponse::NotFound().json(json!({
-                "error": format!("Marker with name '{}' not found", marker_name)
-            }))
-        }
-    }
+    info!("Retrieved {} markers", summaries.len());
+    Ok(HttpResponse::Ok().json(summaries))
 }
 
+#[utoipa::path(
+    tag = "markers",
+    responses(
+        (status = 200, body = MarkerInfo),
+        (status = 404, description = "Marker not found", body = ErrorBody)
+    )
+)]
+#[get("/markers/{marker_id}")]
+async fn get_marker(
+    repos: web::Data<Repositories>,
+    marker_id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let marker_id = marker_id.into_inner();
+    let marker = repos
+        .markers
+        .get(marker_id)
+        .await?
+        .ok_or_else(|| marker_not_found(marker_id))?;
+
+    Ok(HttpResponse::Ok().json(marker))
+}
+
+// Superseded by GET /markers?name=, which returns the whole marker
+#[get("/markers")]
+async fn get_marker_by_name(
+    repos: web::Data<Repositories>,
+    request: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let marker_name = request
+        .get("name")
+        .ok_or_else(|| ApiError::bad_request("marker_name parameter is required"))?;
+
+    let marker_id = repos
+        .markers
+        .find_by_name(marker_name)
+        .await?
+        .ok_or_else(|| {
+            ApiError::not_found(format!("Marker with name '{}' not found", marker_name))
+        })?;
+
+    info!("Retrieved MarkerID {} for name {}", marker_id, marker_name);
+    Ok(HttpResponse::Ok().json(json!({ "marker_id": marker_id })))
+}
+
+#[utoipa::path(
+    tag = "markers",
+    responses(
+        (status = 201, body = serde_json::Value, example = json!({ "marker_id": 1 })),
+        (status = 409, description = "Name already taken", body = ErrorBody),
+        (status = 422, description = "Invalid marker or formula", body = ErrorBody)
+    )
+)]
 #[post("/markers")]
-async fn create_marker(pool: web::Data<PgPool>, marker: web::Json<MarkerCreate>) -> HttpResponse {
-    if !marker.color.starts_with('#') || marker.color.len() != 7 {
-        return HttpResponse::BadRequest().json(json!({
-            "error": "Invalid color format. Must be a hex color (e.g., '#FF0000')"
-        }));
-    }
+async fn create_marker(
+    pool: web::Data<PgPool>,
+    repos: web::Data<Repositories>,
+    marker: web::Json<MarkerCreate>,
+) -> Result<HttpResponse, ApiError> {
+    validate(&*marker).finish()?;
+    validate_formula(pool.get_ref(), &marker.settings).await?;
 
-    match sqlx::query("INSERT INTO MarkerList (MarkerName, Clr) VALUES ($1, $2) RETURNING MarkerID")
-        .bind(&marker.name)
-        .bind(&marker.color)
-        .fetch_one(pool.get_ref())
-        .await
-    {
-        Ok(row) => {
-            let marker_id: i32 = row.get("markerid");
-            info!("Created new marker: {} with ID {}", marker.name, marker_id);
-            HttpResponse::Created().json(json!({ "marker_id": marker_id }))
-        }
-        Err(e) => {
-            error!("Failed to create marker: {}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to create marker"
-            }))
-        }
-    }
+    let marker_id = repos
+        .markers
+        .create(&marker.name, &marker.color, &marker.settings)
+        .await?;
+
+    info!("Created new marker: {} with ID {}", marker.name, marker_id);
+    Ok(HttpResponse::Created().json(json!({ "marker_id": marker_id })))
 }
 
+#[utoipa::path(
+    tag = "markers",
+    responses(
+        (status = 200, body = serde_json::Value, example = json!({ "status": "updated" })),
+        (status = 404, description = "Marker not found", body = ErrorBody),
+        (status = 422, description = "Invalid marker or formula", body = ErrorBody)
+    )
+)]
 #[put("/markers/{marker_id}")]
 async fn update_marker(
     pool: web::Data<PgPool>,
+    repos: web::Data<Repositories>,
     marker_id: web::Path<i32>,
     update: web::Json<MarkerUpdate>,
-) -> HttpResponse {
-    if !update.color.starts_with('#') || update.color.len() != 7 {
-        return HttpResponse::BadRequest().json(json!({
-            "error": "Invalid color format. Must be a hex color (e.g., '#FF0000')"
-        }));
-    }
+) -> Result<HttpResponse, ApiError> {
+    validate(&*update).finish()?;
+    validate_formula(pool.get_ref(), &update.settings).await?;
 
     let marker_id = marker_id.into_inner();
-    match sqlx::query("UPDATE MarkerList SET MarkerName = $1, Clr = $2 WHERE MarkerID = $3")
-        .bind(&update.name)
-        .bind(&update.color)
-        .bind(marker_id)
-        .execute(pool.get_ref())
-        .await
+    if !repos
+        .markers
+        .update(marker_id, &update.name, &update.color, &update.settings)
+        .await?
     {
-        Ok(_) => {
-            info!("Updated marker {}", marker_id);
-            HttpResponse::Ok().json(json!({ "status": "updated" }))
-        }
-        Err(e) => {
-            error!("Failed to update marker {}: {}", marker_id, e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": format!("Failed to update marker {}", marker_id)
-            }))
-        }
+        return Err(marker_not_found(marker_id));
     }
+
+    info!("Updated marker {}", marker_id);
+    Ok(HttpResponse::Ok().json(json!({ "status": "updated" })))
 }
 
+#[utoipa::path(
+    tag = "markers",
+    summary = "Delete a marker and its entries",
+    responses(
+        (status = 200, body = serde_json::Value, example = json!({ "status": "deleted" })),
+        (status = 404, description = "Marker not found", body = ErrorBody)
+    )
+)]
 #[delete("/markers/{marker_id}")]
-async fn delete_marker(pool: web::Data<PgPool>, marker_id: web::Path<i32>) -> HttpResponse {
+async fn delete_marker(
+    repos: web::Data<Repositories>,
+    marker_id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
     let marker_id = marker_id.into_inner();
-
-    // Delete from Markers table first
-    if let Err(e) = sqlx::query("DELETE FROM Markers WHERE MarkerID = $1")
-        .bind(marker_id)
-        .execute(pool.get_ref())
-        .await
-    {
-        error!("Failed to delete from Markers: {}", e);
-        return HttpResponse::InternalServerError().json(json!({
-            "error": "Failed to delete marker logs"
-        }));
+    if !repos.markers.delete(marker_id).await? {
+        return Err(marker_not_found(marker_id));
     }
 
-    // Then delete from MarkerList
-    match sqlx::query("DELETE FROM MarkerList WHERE MarkerID = $1")
-        .bind(marker_id)
-        .execute(pool.get_ref())
-        .await
-    {
-        Ok(_) => {
-            info!("Deleted marker {}", marker_id);
-            HttpResponse::Ok().json(json!({ "status": "deleted" }))
-        }
-        Err(e) => {
-            error!("Failed to delete from MarkerList: {}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to delete marker"
-            }))
-        }
-    }
+    info!("Deleted marker {}", marker_id);
+    Ok(HttpResponse::Ok().json(json!({ "status": "deleted" })))
 }
 
+#[utoipa::path(
+    tag = "markers",
+    summary = "Log a value",
+    responses(
+        (status = 201, body = serde_json::Value, example = json!({ "status": "logged", "entry_id": 1 })),
+        (status = 404, description = "Marker not found", body = ErrorBody),
+        (status = 422, description = "Invalid value", body = ErrorBody)
+    )
+)]
 #[post("/markers/{marker_id}/logs")]
 async fn log_marker_value(
-    pool: web::Data<PgPool>,
+    repos: web::Data<Repositories>,
     marker_id: web::Path<i32>,
     value: web::Json<MarkerValue>,
-) -> HttpResponse {
+) -> Result<HttpResponse, ApiError> {
     let marker_id = marker_id.into_inner();
-    match sqlx::query("INSERT INTO Markers (MarkerID, Value, Date) VALUES ($1, $2, $3)")
-        .bind(marker_id)
-        .bind(value.value)
-        .bind(value.date)
-        .execute(pool.get_ref())
-        .await
-    {
-        Ok(_) => {
-            info!("Logged value {} for marker {}", value.value, marker_id);
-            HttpResponse::Created().json(json!({ "status": "logged" }))
+    validate(&*value).finish()?;
+    let entry_id = service::log_marker_value(&repos, marker_id, &value).await?;
+
+    info!("Logged value {} for marker {}", value.value, marker_id);
+    Ok(HttpResponse::Created().json(json!({ "status": "logged", "entry_id": entry_id })))
+}
+
+#[utoipa::path(
+    tag = "markers",
+    summary = "Logged entries, oldest first",
+    params(
+        ("from" = Option<NaiveDate>, Query, description = "Inclusive, YYYY-MM-DD"),
+        ("to" = Option<NaiveDate>, Query, description = "Inclusive, YYYY-MM-DD")
+    ),
+    responses(
+        (status = 200, body = Vec<LogEntry>),
+        (status = 400, description = "Invalid date", body = ErrorBody)
+    )
+)]
+#[get("/markers/{marker_id}/logs")]
+async fn list_marker_logs(
+    repos: web::Data<Repositories>,
+    marker_id: web::Path<i32>,
+    request: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    // Both bounds are optional here, unlike the timeline
+    let mut bounds = [None, None];
+    for (bound, key) in bounds.iter_mut().zip(["from", "to"]) {
+        if let Some(d) = request.get(key) {
+            let date = NaiveDate::parse_from_str(d, "%Y-%m-%d").map_err(|_| {
+                ApiError::bad_request(format!("Invalid '{}' date. Format: YYYY-MM-DD", key))
+            })?;
+            *bound = Some(date);
         }
-        Err(e) => {
-            error!("Failed to log marker value: {}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to log marker value"
-            }))
+    }
+    let [start_date, end_date] = bounds;
+
+    let marker_id = marker_id.into_inner();
+    let entries = repos
+        .markers
+        .entries(marker_id, start_date, end_date)
+        .await?;
+    info!("Retrieved {} entries for marker {}", entries.len(), marker_id);
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+#[utoipa::path(
+    tag = "markers",
+    responses((status = 200, body = LogEntry), (status = 404, description = "Marker or entry not found", body = ErrorBody))
+)]
+#[get("/markers/{marker_id}/logs/{entry_id}")]
+async fn get_marker_log(
+    repos: web::Data<Repositories>,
+    path: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, ApiError> {
+    let (marker_id, entry_id) = path.into_inner();
+    let entry = repos
+        .markers
+        .entry(marker_id, entry_id)
+        .await?
+        .ok_or_else(|| entry_not_found(marker_id, entry_id))?;
+
+    Ok(HttpResponse::Ok().json(entry))
+}
+
+#[utoipa::path(
+    tag = "markers",
+    responses(
+        (status = 200, body = serde_json::Value, example = json!({ "status": "updated" })),
+        (status = 404, description = "Marker or entry not found", body = ErrorBody),
+        (status = 422, description = "Invalid value", body = ErrorBody)
+    )
+)]
+#[put("/markers/{marker_id}/logs/{entry_id}")]
+async fn update_marker_log(
+    repos: web::Data<Repositories>,
+    path: web::Path<(i32, i32)>,
+    value: web::Json<MarkerValue>,
+) -> Result<HttpResponse, ApiError> {
+    let (marker_id, entry_id) = path.into_inner();
+    validate(&*value).finish()?;
+
+    if !service::update_marker_entry(&repos, marker_id, entry_id, &value).await? {
+        return Err(entry_not_found(marker_id, entry_id));
+    }
+
+    info!("Updated entry {} of marker {}", entry_id, marker_id);
+    Ok(HttpResponse::Ok().json(json!({ "status": "updated" })))
+}
+
+#[utoipa::path(
+    tag = "markers",
+    responses(
+        (status = 200, body = serde_json::Value, example = json!({ "status": "deleted" })),
+        (status = 404, description = "Marker or entry not found", body = ErrorBody)
+    )
+)]
+#[delete("/markers/{marker_id}/logs/{entry_id}")]
+async fn delete_marker_log(
+    repos: web::Data<Repositories>,
+    path: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, ApiError> {
+    let (marker_id, entry_id) = path.into_inner();
+    if !repos.markers.delete_entry(marker_id, entry_id).await? {
+        return Err(entry_not_found(marker_id, entry_id));
+    }
+
+    info!("Deleted entry {} of marker {}", entry_id, marker_id);
+    Ok(HttpResponse::Ok().json(json!({ "status": "deleted" })))
+}
+
+fn marker_not_found(marker_id: i32) -> ApiError {
+    ApiError::not_found(format!("Marker with ID {} not found", marker_id))
+}
+
+fn entry_not_found(marker_id: i32, entry_id: i32) -> ApiError {
+    ApiError::not_found(format!(
+        "Entry {} not found for marker {}",
+        entry_id, marker_id
+    ))
+}
+
+impl MarkerSettings {
+    pub(crate) fn rules(&self, v: &mut Validator) {
+        if let Some(unit) = &self.unit {
+            v.required("unit", unit, 32);
+        }
+        v.range("precision", self.precision, 0, 6);
+        if let Some(goal_value) = self.goal_value {
+            v.check(
+                "goal_value",
+                goal_value.is_finite(),
+                "invalid",
+                "Must be a number",
+            );
+        }
+        if self.goal_date.is_some() {
+            v.present("goal_value", &self.goal_value);
+        }
+        if let Some(formula) = &self.formula {
+            v.required("formula", formula, MAX_FORMULA_LEN);
         }
     }
 }
 
-#[get("/markers/{marker_id}/analytics")]
-async fn get_marker_analytics(
-    pool: web::Data<PgPool>,
-    marker_id: web::Path<i32>,
-    request: web::Query<HashMap<String, String>>,
-) -> HttpResponse {
-    let start_date = match request
-        .get("from")
-        .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
-    {
-        Some(date) => date,
-        None => {
-            return HttpResponse::BadRequest().json(json!({
-                "error": "Invalid or missing 'from' date. Format: YYYY-MM-DD"
-            }))
+impl Validate for MarkerCreate {
+    fn rules(&self, v: &mut Validator) {
+        v.required("name", &self.name, 255);
+        v.hex_color("color", &self.color);
+        self.settings.rules(v);
+    }
+}
+
+impl Validate for MarkerUpdate {
+    fn rules(&self, v: &mut Validator) {
+        v.required("name", &self.name, 255);
+        v.hex_color("color", &self.color);
+        self.settings.rules(v);
+    }
+}
+
+impl Validate for MarkerValue {
+    fn rules(&self, v: &mut Validator) {
+        v.check(
+            "value",
+            self.value.is_finite(),
+            "invalid",
+            "Must be a number",
+        );
+        if let Err(message) = self.entry_date() {
+            v.error("date", "invalid", message);
         }
+    }
+}
+
+async fn validate_formula(pool: &PgPool, settings: &MarkerSettings) -> Result<(), ApiError> {
+    let Some(formula) = &settings.formula else {
+        return Ok(());
     };
 
-    let end_date = match request
-        .get("to")
-        .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
-    {
-        Some(date) => date,
+    let mut conn = pool.acquire().await?;
+    match derived::validate(&mut conn, formula).await {
+        Ok(()) => Ok(()),
+        Err(FormulaError::Database(e)) => Err(ApiError::Database(e)),
+        Err(e) => Err(ApiError::field("formula", "invalid_formula", e.to_string())),
+    }
+}
+
+// Logged entries for regular markers, evaluated values for derived ones.
+// Without a start date the whole history up to the end date is returned.
+async fn marker_series(
+    pool: &PgPool,
+    repos: &Repositories,
+    marker_id: i32,
+    settings: &MarkerSettings,
+    start_date: Option<NaiveDate>,
+    end_date: NaiveDate,
+) -> Result<Vec<(NaiveDate, f64)>, FormulaError> {
+    match &settings.formula {
+        Some(formula) => {
+            let start_date = start_date.unwrap_or(NaiveDate::MIN);
+            derived::evaluate_series(pool, formula, start_date, end_date).await
+        }
         None => {
-            return HttpResponse::BadRequest().json(json!({
-                "error": "Invalid or missing 'to' date. Format: YYYY-MM-DD"
-            }))
+            let policy = settings.reading_policy;
+            Ok(repos
+                .markers
+                .readings(marker_id, policy, start_date, end_date)
+                .await?)
         }
+    }
+}
+
+// Latest value minus the last one on or before `days` before today; None
+// without a value that old
+fn change_since(entries: &[(NaiveDate, f64)], today: NaiveDate, days: i64) -> Option<f64> {
+    let cutoff = today.checked_sub_signed(Duration::days(days))?;
+    let (_, then) = entries.iter().rev().find(|(date, _)| *date <= cutoff)?;
+    let (_, now) = entries.last()?;
+    Some(now - then)
+}
+
+// The markers' standing as of today, in the order given
+pub(crate) async fn summarize(
+    pool: &PgPool,
+    repos: &Repositories,
+    markers: Vec<MarkerInfo>,
+    today: NaiveDate,
+) -> Result<Vec<MarkerSummary>, ApiError> {
+    let mut summaries = Vec::with_capacity(markers.len());
+    for marker in markers {
+        let entries =
+            marker_series(pool, repos, marker.marker_id, &marker.settings, None, today).await?;
+        let precision = marker.settings.precision;
+        let round = |value: f64| round_to(value, precision);
+
+        let sparkline_start = today
+            .checked_sub_signed(Duration::days(SPARKLINE_DAYS))
+            .unwrap_or(NaiveDate::MIN);
+        summaries.push(MarkerSummary {
+            latest_value: entries.last().map(|(_, value)| round(*value)),
+            last_entry_date: entries.last().map(|(date, _)| *date),
+            change_7d: change_since(&entries, today, 7).map(round),
+            change_30d: change_since(&entries, today, 30).map(round),
+            sparkline: entries
+                .iter()
+                .filter(|(date, _)| *date > sparkline_start)
+                .map(|(_, value)| round(*value))
+                .collect(),
+            marker,
+        });
+    }
+    Ok(summaries)
+}
+
+// The optional ?date= that summaries are computed as of, today by default
+pub(crate) fn as_of_date(request: &HashMap<String, String>) -> Result<NaiveDate, ApiError> {
+    match request.get("date") {
+        Some(value) => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .ok()
+            .filter(|date| plausible_date(*date))
+            .ok_or_else(|| {
+                ApiError::bad_request(format!(
+                    "Invalid 'date' parameter. Format: YYYY-MM-DD, within {} years of today",
+                    MAX_YEARS_AWAY
+                ))
+            }),
+        None => Ok(Local::now().date_naive()),
+    }
+}
+
+fn round_to(value: f64, precision: i16) -> f64 {
+    let factor = 10f64.powi(i32::from(precision));
+    (value * factor).round() / factor
+}
+
+// Least-squares slope of value over days, in units per day
+fn trend_per_day(entries: &[(NaiveDate, f64)]) -> Option<f64> {
+    let first_date = entries.first()?.0;
+    let points: Vec<(f64, f64)> = entries
+        .iter()
+        .map(|(date, value)| ((*date - first_date).num_days() as f64, *value))
+        .collect();
+
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let covariance: f64 = points
+        .iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum();
+    let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+
+    if variance == 0.0 {
+        None
+    } else {
+        Some(covariance / variance)
+    }
+}
+
+// The entries on or after the date, from a series sorted by date
+fn since(entries: &[(NaiveDate, f64)], start_date: NaiveDate) -> &[(NaiveDate, f64)] {
+    &entries[entries.partition_point(|(date, _)| *date < start_date)..]
+}
+
+// Measured from the first value ever logged, so that it does not depend on
+// the range being viewed
+fn goal_progress(settings: &MarkerSettings, entries: &[(NaiveDate, f64)]) -> Option<GoalProgress> {
+    let goal_value = settings.goal_value?;
+    let (_, start_value) = *entries.first()?;
+    let (last_date, current_value) = *entries.last()?;
+
+    let achieved = match settings.direction {
+        Direction::Higher => current_value >= goal_value,
+        Direction::Lower => current_value <= goal_value,
     };
 
-    let metric = match request
-        .get("metric")
-        .and_then(|m| m.parse::<MetricType>().ok())
-    {
-        Some(m) => m,
-        None => {
-            return HttpResponse::BadRequest().json(json!({
-                "error": "Invalid or missing 'metric' parameter. Must be 'average' or 'sum'"
-            }))
+    let percent_complete = if achieved {
+        100.0
+    } else if goal_value == start_value {
+        0.0
+    } else {
+        ((current_value - start_value) / (goal_value - start_value) * 100.0).clamp(0.0, 100.0)
+    };
+
+    let trend = trend_per_day(entries);
+
+    // Only project forward when the trend is moving towards the goal
+    let projected_date = match trend {
+        _ if achieved => None,
+        Some(slope) if slope != 0.0 && (goal_value - current_value).signum() == slope.signum() => {
+            let days = ((goal_value - current_value) / slope).ceil() as i64;
+            last_date.checked_add_signed(chrono::Duration::days(days))
         }
+        _ => None,
     };
 
-    let query_str = match metric {
-        MetricType::Average => "SELECT AVG(Value) as result",
-        MetricType::Sum => "SELECT SUM(Value) as result",
+    let on_track = match (settings.goal_date, projected_date) {
+        _ if achieved => Some(true),
+        (Some(deadline), Some(projected)) => Some(projected <= deadline),
+        (Some(_), None) => Some(false),
+        (None, _) => None,
     };
 
-    let query_str = format!(
-        "{} FROM Markers WHERE MarkerID = $1 AND Date BETWEEN $2 AND $3",
-        query_str
-    );
+    Some(GoalProgress {
+        goal_value: round_to(goal_value, settings.precision),
+        goal_date: settings.goal_date,
+        start_value: round_to(start_value, settings.precision),
+        current_value: round_to(current_value, settings.precision),
+        remaining: round_to((goal_value - current_value).abs(), settings.precision),
+        percent_complete: round_to(percent_complete, 1),
+        achieved,
+        trend_per_day: trend.map(|slope| round_to(slope, settings.precision + 2)),
+        projected_date,
+        on_track,
+    })
+}
+
+fn required_date(request: &HashMap<String, String>, key: &str) -> Result<NaiveDate, ApiError> {
+    request
+        .get(key)
+        .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+        .ok_or_else(|| {
+            ApiError::bad_request(format!(
+                "Invalid or missing '{}' date. Format: YYYY-MM-DD",
+                key
+            ))
+        })
+}
+
+#[utoipa::path(
+    tag = "markers",
+    summary = "Average or sum of the values in a date range",
+    params(
+        ("from" = NaiveDate, Query, description = "Inclusive, YYYY-MM-DD"),
+        ("to" = NaiveDate, Query, description = "Inclusive, YYYY-MM-DD"),
+        ("metric" = String, Query, description = "average or sum")
+    ),
+    responses(
+        (status = 200, description = "The result keyed by metric name, null without values", body = serde_json::Value,
+            example = json!({ "average": 81.4, "unit": "kg", "goal": null })),
+        (status = 400, description = "Invalid dates or metric", body = ErrorBody),
+        (status = 404, description = "Marker not found", body = ErrorBody)
+    )
+)]
+#[get("/markers/{marker_id}/analytics")]
+async fn get_marker_analytics(
+    pool: web::Data<PgPool>,
+    repos: web::Data<Repositories>,
+    marker_id: web::Path<i32>,
+    request: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let start_date = required_date(&request, "from")?;
+    let end_date = required_date(&request, "to")?;
+
+    let metric = request
+        .get("metric")
+        .and_then(|m| m.parse::<MetricType>().ok())
+        .ok_or_else(|| {
+            ApiError::bad_request(
+                "Invalid or missing 'metric' parameter. Must be 'average' or 'sum'",
+            )
+        })?;
 
     let marker_id = marker_id.into_inner();
-    match sqlx::query(&query_str)
-        .bind(marker_id)
-        .bind(start_date)
-        .bind(end_date)
-        .fetch_one(pool.get_ref())
-        .await
-    {
-        Ok(row) => match metric {
-            MetricType::Sum => {
-                let result: f32 = row.get("result");
-                info!("Calculated {} for marker {}", metric, marker_id);
-                HttpResponse::Ok().json(json!({ "sum": result }))
-            }
-            MetricType::Average => {
-                let result: f64 = row.get("result");
-                info!("Calculated {} for marker {}", metric, marker_id);
-                HttpResponse::Ok().json(json!({ "average": result }))
-            }
-        },
-        Err(e) => {
-            error!("Failed to calculate analytics: {}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to calculate marker analytics"
-            }))
+    let settings = repos
+        .markers
+        .settings(marker_id)
+        .await?
+        .ok_or_else(|| marker_not_found(marker_id))?;
+
+    let history =
+        marker_series(pool.get_ref(), &repos, marker_id, &settings, None, end_date).await?;
+    let entries = since(&history, start_date);
+
+    // Aggregated here so that the reading policy applies to analytics too
+    let result = if entries.is_empty() {
+        None
+    } else {
+        let sum: f64 = entries.iter().map(|(_, value)| value).sum();
+        match metric {
+            MetricType::Sum => Some(sum),
+            MetricType::Average => Some(sum / entries.len() as f64),
         }
-    }
+    };
+
+    info!("Calculated {} for marker {}", metric, marker_id);
+    Ok(HttpResponse::Ok().json(json!({
+        metric.to_string(): result.map(|value| round_to(value, settings.precision)),
+        "unit": settings.unit,
+        "goal": goal_progress(&settings, &history),
+    })))
 }
 
+#[utoipa::path(
+    tag = "markers",
+    summary = "Values in a date range with goal progress",
+    params(
+        ("from" = NaiveDate, Query, description = "Inclusive, YYYY-MM-DD"),
+        ("to" = NaiveDate, Query, description = "Inclusive, YYYY-MM-DD")
+    ),
+    responses(
+        (status = 200, body = TimelineResponse),
+        (status = 400, description = "Invalid dates", body = ErrorBody),
+        (status = 404, description = "Marker not found", body = ErrorBody)
+    )
+)]
 #[get("/markers/{marker_id}/timeline")]
 async fn get_marker_timeline(
     pool: web::Data<PgPool>,
+    repos: web::Data<Repositories>,
     marker_id: web::Path<i32>,
     request: web::Query<HashMap<String, String>>,
-) -> HttpResponse {
-    let start_date = match request
-        .get("from")
-        .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
-    {
-        Some(date) => date,
-        None => {
-            return HttpResponse::BadRequest().json(json!({
-                "error": "Invalid or missing 'from' date. Format: YYYY-MM-DD"
-            }))
-        }
-    };
+) -> Result<HttpResponse, ApiError> {
+    let timeline =
+        marker_timeline(pool.get_ref(), &repos, marker_id.into_inner(), &request).await?;
+    Ok(HttpResponse::Ok().json(timeline))
+}
 
-    let end_date = match request
-        .get("to")
-        .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
-    {
-        Some(date) => date,
-        None => {
-            return HttpResponse::BadRequest().json(json!({
-                "error": "Invalid or missing 'to' date. Format: YYYY-MM-DD"
-            }))
-        }
-    };
+// The unversioned route keeps its original shape, the bare list of entries
+#[get("/markers/{marker_id}/timeline")]
+async fn legacy_marker_timeline(
+    pool: web::Data<PgPool>,
+    repos: web::Data<Repositories>,
+    marker_id: web::Path<i32>,
+    request: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let timeline =
+        marker_timeline(pool.get_ref(), &repos, marker_id.into_inner(), &request).await?;
+    Ok(HttpResponse::Ok().json(timeline.entries))
+}
 
-    let marker_id = marker_id.into_inner();
-    match sqlx::query(
-        "SELECT Value, Date FROM Markers
-          WHERE MarkerID = $1 AND Date BETWEEN $2 AND $3
-          ORDER BY Date ASC",
-    )
-    .bind(marker_id)
-    .bind(start_date)
-    .bind(end_date)
-    .fetch_all(pool.get_ref())
-    .await
-    {
-        Ok(rows) => {
-            let timeline: Vec<TimelineEntry> = rows
-                .iter()
-                .map(|row| TimelineEntry {
-                    value: row.get::<f32, _>("value") as f64, // Convert f32 to f64
-                    date: row
-                        .get::<NaiveDate, _>("date")
-                        .format("%Y-%m-%d")
-                        .to_string(),
-                })
-                .collect();
-
-            info!("Retrieved timeline for marker {}", marker_id);
-            HttpResponse::Ok().json(timeline)
-        }
-        Err(e) => {
-            error!("Failed to fetch marker timeline: {}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to fetch marker timeline"
-            }))
+async fn marker_timeline(
+    pool: &PgPool,
+    repos: &Repositories,
+    marker_id: i32,
+    request: &HashMap<String, String>,
+) -> Result<TimelineResponse, ApiError> {
+    let start_date = required_date(request, "from")?;
+    let end_date = required_date(request, "to")?;
+
+    let settings = repos
+        .markers
+        .settings(marker_id)
+        .await?
+        .ok_or_else(|| marker_not_found(marker_id))?;
+
+    let history = marker_series(pool, repos, marker_id, &settings, None, end_date).await?;
+    let timeline: Vec<TimelineEntry> = since(&history, start_date)
+        .iter()
+        .map(|(date, value)| TimelineEntry {
+            value: round_to(*value, settings.precision),
+            date: date.format("%Y-%m-%d").to_string(),
+        })
+        .collect();
+
+    info!("Retrieved timeline for marker {}", marker_id);
+    Ok(TimelineResponse {
+        goal: goal_progress(&settings, &history),
+        unit: settings.unit,
+        precision: settings.precision,
+        direction: settings.direction,
+        entries: timeline,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::test_support::TestApp;
+
+    #[actix_web::test]
+    async fn create_find_update_and_delete() {
+        let app = TestApp::new().await;
+
+        let body = app
+            .post(
+                "/markers",
+                json!({ "name": "Bodyweight", "color": "#336699", "unit": "kg", "direction": "lower" }),
+            )
+            .await
+            .expect(201)
+            .json();
+        let marker_id = body["marker_id"].as_i64().unwrap();
+
+        let found = app.get("/markers?name=Bodyweight").await.expect(200).json();
+        assert_eq!(found, json!({ "marker_id": marker_id }));
+        app.get("/markers?name=Waist").await.expect(404);
+        app.get("/markers").await.expect(400);
+
+        app.put(
+            &format!("/markers/{}", marker_id),
+            json!({ "name": "Weight", "color": "#336699", "precision": 2 }),
+        )
+        .await
+        .expect(200);
+        app.get("/markers?name=Weight").await.expect(200);
+        app.put(
+            "/markers/999",
+            json!({ "name": "Weight", "color": "#336699" }),
+        )
+        .await
+        .expect(404);
+
+        app.post(
+            &format!("/api/v1/markers/{}/logs", marker_id),
+            json!({ "value": 80.0, "date": "2026-01-05" }),
+        )
+        .await
+        .expect(201);
+        app.delete(&format!("/markers/{}", marker_id))
+            .await
+            .expect(200);
+        assert_eq!(app.count("Markers").await, 0);
+        app.delete(&format!("/markers/{}", marker_id))
+            .await
+            .expect(404);
+    }
+
+    #[actix_web::test]
+    async fn list_and_get_under_v1() {
+        let app = TestApp::new().await;
+        let weight = app.marker("Weight").create().await;
+        let waist = app
+            .post(
+                "/api/v1/markers",
+                json!({ "name": "Waist", "color": "#aa0000", "unit": "cm", "direction": "lower" }),
+            )
+            .await
+            .expect(201)
+            .json()["marker_id"]
+            .clone();
+
+        let list = app.get("/api/v1/markers").await.expect(200).json();
+        let names: Vec<&str> = list
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["Waist", "Weight"]);
+
+        let found = app.get("/api/v1/markers?name=Weight").await.expect(200).json();
+        assert_eq!(found.as_array().unwrap().len(), 1);
+        assert_eq!(found[0]["marker_id"], weight);
+        let none = app.get("/api/v1/markers?name=Chest").await.expect(200).json();
+        assert_eq!(none, json!([]));
+
+        let marker = app
+            .get(&format!("/api/v1/markers/{}", waist))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(marker["color"], "#aa0000");
+        assert_eq!(marker["unit"], "cm");
+        assert_eq!(marker["direction"], "lower");
+        app.get("/api/v1/markers/999").await.expect(404);
+    }
+
+    #[actix_web::test]
+    async fn list_summarizes_latest_values() {
+        let app = TestApp::new().await;
+        app.marker("Weight")
+            .entry("2026-01-01", 82.0)
+            .entry("2026-01-20", 81.0)
+            .entry("2026-01-28", 80.4)
+            .entry("2026-02-03", 80.0)
+            .create()
+            .await;
+        app.marker("Double").formula(r#"marker("Weight") * 2"#).create().await;
+        app.marker("Waist").create().await;
+
+        let list = app
+            .get("/api/v1/markers?date=2026-02-04")
+            .await
+            .expect(200)
+            .json();
+        let weight = &list[2];
+        assert_eq!(weight["name"], "Weight");
+        assert_eq!(weight["latest_value"], 80.0);
+        assert_eq!(weight["last_entry_date"], "2026-02-03");
+        assert_eq!(weight["change_7d"], -0.4);
+        assert_eq!(weight["change_30d"], -2.0);
+        assert_eq!(weight["sparkline"], json!([81.0, 80.4, 80.0]));
+
+        let double = &list[0];
+        assert_eq!(double["latest_value"], 160.0);
+        assert_eq!(double["change_30d"], -4.0);
+
+        let waist = &list[1];
+        assert_eq!(waist["latest_value"], json!(null));
+        assert_eq!(waist["change_7d"], json!(null));
+        assert_eq!(waist["sparkline"], json!([]));
+
+        // Nothing old enough to compare with
+        let list = app
+            .get("/api/v1/markers?name=Weight&date=2026-01-03")
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(list[0]["latest_value"], 82.0);
+        assert_eq!(list[0]["change_7d"], json!(null));
+        app.get("/api/v1/markers?date=yesterday").await.expect(400);
+        app.get("/api/v1/markers?date=-262143-01-05")
+            .await
+            .expect(400);
+    }
+
+    #[actix_web::test]
+    async fn create_rejects_invalid_settings() {
+        let app = TestApp::new().await;
+
+        let body = app
+            .post(
+                "/markers",
+                json!({
+                    "name": "",
+                    "color": "red",
+                    "precision": 9,
+                    "goal_date": "2026-06-01",
+                }),
+            )
+            .await
+            .expect(422)
+            .json();
+        let fields: Vec<&str> = body["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["field"].as_str().unwrap())
+            .collect();
+        assert_eq!(fields, vec!["name", "color", "precision", "goal_value"]);
+
+        let body = app
+            .post(
+                "/markers",
+                json!({ "name": "BMI", "color": "#336699", "formula": "marker(\"Missing\")" }),
+            )
+            .await
+            .expect(422)
+            .json();
+        assert_eq!(body["fields"][0]["code"], "invalid_formula");
+
+        app.marker("Bodyweight").create().await;
+        let body = app
+            .post(
+                "/markers",
+                json!({ "name": "Avg", "color": "#336699", "formula": "avg(marker(\"Bodyweight\"), 100000000)" }),
+            )
+            .await
+            .expect(422)
+            .json();
+        assert_eq!(body["fields"][0]["code"], "invalid_formula");
+
+        let nested = format!("{}1{}", "(".repeat(40), ")".repeat(40));
+        let body = app
+            .post(
+                "/markers",
+                json!({ "name": "Deep", "color": "#336699", "formula": nested }),
+            )
+            .await
+            .expect(422)
+            .json();
+        assert_eq!(body["fields"][0]["code"], "invalid_formula");
+        assert!(body["fields"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("nested too deeply"));
+        let body = app
+            .post(
+                "/markers",
+                json!({ "name": "Negated", "color": "#336699", "formula": format!("{}1", "-".repeat(40)) }),
+            )
+            .await
+            .expect(422)
+            .json();
+        assert_eq!(body["fields"][0]["code"], "invalid_formula");
+        let body = app
+            .post(
+                "/markers",
+                json!({ "name": "Long", "color": "#336699", "formula": "1+".repeat(300) + "1" }),
+            )
+            .await
+            .expect(422)
+            .json();
+        assert_eq!(body["fields"][0]["field"], "formula");
+        assert_eq!(body["fields"][0]["code"], "too_long");
+    }
+
+    #[actix_web::test]
+    async fn log_entries_crud() {
+        let app = TestApp::new().await;
+        let user = app.user().await;
+        let marker = app.marker("Waist").unit("cm").user(user).create().await;
+        let logs = format!("/api/v1/markers/{}/logs", marker);
+
+        let body = app
+            .post(
+                &logs,
+                json!({ "value": 90.5, "logged_at": "2026-01-05T07:30:00", "note": "morning" }),
+            )
+            .await
+            .expect(201)
+            .json();
+        let entry_id = body["entry_id"].as_i64().unwrap();
+        app.post(&logs, json!({ "value": 89.0, "date": "2026-01-12" }))
+            .await
+            .expect(201);
+
+        let entry = app
+            .get(&format!("{}/{}", logs, entry_id))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(entry["date"], "2026-01-05");
+        assert_eq!(entry["note"], "morning");
+
+        let list = app
+            .get(&format!("{}?from=2026-01-10", logs))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(list.as_array().unwrap().len(), 1);
+        assert_eq!(list[0]["value"], 89.0);
+        app.get(&format!("{}?to=yesterday", logs)).await.expect(400);
+
+        app.put(
+            &format!("{}/{}", logs, entry_id),
+            json!({ "value": 91.0, "date": "2026-01-06" }),
+        )
+        .await
+        .expect(200);
+        let entry = app
+            .get(&format!("{}/{}", logs, entry_id))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(entry["value"], 91.0);
+        assert_eq!(entry["date"], "2026-01-06");
+
+        app.delete(&format!("{}/{}", logs, entry_id))
+            .await
+            .expect(200);
+        app.get(&format!("{}/{}", logs, entry_id)).await.expect(404);
+        app.delete(&format!("{}/{}", logs, entry_id))
+            .await
+            .expect(404);
+    }
+
+    #[actix_web::test]
+    async fn log_rejects_bad_values() {
+        let app = TestApp::new().await;
+        let marker = app.marker("Bodyweight").create().await;
+        let derived = app
+            .marker("Double")
+            .formula("marker(\"Bodyweight\") * 2")
+            .create()
+            .await;
+
+        let body = app
+            .post(
+                &format!("/api/v1/markers/{}/logs", marker),
+                json!({ "value": 80.0, "date": "2026-01-05", "logged_at": "2026-01-06T08:00:00" }),
+            )
+            .await
+            .expect(422)
+            .json();
+        assert_eq!(body["fields"][0]["field"], "date");
+        app.post(
+            &format!("/api/v1/markers/{}/logs", derived),
+            json!({ "value": 80.0, "date": "2026-01-05" }),
+        )
+        .await
+        .expect(422);
+        app.post(
+            "/markers/999/logs",
+            json!({ "value": 80.0, "date": "2026-01-05" }),
+        )
+        .await
+        .expect(404);
+    }
+
+    #[actix_web::test]
+    async fn replace_policy_keeps_one_reading_per_day() {
+        let app = TestApp::new().await;
+        let body = app
+            .post(
+                "/markers",
+                json!({ "name": "Resting HR", "color": "#FF0000", "reading_policy": "replace" }),
+            )
+            .await
+            .expect(201)
+            .json();
+        let logs = format!("/api/v1/markers/{}/logs", body["marker_id"]);
+
+        for value in [60.0, 58.0] {
+            app.post(&logs, json!({ "value": value, "date": "2026-01-05" }))
+                .await
+                .expect(201);
         }
+        let list = app.get(&logs).await.expect(200).json();
+        assert_eq!(list.as_array().unwrap().len(), 1);
+        assert_eq!(list[0]["value"], 58.0);
+
+        // Moving another day's reading onto that date replaces it too
+        let moved = app
+            .post(&logs, json!({ "value": 62.0, "date": "2026-01-06" }))
+            .await
+            .expect(201)
+            .json();
+        app.put(
+            &format!("{}/{}", logs, moved["entry_id"]),
+            json!({ "value": 62.0, "date": "2026-01-05" }),
+        )
+        .await
+        .expect(200);
+        let list = app.get(&logs).await.expect(200).json();
+        assert_eq!(list.as_array().unwrap().len(), 1);
+        assert_eq!(list[0]["entry_id"], moved["entry_id"]);
+    }
+
+    #[actix_web::test]
+    async fn switching_to_replace_counts_the_latest_reading() {
+        let app = TestApp::new().await;
+        let marker = app
+            .marker("Resting HR")
+            .entry("2026-01-05", 60.0)
+            .entry("2026-01-05", 58.0)
+            .entry("2026-01-06", 57.0)
+            .create()
+            .await;
+        app.put(
+            &format!("/markers/{}", marker),
+            json!({ "name": "Resting HR", "color": "#FF0000", "reading_policy": "replace" }),
+        )
+        .await
+        .expect(200);
+
+        let timeline = app
+            .get(&format!(
+                "/api/v1/markers/{}/timeline?from=2026-01-01&to=2026-01-31",
+                marker
+            ))
+            .await
+            .expect(200)
+            .json();
+        let values: Vec<f64> = timeline["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["value"].as_f64().unwrap())
+            .collect();
+        assert_eq!(values, vec![58.0, 57.0]);
+    }
+
+    #[actix_web::test]
+    async fn analytics_and_timeline_with_goal() {
+        let app = TestApp::new().await;
+        let marker = app
+            .marker("Bodyweight")
+            .unit("kg")
+            .direction("lower")
+            .entry("2026-01-01", 90.0)
+            .entry("2026-01-11", 88.0)
+            .entry("2026-01-21", 86.0)
+            .create()
+            .await;
+        sqlx::query(
+            "UPDATE MarkerList SET GoalValue = 80, GoalDate = '2026-06-01' WHERE MarkerID = $1",
+        )
+        .bind(marker)
+        .execute(&app.pool)
+        .await
+        .unwrap();
+        let range = "from=2026-01-01&to=2026-01-31";
+
+        let sum = app
+            .get(&format!(
+                "/markers/{}/analytics?{}&metric=sum",
+                marker, range
+            ))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(sum["sum"], 264.0);
+        assert_eq!(sum["unit"], "kg");
+        let average = app
+            .get(&format!(
+                "/markers/{}/analytics?{}&metric=average",
+                marker, range
+            ))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(average["average"], 88.0);
+        assert_eq!(average["goal"]["remaining"], 6.0);
+        assert_eq!(average["goal"]["percent_complete"], 40.0);
+        assert_eq!(average["goal"]["projected_date"], "2026-02-20");
+        assert_eq!(average["goal"]["on_track"], true);
+        app.get(&format!("/markers/{}/analytics?{}", marker, range))
+            .await
+            .expect(400);
+
+        let timeline = app
+            .get(&format!("/api/v1/markers/{}/timeline?{}", marker, range))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(timeline["direction"], "lower");
+        let entries = json!([
+            { "value": 90.0, "date": "2026-01-01" },
+            { "value": 88.0, "date": "2026-01-11" },
+            { "value": 86.0, "date": "2026-01-21" },
+        ]);
+        assert_eq!(timeline["entries"], entries);
+        // The unversioned route still returns just the entries
+        let legacy = app
+            .get(&format!("/markers/{}/timeline?{}", marker, range))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(legacy, entries);
+
+        // Goal progress does not depend on the range viewed
+        let later = app
+            .get(&format!(
+                "/api/v1/markers/{}/timeline?from=2026-01-15&to=2026-01-31",
+                marker
+            ))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(later["entries"].as_array().unwrap().len(), 1);
+        assert_eq!(later["goal"], timeline["goal"]);
+        assert_eq!(later["goal"]["start_value"], 90.0);
+
+        app.get(&format!("/markers/999/timeline?{}", range))
+            .await
+            .expect(404);
+        app.get(&format!("/markers/{}/timeline?from=2026-01-01", marker))
+            .await
+            .expect(400);
+    }
+
+    #[actix_web::test]
+    async fn derived_marker_timeline() {
+        let app = TestApp::new().await;
+        app.marker("Bodyweight")
+            .entry("2026-01-01", 80.0)
+            .entry("2026-01-03", 82.0)
+            .create()
+            .await;
+        let body = app
+            .post(
+                "/markers",
+                json!({ "name": "Double", "color": "#336699", "formula": "marker(\"Bodyweight\") * 2" }),
+            )
+            .await
+            .expect(201)
+            .json();
+
+        let timeline = app
+            .get(&format!(
+                "/api/v1/markers/{}/timeline?from=2026-01-01&to=2026-01-03",
+                body["marker_id"]
+            ))
+            .await
+            .expect(200)
+            .json();
+        let values: Vec<f64> = timeline["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["value"].as_f64().unwrap())
+            .collect();
+        assert_eq!(values, vec![160.0, 164.0]);
     }
 }
@@ -0,0 +1,288 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{get, web, HttpResponse};
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use utoipa::OpenApi;
+
+// Upper bounds in seconds of the request latency histogram buckets
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+// Requests that match no route share one label so that scanners probing
+// random paths cannot grow the series without bound
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+#[derive(Clone, Copy)]
+pub enum WorkoutSource {
+    Api,     // POST /workouts
+    Import,  // POST /imports/workouts
+    Restore, // POST /import
+}
+
+const WORKOUT_SOURCES: [WorkoutSource; 3] = [
+    WorkoutSource::Api,
+    WorkoutSource::Import,
+    WorkoutSource::Restore,
+];
+
+impl WorkoutSource {
+    fn label(self) -> &'static str {
+        match self {
+            WorkoutSource::Api => "api",
+            WorkoutSource::Import => "import",
+            WorkoutSource::Restore => "restore",
+        }
+    }
+}
+
+// Process-wide counters, shared with handlers as web::Data<Metrics> and
+// rendered in the Prometheus text format by GET /metrics
+#[derive(Default)]
+pub struct Metrics {
+    routes: Mutex<BTreeMap<(String, String), RouteStats>>,
+    workouts_logged: [AtomicU64; 3],
+    prs_set: AtomicU64,
+}
+
+#[derive(Default)]
+struct RouteStats {
+    statuses: BTreeMap<u16, u64>,
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Metrics {
+    pub fn observe_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let mut routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        let stats = routes
+            .entry((route.to_string(), method.to_string()))
+            .or_default();
+
+        *stats.statuses.entry(status).or_insert(0) += 1;
+        for (bucket, bound) in stats.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        stats.sum += seconds;
+        stats.count += 1;
+    }
+
+    pub fn workouts_logged(&self, source: WorkoutSource, count: u64) {
+        self.workouts_logged[source as usize].fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn prs_set(&self, count: u64) {
+        self.prs_set.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn render(&self, pool: &PgPool) -> String {
+        let mut out = String::new();
+
+        {
+            let routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+
+            header(
+                &mut out,
+                "strongeryou_http_requests_total",
+                "counter",
+                "HTTP requests handled, by route pattern and status.",
+            );
+            for ((route, method), stats) in routes.iter() {
+                for (status, count) in &stats.statuses {
+                    let _ = writeln!(
+                        out,
+                        "strongeryou_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                        method,
+                        escape(route),
+                        status,
+                        count
+                    );
+                }
+            }
+
+            header(
+                &mut out,
+                "strongeryou_http_request_duration_seconds",
+                "histogram",
+                "Time from receiving a request to having its response, by route pattern.",
+            );
+            for ((route, method), stats) in routes.iter() {
+                let labels = format!("method=\"{}\",route=\"{}\"", method, escape(route));
+                for (count, bound) in stats.buckets.iter().zip(LATENCY_BUCKETS) {
+                    let _ = writeln!(
+                        out,
+                        "strongeryou_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                        labels, bound, count
+                    );
+                }
+                let _ = writeln!(
+                    out,
+                    "strongeryou_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                    labels, stats.count
+                );
+                let _ = writeln!(
+                    out,
+                    "strongeryou_http_request_duration_seconds_sum{{{}}} {}",
+                    labels, stats.sum
+                );
+                let _ = writeln!(
+                    out,
+                    "strongeryou_http_request_duration_seconds_count{{{}}} {}",
+                    labels, stats.count
+                );
+            }
+        }
+
+        let idle = pool.num_idle() as u32;
+        header(
+            &mut out,
+            "strongeryou_db_pool_connections",
+            "gauge",
+            "Open database connections, by whether they are checked out.",
+        );
+        let _ = writeln!(
+            out,
+            "strongeryou_db_pool_connections{{state=\"idle\"}} {}",
+            idle
+        );
+        let _ = writeln!(
+            out,
+            "strongeryou_db_pool_connections{{state=\"in_use\"}} {}",
+            pool.size().saturating_sub(idle)
+        );
+        header(
+            &mut out,
+            "strongeryou_db_pool_max_connections",
+            "gauge",
+            "Configured maximum size of the database pool.",
+        );
+        let _ = writeln!(
+            out,
+            "strongeryou_db_pool_max_connections {}",
+            pool.options().get_max_connections()
+        );
+
+        header(
+            &mut out,
+            "strongeryou_workouts_logged_total",
+            "counter",
+            "Workouts saved, by how they were logged.",
+        );
+        for source in WORKOUT_SOURCES {
+            let _ = writeln!(
+                out,
+                "strongeryou_workouts_logged_total{{source=\"{}\"}} {}",
+                source.label(),
+                self.workouts_logged[source as usize].load(Ordering::Relaxed)
+            );
+        }
+        header(
+            &mut out,
+            "strongeryou_prs_set_total",
+            "counter",
+            "Exercises logged with a new heaviest weight or estimated 1RM.",
+        );
+        let _ = writeln!(
+            out,
+            "strongeryou_prs_set_total {}",
+            self.prs_set.load(Ordering::Relaxed)
+        );
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// Middleware recording every request under its route pattern, e.g.
+// "/workouts/{workout_id}", rather than the raw path
+pub async fn track_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let metrics = req.app_data::<web::Data<Metrics>>().cloned();
+    let method = req.method().to_string();
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| UNMATCHED_ROUTE.to_string());
+    let start = Instant::now();
+
+    let result = next.call(req).await;
+
+    if let Some(metrics) = metrics {
+        let status = match &result {
+            Ok(res) => res.status(),
+            Err(e) => e.as_response_error().status_code(),
+        };
+        metrics.observe_request(&method, &route, status.as_u16(), start.elapsed());
+    }
+    result
+}
+
+#[derive(OpenApi)]
+#[openapi(paths(scrape_metrics))]
+pub(crate) struct ApiDoc;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(scrape_metrics);
+}
+
+#[utoipa::path(
+    tag = "health",
+    summary = "Prometheus metrics",
+    responses((status = 200, content_type = "text/plain; version=0.0.4", body = String))
+)]
+#[get("/metrics")]
+async fn scrape_metrics(pool: web::Data<PgPool>, metrics: web::Data<Metrics>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .body(metrics.render(pool.get_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::TestApp;
+
+    #[actix_web::test]
+    async fn counts_requests_by_route_pattern() {
+        let app = TestApp::new().await;
+        app.get("/api/v1/markers/1/logs/2").await.expect(404);
+        app.get("/api/v1/markers/3/logs/4").await.expect(404);
+        app.get("/no/such/route").await.expect(404);
+
+        let response = app.get("/metrics").await.expect(200);
+        assert_eq!(
+            response.header("content-type"),
+            Some("text/plain; version=0.0.4; charset=utf-8")
+        );
+        let text = response.text();
+        for line in [
+            "strongeryou_http_requests_total{method=\"GET\",route=\"/api/v1/markers/{marker_id}/logs/{entry_id}\",status=\"404\"} 2\n",
+            "strongeryou_http_requests_total{method=\"GET\",route=\"unmatched\",status=\"404\"} 1\n",
+            "strongeryou_db_pool_max_connections 4\n",
+            "strongeryou_prs_set_total 0\n",
+        ] {
+            assert!(text.contains(line), "missing {:?} in:\n{}", line, text);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+// OpenAPI 3 document assembled from the #[utoipa::path] annotations on the
+// handlers. Each module lists its own operations in an ApiDoc next to
+// init_routes; they are merged here. The committed copy in openapi.json is
+// what the frontend types are generated from, and the test below fails when
+// it is out of date. Regenerate it with `backend openapi -o openapi.json`.
+// Only the /api/v1 routes are documented, not the deprecated aliases.
+use actix_web::web;
+use utoipa::openapi::OpenApi as Document;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
+use crate::{
+    activity_export, calendar, dashboard, exercises, export, health, marker_import, markers,
+    metrics, programs, routine_comparison, routines, settings, templates, versioning,
+    workout_import, workouts,
+};
+
+#[derive(OpenApi)]
+#[openapi(info(
+    title = "StrongerYou API",
+    description = "Workouts, routines, programs and body markers"
+))]
+struct ApiDoc;
+
+pub fn document() -> Document {
+    let mut doc = ApiDoc::openapi();
+    doc.merge(health::ApiDoc::openapi());
+    doc.merge(metrics::ApiDoc::openapi());
+    for part in [
+        activity_export::ApiDoc::openapi(),
+        calendar::ApiDoc::openapi(),
+        dashboard::ApiDoc::openapi(),
+        exercises::ApiDoc::openapi(),
+        export::ApiDoc::openapi(),
+        marker_import::ApiDoc::openapi(),
+        markers::ApiDoc::openapi(),
+        programs::ApiDoc::openapi(),
+        routine_comparison::ApiDoc::openapi(),
+        routines::ApiDoc::openapi(),
+        settings::ApiDoc::openapi(),
+        templates::ApiDoc::openapi(),
+        workout_import::ApiDoc::openapi(),
+        workouts::ApiDoc::openapi(),
+    ] {
+        doc = doc.nest(versioning::PREFIX, part);
+    }
+    doc
+}
+
+// Serves the document at /openapi.json and Swagger UI at /docs/
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(SwaggerUi::new("/docs/{_:.*}").url("/openapi.json", document()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::document;
+    use crate::test_support::TestApp;
+
+    const COMMITTED: &str = include_str!("../openapi.json");
+
+    #[test]
+    fn committed_spec_is_up_to_date() {
+        let generated = document().to_pretty_json().unwrap() + "\n";
+        assert!(
+            generated == COMMITTED,
+            "openapi.json is out of date; regenerate it with `cargo run -- openapi -o openapi.json`"
+        );
+    }
+
+    #[actix_web::test]
+    async fn serves_spec_and_docs() {
+        let app = TestApp::new().await;
+
+        let spec = app.get("/openapi.json").await.expect(200).json();
+        assert_eq!(spec["openapi"], "3.1.0");
+        assert!(spec["paths"]["/api/v1/workouts"]["post"].is_object());
+        assert!(spec["components"]["schemas"]["WorkoutData"].is_object());
+
+        let docs = app.get("/docs/").await.expect(200).text();
+        assert!(docs.contains("swagger"));
+    }
+}
@@ -0,0 +1,924 @@
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use chrono::{Datelike, Duration, Local, NaiveDate, Weekday};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{PgPool, Postgres, Row, Transaction};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::errors::{ApiError, ErrorBody};
+use crate::repository::Repositories;
+use crate::validation::{validate, Validate, Validator};
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub(crate) struct ProgramCreate {
+    pub(crate) name: String,
+    pub(crate) schedule_type: ScheduleType,
+    pub(crate) start_date: NaiveDate,
+    pub(crate) weeks: i16,
+    #[serde(default)]
+    pub(crate) deload_weeks: Vec<i16>, // 1-based week numbers
+    pub(crate) slots: Vec<ProgramSlot>,
+}
+
+// Weekly programs give each slot a weekday; rotations repeat the slots day by
+// day in order. A slot without a routine is a rest day.
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub(crate) struct ProgramSlot {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, example = "Mon")]
+    pub(crate) weekday: Option<Weekday>,
+    pub(crate) routine_id: Option<i32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ScheduleType {
+    Weekly,
+    Rotation,
+}
+
+impl fmt::Display for ScheduleType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScheduleType::Weekly => write!(f, "weekly"),
+            ScheduleType::Rotation => write!(f, "rotation"),
+        }
+    }
+}
+
+impl std::str::FromStr for ScheduleType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "weekly" => Ok(ScheduleType::Weekly),
+            "rotation" => Ok(ScheduleType::Rotation),
+            _ => Err("Invalid schedule type. Must be 'weekly' or 'rotation'".to_string()),
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct Program {
+    pub(crate) program_id: i32,
+    pub(crate) name: String,
+    pub(crate) schedule_type: ScheduleType,
+    start_date: NaiveDate,
+    pub(crate) end_date: NaiveDate,
+    pub(crate) weeks: i16,
+    pub(crate) deload_weeks: Vec<i16>,
+    pub(crate) slots: Vec<ProgramSlot>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct PlannedSession {
+    pub(crate) date: NaiveDate,
+    pub(crate) week: i16,
+    pub(crate) deload: bool,
+    pub(crate) routine_id: i32,
+    pub(crate) routine_name: String,
+    pub(crate) workout_id: Option<i32>, // The logged workout that fulfilled it
+}
+
+#[derive(Serialize, ToSchema)]
+struct TodaySession {
+    program_id: i32,
+    program_name: String,
+    #[serde(flatten)]
+    session: PlannedSession,
+}
+
+#[derive(Serialize, Default, ToSchema)]
+struct WeekAdherence {
+    week: i16,
+    deload: bool,
+    planned: usize,
+    completed: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+struct UnscheduledWorkout {
+    workout_id: i32,
+    date: NaiveDate,
+    routine_id: i32,
+}
+
+#[derive(Serialize, ToSchema)]
+struct AdherenceReport {
+    program_id: i32,
+    from: NaiveDate,
+    to: NaiveDate,
+    planned: usize,
+    completed: usize,
+    missed: usize,
+    adherence_percent: Option<f64>,
+    current_streak: usize, // Planned sessions completed in a row, most recent first
+    weeks: Vec<WeekAdherence>,
+    missed_sessions: Vec<PlannedSession>,
+    unscheduled_workouts: Vec<UnscheduledWorkout>,
+}
+
+impl Program {
+    // The week number, deload flag and routine planned on a date, if any
+    fn planned_on(&self, date: NaiveDate) -> Option<(i16, bool, i32)> {
+        if date < self.start_date || date > self.end_date || self.slots.is_empty() {
+            return None;
+        }
+        let offset = (date - self.start_date).num_days();
+        let week = (offset / 7 + 1) as i16;
+        let routine_id = match self.schedule_type {
+            ScheduleType::Weekly => self
+                .slots
+                .iter()
+                .find(|slot| slot.weekday == Some(date.weekday()))
+                .and_then(|slot| slot.routine_id),
+            ScheduleType::Rotation => {
+                self.slots[(offset % self.slots.len() as i64) as usize].routine_id
+            }
+        }?;
+        Some((week, self.deload_weeks.contains(&week), routine_id))
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        get_today,
+        list_programs,
+        create_program,
+        get_program,
+        update_program,
+        delete_program,
+        get_schedule,
+        get_adherence
+    ),
+    tags((name = "programs", description = "Multi-week plans that schedule routines"))
+)]
+pub(crate) struct ApiDoc;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    // Registered before /programs/{program_id} so "today" is not taken for an ID
+    cfg.service(get_today)
+        .service(list_programs)
+        .service(create_program)
+        .service(get_program)
+        .service(update_program)
+        .service(delete_program)
+        .service(get_schedule)
+        .service(get_adherence);
+}
+
+impl Validate for ProgramCreate {
+    fn rules(&self, v: &mut Validator) {
+        v.required("name", &self.name, 255);
+        v.date("start_date", self.start_date);
+        schedule_rules(
+            v,
+            self.schedule_type,
+            self.weeks,
+            &self.deload_weeks,
+            &self.slots,
+        );
+    }
+}
+
+// Rules on the schedule alone, shared with program templates
+pub(crate) fn schedule_rules(
+    v: &mut Validator,
+    schedule_type: ScheduleType,
+    weeks: i16,
+    deload_weeks: &[i16],
+    slots: &[ProgramSlot],
+) {
+    v.range("weeks", weeks, 1, 104);
+    for (i, week) in deload_weeks.iter().enumerate() {
+        if !(1..=weeks).contains(week) {
+            v.error(
+                &format!("deload_weeks[{}]", i),
+                "out_of_range",
+                format!("Week {} is outside the program's {} weeks", week, weeks),
+            );
+        }
+    }
+    v.check(
+        "slots",
+        slots.iter().any(|slot| slot.routine_id.is_some()),
+        "required",
+        "A program needs at least one routine",
+    );
+
+    let mut weekdays = HashSet::new();
+    for (i, slot) in slots.iter().enumerate() {
+        v.nested(&format!("slots[{}]", i), |v| {
+            if let Some(routine_id) = slot.routine_id {
+                v.routine_ref("routine_id", routine_id);
+            }
+            match (schedule_type, slot.weekday) {
+                (ScheduleType::Weekly, None) => v.error(
+                    "weekday",
+                    "required",
+                    "Every slot of a weekly program needs a weekday",
+                ),
+                (ScheduleType::Weekly, Some(weekday)) if !weekdays.insert(weekday) => v.error(
+                    "weekday",
+                    "duplicate",
+                    format!("{} is scheduled more than once", weekday),
+                ),
+                (ScheduleType::Rotation, Some(_)) => v.error(
+                    "weekday",
+                    "not_allowed",
+                    "Rotation slots follow each other day by day and take no weekday",
+                ),
+                _ => {}
+            }
+        });
+    }
+}
+
+fn program_from_row(row: &sqlx::postgres::PgRow, slots: Vec<ProgramSlot>) -> Program {
+    let start_date: NaiveDate = row.get("startdate");
+    let weeks: i16 = row.get("weeks");
+    Program {
+        program_id: row.get("programid"),
+        name: row.get("programname"),
+        schedule_type: row
+            .get::<String, _>("scheduletype")
+            .parse()
+            .unwrap_or(ScheduleType::Weekly),
+        start_date,
+        end_date: start_date
+            .checked_add_signed(Duration::days(i64::from(weeks) * 7 - 1))
+            .unwrap_or(NaiveDate::MAX),
+        weeks,
+        deload_weeks: row.get("deloadweeks"),
+        slots,
+    }
+}
+
+// All programs, or just one when an ID is given
+pub(crate) async fn fetch_programs(
+    pool: &PgPool,
+    program_id: Option<i32>,
+) -> Result<Vec<Program>, sqlx::Error> {
+    let mut slots: HashMap<i32, Vec<ProgramSlot>> = HashMap::new();
+    for row in sqlx::query(
+        "SELECT ProgramID, Weekday, RoutineID FROM ProgramSlots
+         WHERE ($1::INTEGER IS NULL OR ProgramID = $1)
+         ORDER BY ProgramID, Position",
+    )
+    .bind(program_id)
+    .fetch_all(pool)
+    .await?
+    {
+        slots
+            .entry(row.get("programid"))
+            .or_default()
+            .push(ProgramSlot {
+                weekday: row
+                    .get::<Option<i16>, _>("weekday")
+                    .and_then(|day| Weekday::try_from(day as u8).ok()),
+                routine_id: row.get("routineid"),
+            });
+    }
+
+    Ok(sqlx::query(
+        "SELECT ProgramID, ProgramName, ScheduleType, StartDate, Weeks, DeloadWeeks
+         FROM Programs
+         WHERE ($1::INTEGER IS NULL OR ProgramID = $1)
+         ORDER BY StartDate, ProgramID",
+    )
+    .bind(program_id)
+    .fetch_all(pool)
+    .await?
+    .iter()
+    .map(|row| {
+        let id: i32 = row.get("programid");
+        program_from_row(row, slots.remove(&id).unwrap_or_default())
+    })
+    .collect())
+}
+
+// Planned sessions between two dates, each matched against logged workouts of
+// the planned routine on the same day
+pub(crate) async fn planned_sessions(
+    pool: &PgPool,
+    program: &Program,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<PlannedSession>, sqlx::Error> {
+    let from = from.max(program.start_date);
+    let to = to.min(program.end_date);
+    if from > to {
+        return Ok(Vec::new());
+    }
+
+    let days: Vec<(NaiveDate, (i16, bool, i32))> = from
+        .iter_days()
+        .take_while(|date| *date <= to)
+        .filter_map(|date| program.planned_on(date).map(|planned| (date, planned)))
+        .collect();
+    let routine_ids: Vec<i32> = days.iter().map(|(_, (_, _, id))| *id).collect();
+
+    let routine_names: HashMap<i32, String> =
+        sqlx::query("SELECT RoutineID, RoutineName FROM Routines WHERE RoutineID = ANY($1)")
+            .bind(&routine_ids)
+            .fetch_all(pool)
+            .await?
+            .iter()
+            .map(|row| (row.get("routineid"), row.get("routinename")))
+            .collect();
+
+    let workouts = logged_workouts(pool, &routine_ids, from, to).await?;
+    let mut completed: HashMap<(i32, NaiveDate), i32> = HashMap::new();
+    for workout in workouts {
+        completed
+            .entry((workout.routine_id, workout.date))
+            .or_insert(workout.workout_id);
+    }
+
+    Ok(days
+        .into_iter()
+        .map(|(date, (week, deload, routine_id))| PlannedSession {
+            date,
+            week,
+            deload,
+            routine_id,
+            routine_name: routine_names.get(&routine_id).cloned().unwrap_or_default(),
+            workout_id: completed.get(&(routine_id, date)).copied(),
+        })
+        .collect())
+}
+
+async fn logged_workouts(
+    pool: &PgPool,
+    routine_ids: &[i32],
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<UnscheduledWorkout>, sqlx::Error> {
+    Ok(sqlx::query(
+        "SELECT WorkoutID, RoutineID, Start::DATE AS day FROM Workout
+         WHERE RoutineID = ANY($1) AND Start::DATE BETWEEN $2 AND $3
+         ORDER BY Start",
+    )
+    .bind(routine_ids)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await?
+    .iter()
+    .map(|row| UnscheduledWorkout {
+        workout_id: row.get("workoutid"),
+        date: row.get("day"),
+        routine_id: row.get("routineid"),
+    })
+    .collect())
+}
+
+// Creates a validated program and returns its ID
+pub(crate) async fn insert_program(
+    tx: &mut Transaction<'_, Postgres>,
+    program: &ProgramCreate,
+) -> Result<i32, sqlx::Error> {
+    let program_id: i32 = sqlx::query(
+        "INSERT INTO Programs (ProgramName, ScheduleType, StartDate, Weeks, DeloadWeeks)
+         VALUES ($1, $2, $3, $4, $5) RETURNING ProgramID",
+    )
+    .bind(program.name.trim())
+    .bind(program.schedule_type.to_string())
+    .bind(program.start_date)
+    .bind(program.weeks)
+    .bind(&program.deload_weeks)
+    .fetch_one(&mut **tx)
+    .await?
+    .get("programid");
+
+    insert_slots(tx, program_id, &program.slots).await?;
+    Ok(program_id)
+}
+
+async fn insert_slots(
+    tx: &mut Transaction<'_, Postgres>,
+    program_id: i32,
+    slots: &[ProgramSlot],
+) -> Result<(), sqlx::Error> {
+    for (position, slot) in slots.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO ProgramSlots (ProgramID, Position, Weekday, RoutineID)
+             VALUES ($1, $2, $3, $4)",
+        )
+        .bind(program_id)
+        .bind(position as i16)
+        .bind(slot.weekday.map(|day| day.num_days_from_monday() as i16))
+        .bind(slot.routine_id)
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+fn date_param(
+    request: &HashMap<String, String>,
+    name: &str,
+) -> Result<Option<NaiveDate>, ApiError> {
+    request
+        .get(name)
+        .map(|value| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| {
+                ApiError::bad_request(format!("Invalid '{}' date. Use YYYY-MM-DD", name))
+            })
+        })
+        .transpose()
+}
+
+fn program_not_found(program_id: i32) -> ApiError {
+    ApiError::not_found(format!("Program with ID {} not found", program_id))
+}
+
+async fn fetch_program(pool: &PgPool, program_id: i32) -> Result<Program, ApiError> {
+    fetch_programs(pool, Some(program_id))
+        .await?
+        .pop()
+        .ok_or_else(|| program_not_found(program_id))
+}
+
+#[utoipa::path(
+    tag = "programs",
+    responses(
+        (status = 201, body = serde_json::Value, example = json!({ "program_id": 1 })),
+        (status = 422, description = "Invalid program or unknown routine", body = ErrorBody)
+    )
+)]
+#[post("/programs")]
+async fn create_program(
+    pool: web::Data<PgPool>,
+    repos: web::Data<Repositories>,
+    program: web::Json<ProgramCreate>,
+) -> Result<HttpResponse, ApiError> {
+    validate(&*program)
+        .routines_exist(repos.routines.as_ref())
+        .await?
+        .finish()?;
+
+    let mut tx = pool.begin().await?;
+    let program_id = insert_program(&mut tx, &program).await?;
+    tx.commit().await?;
+
+    info!("Created program {} with ID {}", program.name, program_id);
+    Ok(HttpResponse::Created().json(json!({ "program_id": program_id })))
+}
+
+#[utoipa::path(
+    tag = "programs",
+    responses((status = 200, body = Vec<Program>))
+)]
+#[get("/programs")]
+async fn list_programs(pool: web::Data<PgPool>) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok().json(fetch_programs(pool.get_ref(), None).await?))
+}
+
+#[utoipa::path(
+    tag = "programs",
+    responses(
+        (status = 200, body = Program),
+        (status = 404, description = "Program not found", body = ErrorBody)
+    )
+)]
+#[get("/programs/{program_id}")]
+async fn get_program(
+    pool: web::Data<PgPool>,
+    program_id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let program = fetch_program(pool.get_ref(), program_id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(program))
+}
+
+#[utoipa::path(
+    tag = "programs",
+    summary = "Replace a program",
+    responses(
+        (status = 200, body = serde_json::Value, example = json!({ "status": "updated" })),
+        (status = 404, description = "Program not found", body = ErrorBody),
+        (status = 422, description = "Invalid program or unknown routine", body = ErrorBody)
+    )
+)]
+#[put("/programs/{program_id}")]
+async fn update_program(
+    pool: web::Data<PgPool>,
+    repos: web::Data<Repositories>,
+    program_id: web::Path<i32>,
+    program: web::Json<ProgramCreate>,
+) -> Result<HttpResponse, ApiError> {
+    let program_id = program_id.into_inner();
+    validate(&*program)
+        .routines_exist(repos.routines.as_ref())
+        .await?
+        .finish()?;
+
+    // Rolled back on drop if any statement fails
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query(
+        "UPDATE Programs
+         SET ProgramName = $1, ScheduleType = $2, StartDate = $3, Weeks = $4, DeloadWeeks = $5
+         WHERE ProgramID = $6",
+    )
+    .bind(program.name.trim())
+    .bind(program.schedule_type.to_string())
+    .bind(program.start_date)
+    .bind(program.weeks)
+    .bind(&program.deload_weeks)
+    .bind(program_id)
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(program_not_found(program_id));
+    }
+
+    // Replace the slots wholesale
+    sqlx::query("DELETE FROM ProgramSlots WHERE ProgramID = $1")
+        .bind(program_id)
+        .execute(&mut *tx)
+        .await?;
+    insert_slots(&mut tx, program_id, &program.slots).await?;
+    tx.commit().await?;
+
+    info!("Updated program {}", program_id);
+    Ok(HttpResponse::Ok().json(json!({ "status": "updated" })))
+}
+
+#[utoipa::path(
+    tag = "programs",
+    responses(
+        (status = 200, body = serde_json::Value, example = json!({ "status": "deleted" })),
+        (status = 404, description = "Program not found", body = ErrorBody)
+    )
+)]
+#[delete("/programs/{program_id}")]
+async fn delete_program(
+    pool: web::Data<PgPool>,
+    program_id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let program_id = program_id.into_inner();
+    let result = sqlx::query("DELETE FROM Programs WHERE ProgramID = $1")
+        .bind(program_id)
+        .execute(pool.get_ref())
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(program_not_found(program_id));
+    }
+    info!("Deleted program {}", program_id);
+    Ok(HttpResponse::Ok().json(json!({ "status": "deleted" })))
+}
+
+// What should I do today? Sessions planned by every program running on the date
+#[utoipa::path(
+    tag = "programs",
+    summary = "Sessions planned for a day by every running program",
+    params(("date" = Option<NaiveDate>, Query, description = "Defaults to today")),
+    responses(
+        (status = 200, body = serde_json::Value, example = json!({
+            "date": "2024-03-04",
+            "rest_day": false,
+            "sessions": [{
+                "program_id": 1, "program_name": "Strength block", "date": "2024-03-04", "week": 1,
+                "deload": false, "routine_id": 2, "routine_name": "Push", "workout_id": null
+            }]
+        })),
+        (status = 400, description = "Invalid date", body = ErrorBody)
+    )
+)]
+#[get("/programs/today")]
+async fn get_today(
+    pool: web::Data<PgPool>,
+    request: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let date = date_param(&request, "date")?.unwrap_or_else(|| Local::now().date_naive());
+
+    let mut sessions = Vec::new();
+    for program in &fetch_programs(pool.get_ref(), None).await? {
+        let planned = planned_sessions(pool.get_ref(), program, date, date).await?;
+        sessions.extend(planned.into_iter().map(|session| TodaySession {
+            program_id: program.program_id,
+            program_name: program.name.clone(),
+            session,
+        }));
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "date": date,
+        "rest_day": sessions.is_empty(),
+        "sessions": sessions
+    })))
+}
+
+#[utoipa::path(
+    tag = "programs",
+    summary = "Planned sessions in a date range",
+    params(
+        ("from" = Option<NaiveDate>, Query, description = "Defaults to the program's start"),
+        ("to" = Option<NaiveDate>, Query, description = "Defaults to the program's end")
+    ),
+    responses(
+        (status = 200, body = Vec<PlannedSession>),
+        (status = 400, description = "Invalid date", body = ErrorBody),
+        (status = 404, description = "Program not found", body = ErrorBody)
+    )
+)]
+#[get("/programs/{program_id}/schedule")]
+async fn get_schedule(
+    pool: web::Data<PgPool>,
+    program_id: web::Path<i32>,
+    request: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let from = date_param(&request, "from")?;
+    let to = date_param(&request, "to")?;
+    let program = fetch_program(pool.get_ref(), program_id.into_inner()).await?;
+
+    let from = from.unwrap_or(program.start_date);
+    let to = to.unwrap_or(program.end_date);
+    let sessions = planned_sessions(pool.get_ref(), &program, from, to).await?;
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+#[utoipa::path(
+    tag = "programs",
+    summary = "Planned sessions completed so far",
+    params(("date" = Option<NaiveDate>, Query, description = "Count up to this day instead of today")),
+    responses(
+        (status = 200, body = AdherenceReport),
+        (status = 400, description = "Invalid date", body = ErrorBody),
+        (status = 404, description = "Program not found", body = ErrorBody)
+    )
+)]
+#[get("/programs/{program_id}/adherence")]
+async fn get_adherence(
+    pool: web::Data<PgPool>,
+    program_id: web::Path<i32>,
+    request: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let program_id = program_id.into_inner();
+    let today = date_param(&request, "date")?.unwrap_or_else(|| Local::now().date_naive());
+    let program = fetch_program(pool.get_ref(), program_id).await?;
+
+    // Sessions due so far; today's only counts once it has been done
+    let to = today.min(program.end_date);
+    let sessions: Vec<PlannedSession> =
+        planned_sessions(pool.get_ref(), &program, program.start_date, to)
+            .await?
+            .into_iter()
+            .filter(|session| session.date < today || session.workout_id.is_some())
+            .collect();
+
+    // Workouts of the program's routines logged on days they were not planned
+    let routine_ids: Vec<i32> = program.slots.iter().filter_map(|s| s.routine_id).collect();
+    let fulfilled: HashSet<i32> = sessions.iter().filter_map(|s| s.workout_id).collect();
+    let unscheduled_workouts =
+        logged_workouts(pool.get_ref(), &routine_ids, program.start_date, to)
+            .await?
+            .into_iter()
+            .filter(|workout| !fulfilled.contains(&workout.workout_id))
+            .collect();
+    let mut weeks: BTreeMap<i16, WeekAdherence> = BTreeMap::new();
+    for session in &sessions {
+        let week = weeks.entry(session.week).or_insert_with(|| WeekAdherence {
+            week: session.week,
+            deload: session.deload,
+            ..Default::default()
+        });
+        week.planned += 1;
+        if session.workout_id.is_some() {
+            week.completed += 1;
+        }
+    }
+
+    let planned = sessions.len();
+    let completed = sessions.iter().filter(|s| s.workout_id.is_some()).count();
+    let current_streak = sessions
+        .iter()
+        .rev()
+        .take_while(|s| s.workout_id.is_some())
+        .count();
+
+    Ok(HttpResponse::Ok().json(AdherenceReport {
+        program_id,
+        from: program.start_date,
+        to,
+        planned,
+        completed,
+        missed: planned - completed,
+        adherence_percent: (planned > 0)
+            .then(|| (completed as f64 / planned as f64 * 1000.0).round() / 10.0),
+        current_streak,
+        weeks: weeks.into_values().collect(),
+        missed_sessions: sessions
+            .into_iter()
+            .filter(|s| s.workout_id.is_none())
+            .collect(),
+        unscheduled_workouts,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use crate::test_support::TestApp;
+
+    // Two weeks from Monday 5 January 2026, the second a deload
+    fn weekly_program(push: i32, pull: i32) -> Value {
+        json!({
+            "name": "Push Pull",
+            "schedule_type": "weekly",
+            "start_date": "2026-01-05",
+            "weeks": 2,
+            "deload_weeks": [2],
+            "slots": [
+                { "weekday": "Mon", "routine_id": push },
+                { "weekday": "Thu", "routine_id": pull },
+            ],
+        })
+    }
+
+    #[actix_web::test]
+    async fn create_list_update_and_delete() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
+        let push = app.routine("Push").exercise(bench, 3).create().await;
+        let pull = app.routine("Pull").exercise(bench, 3).create().await;
+
+        let body = app
+            .post("/api/v1/programs", weekly_program(push, pull))
+            .await
+            .expect(201)
+            .json();
+        let uri = format!("/api/v1/programs/{}", body["program_id"]);
+
+        let program = app.get(&uri).await.expect(200).json();
+        assert_eq!(program["end_date"], "2026-01-18");
+        assert_eq!(
+            program["slots"][1],
+            json!({ "weekday": "Thu", "routine_id": pull })
+        );
+        let list = app.get("/api/v1/programs").await.expect(200).json();
+        assert_eq!(list.as_array().unwrap().len(), 1);
+
+        let mut update = weekly_program(push, pull);
+        update["schedule_type"] = json!("rotation");
+        update["slots"] = json!([{ "routine_id": push }, { "routine_id": null }]);
+        app.put(&uri, update.clone()).await.expect(200);
+        let program = app.get(&uri).await.expect(200).json();
+        assert_eq!(program["schedule_type"], "rotation");
+        assert_eq!(program["slots"].as_array().unwrap().len(), 2);
+        app.put("/api/v1/programs/999", update).await.expect(404);
+
+        app.delete(&uri).await.expect(200);
+        app.get(&uri).await.expect(404);
+        app.delete(&uri).await.expect(404);
+        assert_eq!(app.count("ProgramSlots").await, 0);
+    }
+
+    #[actix_web::test]
+    async fn create_rejects_invalid_programs() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
+        let push = app.routine("Push").exercise(bench, 3).create().await;
+
+        let mut program = weekly_program(push, push);
+        program["slots"][1]["weekday"] = json!("Mon");
+        let body = app
+            .post("/api/v1/programs", program)
+            .await
+            .expect(422)
+            .json();
+        assert_eq!(body["fields"][0]["field"], "slots[1].weekday");
+        assert_eq!(body["fields"][0]["code"], "duplicate");
+
+        let mut program = weekly_program(push, push);
+        program["name"] = json!(" ");
+        program["deload_weeks"] = json!([3]);
+        program["schedule_type"] = json!("rotation");
+        let body = app
+            .post("/api/v1/programs", program)
+            .await
+            .expect(422)
+            .json();
+        let fields: Vec<(&str, &str)> = body["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| (f["field"].as_str().unwrap(), f["code"].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("name", "required"),
+                ("deload_weeks[0]", "out_of_range"),
+                ("slots[0].weekday", "not_allowed"),
+                ("slots[1].weekday", "not_allowed"),
+            ]
+        );
+
+        let body = app
+            .post("/api/v1/programs", weekly_program(push, 999))
+            .await
+            .expect(422)
+            .json();
+        assert_eq!(body["fields"][0]["field"], "slots[1].routine_id");
+        assert_eq!(body["fields"][0]["code"], "not_found");
+
+        // Far-off dates parse, but their end date would overflow
+        let mut program = weekly_program(push, push);
+        program["start_date"] = json!("+262142-12-01");
+        let body = app
+            .post("/api/v1/programs", program)
+            .await
+            .expect(422)
+            .json();
+        assert_eq!(body["fields"][0]["field"], "start_date");
+        assert_eq!(body["fields"][0]["code"], "out_of_range");
+        assert_eq!(app.count("Programs").await, 0);
+    }
+
+    #[actix_web::test]
+    async fn schedule_today_and_adherence() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
+        let push = app.routine("Push").exercise(bench, 3).create().await;
+        let pull = app.routine("Pull").exercise(bench, 3).create().await;
+        let body = app
+            .post("/api/v1/programs", weekly_program(push, pull))
+            .await
+            .expect(201)
+            .json();
+        let uri = format!("/api/v1/programs/{}", body["program_id"]);
+
+        let done = app
+            .workout()
+            .routine(push)
+            .at("2026-01-05T18:00:00")
+            .set(bench, 100, 5)
+            .create()
+            .await;
+        let late = app
+            .workout()
+            .routine(pull)
+            .at("2026-01-09T18:00:00")
+            .set(bench, 100, 5)
+            .create()
+            .await;
+
+        let schedule = app
+            .get(&format!("{}/schedule", uri))
+            .await
+            .expect(200)
+            .json();
+        let planned: Vec<(&str, bool)> = schedule
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| (s["date"].as_str().unwrap(), s["deload"].as_bool().unwrap()))
+            .collect();
+        assert_eq!(
+            planned,
+            vec![
+                ("2026-01-05", false),
+                ("2026-01-08", false),
+                ("2026-01-12", true),
+                ("2026-01-15", true),
+            ]
+        );
+        assert_eq!(schedule[0]["workout_id"], done);
+        assert_eq!(schedule[1]["routine_name"], "Pull");
+        app.get(&format!("{}/schedule?from=soon", uri))
+            .await
+            .expect(400);
+
+        let today = app
+            .get("/api/v1/programs/today?date=2026-01-08")
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(today["rest_day"], false);
+        assert_eq!(today["sessions"][0]["routine_id"], pull);
+        let today = app
+            .get("/api/v1/programs/today?date=2026-01-10")
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(today["rest_day"], true);
+
+        let report = app
+            .get(&format!("{}/adherence?date=2026-01-13", uri))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(report["planned"], 3);
+        assert_eq!(report["completed"], 1);
+        assert_eq!(report["adherence_percent"], 33.3);
+        assert_eq!(report["current_streak"], 0);
+        assert_eq!(report["unscheduled_workouts"][0]["workout_id"], late);
+        app.get("/api/v1/programs/999/adherence").await.expect(404);
+    }
+}
@@ -0,0 +1,282 @@
+// Storage behind the handlers and services. Each trait covers one part of the
+// domain; postgres.rs implements them over the pool and memory.rs keeps
+// everything in process for unit tests.
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use crate::exercises::{ExerciseStats, PersonalRecord};
+use crate::markers::{LogEntry, MarkerInfo, MarkerSettings, MarkerValue, ReadingPolicy};
+use crate::routines::{
+    RoutineExercise, RoutineExerciseView, RoutineInfo, RoutineStats, RoutineVersion,
+};
+use crate::workouts::{Exercise, WorkoutSummary};
+
+#[cfg(test)]
+pub(crate) mod memory;
+pub(crate) mod postgres;
+
+pub(crate) type RepoResult<T> = Result<T, sqlx::Error>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ExerciseRecord {
+    pub(crate) exercise_id: i32,
+    pub(crate) name: String,
+    pub(crate) muscles_trained: Vec<String>,
+    pub(crate) exercise_type: String,
+}
+
+// Outcome of deleting something other records may still point at
+#[derive(Debug, PartialEq)]
+pub(crate) enum Removal {
+    Deleted,
+    NotFound,
+    InUse,
+}
+
+// An exercise of a routine's current definition, as a workout starts from it
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct PlannedExercise {
+    pub(crate) exercise_id: i32,
+    pub(crate) exercise_name: String,
+    pub(crate) sets: i16,
+}
+
+// A routine as it is defined now, its exercises in routine order
+pub(crate) struct CurrentRoutine {
+    pub(crate) name: String,
+    pub(crate) version: Option<i32>,
+    pub(crate) exercises: Vec<RoutineExerciseView>,
+}
+
+// A workout with its sets numbered per exercise in the order they were logged
+#[derive(Serialize, ToSchema)]
+pub(crate) struct LoggedWorkout {
+    pub(crate) routine_id: Option<i32>,
+    pub(crate) routine_version: Option<i32>,
+    pub(crate) routine_name: Option<String>,
+    pub(crate) exercises: Vec<Exercise>, // By exercise ID
+}
+
+// One exercise's bests within a single workout, as stored in PRs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct PrRecord {
+    pub(crate) heaviest_weight: i16,
+    pub(crate) one_rm: f32,
+    pub(crate) set_volume: i32,
+}
+
+// Bests that beat every earlier workout's heaviest weight or estimated 1RM
+#[derive(Serialize, Debug, PartialEq, ToSchema)]
+pub(crate) struct RecordSet {
+    pub(crate) workout_id: i32,
+    pub(crate) start_time: NaiveDateTime,
+    pub(crate) exercise_id: i32,
+    pub(crate) exercise_name: String,
+    pub(crate) heaviest_weight: i16,
+    pub(crate) one_rm: f32,
+    pub(crate) set_volume: i32,
+}
+
+#[async_trait]
+pub(crate) trait ExerciseRepository: Send + Sync {
+    // The given IDs that exist, in any order
+    async fn existing(&self, ids: &[i32]) -> RepoResult<Vec<i32>>;
+    // By name; without a limit every match is returned
+    async fn search(
+        &self,
+        partial_name: &str,
+        limit: Option<i64>,
+    ) -> RepoResult<Vec<ExerciseRecord>>;
+    async fn get(&self, exercise_id: i32) -> RepoResult<Option<ExerciseRecord>>;
+    // Case-insensitive
+    async fn find_by_name(&self, name: &str) -> RepoResult<Option<ExerciseRecord>>;
+    async fn create(
+        &self,
+        name: &str,
+        muscles_trained: &[String],
+        exercise_type: &str,
+    ) -> RepoResult<ExerciseRecord>;
+    // None if the exercise is missing
+    async fn update(
+        &self,
+        exercise_id: i32,
+        name: &str,
+        muscles_trained: &[String],
+        exercise_type: &str,
+    ) -> RepoResult<Option<ExerciseRecord>>;
+    async fn delete(&self, exercise_id: i32) -> RepoResult<Removal>;
+    // Total weight x reps per workout, oldest first
+    async fn volume_history(&self, exercise_id: i32) -> RepoResult<Vec<ExerciseStats>>;
+    // Heaviest set per workout, oldest first
+    async fn max_weight_history(&self, exercise_id: i32) -> RepoResult<Vec<ExerciseStats>>;
+}
+
+#[async_trait]
+pub(crate) trait RoutineRepository: Send + Sync {
+    async fn list(&self, include_last_performed: bool) -> RepoResult<Vec<RoutineInfo>>;
+    async fn find_by_name(&self, name: &str) -> RepoResult<Option<i32>>;
+    async fn exists(&self, routine_id: i32) -> RepoResult<bool>;
+    // The given IDs that exist, in any order
+    async fn existing(&self, ids: &[i32]) -> RepoResult<Vec<i32>>;
+    // Creates the routine at version 1 and returns its ID
+    async fn create(
+        &self,
+        name: &str,
+        created_at: NaiveDateTime,
+        exercises: &[RoutineExercise],
+    ) -> RepoResult<i32>;
+    // Replaces the definition and returns the current version number, which
+    // only moves on when something changed. None if the routine is missing.
+    async fn update(
+        &self,
+        routine_id: i32,
+        name: &str,
+        exercises: &[RoutineExercise],
+    ) -> RepoResult<Option<i32>>;
+    async fn delete(&self, routine_id: i32) -> RepoResult<bool>;
+    async fn planned_exercises(&self, routine_id: i32) -> RepoResult<Vec<PlannedExercise>>;
+    async fn current(&self, routine_id: i32) -> RepoResult<Option<CurrentRoutine>>;
+    // Over the workouts performed from any of its versions
+    async fn stats(&self, routine_id: i32) -> RepoResult<RoutineStats>;
+    // Oldest first; empty if the routine is missing
+    async fn versions(&self, routine_id: i32) -> RepoResult<Vec<RoutineVersion>>;
+}
+
+#[async_trait]
+pub(crate) trait WorkoutRepository: Send + Sync {
+    // Newest first; without a limit every workout is returned
+    async fn list(&self, limit: Option<i64>) -> RepoResult<Vec<WorkoutSummary>>;
+    async fn exists(&self, workout_id: i32) -> RepoResult<bool>;
+    // None when the workout is missing or has no sets
+    async fn get(&self, workout_id: i32) -> RepoResult<Option<LoggedWorkout>>;
+    // Ties the workout to the routine's latest version
+    async fn create(
+        &self,
+        start: Option<NaiveDateTime>,
+        end: Option<NaiveDateTime>,
+        routine_id: Option<i32>,
+    ) -> RepoResult<i32>;
+    async fn add_sets(&self, workout_id: i32, exercise: &Exercise) -> RepoResult<()>;
+    // Removes the workout with its sets and rebuilds the PRs of its exercises
+    async fn delete(&self, workout_id: i32) -> RepoResult<bool>;
+}
+
+#[async_trait]
+pub(crate) trait PrRepository: Send + Sync {
+    // The most recently recorded bests for the exercise
+    async fn latest(&self, exercise_id: i32) -> RepoResult<Option<PrRecord>>;
+    async fn highest_reps(&self, exercise_id: i32, weight: i16) -> RepoResult<Option<i16>>;
+    // Best heaviest weight and 1RM over every other workout
+    async fn best_excluding(
+        &self,
+        exercise_id: i32,
+        workout_id: i32,
+    ) -> RepoResult<Option<(i16, f32)>>;
+    // Each workout's bests for the exercise, highest 1RM first
+    async fn history(&self, exercise_id: i32) -> RepoResult<Vec<PersonalRecord>>;
+    // Newest first, by workout start
+    async fn recent_records(&self, limit: i64) -> RepoResult<Vec<RecordSet>>;
+    // Stores the workout's bests and raises the rep records it beat
+    async fn record(
+        &self,
+        workout_id: i32,
+        exercise_id: i32,
+        pr: PrRecord,
+        highest_reps: &HashMap<i16, i16>,
+    ) -> RepoResult<()>;
+}
+
+#[async_trait]
+pub(crate) trait MarkerRepository: Send + Sync {
+    // By name
+    async fn list(&self) -> RepoResult<Vec<MarkerInfo>>;
+    async fn get(&self, marker_id: i32) -> RepoResult<Option<MarkerInfo>>;
+    async fn find_by_name(&self, name: &str) -> RepoResult<Option<i32>>;
+    async fn settings(&self, marker_id: i32) -> RepoResult<Option<MarkerSettings>>;
+    async fn create(&self, name: &str, color: &str, settings: &MarkerSettings) -> RepoResult<i32>;
+    async fn update(
+        &self,
+        marker_id: i32,
+        name: &str,
+        color: &str,
+        settings: &MarkerSettings,
+    ) -> RepoResult<bool>;
+    // Removes the marker together with its entries
+    async fn delete(&self, marker_id: i32) -> RepoResult<bool>;
+    // With replace_day the reading supersedes the others logged on its date
+    async fn log(
+        &self,
+        marker_id: i32,
+        date: NaiveDate,
+        value: &MarkerValue,
+        replace_day: bool,
+    ) -> RepoResult<i32>;
+    // Oldest first; both bounds are inclusive
+    async fn entries(
+        &self,
+        marker_id: i32,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> RepoResult<Vec<LogEntry>>;
+    // The values the reading policy counts: one a day when it averages or
+    // replaces, every entry when it keeps them. Oldest first; both bounds are
+    // inclusive.
+    async fn readings(
+        &self,
+        marker_id: i32,
+        policy: ReadingPolicy,
+        from: Option<NaiveDate>,
+        to: NaiveDate,
+    ) -> RepoResult<Vec<(NaiveDate, f64)>>;
+    async fn entry(&self, marker_id: i32, entry_id: i32) -> RepoResult<Option<LogEntry>>;
+    // With replace_day the entry supersedes the others on its new date
+    async fn update_entry(
+        &self,
+        marker_id: i32,
+        entry_id: i32,
+        date: NaiveDate,
+        value: &MarkerValue,
+        replace_day: bool,
+    ) -> RepoResult<bool>;
+    async fn delete_entry(&self, marker_id: i32, entry_id: i32) -> RepoResult<bool>;
+}
+
+// The repositories the app runs against, shared as app data
+#[derive(Clone)]
+pub(crate) struct Repositories {
+    pub(crate) exercises: Arc<dyn ExerciseRepository>,
+    pub(crate) routines: Arc<dyn RoutineRepository>,
+    pub(crate) workouts: Arc<dyn WorkoutRepository>,
+    pub(crate) prs: Arc<dyn PrRepository>,
+    pub(crate) markers: Arc<dyn MarkerRepository>,
+}
+
+impl Repositories {
+    pub(crate) fn postgres(pool: &PgPool) -> Self {
+        let repo = Arc::new(postgres::PgRepository::new(pool.clone()));
+        Repositories {
+            exercises: repo.clone(),
+            routines: repo.clone(),
+            workouts: repo.clone(),
+            prs: repo.clone(),
+            markers: repo,
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn in_memory() -> Self {
+        let repo = Arc::new(memory::MemoryRepository::default());
+        Repositories {
+            exercises: repo.clone(),
+            routines: repo.clone(),
+            workouts: repo.clone(),
+            prs: repo.clone(),
+            markers: repo,
+        }
+    }
+}
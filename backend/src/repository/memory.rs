@@ -0,0 +1,895 @@
+// Everything in one mutex-guarded state so that references between records
+// (exercises used by routines, workouts tied to routine versions) behave like
+// the foreign keys in Postgres
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use super::{
+    CurrentRoutine, ExerciseRecord, ExerciseRepository, LoggedWorkout, MarkerRepository,
+    PlannedExercise, PrRecord, PrRepository, RecordSet, Removal, RepoResult, RoutineRepository,
+    WorkoutRepository,
+};
+use crate::exercises::{ExerciseStats, PersonalRecord};
+use crate::markers::{LogEntry, MarkerInfo, MarkerSettings, MarkerValue, ReadingPolicy};
+use crate::routines::{
+    RoutineExercise, RoutineExerciseDetail, RoutineExerciseView, RoutineInfo, RoutineStats,
+    RoutineVersion, VolumePoint,
+};
+use crate::workouts::{Exercise, Set, WorkoutSummary};
+
+#[derive(Default)]
+pub(crate) struct MemoryRepository {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: i32, // Shared by every kind of record
+    exercises: BTreeMap<i32, ExerciseRecord>,
+    routines: BTreeMap<i32, Routine>,
+    workouts: BTreeMap<i32, Workout>,
+    prs: Vec<(i32, i32, PrRecord)>, // (workout ID, exercise ID, bests), oldest first
+    highest_reps: HashMap<(i32, i16), i16>, // (exercise ID, weight) -> reps
+    markers: BTreeMap<i32, Marker>,
+    entries: BTreeMap<i32, (i32, LogEntry)>, // Entry ID -> (marker ID, entry)
+}
+
+struct Routine {
+    name: String,
+    created_at: NaiveDateTime,
+    version: i32,
+    exercises: Vec<RoutineExercise>,
+    history: Vec<SavedVersion>, // Oldest first, the current one included
+}
+
+struct SavedVersion {
+    name: String,
+    created_at: NaiveDateTime,
+    exercises: Vec<RoutineExercise>,
+}
+
+struct Workout {
+    start: NaiveDateTime,
+    end: Option<NaiveDateTime>,
+    routine: Option<(i32, i32)>, // (routine ID, version)
+    sets: Vec<(i32, Set)>,       // (exercise ID, set) in logging order
+}
+
+struct Marker {
+    name: String,
+    color: Option<String>,
+    settings: MarkerSettings,
+}
+
+impl Marker {
+    fn info(&self, marker_id: i32) -> MarkerInfo {
+        MarkerInfo {
+            marker_id,
+            name: self.name.clone(),
+            color: self.color.clone(),
+            settings: self.settings.clone(),
+        }
+    }
+}
+
+impl State {
+    fn next_id(&mut self) -> i32 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    // The exercise's sets grouped by the start of their workout
+    fn sets_by_start(&self, exercise_id: i32) -> BTreeMap<NaiveDateTime, Vec<&Set>> {
+        let mut sets: BTreeMap<NaiveDateTime, Vec<&Set>> = BTreeMap::new();
+        for workout in self.workouts.values() {
+            for (id, set) in &workout.sets {
+                if *id == exercise_id {
+                    sets.entry(workout.start).or_default().push(set);
+                }
+            }
+        }
+        sets
+    }
+}
+
+fn volume(sets: &[&Set]) -> i64 {
+    sets.iter()
+        .map(|set| i64::from(set.weight) * i64::from(set.reps))
+        .sum()
+}
+
+impl MemoryRepository {
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+}
+
+#[async_trait]
+impl ExerciseRepository for MemoryRepository {
+    async fn existing(&self, ids: &[i32]) -> RepoResult<Vec<i32>> {
+        let state = self.state();
+        Ok(ids
+            .iter()
+            .copied()
+            .filter(|id| state.exercises.contains_key(id))
+            .collect())
+    }
+
+    async fn search(
+        &self,
+        partial_name: &str,
+        limit: Option<i64>,
+    ) -> RepoResult<Vec<ExerciseRecord>> {
+        let needle = partial_name.to_lowercase();
+        let mut found: Vec<ExerciseRecord> = self
+            .state()
+            .exercises
+            .values()
+            .filter(|e| e.name.to_lowercase().contains(&needle))
+            .cloned()
+            .collect();
+        found.sort_by(|a, b| a.name.cmp(&b.name));
+        if let Some(limit) = limit {
+            found.truncate(limit as usize);
+        }
+        Ok(found)
+    }
+
+    async fn get(&self, exercise_id: i32) -> RepoResult<Option<ExerciseRecord>> {
+        Ok(self.state().exercises.get(&exercise_id).cloned())
+    }
+
+    async fn find_by_name(&self, name: &str) -> RepoResult<Option<ExerciseRecord>> {
+        Ok(self
+            .state()
+            .exercises
+            .values()
+            .find(|e| e.name.eq_ignore_ascii_case(name))
+            .cloned())
+    }
+
+    async fn create(
+        &self,
+        name: &str,
+        muscles_trained: &[String],
+        exercise_type: &str,
+    ) -> RepoResult<ExerciseRecord> {
+        let mut state = self.state();
+        let exercise = ExerciseRecord {
+            exercise_id: state.next_id(),
+            name: name.to_string(),
+            muscles_trained: muscles_trained.to_vec(),
+            exercise_type: exercise_type.to_string(),
+        };
+        state
+            .exercises
+            .insert(exercise.exercise_id, exercise.clone());
+        Ok(exercise)
+    }
+
+    async fn update(
+        &self,
+        exercise_id: i32,
+        name: &str,
+        muscles_trained: &[String],
+        exercise_type: &str,
+    ) -> RepoResult<Option<ExerciseRecord>> {
+        let mut state = self.state();
+        let Some(exercise) = state.exercises.get_mut(&exercise_id) else {
+            return Ok(None);
+        };
+        exercise.name = name.to_string();
+        exercise.muscles_trained = muscles_trained.to_vec();
+        exercise.exercise_type = exercise_type.to_string();
+        Ok(Some(exercise.clone()))
+    }
+
+    async fn delete(&self, exercise_id: i32) -> RepoResult<Removal> {
+        let mut state = self.state();
+        if !state.exercises.contains_key(&exercise_id) {
+            return Ok(Removal::NotFound);
+        }
+        let in_routine = state
+            .routines
+            .values()
+            .any(|r| r.exercises.iter().any(|e| e.exercise_id == exercise_id));
+        let in_workout = state
+            .workouts
+            .values()
+            .any(|w| w.sets.iter().any(|(id, _)| *id == exercise_id));
+        if in_routine || in_workout {
+            return Ok(Removal::InUse);
+        }
+        state.exercises.remove(&exercise_id);
+        Ok(Removal::Deleted)
+    }
+
+    async fn volume_history(&self, exercise_id: i32) -> RepoResult<Vec<ExerciseStats>> {
+        Ok(self
+            .state()
+            .sets_by_start(exercise_id)
+            .into_iter()
+            .map(|(start, sets)| ExerciseStats {
+                date: start.and_utc(),
+                value: volume(&sets) as f64,
+            })
+            .collect())
+    }
+
+    async fn max_weight_history(&self, exercise_id: i32) -> RepoResult<Vec<ExerciseStats>> {
+        Ok(self
+            .state()
+            .sets_by_start(exercise_id)
+            .into_iter()
+            .filter_map(|(start, sets)| {
+                Some(ExerciseStats {
+                    date: start.and_utc(),
+                    value: f64::from(sets.iter().map(|set| set.weight).max()?),
+                })
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl RoutineRepository for MemoryRepository {
+    async fn list(&self, include_last_performed: bool) -> RepoResult<Vec<RoutineInfo>> {
+        let state = self.state();
+        let mut routines: Vec<RoutineInfo> = state
+            .routines
+            .iter()
+            .map(|(id, routine)| RoutineInfo {
+                routine_id: *id,
+                name: routine.name.clone(),
+                timestamp: routine.created_at,
+                last_performed: include_last_performed
+                    .then(|| {
+                        state
+                            .workouts
+                            .values()
+                            .filter(|w| w.routine.map(|(r, _)| r) == Some(*id))
+                            .map(|w| w.start.date())
+                            .max()
+                    })
+                    .flatten(),
+            })
+            .collect();
+        routines.sort_by_key(|r| r.timestamp);
+        Ok(routines)
+    }
+
+    async fn find_by_name(&self, name: &str) -> RepoResult<Option<i32>> {
+        Ok(self
+            .state()
+            .routines
+            .iter()
+            .find(|(_, r)| r.name == name)
+            .map(|(id, _)| *id))
+    }
+
+    async fn exists(&self, routine_id: i32) -> RepoResult<bool> {
+        Ok(self.state().routines.contains_key(&routine_id))
+    }
+
+    async fn existing(&self, ids: &[i32]) -> RepoResult<Vec<i32>> {
+        let state = self.state();
+        Ok(ids
+            .iter()
+            .copied()
+            .filter(|id| state.routines.contains_key(id))
+            .collect())
+    }
+
+    async fn create(
+        &self,
+        name: &str,
+        created_at: NaiveDateTime,
+        exercises: &[RoutineExercise],
+    ) -> RepoResult<i32> {
+        let mut state = self.state();
+        let routine_id = state.next_id();
+        state.routines.insert(
+            routine_id,
+            Routine {
+                name: name.to_string(),
+                created_at,
+                version: 1,
+                exercises: exercises.to_vec(),
+                history: vec![SavedVersion {
+                    name: name.to_string(),
+                    created_at,
+                    exercises: exercises.to_vec(),
+                }],
+            },
+        );
+        Ok(routine_id)
+    }
+
+    async fn update(
+        &self,
+        routine_id: i32,
+        name: &str,
+        exercises: &[RoutineExercise],
+    ) -> RepoResult<Option<i32>> {
+        let mut state = self.state();
+        let Some(routine) = state.routines.get_mut(&routine_id) else {
+            return Ok(None);
+        };
+        if routine.name != name || routine.exercises != exercises {
+            routine.name = name.to_string();
+            routine.exercises = exercises.to_vec();
+            routine.version += 1;
+            routine.history.push(SavedVersion {
+                name: name.to_string(),
+                created_at: Utc::now().naive_utc(),
+                exercises: exercises.to_vec(),
+            });
+        }
+        Ok(Some(routine.version))
+    }
+
+    async fn delete(&self, routine_id: i32) -> RepoResult<bool> {
+        Ok(self.state().routines.remove(&routine_id).is_some())
+    }
+
+    async fn planned_exercises(&self, routine_id: i32) -> RepoResult<Vec<PlannedExercise>> {
+        let state = self.state();
+        let Some(routine) = state.routines.get(&routine_id) else {
+            return Ok(Vec::new());
+        };
+        Ok(routine
+            .exercises
+            .iter()
+            .filter_map(|e| {
+                Some(PlannedExercise {
+                    exercise_id: e.exercise_id,
+                    exercise_name: state.exercises.get(&e.exercise_id)?.name.clone(),
+                    sets: e.sets as i16,
+                })
+            })
+            .collect())
+    }
+
+    async fn current(&self, routine_id: i32) -> RepoResult<Option<CurrentRoutine>> {
+        let state = self.state();
+        let Some(routine) = state.routines.get(&routine_id) else {
+            return Ok(None);
+        };
+        let exercises = routine
+            .exercises
+            .iter()
+            .filter_map(|e| Some((e, state.exercises.get(&e.exercise_id)?)))
+            .zip(1..)
+            .map(|((e, exercise), position)| RoutineExerciseView {
+                position,
+                exercise_id: e.exercise_id,
+                exercise_name: exercise.name.clone(),
+                muscles_trained: exercise.muscles_trained.clone(),
+                sets: e.sets,
+                target_reps: e.target_reps,
+                target_weight: e.target_weight,
+            })
+            .collect();
+        Ok(Some(CurrentRoutine {
+            name: routine.name.clone(),
+            version: Some(routine.version),
+            exercises,
+        }))
+    }
+
+    async fn stats(&self, routine_id: i32) -> RepoResult<RoutineStats> {
+        let state = self.state();
+        let mut workouts: Vec<(i32, &Workout)> = state
+            .workouts
+            .iter()
+            .filter(|(_, w)| w.routine.map(|(r, _)| r) == Some(routine_id))
+            .map(|(id, w)| (*id, w))
+            .collect();
+        workouts.sort_by_key(|(_, w)| w.start);
+
+        let durations: Vec<f64> = workouts
+            .iter()
+            .filter_map(|(_, w)| Some((w.end? - w.start).num_seconds() as f64 / 60.0))
+            .collect();
+        Ok(RoutineStats {
+            times_performed: workouts.len() as i64,
+            last_performed: workouts.last().map(|(_, w)| w.start),
+            average_duration_minutes: (!durations.is_empty())
+                .then(|| durations.iter().sum::<f64>() / durations.len() as f64),
+            volume_trend: workouts
+                .iter()
+                .map(|(workout_id, w)| VolumePoint {
+                    workout_id: *workout_id,
+                    date: w.start.date(),
+                    volume: volume(&w.sets.iter().map(|(_, set)| set).collect::<Vec<_>>()),
+                })
+                .collect(),
+        })
+    }
+
+    async fn versions(&self, routine_id: i32) -> RepoResult<Vec<RoutineVersion>> {
+        let state = self.state();
+        let Some(routine) = state.routines.get(&routine_id) else {
+            return Ok(Vec::new());
+        };
+        Ok(routine
+            .history
+            .iter()
+            .zip(1..)
+            .map(|(saved, version)| RoutineVersion {
+                version,
+                name: saved.name.clone(),
+                created_at: saved.created_at,
+                workouts: state
+                    .workouts
+                    .values()
+                    .filter(|w| w.routine == Some((routine_id, version)))
+                    .count() as i64,
+                exercises: saved
+                    .exercises
+                    .iter()
+                    .filter_map(|e| {
+                        Some(RoutineExerciseDetail {
+                            exercise_id: e.exercise_id,
+                            exercise_name: state.exercises.get(&e.exercise_id)?.name.clone(),
+                            sets: e.sets,
+                            target_reps: e.target_reps,
+                            target_weight: e.target_weight,
+                        })
+                    })
+                    .collect(),
+                changes: None,
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl WorkoutRepository for MemoryRepository {
+    async fn list(&self, limit: Option<i64>) -> RepoResult<Vec<WorkoutSummary>> {
+        let state = self.state();
+        let mut workouts: Vec<WorkoutSummary> = state
+            .workouts
+            .iter()
+            .map(|(id, w)| WorkoutSummary {
+                workout_id: *id,
+                routine_name: w
+                    .routine
+                    .and_then(|(r, _)| state.routines.get(&r))
+                    .map(|r| r.name.clone()),
+                start_time: w.start,
+            })
+            .collect();
+        workouts.sort_by_key(|w| std::cmp::Reverse(w.start_time));
+        if let Some(limit) = limit {
+            workouts.truncate(limit as usize);
+        }
+        Ok(workouts)
+    }
+
+    async fn exists(&self, workout_id: i32) -> RepoResult<bool> {
+        Ok(self.state().workouts.contains_key(&workout_id))
+    }
+
+    async fn get(&self, workout_id: i32) -> RepoResult<Option<LoggedWorkout>> {
+        let state = self.state();
+        let Some(workout) = state.workouts.get(&workout_id) else {
+            return Ok(None);
+        };
+        if workout.sets.is_empty() {
+            return Ok(None);
+        }
+
+        let mut exercises: BTreeMap<i32, Exercise> = BTreeMap::new();
+        for (exercise_id, set) in &workout.sets {
+            let exercise = exercises.entry(*exercise_id).or_insert_with(|| Exercise {
+                exercise_id: *exercise_id,
+                exercise_name: state
+                    .exercises
+                    .get(exercise_id)
+                    .map(|e| e.name.clone())
+                    .unwrap_or_default(),
+                sets: HashMap::new(),
+            });
+            let set_number = exercise.sets.len() as i16 + 1;
+            exercise.sets.insert(set_number, set.clone());
+        }
+
+        let routine_id = workout.routine.map(|(r, _)| r);
+        Ok(Some(LoggedWorkout {
+            routine_id,
+            routine_version: workout.routine.map(|(_, v)| v),
+            routine_name: routine_id
+                .and_then(|r| state.routines.get(&r))
+                .map(|r| r.name.clone()),
+            exercises: exercises.into_values().collect(),
+        }))
+    }
+
+    async fn create(
+        &self,
+        start: Option<NaiveDateTime>,
+        end: Option<NaiveDateTime>,
+        routine_id: Option<i32>,
+    ) -> RepoResult<i32> {
+        let mut state = self.state();
+        let routine = routine_id.and_then(|r| Some((r, state.routines.get(&r)?.version)));
+        let workout_id = state.next_id();
+        state.workouts.insert(
+            workout_id,
+            Workout {
+                start: start.ok_or(sqlx::Error::RowNotFound)?,
+                end,
+                routine,
+                sets: Vec::new(),
+            },
+        );
+        Ok(workout_id)
+    }
+
+    async fn add_sets(&self, workout_id: i32, exercise: &Exercise) -> RepoResult<()> {
+        let mut state = self.state();
+        let workout = state
+            .workouts
+            .get_mut(&workout_id)
+            .ok_or(sqlx::Error::RowNotFound)?;
+        let mut numbers: Vec<&i16> = exercise.sets.keys().collect();
+        numbers.sort();
+        for number in numbers {
+            workout
+                .sets
+                .push((exercise.exercise_id, exercise.sets[number].clone()));
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, workout_id: i32) -> RepoResult<bool> {
+        let mut state = self.state();
+        let Some(workout) = state.workouts.remove(&workout_id) else {
+            return Ok(false);
+        };
+        state.prs.retain(|(workout, _, _)| *workout != workout_id);
+
+        // Rep records of the workout's exercises come from what is left
+        let exercise_ids: Vec<i32> = workout.sets.iter().map(|(id, _)| *id).collect();
+        state
+            .highest_reps
+            .retain(|(exercise_id, _), _| !exercise_ids.contains(exercise_id));
+        let remaining: Vec<(i32, Set)> = state
+            .workouts
+            .values()
+            .flat_map(|w| w.sets.iter().cloned())
+            .filter(|(id, _)| exercise_ids.contains(id))
+            .collect();
+        for (exercise_id, set) in remaining {
+            let best = state
+                .highest_reps
+                .entry((exercise_id, set.weight))
+                .or_insert(set.reps);
+            *best = (*best).max(set.reps);
+        }
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl PrRepository for MemoryRepository {
+    async fn latest(&self, exercise_id: i32) -> RepoResult<Option<PrRecord>> {
+        Ok(self
+            .state()
+            .prs
+            .iter()
+            .rev()
+            .find(|(_, id, _)| *id == exercise_id)
+            .map(|(_, _, pr)| *pr))
+    }
+
+    async fn highest_reps(&self, exercise_id: i32, weight: i16) -> RepoResult<Option<i16>> {
+        Ok(self
+            .state()
+            .highest_reps
+            .get(&(exercise_id, weight))
+            .copied())
+    }
+
+    async fn best_excluding(
+        &self,
+        exercise_id: i32,
+        workout_id: i32,
+    ) -> RepoResult<Option<(i16, f32)>> {
+        Ok(self
+            .state()
+            .prs
+            .iter()
+            .filter(|(workout, exercise, _)| *exercise == exercise_id && *workout != workout_id)
+            .map(|(_, _, pr)| (pr.heaviest_weight, pr.one_rm))
+            .reduce(|(weight, one_rm), (w, o)| (weight.max(w), one_rm.max(o))))
+    }
+
+    async fn history(&self, exercise_id: i32) -> RepoResult<Vec<PersonalRecord>> {
+        let state = self.state();
+        let mut records: Vec<PersonalRecord> = state
+            .prs
+            .iter()
+            .filter(|(_, id, _)| *id == exercise_id)
+            .map(|(workout, _, pr)| PersonalRecord {
+                workout_date: state.workouts[workout].start.and_utc(),
+                weight: pr.heaviest_weight,
+                reps: 0,
+                one_rm: pr.one_rm,
+                set_volume: pr.set_volume,
+            })
+            .collect();
+        records.sort_by(|a, b| {
+            b.one_rm
+                .total_cmp(&a.one_rm)
+                .then(b.weight.cmp(&a.weight))
+                .then(b.set_volume.cmp(&a.set_volume))
+        });
+        Ok(records)
+    }
+
+    async fn recent_records(&self, limit: i64) -> RepoResult<Vec<RecordSet>> {
+        let state = self.state();
+        let mut prs: Vec<(NaiveDateTime, i32, i32, PrRecord)> = state
+            .prs
+            .iter()
+            .map(|(workout, exercise, pr)| {
+                (state.workouts[workout].start, *workout, *exercise, *pr)
+            })
+            .collect();
+        prs.sort_by_key(|(start, workout, _, _)| (*start, *workout));
+
+        let mut best: HashMap<i32, (i16, f32)> = HashMap::new();
+        let mut records = Vec::new();
+        for (start, workout_id, exercise_id, pr) in prs {
+            let beaten = match best.get(&exercise_id) {
+                Some((weight, one_rm)) => pr.heaviest_weight > *weight || pr.one_rm > *one_rm,
+                None => pr.heaviest_weight > 0 || pr.one_rm > 0.0,
+            };
+            if beaten {
+                records.push(RecordSet {
+                    workout_id,
+                    start_time: start,
+                    exercise_id,
+                    exercise_name: state.exercises[&exercise_id].name.clone(),
+                    heaviest_weight: pr.heaviest_weight,
+                    one_rm: pr.one_rm,
+                    set_volume: pr.set_volume,
+                });
+            }
+            let previous = best.entry(exercise_id).or_insert((0, 0.0));
+            *previous = (
+                previous.0.max(pr.heaviest_weight),
+                previous.1.max(pr.one_rm),
+            );
+        }
+
+        records.reverse();
+        records.truncate(limit as usize);
+        Ok(records)
+    }
+
+    async fn record(
+        &self,
+        workout_id: i32,
+        exercise_id: i32,
+        pr: PrRecord,
+        highest_reps: &HashMap<i16, i16>,
+    ) -> RepoResult<()> {
+        let mut state = self.state();
+        state.prs.push((workout_id, exercise_id, pr));
+        for (weight, reps) in highest_reps {
+            let best = state
+                .highest_reps
+                .entry((exercise_id, *weight))
+                .or_insert(*reps);
+            *best = (*best).max(*reps);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MarkerRepository for MemoryRepository {
+    async fn list(&self) -> RepoResult<Vec<MarkerInfo>> {
+        let mut markers: Vec<MarkerInfo> = self
+            .state()
+            .markers
+            .iter()
+            .map(|(id, m)| m.info(*id))
+            .collect();
+        markers.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(markers)
+    }
+
+    async fn get(&self, marker_id: i32) -> RepoResult<Option<MarkerInfo>> {
+        Ok(self
+            .state()
+            .markers
+            .get(&marker_id)
+            .map(|m| m.info(marker_id)))
+    }
+
+    async fn find_by_name(&self, name: &str) -> RepoResult<Option<i32>> {
+        Ok(self
+            .state()
+            .markers
+            .iter()
+            .find(|(_, m)| m.name == name)
+            .map(|(id, _)| *id))
+    }
+
+    async fn settings(&self, marker_id: i32) -> RepoResult<Option<MarkerSettings>> {
+        Ok(self
+            .state()
+            .markers
+            .get(&marker_id)
+            .map(|m| m.settings.clone()))
+    }
+
+    async fn create(&self, name: &str, color: &str, settings: &MarkerSettings) -> RepoResult<i32> {
+        let mut state = self.state();
+        let marker_id = state.next_id();
+        state.markers.insert(
+            marker_id,
+            Marker {
+                name: name.to_string(),
+                color: Some(color.to_string()),
+                settings: settings.clone(),
+            },
+        );
+        Ok(marker_id)
+    }
+
+    async fn update(
+        &self,
+        marker_id: i32,
+        name: &str,
+        color: &str,
+        settings: &MarkerSettings,
+    ) -> RepoResult<bool> {
+        let mut state = self.state();
+        let Some(marker) = state.markers.get_mut(&marker_id) else {
+            return Ok(false);
+        };
+        marker.name = name.to_string();
+        marker.color = Some(color.to_string());
+        marker.settings = settings.clone();
+        Ok(true)
+    }
+
+    async fn delete(&self, marker_id: i32) -> RepoResult<bool> {
+        let mut state = self.state();
+        state.entries.retain(|_, (marker, _)| *marker != marker_id);
+        Ok(state.markers.remove(&marker_id).is_some())
+    }
+
+    async fn log(
+        &self,
+        marker_id: i32,
+        date: NaiveDate,
+        value: &MarkerValue,
+        replace_day: bool,
+    ) -> RepoResult<i32> {
+        let mut state = self.state();
+        if !state.markers.contains_key(&marker_id) {
+            return Err(sqlx::Error::RowNotFound);
+        }
+        if replace_day {
+            state
+                .entries
+                .retain(|_, (marker, entry)| *marker != marker_id || entry.date != date);
+        }
+        let entry_id = state.next_id();
+        state.entries.insert(
+            entry_id,
+            (
+                marker_id,
+                LogEntry {
+                    entry_id,
+                    value: value.value,
+                    date,
+                    logged_at: value.logged_at,
+                    note: value.note.clone(),
+                },
+            ),
+        );
+        Ok(entry_id)
+    }
+
+    async fn entries(
+        &self,
+        marker_id: i32,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> RepoResult<Vec<LogEntry>> {
+        let mut entries: Vec<LogEntry> = self
+            .state()
+            .entries
+            .values()
+            .filter(|(marker, entry)| {
+                *marker == marker_id
+                    && from.is_none_or(|from| entry.date >= from)
+                    && to.is_none_or(|to| entry.date <= to)
+            })
+            .map(|(_, entry)| entry.clone())
+            .collect();
+        entries.sort_by_key(|e| (e.date, e.logged_at, e.entry_id));
+        Ok(entries)
+    }
+
+    async fn readings(
+        &self,
+        marker_id: i32,
+        policy: ReadingPolicy,
+        from: Option<NaiveDate>,
+        to: NaiveDate,
+    ) -> RepoResult<Vec<(NaiveDate, f64)>> {
+        let entries = self.entries(marker_id, from, Some(to)).await?;
+        let mut days: BTreeMap<NaiveDate, Vec<f64>> = BTreeMap::new();
+        for entry in &entries {
+            days.entry(entry.date).or_default().push(entry.value);
+        }
+        Ok(match policy {
+            ReadingPolicy::Keep => entries.iter().map(|e| (e.date, e.value)).collect(),
+            // Entries come in logging order, so the last of a day is the latest
+            ReadingPolicy::Replace => days
+                .into_iter()
+                .filter_map(|(date, values)| Some((date, *values.last()?)))
+                .collect(),
+            ReadingPolicy::Average => days
+                .into_iter()
+                .map(|(date, values)| (date, values.iter().sum::<f64>() / values.len() as f64))
+                .collect(),
+        })
+    }
+
+    async fn entry(&self, marker_id: i32, entry_id: i32) -> RepoResult<Option<LogEntry>> {
+        Ok(self
+            .state()
+            .entries
+            .get(&entry_id)
+            .filter(|(marker, _)| *marker == marker_id)
+            .map(|(_, entry)| entry.clone()))
+    }
+
+    async fn update_entry(
+        &self,
+        marker_id: i32,
+        entry_id: i32,
+        date: NaiveDate,
+        value: &MarkerValue,
+        replace_day: bool,
+    ) -> RepoResult<bool> {
+        let mut state = self.state();
+        match state.entries.get_mut(&entry_id) {
+            Some((marker, entry)) if *marker == marker_id => {
+                entry.value = value.value;
+                entry.date = date;
+                entry.logged_at = value.logged_at;
+                entry.note = value.note.clone();
+            }
+            _ => return Ok(false),
+        }
+        if replace_day {
+            state.entries.retain(|id, (marker, entry)| {
+                *id == entry_id || *marker != marker_id || entry.date != date
+            });
+        }
+        Ok(true)
+    }
+
+    async fn delete_entry(&self, marker_id: i32, entry_id: i32) -> RepoResult<bool> {
+        let mut state = self.state();
+        match state.entries.get(&entry_id) {
+            Some((marker, _)) if *marker == marker_id => {
+                state.entries.remove(&entry_id);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
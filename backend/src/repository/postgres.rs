@@ -0,0 +1,1346 @@
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime};
+use sqlx::postgres::PgRow;
+use sqlx::{PgConnection, PgPool, Row};
+use std::collections::HashMap;
+use tracing::instrument;
+
+use super::{
+    CurrentRoutine, ExerciseRecord, ExerciseRepository, LoggedWorkout, MarkerRepository,
+    PlannedExercise, PrRecord, PrRepository, RecordSet, Removal, RepoResult, RoutineRepository,
+    WorkoutRepository,
+};
+use crate::exercises::{ExerciseStats, PersonalRecord};
+use crate::markers::{LogEntry, MarkerInfo, MarkerSettings, MarkerValue, ReadingPolicy};
+use crate::routines::{
+    RoutineExercise, RoutineExerciseDetail, RoutineExerciseView, RoutineInfo, RoutineStats,
+    RoutineVersion, VolumePoint,
+};
+use crate::workouts::{Exercise, Set, WorkoutSummary};
+
+pub(crate) struct PgRepository {
+    pool: PgPool,
+}
+
+impl PgRepository {
+    pub(crate) fn new(pool: PgPool) -> Self {
+        PgRepository { pool }
+    }
+}
+
+#[async_trait]
+impl ExerciseRepository for PgRepository {
+    async fn existing(&self, ids: &[i32]) -> RepoResult<Vec<i32>> {
+        sqlx::query_scalar(
+            "SELECT ExerciseID::INTEGER FROM ExerciseList WHERE ExerciseID = ANY($1)",
+        )
+        .bind(ids)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn search(
+        &self,
+        partial_name: &str,
+        limit: Option<i64>,
+    ) -> RepoResult<Vec<ExerciseRecord>> {
+        let search_term = format!("%{}%", partial_name);
+        sqlx::query_as!(
+            ExerciseRecord,
+            r#"
+            SELECT
+                ExerciseID as exercise_id,
+                ExerciseName as name,
+                MusclesTrained as muscles_trained,
+                ExerciseType as exercise_type
+            FROM ExerciseList
+            WHERE ExerciseName ILIKE $1
+            ORDER BY ExerciseName
+            LIMIT $2
+            "#,
+            search_term,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn get(&self, exercise_id: i32) -> RepoResult<Option<ExerciseRecord>> {
+        sqlx::query_as!(
+            ExerciseRecord,
+            r#"
+            SELECT
+                ExerciseID as exercise_id,
+                ExerciseName as name,
+                MusclesTrained as muscles_trained,
+                ExerciseType as exercise_type
+            FROM ExerciseList
+            WHERE ExerciseID = $1
+            "#,
+            exercise_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn find_by_name(&self, name: &str) -> RepoResult<Option<ExerciseRecord>> {
+        sqlx::query_as!(
+            ExerciseRecord,
+            r#"
+            SELECT
+                ExerciseID as exercise_id,
+                ExerciseName as name,
+                MusclesTrained as muscles_trained,
+                ExerciseType as exercise_type
+            FROM ExerciseList
+            WHERE ExerciseName ILIKE $1
+            "#,
+            name
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn create(
+        &self,
+        name: &str,
+        muscles_trained: &[String],
+        exercise_type: &str,
+    ) -> RepoResult<ExerciseRecord> {
+        sqlx::query_as!(
+            ExerciseRecord,
+            r#"
+            INSERT INTO ExerciseList (ExerciseName, MusclesTrained, ExerciseType)
+            VALUES ($1, $2, $3)
+            RETURNING
+                ExerciseID as exercise_id,
+                ExerciseName as name,
+                MusclesTrained as muscles_trained,
+                ExerciseType as exercise_type
+            "#,
+            name,
+            muscles_trained,
+            exercise_type
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn update(
+        &self,
+        exercise_id: i32,
+        name: &str,
+        muscles_trained: &[String],
+        exercise_type: &str,
+    ) -> RepoResult<Option<ExerciseRecord>> {
+        sqlx::query_as!(
+            ExerciseRecord,
+            r#"
+            UPDATE ExerciseList
+            SET ExerciseName = $1, MusclesTrained = $2, ExerciseType = $3
+            WHERE ExerciseID = $4
+            RETURNING
+                ExerciseID as exercise_id,
+                ExerciseName as name,
+                MusclesTrained as muscles_trained,
+                ExerciseType as exercise_type
+            "#,
+            name,
+            muscles_trained,
+            exercise_type,
+            exercise_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn delete(&self, exercise_id: i32) -> RepoResult<Removal> {
+        let result = sqlx::query!(
+            "DELETE FROM ExerciseList WHERE ExerciseID = $1",
+            exercise_id
+        )
+        .execute(&self.pool)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => Ok(Removal::NotFound),
+            Ok(_) => Ok(Removal::Deleted),
+            // Routines, workouts and PRs reference the exercise
+            Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => Ok(Removal::InUse),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn volume_history(&self, exercise_id: i32) -> RepoResult<Vec<ExerciseStats>> {
+        let rows = sqlx::query(
+            r#"SELECT w.Start, SUM(s.Weight::BIGINT * s.Reps)::BIGINT AS volume
+             FROM Workout w
+             JOIN Workout_Exercises_Sets wes ON w.WorkoutID = wes.WorkoutID
+             JOIN "Set" s ON wes.SetID = s.SetID
+             WHERE wes.ExerciseID = $1
+             GROUP BY w.Start
+             ORDER BY w.Start"#,
+        )
+        .bind(exercise_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| ExerciseStats {
+                date: row.get::<NaiveDateTime, _>("start").and_utc(),
+                value: row.get::<i64, _>("volume") as f64,
+            })
+            .collect())
+    }
+
+    async fn max_weight_history(&self, exercise_id: i32) -> RepoResult<Vec<ExerciseStats>> {
+        let rows = sqlx::query(
+            r#"SELECT w.Start, MAX(s.Weight) AS max_weight
+             FROM Workout w
+             JOIN Workout_Exercises_Sets wes ON w.WorkoutID = wes.WorkoutID
+             JOIN "Set" s ON wes.SetID = s.SetID
+             WHERE wes.ExerciseID = $1
+             GROUP BY w.Start
+             ORDER BY w.Start"#,
+        )
+        .bind(exercise_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| ExerciseStats {
+                date: row.get::<NaiveDateTime, _>("start").and_utc(),
+                value: f64::from(row.get::<i16, _>("max_weight")),
+            })
+            .collect())
+    }
+}
+
+// Stores the exercises of a routine's current definition
+async fn insert_exercises(
+    conn: &mut PgConnection,
+    routine_id: i32,
+    exercises: &[RoutineExercise],
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO Routines_Exercises_Sets
+             (RoutineID, Position, ExerciseID, NumberOfSets, TargetReps, TargetWeight)
+         SELECT $1, position - 1, exercise_id, sets, target_reps, target_weight
+         FROM UNNEST($2::INTEGER[], $3::INTEGER[], $4::SMALLINT[], $5::SMALLINT[])
+              WITH ORDINALITY AS e(exercise_id, sets, target_reps, target_weight, position)",
+    )
+    .bind(routine_id)
+    .bind(
+        exercises
+            .iter()
+            .map(|e| e.exercise_id)
+            .collect::<Vec<i32>>(),
+    )
+    .bind(exercises.iter().map(|e| e.sets).collect::<Vec<i32>>())
+    .bind(
+        exercises
+            .iter()
+            .map(|e| e.target_reps)
+            .collect::<Vec<Option<i16>>>(),
+    )
+    .bind(
+        exercises
+            .iter()
+            .map(|e| e.target_weight)
+            .collect::<Vec<Option<i16>>>(),
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+// Creates a routine at version 1 and returns its ID. Takes a connection so
+// imports can create routines inside their own transaction.
+pub(crate) async fn insert_routine(
+    conn: &mut PgConnection,
+    name: &str,
+    created_at: NaiveDateTime,
+    exercises: &[RoutineExercise],
+) -> Result<i32, sqlx::Error> {
+    let routine_id: i32 = sqlx::query(
+        "INSERT INTO Routines (RoutineName, Timestamp) VALUES ($1, $2) RETURNING RoutineID",
+    )
+    .bind(name)
+    .bind(created_at)
+    .fetch_one(&mut *conn)
+    .await?
+    .get("routineid");
+    insert_exercises(conn, routine_id, exercises).await?;
+    save_version(conn, routine_id, name, exercises).await?;
+    Ok(routine_id)
+}
+
+// Records a routine definition as a new version unless it matches the latest
+// one. Returns the version number.
+async fn save_version(
+    conn: &mut PgConnection,
+    routine_id: i32,
+    name: &str,
+    exercises: &[RoutineExercise],
+) -> Result<i32, sqlx::Error> {
+    let latest = sqlx::query(
+        "SELECT Version, RoutineName FROM RoutineVersions
+         WHERE RoutineID = $1 ORDER BY Version DESC LIMIT 1",
+    )
+    .bind(routine_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let version = match latest {
+        Some(row) => {
+            let version: i32 = row.get("version");
+            let current: Vec<RoutineExercise> = sqlx::query(
+                "SELECT ExerciseID::INTEGER AS exerciseid, NumberOfSets::INTEGER AS numberofsets,
+                        TargetReps, TargetWeight
+                 FROM RoutineVersionExercises
+                 WHERE RoutineID = $1 AND Version = $2 ORDER BY Position",
+            )
+            .bind(routine_id)
+            .bind(version)
+            .fetch_all(&mut *conn)
+            .await?
+            .iter()
+            .map(|row| RoutineExercise {
+                exercise_id: row.get("exerciseid"),
+                sets: row.get("numberofsets"),
+                target_reps: row.get("targetreps"),
+                target_weight: row.get("targetweight"),
+            })
+            .collect();
+
+            if row.get::<String, _>("routinename") == name && current == exercises {
+                return Ok(version);
+            }
+            version + 1
+        }
+        None => 1,
+    };
+
+    sqlx::query(
+        "INSERT INTO RoutineVersions (RoutineID, Version, RoutineName) VALUES ($1, $2, $3)",
+    )
+    .bind(routine_id)
+    .bind(version)
+    .bind(name)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO RoutineVersionExercises
+             (RoutineID, Version, Position, ExerciseID, NumberOfSets, TargetReps, TargetWeight)
+         SELECT $1, $2, * FROM UNNEST($3::SMALLINT[], $4::INTEGER[], $5::INTEGER[],
+                                      $6::SMALLINT[], $7::SMALLINT[])",
+    )
+    .bind(routine_id)
+    .bind(version)
+    .bind((0..exercises.len() as i16).collect::<Vec<i16>>())
+    .bind(
+        exercises
+            .iter()
+            .map(|e| e.exercise_id)
+            .collect::<Vec<i32>>(),
+    )
+    .bind(exercises.iter().map(|e| e.sets).collect::<Vec<i32>>())
+    .bind(
+        exercises
+            .iter()
+            .map(|e| e.target_reps)
+            .collect::<Vec<Option<i16>>>(),
+    )
+    .bind(
+        exercises
+            .iter()
+            .map(|e| e.target_weight)
+            .collect::<Vec<Option<i16>>>(),
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(version)
+}
+
+#[async_trait]
+impl RoutineRepository for PgRepository {
+    async fn list(&self, include_last_performed: bool) -> RepoResult<Vec<RoutineInfo>> {
+        let query = if include_last_performed {
+            "SELECT r.RoutineID, r.RoutineName, r.Timestamp,
+             (SELECT MAX(w.Start::date) FROM Workout w WHERE w.RoutineID = r.RoutineID) as last_performed
+             FROM Routines r ORDER BY r.Timestamp ASC"
+        } else {
+            "SELECT r.RoutineID, r.RoutineName, r.Timestamp,
+             NULL as last_performed
+             FROM Routines r ORDER BY r.Timestamp ASC"
+        };
+
+        let rows = sqlx::query(query).fetch_all(&self.pool).await?;
+        Ok(rows
+            .iter()
+            .map(|row| RoutineInfo {
+                routine_id: row.get("routineid"),
+                name: row.get("routinename"),
+                timestamp: row.get("timestamp"),
+                last_performed: row.get("last_performed"),
+            })
+            .collect())
+    }
+
+    async fn find_by_name(&self, name: &str) -> RepoResult<Option<i32>> {
+        sqlx::query_scalar("SELECT RoutineID FROM Routines WHERE RoutineName = $1")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn exists(&self, routine_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query("SELECT routineid FROM Routines WHERE routineid = $1")
+            .bind(routine_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(result.is_some())
+    }
+
+    async fn existing(&self, ids: &[i32]) -> RepoResult<Vec<i32>> {
+        sqlx::query_scalar("SELECT RoutineID FROM Routines WHERE RoutineID = ANY($1)")
+            .bind(ids)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn create(
+        &self,
+        name: &str,
+        created_at: NaiveDateTime,
+        exercises: &[RoutineExercise],
+    ) -> RepoResult<i32> {
+        // Insert the routine with its exercises and sets in one transaction
+        let mut tx = self.pool.begin().await?;
+        let routine_id = insert_routine(&mut tx, name, created_at, exercises).await?;
+        tx.commit().await?;
+        Ok(routine_id)
+    }
+
+    async fn update(
+        &self,
+        routine_id: i32,
+        name: &str,
+        exercises: &[RoutineExercise],
+    ) -> RepoResult<Option<i32>> {
+        // Start a transaction; it rolls back if dropped before the commit
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query("UPDATE Routines SET RoutineName = $1 WHERE RoutineID = $2")
+            .bind(name)
+            .bind(routine_id)
+            .execute(&mut *tx)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        // Replace the exercise associations
+        sqlx::query("DELETE FROM Routines_Exercises_Sets WHERE RoutineID = $1")
+            .bind(routine_id)
+            .execute(&mut *tx)
+            .await?;
+        insert_exercises(&mut tx, routine_id, exercises).await?;
+
+        let version = save_version(&mut tx, routine_id, name, exercises).await?;
+        tx.commit().await?;
+        Ok(Some(version))
+    }
+
+    async fn delete(&self, routine_id: i32) -> RepoResult<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        // Delete from Routines_Exercises_Sets first
+        sqlx::query("DELETE FROM Routines_Exercises_Sets WHERE RoutineID = $1")
+            .bind(routine_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let result = sqlx::query("DELETE FROM Routines WHERE RoutineID = $1")
+            .bind(routine_id)
+            .execute(&mut *tx)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn planned_exercises(&self, routine_id: i32) -> RepoResult<Vec<PlannedExercise>> {
+        let rows = sqlx::query(
+            r#"SELECT e.exerciseid, e.exercisename, r.numberofsets
+             FROM ExerciseList e
+             JOIN Routines_Exercises_Sets r ON e.exerciseid = r.exerciseid
+             WHERE r.routineid = $1
+             ORDER BY r.position NULLS LAST, e.exerciseid"#,
+        )
+        .bind(routine_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| PlannedExercise {
+                exercise_id: row.get("exerciseid"),
+                exercise_name: row.get("exercisename"),
+                sets: row.get("numberofsets"),
+            })
+            .collect())
+    }
+
+    async fn current(&self, routine_id: i32) -> RepoResult<Option<CurrentRoutine>> {
+        let Some(routine) = sqlx::query(
+            "SELECT RoutineName,
+                    (SELECT MAX(Version) FROM RoutineVersions v WHERE v.RoutineID = r.RoutineID) AS version
+             FROM Routines r WHERE RoutineID = $1",
+        )
+        .bind(routine_id)
+        .fetch_optional(&self.pool)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let exercises = sqlx::query(
+            "SELECT res.ExerciseID::INTEGER AS exerciseid, e.ExerciseName, e.MusclesTrained,
+                    res.NumberOfSets::INTEGER AS numberofsets, res.TargetReps, res.TargetWeight
+             FROM Routines_Exercises_Sets res
+             JOIN ExerciseList e ON res.ExerciseID = e.ExerciseID
+             WHERE res.RoutineID = $1
+             ORDER BY res.Position NULLS LAST, res.ExerciseID",
+        )
+        .bind(routine_id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .enumerate()
+        .map(|(i, row)| RoutineExerciseView {
+            position: i as i32 + 1,
+            exercise_id: row.get("exerciseid"),
+            exercise_name: row.get("exercisename"),
+            muscles_trained: row.get("musclestrained"),
+            sets: row.get("numberofsets"),
+            target_reps: row.get("targetreps"),
+            target_weight: row.get("targetweight"),
+        })
+        .collect();
+
+        Ok(Some(CurrentRoutine {
+            name: routine.get("routinename"),
+            version: routine.get("version"),
+            exercises,
+        }))
+    }
+
+    async fn stats(&self, routine_id: i32) -> RepoResult<RoutineStats> {
+        let summary = sqlx::query(
+            r#"SELECT COUNT(*) AS times_performed, MAX(start) AS last_performed,
+                      AVG(EXTRACT(EPOCH FROM ("end" - start)) / 60)::FLOAT8 AS average_duration
+               FROM Workout
+               WHERE routineid = $1"#,
+        )
+        .bind(routine_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        let volume_trend = sqlx::query(
+            r#"SELECT w.workoutid, w.start::DATE AS date,
+                      COALESCE(SUM(s.weight::BIGINT * s.reps), 0)::BIGINT AS volume
+               FROM Workout w
+               LEFT JOIN Workout_Exercises_Sets wes ON wes.workoutid = w.workoutid
+               LEFT JOIN "Set" s ON s.setid = wes.setid
+               WHERE w.routineid = $1
+               GROUP BY w.workoutid, w.start
+               ORDER BY w.start"#,
+        )
+        .bind(routine_id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| VolumePoint {
+            workout_id: row.get("workoutid"),
+            date: row.get("date"),
+            volume: row.get("volume"),
+        })
+        .collect();
+
+        Ok(RoutineStats {
+            times_performed: summary.get("times_performed"),
+            last_performed: summary.get("last_performed"),
+            average_duration_minutes: summary.get("average_duration"),
+            volume_trend,
+        })
+    }
+
+    async fn versions(&self, routine_id: i32) -> RepoResult<Vec<RoutineVersion>> {
+        let mut exercises: HashMap<i32, Vec<RoutineExerciseDetail>> = HashMap::new();
+        for row in sqlx::query(
+            "SELECT ve.Version, ve.ExerciseID::INTEGER AS exerciseid, e.ExerciseName,
+                    ve.NumberOfSets::INTEGER AS numberofsets, ve.TargetReps, ve.TargetWeight
+             FROM RoutineVersionExercises ve
+             JOIN ExerciseList e ON ve.ExerciseID = e.ExerciseID
+             WHERE ve.RoutineID = $1
+             ORDER BY ve.Version, ve.Position",
+        )
+        .bind(routine_id)
+        .fetch_all(&self.pool)
+        .await?
+        {
+            exercises
+                .entry(row.get("version"))
+                .or_default()
+                .push(RoutineExerciseDetail {
+                    exercise_id: row.get("exerciseid"),
+                    exercise_name: row.get("exercisename"),
+                    sets: row.get("numberofsets"),
+                    target_reps: row.get("targetreps"),
+                    target_weight: row.get("targetweight"),
+                });
+        }
+
+        let versions = sqlx::query(
+            "SELECT v.Version, v.RoutineName, v.CreatedAt,
+                    (SELECT COUNT(*) FROM Workout w
+                     WHERE w.RoutineID = v.RoutineID AND w.RoutineVersion = v.Version) AS workouts
+             FROM RoutineVersions v
+             WHERE v.RoutineID = $1
+             ORDER BY v.Version",
+        )
+        .bind(routine_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(versions
+            .iter()
+            .map(|row| {
+                let version: i32 = row.get("version");
+                RoutineVersion {
+                    version,
+                    name: row.get("routinename"),
+                    created_at: row.get("createdat"),
+                    workouts: row.get("workouts"),
+                    exercises: exercises.remove(&version).unwrap_or_default(),
+                    changes: None,
+                }
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl WorkoutRepository for PgRepository {
+    async fn list(&self, limit: Option<i64>) -> RepoResult<Vec<WorkoutSummary>> {
+        let rows = sqlx::query(
+            r#"SELECT w.workoutid, w.start, r.routinename
+             FROM Workout w
+             LEFT JOIN Routines r ON w.routineid = r.routineid
+             ORDER BY w.start DESC
+             LIMIT $1"#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| WorkoutSummary {
+                workout_id: row.get("workoutid"),
+                routine_name: row.get("routinename"),
+                start_time: row.get("start"),
+            })
+            .collect())
+    }
+
+    async fn exists(&self, workout_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query("SELECT workoutid FROM Workout WHERE workoutid = $1")
+            .bind(workout_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(result.is_some())
+    }
+
+    async fn get(&self, workout_id: i32) -> RepoResult<Option<LoggedWorkout>> {
+        let rows = sqlx::query(
+            r#"SELECT w.routineid, w.routineversion, r.routinename, e.exerciseid, e.exercisename,
+               s.weight, s.reps, wes.setid
+             FROM Workout w
+             JOIN Workout_Exercises_Sets wes ON w.workoutid = wes.workoutid
+             JOIN ExerciseList e ON wes.exerciseid = e.exerciseid
+             JOIN "Set" s ON wes.setid = s.setid
+             LEFT JOIN Routines r ON w.routineid = r.routineid
+             WHERE w.workoutid = $1
+             ORDER BY e.exerciseid, s.setid"#,
+        )
+        .bind(workout_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let Some(first) = rows.first() else {
+            return Ok(None);
+        };
+        let mut workout = LoggedWorkout {
+            routine_id: first.get("routineid"),
+            routine_version: first.get("routineversion"),
+            routine_name: first.get("routinename"),
+            exercises: Vec::new(),
+        };
+
+        // Rows come grouped by exercise, so a new ID starts the next exercise
+        for row in &rows {
+            let exercise_id: i32 = row.get("exerciseid");
+            if workout.exercises.last().map(|e| e.exercise_id) != Some(exercise_id) {
+                workout.exercises.push(Exercise {
+                    exercise_id,
+                    exercise_name: row.get("exercisename"),
+                    sets: HashMap::new(),
+                });
+            }
+            let exercise = workout.exercises.last_mut().unwrap();
+            let set_number = exercise.sets.len() as i16 + 1;
+            exercise.sets.insert(
+                set_number,
+                Set {
+                    weight: row.get("weight"),
+                    reps: row.get("reps"),
+                },
+            );
+        }
+        Ok(Some(workout))
+    }
+
+    async fn create(
+        &self,
+        start: Option<NaiveDateTime>,
+        end: Option<NaiveDateTime>,
+        routine_id: Option<i32>,
+    ) -> RepoResult<i32> {
+        sqlx::query_scalar(
+            r#"INSERT INTO Workout (start, "end", routineid, routineversion)
+                 VALUES ($1, $2, $3,
+                         (SELECT MAX(version) FROM RoutineVersions WHERE routineid = $3))
+                 RETURNING workoutid"#,
+        )
+        .bind(start)
+        .bind(end)
+        .bind(routine_id)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn add_sets(&self, workout_id: i32, exercise: &Exercise) -> RepoResult<()> {
+        // SetIDs follow the set numbers, which is how get() numbers them back
+        let mut numbers: Vec<&i16> = exercise.sets.keys().collect();
+        numbers.sort();
+        for set in numbers.into_iter().map(|number| &exercise.sets[number]) {
+            let set_id: i32 = sqlx::query_scalar(
+                r#"INSERT INTO "Set" (weight, reps)
+                 VALUES ($1, $2)
+                 RETURNING setid"#,
+            )
+            .bind(set.weight)
+            .bind(set.reps)
+            .fetch_one(&self.pool)
+            .await?;
+
+            sqlx::query(
+                "INSERT INTO Workout_Exercises_Sets (workoutid, exerciseid, setid)
+                 VALUES ($1, $2, $3)",
+            )
+            .bind(workout_id)
+            .bind(exercise.exercise_id)
+            .bind(set_id)
+            .execute(&self.pool)
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, workout_id: i32) -> RepoResult<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let exercise_ids: Vec<i32> = sqlx::query_scalar(
+            "SELECT DISTINCT exerciseid::INTEGER FROM Workout_Exercises_Sets WHERE workoutid = $1",
+        )
+        .bind(workout_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        // Rep records point at the workout's PRs; rebuild_prs restores the rest
+        sqlx::query("DELETE FROM HighestRepsPerWeight WHERE exerciseid = ANY($1)")
+            .bind(&exercise_ids)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM PRs WHERE workoutid = $1")
+            .bind(workout_id)
+            .execute(&mut *tx)
+            .await?;
+        let set_ids: Vec<i32> = sqlx::query_scalar(
+            "DELETE FROM Workout_Exercises_Sets WHERE workoutid = $1 RETURNING setid",
+        )
+        .bind(workout_id)
+        .fetch_all(&mut *tx)
+        .await?;
+        sqlx::query(r#"DELETE FROM "Set" WHERE setid = ANY($1)"#)
+            .bind(&set_ids)
+            .execute(&mut *tx)
+            .await?;
+
+        let result = sqlx::query("DELETE FROM Workout WHERE workoutid = $1")
+            .bind(workout_id)
+            .execute(&mut *tx)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        rebuild_prs(&mut tx, &exercise_ids).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl PrRepository for PgRepository {
+    async fn latest(&self, exercise_id: i32) -> RepoResult<Option<PrRecord>> {
+        let row = sqlx::query(
+            "SELECT heaviestweight, onerm, setvolume FROM PRs
+             WHERE exerciseid = $1
+             ORDER BY prid DESC LIMIT 1",
+        )
+        .bind(exercise_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| PrRecord {
+            heaviest_weight: row.get("heaviestweight"),
+            one_rm: row.get("onerm"),
+            set_volume: row.get("setvolume"),
+        }))
+    }
+
+    async fn highest_reps(&self, exercise_id: i32, weight: i16) -> RepoResult<Option<i16>> {
+        sqlx::query_scalar(
+            "SELECT highestreps FROM HighestRepsPerWeight
+             WHERE exerciseid = $1 AND weight = $2",
+        )
+        .bind(exercise_id)
+        .bind(weight)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn best_excluding(
+        &self,
+        exercise_id: i32,
+        workout_id: i32,
+    ) -> RepoResult<Option<(i16, f32)>> {
+        let row = sqlx::query(
+            "SELECT MAX(heaviestweight) AS weight, MAX(onerm) AS one_rm FROM PRs
+             WHERE exerciseid = $1 AND workoutid <> $2",
+        )
+        .bind(exercise_id)
+        .bind(workout_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        let weight: Option<i16> = row.get("weight");
+        let one_rm: Option<f32> = row.get("one_rm");
+        Ok(weight.zip(one_rm))
+    }
+
+    async fn history(&self, exercise_id: i32) -> RepoResult<Vec<PersonalRecord>> {
+        let rows = sqlx::query(
+            "SELECT w.Start, p.HeaviestWeight, p.OneRM, p.SetVolume
+             FROM PRs p
+             JOIN Workout w ON p.WorkoutID = w.WorkoutID
+             WHERE p.ExerciseID = $1
+             ORDER BY p.OneRM DESC, p.HeaviestWeight DESC, p.SetVolume DESC",
+        )
+        .bind(exercise_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| PersonalRecord {
+                workout_date: row.get::<NaiveDateTime, _>("start").and_utc(),
+                weight: row.get("heaviestweight"),
+                reps: 0,
+                one_rm: row.get("onerm"),
+                set_volume: row.get("setvolume"),
+            })
+            .collect())
+    }
+
+    async fn recent_records(&self, limit: i64) -> RepoResult<Vec<RecordSet>> {
+        let rows = sqlx::query(
+            r#"SELECT workoutid, start, exerciseid, exercisename, heaviestweight, onerm, setvolume
+             FROM (
+                 SELECT p.workoutid, w.start, p.exerciseid::INTEGER AS exerciseid, e.exercisename,
+                        p.heaviestweight, p.onerm, p.setvolume,
+                        MAX(p.heaviestweight) OVER earlier AS previous_weight,
+                        MAX(p.onerm) OVER earlier AS previous_one_rm
+                 FROM PRs p
+                 JOIN Workout w ON p.workoutid = w.workoutid
+                 JOIN ExerciseList e ON p.exerciseid = e.exerciseid
+                 WINDOW earlier AS (
+                     PARTITION BY p.exerciseid ORDER BY w.start, p.workoutid
+                     ROWS BETWEEN UNBOUNDED PRECEDING AND 1 PRECEDING
+                 )
+             ) bests
+             WHERE (heaviestweight > 0 OR onerm > 0)
+               AND (previous_weight IS NULL
+                    OR heaviestweight > previous_weight
+                    OR onerm > previous_one_rm)
+             ORDER BY start DESC, workoutid DESC, exercisename
+             LIMIT $1"#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| RecordSet {
+                workout_id: row.get("workoutid"),
+                start_time: row.get("start"),
+                exercise_id: row.get("exerciseid"),
+                exercise_name: row.get("exercisename"),
+                heaviest_weight: row.get("heaviestweight"),
+                one_rm: row.get("onerm"),
+                set_volume: row.get("setvolume"),
+            })
+            .collect())
+    }
+
+    async fn record(
+        &self,
+        workout_id: i32,
+        exercise_id: i32,
+        pr: PrRecord,
+        highest_reps: &HashMap<i16, i16>,
+    ) -> RepoResult<()> {
+        let pr_id: i32 = sqlx::query_scalar(
+            "INSERT INTO PRs (heaviestweight, onerm, setvolume, exerciseid, workoutid)
+             VALUES ($1, $2, $3, $4, $5)
+             RETURNING prid",
+        )
+        .bind(pr.heaviest_weight)
+        .bind(pr.one_rm)
+        .bind(pr.set_volume)
+        .bind(exercise_id)
+        .bind(workout_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        for (weight, reps) in highest_reps {
+            sqlx::query(
+                "INSERT INTO HighestRepsPerWeight (weight, highestreps, exerciseid, prid)
+                 VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (exerciseid, weight)
+                 DO UPDATE SET highestreps = EXCLUDED.highestreps, prid = EXCLUDED.prid
+                 WHERE HighestRepsPerWeight.highestreps < EXCLUDED.highestreps",
+            )
+            .bind(weight)
+            .bind(reps)
+            .bind(exercise_id)
+            .bind(pr_id)
+            .execute(&self.pool)
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+// Recomputes PRs and HighestRepsPerWeight for the given exercises from the
+// full workout history, matching what service::update_prs records workout by
+// workout
+#[instrument(skip_all, fields(exercises = exercise_ids.len()))]
+pub(crate) async fn rebuild_prs(
+    conn: &mut PgConnection,
+    exercise_ids: &[i32],
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM HighestRepsPerWeight WHERE exerciseid = ANY($1)")
+        .bind(exercise_ids)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query("DELETE FROM PRs WHERE exerciseid = ANY($1)")
+        .bind(exercise_ids)
+        .execute(&mut *conn)
+        .await?;
+
+    // PRIDs are assigned in workout order so the latest row stays the newest
+    sqlx::query(
+        r#"INSERT INTO PRs (heaviestweight, onerm, setvolume, exerciseid, workoutid)
+         SELECT MAX(s.weight),
+                MAX(CASE WHEN s.reps < 37 THEN s.weight * (36.0 / (37.0 - s.reps)) ELSE 0 END),
+                SUM(s.weight::INTEGER * s.reps),
+                wes.exerciseid,
+                wes.workoutid
+         FROM Workout_Exercises_Sets wes
+         JOIN "Set" s ON wes.setid = s.setid
+         JOIN Workout w ON wes.workoutid = w.workoutid
+         WHERE wes.exerciseid = ANY($1)
+         GROUP BY wes.workoutid, wes.exerciseid, w.start
+         ORDER BY w.start, wes.workoutid"#,
+    )
+    .bind(exercise_ids)
+    .execute(&mut *conn)
+    .await?;
+
+    // Earliest workout in which the best rep count at each weight was reached
+    sqlx::query(
+        r#"INSERT INTO HighestRepsPerWeight (weight, highestreps, exerciseid, prid)
+         SELECT DISTINCT ON (wes.exerciseid, s.weight) s.weight, s.reps, wes.exerciseid, p.prid
+         FROM Workout_Exercises_Sets wes
+         JOIN "Set" s ON wes.setid = s.setid
+         JOIN Workout w ON wes.workoutid = w.workoutid
+         JOIN PRs p ON p.workoutid = wes.workoutid AND p.exerciseid = wes.exerciseid
+         WHERE wes.exerciseid = ANY($1)
+         ORDER BY wes.exerciseid, s.weight, s.reps DESC, w.start ASC"#,
+    )
+    .bind(exercise_ids)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn fetch_marker_settings(
+    pool: &PgPool,
+    marker_id: i32,
+) -> Result<Option<MarkerSettings>, sqlx::Error> {
+    let row = sqlx::query(
+        "SELECT Unit, DecimalPlaces, Direction, GoalValue, GoalDate, ReadingPolicy, Formula
+         FROM MarkerList WHERE MarkerID = $1",
+    )
+    .bind(marker_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.as_ref().map(marker_settings_from_row))
+}
+
+// See MarkerRepository::readings; derived.rs reads the markers a formula
+// refers to through it too
+pub(crate) async fn fetch_readings(
+    pool: &PgPool,
+    marker_id: i32,
+    policy: ReadingPolicy,
+    from: Option<NaiveDate>,
+    to: NaiveDate,
+) -> Result<Vec<(NaiveDate, f64)>, sqlx::Error> {
+    let query = match policy {
+        ReadingPolicy::Average => {
+            "SELECT AVG(Value)::REAL AS value, Date FROM Markers
+              WHERE MarkerID = $1 AND ($2::DATE IS NULL OR Date >= $2) AND Date <= $3
+              GROUP BY Date
+              ORDER BY Date ASC"
+        }
+        // Days logged before the marker switched to replace can still hold
+        // several readings; the latest one counts
+        ReadingPolicy::Replace => {
+            "SELECT DISTINCT ON (Date) Value, Date FROM Markers
+              WHERE MarkerID = $1 AND ($2::DATE IS NULL OR Date >= $2) AND Date <= $3
+              ORDER BY Date ASC, LoggedAt DESC NULLS LAST, EntryID DESC"
+        }
+        ReadingPolicy::Keep => {
+            "SELECT Value, Date FROM Markers
+              WHERE MarkerID = $1 AND ($2::DATE IS NULL OR Date >= $2) AND Date <= $3
+              ORDER BY Date ASC, LoggedAt ASC NULLS FIRST, EntryID ASC"
+        }
+    };
+
+    let rows = sqlx::query(query)
+        .bind(marker_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| (row.get("date"), f64::from(row.get::<f32, _>("value"))))
+        .collect())
+}
+
+const MARKER_COLUMNS: &str = "MarkerID, MarkerName, Clr, Unit, DecimalPlaces, Direction, GoalValue,
+     GoalDate, ReadingPolicy, Formula";
+
+fn marker_settings_from_row(row: &PgRow) -> MarkerSettings {
+    MarkerSettings {
+        unit: row.get("unit"),
+        precision: row.get("decimalplaces"),
+        direction: row
+            .get::<String, _>("direction")
+            .parse()
+            .unwrap_or_default(),
+        goal_value: row.get::<Option<f32>, _>("goalvalue").map(f64::from),
+        goal_date: row.get("goaldate"),
+        reading_policy: row
+            .get::<String, _>("readingpolicy")
+            .parse()
+            .unwrap_or_default(),
+        formula: row.get("formula"),
+    }
+}
+
+fn marker_from_row(row: &PgRow) -> MarkerInfo {
+    MarkerInfo {
+        marker_id: row.get("markerid"),
+        name: row.get("markername"),
+        color: row.get("clr"),
+        settings: marker_settings_from_row(row),
+    }
+}
+
+fn log_entry_from_row(row: &PgRow) -> LogEntry {
+    LogEntry {
+        entry_id: row.get("entryid"),
+        value: row.get::<f32, _>("value") as f64,
+        date: row.get("date"),
+        logged_at: row.get("loggedat"),
+        note: row.get("note"),
+    }
+}
+
+#[async_trait]
+impl MarkerRepository for PgRepository {
+    async fn list(&self) -> RepoResult<Vec<MarkerInfo>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM MarkerList ORDER BY MarkerName, MarkerID",
+            MARKER_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.iter().map(marker_from_row).collect())
+    }
+
+    async fn get(&self, marker_id: i32) -> RepoResult<Option<MarkerInfo>> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM MarkerList WHERE MarkerID = $1",
+            MARKER_COLUMNS
+        ))
+        .bind(marker_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.as_ref().map(marker_from_row))
+    }
+
+    async fn find_by_name(&self, name: &str) -> RepoResult<Option<i32>> {
+        sqlx::query_scalar("SELECT MarkerID FROM MarkerList WHERE MarkerName = $1")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn settings(&self, marker_id: i32) -> RepoResult<Option<MarkerSettings>> {
+        fetch_marker_settings(&self.pool, marker_id).await
+    }
+
+    async fn create(&self, name: &str, color: &str, settings: &MarkerSettings) -> RepoResult<i32> {
+        sqlx::query_scalar(
+            "INSERT INTO MarkerList
+                (MarkerName, Clr, Unit, DecimalPlaces, Direction, GoalValue, GoalDate, ReadingPolicy, Formula)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING MarkerID",
+        )
+        .bind(name)
+        .bind(color)
+        .bind(&settings.unit)
+        .bind(settings.precision)
+        .bind(settings.direction.to_string())
+        .bind(settings.goal_value.map(|v| v as f32))
+        .bind(settings.goal_date)
+        .bind(settings.reading_policy.to_string())
+        .bind(&settings.formula)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn update(
+        &self,
+        marker_id: i32,
+        name: &str,
+        color: &str,
+        settings: &MarkerSettings,
+    ) -> RepoResult<bool> {
+        let result = sqlx::query(
+            "UPDATE MarkerList
+             SET MarkerName = $1, Clr = $2, Unit = $3, DecimalPlaces = $4,
+                 Direction = $5, GoalValue = $6, GoalDate = $7, ReadingPolicy = $8, Formula = $9
+             WHERE MarkerID = $10",
+        )
+        .bind(name)
+        .bind(color)
+        .bind(&settings.unit)
+        .bind(settings.precision)
+        .bind(settings.direction.to_string())
+        .bind(settings.goal_value.map(|v| v as f32))
+        .bind(settings.goal_date)
+        .bind(settings.reading_policy.to_string())
+        .bind(&settings.formula)
+        .bind(marker_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete(&self, marker_id: i32) -> RepoResult<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        // Delete from Markers table first
+        sqlx::query("DELETE FROM Markers WHERE MarkerID = $1")
+            .bind(marker_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let result = sqlx::query("DELETE FROM MarkerList WHERE MarkerID = $1")
+            .bind(marker_id)
+            .execute(&mut *tx)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn log(
+        &self,
+        marker_id: i32,
+        date: NaiveDate,
+        value: &MarkerValue,
+        replace_day: bool,
+    ) -> RepoResult<i32> {
+        // Start a transaction; it rolls back if dropped before the commit
+        let mut tx = self.pool.begin().await?;
+
+        if replace_day {
+            sqlx::query("DELETE FROM Markers WHERE MarkerID = $1 AND Date = $2")
+                .bind(marker_id)
+                .bind(date)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let entry_id: i32 = sqlx::query_scalar(
+            "INSERT INTO Markers (MarkerID, Value, Date, LoggedAt, Note)
+             VALUES ($1, $2, $3, $4, $5) RETURNING EntryID",
+        )
+        .bind(marker_id)
+        .bind(value.value as f32)
+        .bind(date)
+        .bind(value.logged_at)
+        .bind(&value.note)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(entry_id)
+    }
+
+    async fn entries(
+        &self,
+        marker_id: i32,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> RepoResult<Vec<LogEntry>> {
+        let rows = sqlx::query(
+            "SELECT EntryID, Value, Date, LoggedAt, Note FROM Markers
+              WHERE MarkerID = $1
+                AND ($2::DATE IS NULL OR Date >= $2)
+                AND ($3::DATE IS NULL OR Date <= $3)
+              ORDER BY Date ASC, LoggedAt ASC NULLS FIRST, EntryID ASC",
+        )
+        .bind(marker_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.iter().map(log_entry_from_row).collect())
+    }
+
+    async fn readings(
+        &self,
+        marker_id: i32,
+        policy: ReadingPolicy,
+        from: Option<NaiveDate>,
+        to: NaiveDate,
+    ) -> RepoResult<Vec<(NaiveDate, f64)>> {
+        fetch_readings(&self.pool, marker_id, policy, from, to).await
+    }
+
+    async fn entry(&self, marker_id: i32, entry_id: i32) -> RepoResult<Option<LogEntry>> {
+        let row = sqlx::query(
+            "SELECT EntryID, Value, Date, LoggedAt, Note FROM Markers
+              WHERE MarkerID = $1 AND EntryID = $2",
+        )
+        .bind(marker_id)
+        .bind(entry_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.as_ref().map(log_entry_from_row))
+    }
+
+    async fn update_entry(
+        &self,
+        marker_id: i32,
+        entry_id: i32,
+        date: NaiveDate,
+        value: &MarkerValue,
+        replace_day: bool,
+    ) -> RepoResult<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(
+            "UPDATE Markers SET Value = $1, Date = $2, LoggedAt = $3, Note = $4
+              WHERE MarkerID = $5 AND EntryID = $6",
+        )
+        .bind(value.value as f32)
+        .bind(date)
+        .bind(value.logged_at)
+        .bind(&value.note)
+        .bind(marker_id)
+        .bind(entry_id)
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        if replace_day {
+            sqlx::query("DELETE FROM Markers WHERE MarkerID = $1 AND Date = $2 AND EntryID <> $3")
+                .bind(marker_id)
+                .bind(date)
+                .bind(entry_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn delete_entry(&self, marker_id: i32, entry_id: i32) -> RepoResult<bool> {
+        let result = sqlx::query("DELETE FROM Markers WHERE MarkerID = $1 AND EntryID = $2")
+            .bind(marker_id)
+            .bind(entry_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,105 @@
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use serde_json::Value;
+use std::time::Instant;
+use tracing::{info, info_span, Instrument};
+use uuid::Uuid;
+
+pub const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+// Probes and scrapes arrive every few seconds and would drown out the
+// requests worth reading
+const QUIET_PATHS: [&str; 3] = ["/healthz", "/readyz", "/metrics"];
+
+// Middleware giving each request a correlation ID: the caller's X-Request-ID
+// if it is a sensible token, otherwise a new UUID. Everything logged while
+// handling the request, SQL statements included, is inside a span carrying
+// the ID; it is echoed in the X-Request-ID response header and added to JSON
+// error bodies as "request_id". Also logs one access line per request.
+pub async fn correlate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let request_id = req
+        .headers()
+        .get(&REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid(id))
+        .map(String::from)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let span = info_span!(
+        "request",
+        request_id = %request_id,
+        method = %req.method(),
+        path = %req.path(),
+    );
+    let quiet = QUIET_PATHS.contains(&req.path());
+    let remote_addr = req
+        .connection_info()
+        .realip_remote_addr()
+        .unwrap_or("-")
+        .to_string();
+    let start = Instant::now();
+
+    let res = next.call(req).instrument(span.clone()).await?;
+    let mut res = add_to_error_body(res, &request_id).await?;
+
+    if !quiet {
+        span.in_scope(|| {
+            info!(
+                status = res.status().as_u16(),
+                duration_ms = start.elapsed().as_millis() as u64,
+                remote_addr = %remote_addr,
+                "request completed"
+            )
+        });
+    }
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        res.headers_mut().insert(REQUEST_ID, value);
+    }
+    Ok(res)
+}
+
+// At most 128 characters of letters, digits and "-_.:", so a caller cannot
+// inject arbitrary text into the logs
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 128
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_.:".contains(&b))
+}
+
+async fn add_to_error_body(
+    res: ServiceResponse<impl MessageBody + 'static>,
+    request_id: &str,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let is_json = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !(res.status().is_client_error() || res.status().is_server_error()) || !is_json {
+        return Ok(res.map_into_boxed_body());
+    }
+
+    let (req, res) = res.into_parts();
+    let (res, body) = res.into_parts();
+    let bytes = body::to_bytes(body)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e.into().to_string()))?;
+
+    let bytes = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(Value::Object(mut fields)) => {
+            fields
+                .entry("request_id")
+                .or_insert_with(|| Value::from(request_id));
+            serde_json::to_vec(&fields).map_or(bytes, Into::into)
+        }
+        _ => bytes,
+    };
+    Ok(ServiceResponse::new(req, res.set_body(BoxBody::new(bytes))))
+}
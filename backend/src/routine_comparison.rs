@@ -0,0 +1,386 @@
+use actix_web::{get, web, HttpResponse};
+use chrono::NaiveDateTime;
+use log::info;
+use serde::Serialize;
+use sqlx::{PgPool, Row};
+use std::collections::HashMap;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::errors::{ApiError, ErrorBody};
+use crate::service::estimated_one_rm;
+
+// One logged session of a routine
+struct Session {
+    workout_id: i32,
+    start: NaiveDateTime,
+    exercises: Vec<SessionExercise>, // In the order they were logged
+}
+
+struct SessionExercise {
+    exercise_id: i32,
+    exercise_name: String,
+    sets: Vec<SetEntry>,
+}
+
+#[derive(Serialize, Clone, Copy, ToSchema)]
+struct SetEntry {
+    weight: i16, // kg
+    reps: i16,
+}
+
+// What one exercise looked like in one session
+#[derive(Serialize, ToSchema)]
+struct ExercisePerformance {
+    workout_id: i32,
+    date: NaiveDateTime,
+    sets: Vec<SetEntry>,
+    volume: i64, // Sum of weight x reps, kg
+    e1rm: f64,   // Best estimated 1RM of the session's sets
+}
+
+#[derive(Serialize, ToSchema)]
+struct ExerciseComparison {
+    exercise_id: i32,
+    exercise_name: String,
+    current: ExercisePerformance,
+    previous: Option<ExercisePerformance>, // Last earlier session with this exercise
+    best: Option<ExercisePerformance>,     // Earlier session with the highest e1RM
+    volume_delta_previous: Option<i64>,
+    e1rm_delta_previous: Option<f64>,
+    volume_delta_best: Option<i64>,
+    e1rm_delta_best: Option<f64>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ScorePoint {
+    workout_id: i32,
+    date: NaiveDateTime,
+    score: f64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct RoutineComparison {
+    routine_id: i32,
+    routine_name: String,
+    workout_id: i32,
+    date: NaiveDateTime,
+    previous_workout_id: Option<i32>,
+    exercises: Vec<ExerciseComparison>,
+    performance_trend: Vec<ScorePoint>, // Every session, oldest first
+}
+
+#[derive(OpenApi)]
+#[openapi(paths(compare_sessions))]
+pub(crate) struct ApiDoc;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(compare_sessions);
+}
+
+fn round(value: f64) -> f64 {
+    (value * 10.0).round() / 10.0
+}
+
+impl SessionExercise {
+    fn volume(&self) -> i64 {
+        self.sets
+            .iter()
+            .map(|set| i64::from(set.weight) * i64::from(set.reps))
+            .sum()
+    }
+
+    fn e1rm(&self) -> f64 {
+        self.sets
+            .iter()
+            .map(|set| f64::from(estimated_one_rm(set.weight, set.reps)))
+            .fold(0.0, f64::max)
+    }
+
+    fn performance(&self, session: &Session) -> ExercisePerformance {
+        ExercisePerformance {
+            workout_id: session.workout_id,
+            date: session.start,
+            sets: self.sets.clone(),
+            volume: self.volume(),
+            e1rm: round(self.e1rm()),
+        }
+    }
+}
+
+async fn fetch_sessions(pool: &PgPool, routine_id: i32) -> Result<Vec<Session>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"SELECT w.workoutid, w.start, wes.exerciseid::INTEGER AS exerciseid, e.exercisename,
+                  s.weight, s.reps
+           FROM Workout w
+           JOIN Workout_Exercises_Sets wes ON wes.workoutid = w.workoutid
+           JOIN ExerciseList e ON wes.exerciseid = e.exerciseid
+           JOIN "Set" s ON wes.setid = s.setid
+           WHERE w.routineid = $1
+           ORDER BY w.start, w.workoutid, s.setid"#,
+    )
+    .bind(routine_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut sessions: Vec<Session> = Vec::new();
+    for row in &rows {
+        let workout_id: i32 = row.get("workoutid");
+        if sessions.last().map(|s| s.workout_id) != Some(workout_id) {
+            sessions.push(Session {
+                workout_id,
+                start: row.get("start"),
+                exercises: Vec::new(),
+            });
+        }
+        let Some(session) = sessions.last_mut() else {
+            continue;
+        };
+
+        let exercise_id: i32 = row.get("exerciseid");
+        let set = SetEntry {
+            weight: row.get("weight"),
+            reps: row.get("reps"),
+        };
+        match session
+            .exercises
+            .iter_mut()
+            .find(|e| e.exercise_id == exercise_id)
+        {
+            Some(exercise) => exercise.sets.push(set),
+            None => session.exercises.push(SessionExercise {
+                exercise_id,
+                exercise_name: row.get("exercisename"),
+                sets: vec![set],
+            }),
+        }
+    }
+    Ok(sessions)
+}
+
+// Compares every exercise of sessions[index] with the sessions before it
+fn compare(sessions: &[Session], index: usize) -> Vec<ExerciseComparison> {
+    let session = &sessions[index];
+    let earlier = &sessions[..index];
+
+    session
+        .exercises
+        .iter()
+        .map(|exercise| {
+            let history = earlier.iter().filter_map(|s| {
+                s.exercises
+                    .iter()
+                    .find(|e| e.exercise_id == exercise.exercise_id)
+                    .map(|e| (s, e))
+            });
+            let previous = history.clone().next_back();
+            let best = history.fold(
+                None,
+                |best: Option<(&Session, &SessionExercise)>, (s, e)| match best {
+                    Some((_, b)) if (b.e1rm(), b.volume()) >= (e.e1rm(), e.volume()) => best,
+                    _ => Some((s, e)),
+                },
+            );
+
+            let current = exercise.performance(session);
+            let previous = previous.map(|(s, e)| e.performance(s));
+            let best = best.map(|(s, e)| e.performance(s));
+            ExerciseComparison {
+                exercise_id: exercise.exercise_id,
+                exercise_name: exercise.exercise_name.clone(),
+                volume_delta_previous: previous.as_ref().map(|p| current.volume - p.volume),
+                e1rm_delta_previous: previous.as_ref().map(|p| round(current.e1rm - p.e1rm)),
+                volume_delta_best: best.as_ref().map(|b| current.volume - b.volume),
+                e1rm_delta_best: best.as_ref().map(|b| round(current.e1rm - b.e1rm)),
+                current,
+                previous,
+                best,
+            }
+        })
+        .collect()
+}
+
+// A session scores 100 when every exercise matches its best earlier e1RM in
+// this routine; above 100 means new bests. Exercises without history are left
+// out, and a session with none at all is the 100 baseline.
+fn performance_trend(sessions: &[Session]) -> Vec<ScorePoint> {
+    let mut best: HashMap<i32, f64> = HashMap::new();
+    sessions
+        .iter()
+        .map(|session| {
+            let ratios: Vec<f64> = session
+                .exercises
+                .iter()
+                .filter_map(|e| {
+                    let previous = *best.get(&e.exercise_id)?;
+                    (previous > 0.0).then(|| e.e1rm() / previous)
+                })
+                .collect();
+            for e in &session.exercises {
+                let entry = best.entry(e.exercise_id).or_insert(0.0);
+                *entry = entry.max(e.e1rm());
+            }
+
+            let score = if ratios.is_empty() {
+                100.0
+            } else {
+                100.0 * ratios.iter().sum::<f64>() / ratios.len() as f64
+            };
+            ScorePoint {
+                workout_id: session.workout_id,
+                date: session.start,
+                score: round(score),
+            }
+        })
+        .collect()
+}
+
+// Session-over-session comparison; ?workout_id= picks the session, by default
+// the latest one
+#[utoipa::path(
+    tag = "routines",
+    summary = "Compare a session of a routine with earlier ones",
+    params(("workout_id" = Option<i32>, Query, description = "Session to compare, defaults to the latest")),
+    responses(
+        (status = 200, body = RoutineComparison),
+        (status = 400, description = "Invalid workout_id", body = ErrorBody),
+        (status = 404, description = "Routine or session not found", body = ErrorBody)
+    )
+)]
+#[get("/routines/{routine_id}/comparison")]
+async fn compare_sessions(
+    pool: web::Data<PgPool>,
+    routine_id: web::Path<i32>,
+    request: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let routine_id = routine_id.into_inner();
+
+    let workout_id = match request.get("workout_id").map(|id| id.parse::<i32>()) {
+        Some(Ok(id)) => Some(id),
+        Some(Err(_)) => return Err(ApiError::bad_request("Invalid 'workout_id'")),
+        None => None,
+    };
+
+    let routine_name: String =
+        sqlx::query_scalar("SELECT RoutineName FROM Routines WHERE RoutineID = $1")
+            .bind(routine_id)
+            .fetch_optional(pool.get_ref())
+            .await?
+            .ok_or_else(|| {
+                ApiError::not_found(format!("Routine with ID {} not found", routine_id))
+            })?;
+
+    let sessions = fetch_sessions(pool.get_ref(), routine_id).await?;
+
+    let index = match workout_id {
+        Some(id) => sessions.iter().position(|s| s.workout_id == id),
+        None => sessions.len().checked_sub(1),
+    };
+    let Some(index) = index else {
+        return Err(ApiError::not_found(match workout_id {
+            Some(id) => format!(
+                "Workout {} is not a logged session of routine {}",
+                id, routine_id
+            ),
+            None => format!("Routine {} has no logged sessions", routine_id),
+        }));
+    };
+
+    info!(
+        "Compared session {} of routine {}",
+        sessions[index].workout_id, routine_id
+    );
+    Ok(HttpResponse::Ok().json(RoutineComparison {
+        routine_id,
+        routine_name,
+        workout_id: sessions[index].workout_id,
+        date: sessions[index].start,
+        previous_workout_id: index.checked_sub(1).map(|i| sessions[i].workout_id),
+        exercises: compare(&sessions, index),
+        performance_trend: performance_trend(&sessions),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::test_support::TestApp;
+
+    #[actix_web::test]
+    async fn compares_with_previous_and_best_sessions() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
+        let user = app.user().await;
+        let push = app
+            .routine("Push")
+            .exercise(bench, 1)
+            .user(user)
+            .create()
+            .await;
+
+        let mut workouts = Vec::new();
+        for (day, weight, reps) in [(5, 100, 5), (12, 90, 5), (19, 100, 6)] {
+            let workout = app
+                .workout()
+                .routine(push)
+                .at(&format!("2026-01-{:02}T18:00:00", day))
+                .minutes(45)
+                .set(bench, weight, reps)
+                .create()
+                .await;
+            workouts.push(workout);
+        }
+
+        let body = app
+            .get(&format!("/api/v1/routines/{}/comparison", push))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(body["workout_id"], workouts[2]);
+        assert_eq!(body["previous_workout_id"], workouts[1]);
+        let exercise = &body["exercises"][0];
+        assert_eq!(exercise["current"]["e1rm"], 116.1);
+        assert_eq!(exercise["previous"]["workout_id"], workouts[1]);
+        assert_eq!(exercise["best"]["workout_id"], workouts[0]);
+        assert_eq!(exercise["volume_delta_previous"], 150);
+        assert_eq!(exercise["e1rm_delta_previous"], 14.8);
+        assert_eq!(exercise["volume_delta_best"], 100);
+        assert_eq!(exercise["e1rm_delta_best"], 3.6);
+        let scores: Vec<f64> = body["performance_trend"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["score"].as_f64().unwrap())
+            .collect();
+        assert_eq!(scores, vec![100.0, 90.0, 103.2]);
+
+        let body = app
+            .get(&format!(
+                "/api/v1/routines/{}/comparison?workout_id={}",
+                push, workouts[0]
+            ))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(body["previous_workout_id"], json!(null));
+        assert_eq!(body["exercises"][0]["previous"], json!(null));
+    }
+
+    #[actix_web::test]
+    async fn missing_routines_and_sessions() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
+        let push = app.routine("Push").exercise(bench, 1).create().await;
+
+        app.get("/api/v1/routines/999/comparison").await.expect(404);
+        app.get(&format!("/api/v1/routines/{}/comparison", push))
+            .await
+            .expect(404);
+        app.get(&format!(
+            "/api/v1/routines/{}/comparison?workout_id=x",
+            push
+        ))
+        .await
+        .expect(400);
+    }
+}
@@ -3,56 +3,176 @@ use chrono::{NaiveDate, NaiveDateTime, Utc};
 use log::{error, info};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use sqlx::{FromRow, PgPool, Postgres, Row, Transaction};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use utoipa::{OpenApi, ToSchema};
 
-#[derive(Serialize, Deserialize)]
+use crate::errors::{ApiError, ErrorBody};
+use crate::repository::Repositories;
+use crate::validation::{validate, Validate, Validator};
+
+#[derive(Serialize, Deserialize, ToSchema)]
 struct RoutineCreate {
     name: String,
     exercises: Vec<RoutineExercise>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 struct RoutineUpdate {
     name: String,
     exercises: Vec<RoutineExercise>,
 }
 
-#[derive(Serialize, Deserialize)]
-struct RoutineExercise {
-    exercise_id: i32,
-    sets: i32,
+#[derive(Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+pub(crate) struct RoutineExercise {
+    pub(crate) exercise_id: i32,
+    pub(crate) sets: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) target_reps: Option<i16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) target_weight: Option<i16>, // kg
 }
 
-#[derive(Serialize, FromRow)]
-struct RoutineInfo {
-    routine_id: i32,
-    name: String,
-    timestamp: NaiveDateTime,
-    last_performed: Option<NaiveDate>,
+#[derive(Serialize, ToSchema)]
+pub(crate) struct RoutineInfo {
+    pub(crate) routine_id: i32,
+    pub(crate) name: String,
+    pub(crate) timestamp: NaiveDateTime,
+    pub(crate) last_performed: Option<NaiveDate>,
 }
 
-#[derive(Serialize, FromRow)]
-struct RoutineExerciseDetail {
-    exercise_id: i32,
-    exercise_name: String,
-    sets: i32,
+#[derive(Serialize, Clone, ToSchema)]
+pub(crate) struct RoutineExerciseDetail {
+    pub(crate) exercise_id: i32,
+    pub(crate) exercise_name: String,
+    pub(crate) sets: i32,
+    pub(crate) target_reps: Option<i16>,
+    pub(crate) target_weight: Option<i16>,
 }
 
-#[derive(Serialize)]
+impl RoutineExerciseDetail {
+    fn definition(&self) -> RoutineExercise {
+        RoutineExercise {
+            exercise_id: self.exercise_id,
+            sets: self.sets,
+            target_reps: self.target_reps,
+            target_weight: self.target_weight,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
 struct RoutineViewResponse {
     routine_id: i32,
     routine_name: String,
-    routines: Vec<ExerciseSetPair>,
+    version: Option<i32>,
+    exercises: Vec<RoutineExerciseView>, // In routine order
+    stats: RoutineStats,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct RoutineExerciseView {
+    pub(crate) position: i32, // 1-based
+    pub(crate) exercise_id: i32,
+    pub(crate) exercise_name: String,
+    pub(crate) muscles_trained: Vec<String>,
+    pub(crate) sets: i32,
+    pub(crate) target_reps: Option<i16>,
+    pub(crate) target_weight: Option<i16>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct RoutineStats {
+    pub(crate) times_performed: i64,
+    pub(crate) last_performed: Option<NaiveDateTime>,
+    pub(crate) average_duration_minutes: Option<f64>,
+    pub(crate) volume_trend: Vec<VolumePoint>, // One point per workout, oldest first
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct VolumePoint {
+    pub(crate) workout_id: i32,
+    pub(crate) date: NaiveDate,
+    pub(crate) volume: i64, // Sum of weight x reps, kg
+}
+
+// One saved definition of a routine. Editing a routine adds a version rather
+// than rewriting history, so past workouts keep pointing at what was performed.
+#[derive(Serialize, ToSchema)]
+pub(crate) struct RoutineVersion {
+    pub(crate) version: i32,
+    pub(crate) name: String,
+    pub(crate) created_at: NaiveDateTime,
+    pub(crate) workouts: i64, // Workouts performed from this version
+    pub(crate) exercises: Vec<RoutineExerciseDetail>, // In routine order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) changes: Option<RoutineDiff>, // Compared with the previous version
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct RoutineDiff {
+    from: i32,
+    to: i32,
+    renamed: Option<NameChange>,
+    added: Vec<RoutineExerciseDetail>,
+    removed: Vec<RoutineExerciseDetail>,
+    changed: Vec<ExerciseChange>,
+    reordered: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+struct NameChange {
+    from: String,
+    to: String,
 }
 
-#[derive(Serialize)]
-struct ExerciseSetPair {
+#[derive(Serialize, ToSchema)]
+struct ExerciseChange {
     exercise_id: i32,
-    num_sets: i32,
+    exercise_name: String,
+    sets_from: i32,
+    sets_to: i32,
+    target_reps_from: Option<i16>,
+    target_reps_to: Option<i16>,
+    target_weight_from: Option<i16>,
+    target_weight_to: Option<i16>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct DuplicateRequest {
+    #[serde(default)]
+    name: Option<String>, // Defaults to "<name> (copy)"
 }
 
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        list_routines,
+        create_routine,
+        update_routine,
+        delete_routine,
+        view_routine,
+        duplicate_routine,
+        list_versions,
+        diff_routine
+    ),
+    tags((name = "routines", description = "Versioned workout routines"))
+)]
+pub(crate) struct ApiDoc;
+
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_routine)
+        .service(update_routine)
+        .service(delete_routine)
+        .service(list_routines)
+        .service(view_routine)
+        .service(duplicate_routine)
+        .service(list_versions)
+        .service(diff_routine);
+}
+
+// The unversioned routes, kept as deprecated aliases. /routines/name has to
+// come before /routines/{routine_id}.
+pub fn init_legacy_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(get_routine_by_name)
         .service(create_routine)
         .service(update_routine)
@@ -61,402 +181,747 @@ pub fn init_routes(cfg: &mut web::ServiceConfig) {
         .service(view_routine);
 }
 
-#[get("/routines")]
-async fn list_routines(
-    pool: web::Data<PgPool>,
-    request: web::Query<HashMap<String, String>>,
-) -> HttpResponse {
-    // Check if we need to include lastPerformed
-    let include_last_performed = request.get("include") == Some(&"lastPerformed".to_string());
+fn diff_versions(from: &RoutineVersion, to: &RoutineVersion) -> RoutineDiff {
+    let before: HashMap<i32, &RoutineExerciseDetail> =
+        from.exercises.iter().map(|e| (e.exercise_id, e)).collect();
+    let after: HashMap<i32, &RoutineExerciseDetail> =
+        to.exercises.iter().map(|e| (e.exercise_id, e)).collect();
+
+    let changed = to
+        .exercises
+        .iter()
+        .filter_map(|e| {
+            let old = before.get(&e.exercise_id)?;
+            (old.definition() != e.definition()).then(|| ExerciseChange {
+                exercise_id: e.exercise_id,
+                exercise_name: e.exercise_name.clone(),
+                sets_from: old.sets,
+                sets_to: e.sets,
+                target_reps_from: old.target_reps,
+                target_reps_to: e.target_reps,
+                target_weight_from: old.target_weight,
+                target_weight_to: e.target_weight,
+            })
+        })
+        .collect();
+
+    // Only the exercises both versions share count towards reordering
+    let kept_before: Vec<i32> = from
+        .exercises
+        .iter()
+        .map(|e| e.exercise_id)
+        .filter(|id| after.contains_key(id))
+        .collect();
+    let kept_after: Vec<i32> = to
+        .exercises
+        .iter()
+        .map(|e| e.exercise_id)
+        .filter(|id| before.contains_key(id))
+        .collect();
+
+    RoutineDiff {
+        from: from.version,
+        to: to.version,
+        renamed: (from.name != to.name).then(|| NameChange {
+            from: from.name.clone(),
+            to: to.name.clone(),
+        }),
+        added: to
+            .exercises
+            .iter()
+            .filter(|e| !before.contains_key(&e.exercise_id))
+            .cloned()
+            .collect(),
+        removed: from
+            .exercises
+            .iter()
+            .filter(|e| !after.contains_key(&e.exercise_id))
+            .cloned()
+            .collect(),
+        changed,
+        reordered: kept_before != kept_after,
+    }
+}
 
-    // Query to get routines with optional last performed date
-    let query = if include_last_performed {
-        "SELECT r.RoutineID, r.RoutineName, r.Timestamp, 
-         (SELECT MAX(w.Start::date) FROM Workout w WHERE w.RoutineID = r.RoutineID) as last_performed
-         FROM Routines r ORDER BY r.Timestamp ASC"
-    } else {
-        "SELECT r.RoutineID, r.RoutineName, r.Timestamp,
-         NULL as last_performed
-         FROM Routines r ORDER BY r.Timestamp ASC"
-    };
+fn routine_not_found(routine_id: i32) -> ApiError {
+    ApiError::not_found(format!("Routine with ID {} not found", routine_id))
+}
+
+// NumberOfSets is a SMALLINT; anything past this is a typo, not a plan
+pub(crate) const MAX_SETS: i32 = 100;
+
+// RoutineName is a VARCHAR(255)
+const MAX_NAME_LEN: usize = 255;
+
+const COPY_SUFFIX: &str = " (copy)";
 
-    match sqlx::query(query).fetch_all(pool.get_ref()).await {
-        Ok(rows) => {
-            let routines: Vec<RoutineInfo> = rows
-                .iter()
-                .map(|row| RoutineInfo {
-                    routine_id: row.get("routineid"),
-                    name: row.get("routinename"),
-                    timestamp: row.get("timestamp"),
-                    last_performed: row.get("last_performed"),
-                })
-                .collect();
-
-            info!("Retrieved {} routines", routines.len());
-            HttpResponse::Ok().json(routines)
+impl Validate for RoutineExercise {
+    fn rules(&self, v: &mut Validator) {
+        v.exercise_ref("exercise_id", self.exercise_id);
+        v.range("sets", self.sets, 1, MAX_SETS);
+        if let Some(reps) = self.target_reps {
+            v.min("target_reps", reps, 1);
         }
-        Err(e) => {
-            error!("Failed to fetch routines: {}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to fetch routines"
-            }))
+        if let Some(weight) = self.target_weight {
+            v.min("target_weight", weight, 0);
         }
     }
 }
 
-#[get("/routines/name")]
-async fn get_routine_by_name(
-    pool: web::Data<PgPool>,
-    request: web::Query<HashMap<String, String>>,
-) -> HttpResponse {
-    // Get routine by name
-    let routine_name = match request.get("name") {
-        Some(name) => name,
-        None => {
-            return HttpResponse::BadRequest().json(json!({
-                "error": "name parameter is required"
-            }))
-        }
-    };
-
-    match sqlx::query("SELECT RoutineID FROM Routines WHERE RoutineName = $1")
-        .bind(routine_name)
-        .fetch_one(pool.get_ref())
-        .await
-    {
-        Ok(row) => {
-            let routine_id: i32 = row.get("routineid");
-            info!(
-                "Retrieved RoutineID {} for name {}",
-                routine_id, routine_name
+// Rules shared by creating and updating a routine
+pub(crate) fn definition_rules(v: &mut Validator, name: &str, exercises: &[RoutineExercise]) {
+    v.required("name", name, MAX_NAME_LEN);
+    v.not_empty("exercises", exercises);
+    v.each("exercises", exercises);
+
+    // Routines_Exercises_Sets holds one row per exercise
+    let mut seen = HashSet::new();
+    for (i, exercise) in exercises.iter().enumerate() {
+        if !seen.insert(exercise.exercise_id) {
+            v.error(
+                &format!("exercises[{}].exercise_id", i),
+                "duplicate",
+                "Exercise appears more than once in the routine",
             );
-            HttpResponse::Ok().json(json!({ "routine_id": routine_id }))
-        }
-        Err(e) => {
-            error!("Failed to fetch routine ID: {}", e);
-            HttpResponse::NotFound().json(json!({
-                "error": format!("Routine with name '{}' not found", routine_name)
-            }))
         }
     }
 }
 
-#[post("/routines")]
-async fn create_routine(
-    pool: web::Data<PgPool>,
-    routine: web::Json<RoutineCreate>,
-) -> HttpResponse {
-    if routine.name.is_empty() {
-        return HttpResponse::BadRequest().json(json!({
-            "error": "Routine name cannot be empty"
-        }));
+impl Validate for RoutineCreate {
+    fn rules(&self, v: &mut Validator) {
+        definition_rules(v, &self.name, &self.exercises);
     }
+}
 
-    if routine.exercises.is_empty() {
-        return HttpResponse::BadRequest().json(json!({
-            "error": "Routine must include at least one exercise"
-        }));
+impl Validate for RoutineUpdate {
+    fn rules(&self, v: &mut Validator) {
+        definition_rules(v, &self.name, &self.exercises);
     }
+}
 
-    // Start a transaction
-    let mut tx = match pool.begin().await {
-        Ok(tx) => tx,
-        Err(e) => {
-            error!("Failed to start transaction: {}", e);
-            return HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to create routine: database error"
-            }));
-        }
-    };
-
-    // Insert into Routines table
-    let routine_id = match sqlx::query(
-        "INSERT INTO Routines (RoutineName, Timestamp) 
-         VALUES ($1, $2) RETURNING RoutineID",
-    )
-    .bind(&routine.name)
-    .bind(Utc::now().naive_utc())
-    .fetch_one(&mut *tx)
-    .await
-    {
-        Ok(row) => row.get::<i32, _>("routineid"),
-        Err(e) => {
-            error!("Failed to insert into Routines table: {}", e);
-            let _ = tx.rollback().await;
-            return HttpResponse::InternalServerError().json(json!({
-                "error": format!("Failed to create routine: {}", e)
-            }));
-        }
-    };
-
-    // Insert exercises and sets
-    for exercise in &routine.exercises {
-        if let Err(e) = sqlx::query(
-            "INSERT INTO Routines_Exercises_Sets (RoutineID, ExerciseID, NumberOfSets) 
-             VALUES ($1, $2, $3)",
-        )
-        .bind(routine_id)
-        .bind(exercise.exercise_id)
-        .bind(exercise.sets)
-        .execute(&mut *tx)
-        .await
-        {
-            error!(
-                "Failed to insert exercise {} into routine: {}",
-                exercise.exercise_id, e
-            );
-            let _ = tx.rollback().await;
-            return HttpResponse::InternalServerError().json(json!({
-                "error": format!("Failed to add exercise to routine: {}", e)
-            }));
+impl Validate for DuplicateRequest {
+    fn rules(&self, v: &mut Validator) {
+        if let Some(name) = &self.name {
+            v.required("name", name, MAX_NAME_LEN);
         }
     }
+}
 
-    // Commit the transaction
-    if let Err(e) = tx.commit().await {
-        error!("Failed to commit transaction: {}", e);
-        return HttpResponse::InternalServerError().json(json!({
-            "error": "Failed to complete routine creation"
-        }));
+#[utoipa::path(
+    tag = "routines",
+    params(
+        ("include" = Option<String>, Query, description = "lastPerformed to fill in last_performed"),
+        ("name" = Option<String>, Query, description = "Only the routine with exactly this name")
+    ),
+    responses((status = 200, body = Vec<RoutineInfo>))
+)]
+#[get("/routines")]
+async fn list_routines(
+    repos: web::Data<Repositories>,
+    request: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    // Check if we need to include lastPerformed
+    let include_last_performed = request.get("include") == Some(&"lastPerformed".to_string());
+    let mut routines = repos.routines.list(include_last_performed).await?;
+    if let Some(name) = request.get("name") {
+        routines.retain(|r| &r.name == name);
     }
 
+    info!("Retrieved {} routines", routines.len());
+    Ok(HttpResponse::Ok().json(routines))
+}
+
+// Superseded by GET /routines?name=
+#[get("/routines/name")]
+async fn get_routine_by_name(
+    repos: web::Data<Repositories>,
+    request: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    // Get routine by name
+    let routine_name = request
+        .get("name")
+        .ok_or_else(|| ApiError::bad_request("name parameter is required"))?;
+
+    let routine_id = repos
+        .routines
+        .find_by_name(routine_name)
+        .await?
+        .ok_or_else(|| {
+            ApiError::not_found(format!("Routine with name '{}' not found", routine_name))
+        })?;
+
+    info!(
+        "Retrieved RoutineID {} for name {}",
+        routine_id, routine_name
+    );
+    Ok(HttpResponse::Ok().json(json!({ "routine_id": routine_id })))
+}
+
+#[utoipa::path(
+    tag = "routines",
+    responses(
+        (status = 201, body = serde_json::Value, example = json!({ "routine_id": 1 })),
+        (status = 409, description = "Name already taken", body = ErrorBody),
+        (status = 422, description = "Invalid routine", body = ErrorBody)
+    )
+)]
+#[post("/routines")]
+async fn create_routine(
+    repos: web::Data<Repositories>,
+    routine: web::Json<RoutineCreate>,
+) -> Result<HttpResponse, ApiError> {
+    validate(&*routine)
+        .exercises_exist(repos.exercises.as_ref())
+        .await?
+        .finish()?;
+
+    let routine_id = repos
+        .routines
+        .create(&routine.name, Utc::now().naive_utc(), &routine.exercises)
+        .await?;
+
     info!(
         "Created new routine: {} with ID {}",
         routine.name, routine_id
     );
-    HttpResponse::Created().json(json!({ "routine_id": routine_id }))
+    Ok(HttpResponse::Created().json(json!({ "routine_id": routine_id })))
 }
 
+#[utoipa::path(
+    tag = "routines",
+    summary = "Replace a routine's definition",
+    description = "Adds a version when anything changed; past workouts keep theirs.",
+    responses(
+        (status = 200, body = serde_json::Value, example = json!({ "status": "updated", "version": 2 })),
+        (status = 404, description = "Routine not found", body = ErrorBody),
+        (status = 422, description = "Invalid routine", body = ErrorBody)
+    )
+)]
 #[put("/routines/{routine_id}")]
 async fn update_routine(
-    pool: web::Data<PgPool>,
+    repos: web::Data<Repositories>,
     routine_id: web::Path<i32>,
     update: web::Json<RoutineUpdate>,
-) -> HttpResponse {
+) -> Result<HttpResponse, ApiError> {
     let routine_id = routine_id.into_inner();
+    validate(&*update)
+        .exercises_exist(repos.exercises.as_ref())
+        .await?
+        .finish()?;
+
+    // A new version is only recorded when something actually changed
+    let version = repos
+        .routines
+        .update(routine_id, &update.name, &update.exercises)
+        .await?
+        .ok_or_else(|| routine_not_found(routine_id))?;
+
+    info!("Updated routine {} (version {})", routine_id, version);
+    Ok(HttpResponse::Ok().json(json!({ "status": "updated", "version": version })))
+}
 
-    if update.name.is_empty() {
-        return HttpResponse::BadRequest().json(json!({
-            "error": "Routine name cannot be empty"
-        }));
+#[utoipa::path(
+    tag = "routines",
+    responses(
+        (status = 200, body = serde_json::Value, example = json!({ "status": "deleted" })),
+        (status = 404, description = "Routine not found", body = ErrorBody)
+    )
+)]
+#[delete("/routines/{routine_id}")]
+async fn delete_routine(
+    repos: web::Data<Repositories>,
+    routine_id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let routine_id = routine_id.into_inner();
+    if !repos.routines.delete(routine_id).await? {
+        return Err(routine_not_found(routine_id));
     }
 
-    if update.exercises.is_empty() {
-        return HttpResponse::BadRequest().json(json!({
-            "error": "Routine must include at least one exercise"
-        }));
-    }
+    info!("Deleted routine {}", routine_id);
+    Ok(HttpResponse::Ok().json(json!({ "status": "deleted" })))
+}
 
-    // Check if routine exists
-    match sqlx::query("SELECT RoutineID FROM Routines WHERE RoutineID = $1")
-        .bind(routine_id)
-        .fetch_one(pool.get_ref())
-        .await
-    {
-        Ok(_) => (),
-        Err(e) => {
-            error!("Routine {} not found: {}", routine_id, e);
-            return HttpResponse::NotFound().json(json!({
-                "error": format!("Routine with ID {} not found", routine_id)
-            }));
-        }
-    }
+#[utoipa::path(
+    tag = "routines",
+    summary = "A routine's current definition with usage stats",
+    responses((status = 200, body = RoutineViewResponse), (status = 404, description = "Routine not found", body = ErrorBody))
+)]
+#[get("/routines/{routine_id}")]
+async fn view_routine(
+    repos: web::Data<Repositories>,
+    routine_id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let routine_id = routine_id.into_inner();
+    let routine = repos
+        .routines
+        .current(routine_id)
+        .await?
+        .ok_or_else(|| routine_not_found(routine_id))?;
 
-    // Start a transaction
-    let mut tx = match pool.begin().await {
-        Ok(tx) => tx,
-        Err(e) => {
-            error!("Failed to start transaction: {}", e);
-            return HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to update routine: database error"
-            }));
-        }
+    let response = RoutineViewResponse {
+        routine_id,
+        routine_name: routine.name,
+        version: routine.version,
+        exercises: routine.exercises,
+        stats: repos.routines.stats(routine_id).await?,
     };
 
-    // Update Routines table
-    if let Err(e) = sqlx::query("UPDATE Routines SET RoutineName = $1 WHERE RoutineID = $2")
-        .bind(&update.name)
-        .bind(routine_id)
-        .execute(&mut *tx)
-        .await
-    {
-        error!("Failed to update Routines table: {}", e);
-        let _ = tx.rollback().await;
-        return HttpResponse::InternalServerError().json(json!({
-            "error": format!("Failed to update routine: {}", e)
-        }));
-    }
+    info!("Retrieved details for routine {}", routine_id);
+    Ok(HttpResponse::Ok().json(response))
+}
 
-    // Delete old exercise associations
-    if let Err(e) = sqlx::query("DELETE FROM Routines_Exercises_Sets WHERE RoutineID = $1")
-        .bind(routine_id)
-        .execute(&mut *tx)
-        .await
-    {
-        error!("Failed to delete old exercises: {}", e);
-        let _ = tx.rollback().await;
-        return HttpResponse::InternalServerError().json(json!({
-            "error": "Failed to update routine exercises"
-        }));
-    }
+#[utoipa::path(
+    tag = "routines",
+    request_body(content = Option<DuplicateRequest>),
+    responses(
+        (status = 201, body = serde_json::Value, example = json!({ "routine_id": 2 })),
+        (status = 404, description = "Routine not found", body = ErrorBody),
+        (status = 422, description = "Invalid name", body = ErrorBody)
+    )
+)]
+#[post("/routines/{routine_id}/duplicate")]
+async fn duplicate_routine(
+    repos: web::Data<Repositories>,
+    routine_id: web::Path<i32>,
+    request: Option<web::Json<DuplicateRequest>>,
+) -> Result<HttpResponse, ApiError> {
+    let routine_id = routine_id.into_inner();
 
-    // Insert new exercises and sets
-    for exercise in &update.exercises {
-        if let Err(e) = sqlx::query(
-            "INSERT INTO Routines_Exercises_Sets (RoutineID, ExerciseID, NumberOfSets) 
-             VALUES ($1, $2, $3)",
-        )
-        .bind(routine_id)
-        .bind(exercise.exercise_id)
-        .bind(exercise.sets)
-        .execute(&mut *tx)
-        .await
-        {
-            error!(
-                "Failed to insert exercise {} into routine: {}",
-                exercise.exercise_id, e
-            );
-            let _ = tx.rollback().await;
-            return HttpResponse::InternalServerError().json(json!({
-                "error": format!("Failed to add exercise to routine: {}", e)
-            }));
-        }
-    }
+    let source = repos
+        .routines
+        .versions(routine_id)
+        .await?
+        .pop()
+        .ok_or_else(|| routine_not_found(routine_id))?;
 
-    // Commit the transaction
-    if let Err(e) = tx.commit().await {
-        error!("Failed to commit transaction: {}", e);
-        return HttpResponse::InternalServerError().json(json!({
-            "error": "Failed to complete routine update"
-        }));
+    let request = request.map(|request| request.into_inner());
+    if let Some(request) = &request {
+        validate(request).finish()?;
     }
+    let name = match request.and_then(|request| request.name) {
+        Some(name) => name,
+        // Shortened so that the suffix still fits
+        None => {
+            let kept = MAX_NAME_LEN - COPY_SUFFIX.chars().count();
+            let mut name: String = source.name.chars().take(kept).collect();
+            name.push_str(COPY_SUFFIX);
+            name
+        }
+    };
+    let exercises: Vec<RoutineExercise> = source.exercises.iter().map(|e| e.definition()).collect();
 
-    info!("Updated routine {}", routine_id);
-    HttpResponse::Ok().json(json!({ "status": "updated" }))
+    let new_id = repos
+        .routines
+        .create(&name, Utc::now().naive_utc(), &exercises)
+        .await?;
+
+    info!("Duplicated routine {} as {} ({})", routine_id, new_id, name);
+    Ok(HttpResponse::Created().json(json!({ "routine_id": new_id })))
 }
 
-#[delete("/routines/{routine_id}")]
-async fn delete_routine(pool: web::Data<PgPool>, routine_id: web::Path<i32>) -> HttpResponse {
+#[utoipa::path(
+    tag = "routines",
+    summary = "Every version, oldest first, with its changes",
+    responses((status = 200, body = Vec<RoutineVersion>), (status = 404, description = "Routine not found", body = ErrorBody))
+)]
+#[get("/routines/{routine_id}/versions")]
+async fn list_versions(
+    repos: web::Data<Repositories>,
+    routine_id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
     let routine_id = routine_id.into_inner();
 
-    // Start a transaction
-    let mut tx = match pool.begin().await {
-        Ok(tx) => tx,
-        Err(e) => {
-            error!("Failed to start transaction: {}", e);
-            return HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to delete routine: database error"
-            }));
-        }
-    };
+    let mut versions = repos.routines.versions(routine_id).await?;
+    if versions.is_empty() {
+        return Err(routine_not_found(routine_id));
+    }
 
-    // Delete from Routines_Exercises_Sets first
-    if let Err(e) = sqlx::query("DELETE FROM Routines_Exercises_Sets WHERE RoutineID = $1")
-        .bind(routine_id)
-        .execute(&mut *tx)
-        .await
-    {
-        error!("Failed to delete from Routines_Exercises_Sets: {}", e);
-        let _ = tx.rollback().await;
-        return HttpResponse::InternalServerError().json(json!({
-            "error": "Failed to delete routine exercise associations"
-        }));
+    let changes: Vec<Option<RoutineDiff>> = std::iter::once(None)
+        .chain(
+            versions
+                .windows(2)
+                .map(|pair| Some(diff_versions(&pair[0], &pair[1]))),
+        )
+        .collect();
+    for (version, changes) in versions.iter_mut().zip(changes) {
+        version.changes = changes;
     }
+    info!(
+        "Retrieved {} versions of routine {}",
+        versions.len(),
+        routine_id
+    );
+    Ok(HttpResponse::Ok().json(versions))
+}
 
-    // Then delete from Routines
-    match sqlx::query("DELETE FROM Routines WHERE RoutineID = $1")
-        .bind(routine_id)
-        .execute(&mut *tx)
-        .await
-    {
-        Ok(result) => {
-            if result.rows_affected() == 0 {
-                let _ = tx.rollback().await;
-                return HttpResponse::NotFound().json(json!({
-                    "error": format!("Routine with ID {} not found", routine_id)
-                }));
-            }
-        }
-        Err(e) => {
-            error!("Failed to delete from Routines: {}", e);
-            let _ = tx.rollback().await;
-            return HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to delete routine"
-            }));
+// Compares two versions; by default the latest with the one before it
+#[utoipa::path(
+    tag = "routines",
+    summary = "Differences between two versions",
+    params(
+        ("from" = Option<i32>, Query, description = "Defaults to the version before to"),
+        ("to" = Option<i32>, Query, description = "Defaults to the latest version")
+    ),
+    responses(
+        (status = 200, body = RoutineDiff),
+        (status = 400, description = "Invalid version number", body = ErrorBody),
+        (status = 404, description = "Routine or version not found", body = ErrorBody)
+    )
+)]
+#[get("/routines/{routine_id}/diff")]
+async fn diff_routine(
+    repos: web::Data<Repositories>,
+    routine_id: web::Path<i32>,
+    request: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let routine_id = routine_id.into_inner();
+
+    let mut parsed = HashMap::new();
+    for key in ["from", "to"] {
+        if let Some(value) = request.get(key) {
+            let version = value.parse::<i32>().map_err(|_| {
+                ApiError::bad_request(format!("Invalid {} version '{}'", key, value))
+            })?;
+            parsed.insert(key, version);
         }
     }
 
-    // Commit the transaction
-    if let Err(e) = tx.commit().await {
-        error!("Failed to commit transaction: {}", e);
-        return HttpResponse::InternalServerError().json(json!({
-            "error": "Failed to complete routine deletion"
-        }));
+    let versions = repos.routines.versions(routine_id).await?;
+    if versions.is_empty() {
+        return Err(routine_not_found(routine_id));
     }
 
-    info!("Deleted routine {}", routine_id);
-    HttpResponse::Ok().json(json!({ "status": "deleted" }))
+    let latest = versions.last().map_or(1, |v| v.version);
+    let to = parsed.get("to").copied().unwrap_or(latest);
+    let from = parsed.get("from").copied().unwrap_or((to - 1).max(1));
+    let find = |version: i32| {
+        versions
+            .iter()
+            .find(|v| v.version == version)
+            .ok_or_else(|| {
+                ApiError::not_found(format!("Routine {} has no version {}", routine_id, version))
+            })
+    };
+
+    Ok(HttpResponse::Ok().json(diff_versions(find(from)?, find(to)?)))
 }
 
-#[get("/routines/{routine_id}")]
-async fn view_routine(pool: web::Data<PgPool>, routine_id: web::Path<i32>) -> HttpResponse {
-    let routine_id = routine_id.into_inner();
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::test_support::TestApp;
+
+    #[actix_web::test]
+    async fn create_list_and_find_by_name() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
+
+        let body = app
+            .post(
+                "/routines",
+                json!({ "name": "Push", "exercises": [{ "exercise_id": bench, "sets": 3 }] }),
+            )
+            .await
+            .expect(201)
+            .json();
+        let routine_id = body["routine_id"].as_i64().unwrap();
+        app.workout()
+            .routine(routine_id as i32)
+            .at("2026-01-05T18:00:00")
+            .set(bench, 100, 5)
+            .create()
+            .await;
+
+        let list = app.get("/routines").await.expect(200).json();
+        assert_eq!(list[0]["routine_id"], routine_id);
+        assert_eq!(list[0]["name"], "Push");
+        assert_eq!(list[0]["last_performed"], json!(null));
+
+        let list = app
+            .get("/routines?include=lastPerformed")
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(list[0]["last_performed"], "2026-01-05");
+
+        let found = app.get("/routines/name?name=Push").await.expect(200).json();
+        assert_eq!(found, json!({ "routine_id": routine_id }));
+        app.get("/routines/name?name=Pull").await.expect(404);
+        app.get("/routines/name").await.expect(400);
+
+        let found = app
+            .get("/api/v1/routines?name=Push")
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(found.as_array().unwrap().len(), 1);
+        assert_eq!(found[0]["routine_id"], routine_id);
+        let none = app
+            .get("/api/v1/routines?name=Pull")
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(none, json!([]));
+    }
 
-    // Fetch routine details
-    let routine = match sqlx::query!(
-        r#"SELECT RoutineID as routine_id, RoutineName as routine_name FROM Routines WHERE RoutineID = $1"#,
-        routine_id
-    )
-    .fetch_one(pool.get_ref())
-    .await
-    {
-        Ok(row) => row,
-        Err(e) => {
-            error!("Failed to fetch routine details: {}", e);
-            return HttpResponse::NotFound().json(json!({
-                "error": format!("Routine with ID {} not found", routine_id)
-            }));
+    #[actix_web::test]
+    async fn create_rejects_invalid_definitions() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
+
+        let body = app
+            .post(
+                "/routines",
+                json!({
+                    "name": "",
+                    "exercises": [
+                        { "exercise_id": bench, "sets": 0 },
+                        { "exercise_id": bench, "sets": 3, "target_reps": 0 },
+                        { "exercise_id": 999, "sets": 3 },
+                    ],
+                }),
+            )
+            .await
+            .expect(422)
+            .json();
+        let codes: Vec<(&str, &str)> = body["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| (f["field"].as_str().unwrap(), f["code"].as_str().unwrap()))
+            .collect();
+        for expected in [
+            ("name", "required"),
+            ("exercises[0].sets", "out_of_range"),
+            ("exercises[1].target_reps", "out_of_range"),
+            ("exercises[1].exercise_id", "duplicate"),
+            ("exercises[2].exercise_id", "not_found"),
+        ] {
+            assert!(
+                codes.contains(&expected),
+                "missing {:?} in {:?}",
+                expected,
+                codes
+            );
         }
-    };
+        assert_eq!(app.count("Routines").await, 0);
+    }
 
-    // Fetch exercises and their number of sets for the routine
-    let routines = match sqlx::query!(
-        r#"
-        SELECT res.ExerciseID as exercise_id, COUNT(s.SetID) as num_sets
-        FROM Routines_Exercises_Sets res
-        LEFT JOIN "Set" s ON res.RoutineID = s.SetID
-        WHERE res.RoutineID = $1
-        GROUP BY res.ExerciseID
-        ORDER BY res.ExerciseID
-        "#,
-        routine_id as i16
-    )
-    .fetch_all(pool.get_ref())
-    .await
-    {
-        Ok(rows) => {
-            rows.into_iter()
-                .map(|row| ExerciseSetPair {
-                    exercise_id: row.exercise_id as i32,
-                    num_sets: row.num_sets.unwrap_or(0) as i32, // Handle NULL values
-                })
-                .collect::<Vec<ExerciseSetPair>>()
-        }
-        Err(e) => {
-            error!("Failed to fetch exercises and sets: {}", e);
-            return HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to fetch routine exercises and sets"
-            }));
+    #[actix_web::test]
+    async fn view_routine_with_stats() {
+        let app = TestApp::new().await;
+        let bench = app
+            .exercise("Bench Press")
+            .muscles(&["Chest", "Triceps"])
+            .create()
+            .await;
+        let squat = app.exercise("Squat").muscles(&["Quads"]).create().await;
+        let routine = app
+            .routine("Full body")
+            .exercise_with_targets(squat, 3, 5, 120)
+            .exercise(bench, 2)
+            .create()
+            .await;
+        app.workout()
+            .routine(routine)
+            .at("2026-01-05T18:00:00")
+            .minutes(50)
+            .set(squat, 100, 5)
+            .create()
+            .await;
+        app.workout()
+            .routine(routine)
+            .at("2026-01-08T18:00:00")
+            .minutes(70)
+            .set(squat, 110, 5)
+            .set(bench, 80, 10)
+            .create()
+            .await;
+
+        let body = app
+            .get(&format!("/routines/{}", routine))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(body["routine_name"], "Full body");
+        assert_eq!(body["version"], 1);
+        assert_eq!(
+            body["exercises"],
+            json!([
+                {
+                    "position": 1, "exercise_id": squat, "exercise_name": "Squat",
+                    "muscles_trained": ["Quads"], "sets": 3, "target_reps": 5, "target_weight": 120,
+                },
+                {
+                    "position": 2, "exercise_id": bench, "exercise_name": "Bench Press",
+                    "muscles_trained": ["Chest", "Triceps"], "sets": 2, "target_reps": null,
+                    "target_weight": null,
+                },
+            ])
+        );
+        let stats = &body["stats"];
+        assert_eq!(stats["times_performed"], 2);
+        assert_eq!(stats["last_performed"], "2026-01-08T18:00:00");
+        assert_eq!(stats["average_duration_minutes"], 60.0);
+        assert_eq!(stats["volume_trend"][0]["volume"], 500);
+        assert_eq!(stats["volume_trend"][1]["volume"], 550 + 800);
+
+        app.get("/routines/999").await.expect(404);
+    }
+
+    #[actix_web::test]
+    async fn update_records_versions_and_diffs() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
+        let squat = app.exercise("Squat").create().await;
+        let routine = app.routine("Push").exercise(bench, 3).create().await;
+        let uri = format!("/api/v1/routines/{}", routine);
+
+        let body = app
+            .put(
+                &uri,
+                json!({ "name": "Push", "exercises": [{ "exercise_id": bench, "sets": 3 }] }),
+            )
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(body["version"], 1); // Unchanged definitions add no version
+
+        let body = app
+            .put(
+                &uri,
+                json!({
+                    "name": "Legs and push",
+                    "exercises": [
+                        { "exercise_id": squat, "sets": 5 },
+                        { "exercise_id": bench, "sets": 4 },
+                    ],
+                }),
+            )
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(body["version"], 2);
+
+        let versions = app
+            .get(&format!("{}/versions", uri))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(versions.as_array().unwrap().len(), 2);
+        assert!(versions[0].get("changes").is_none());
+        assert_eq!(versions[1]["changes"]["renamed"]["to"], "Legs and push");
+
+        let diff = app.get(&format!("{}/diff", uri)).await.expect(200).json();
+        assert_eq!(
+            (diff["from"].as_i64(), diff["to"].as_i64()),
+            (Some(1), Some(2))
+        );
+        assert_eq!(diff["added"][0]["exercise_id"], squat);
+        assert_eq!(diff["changed"][0]["sets_from"], 3);
+        assert_eq!(diff["changed"][0]["sets_to"], 4);
+        assert_eq!(diff["reordered"], false);
+
+        app.get(&format!("{}/diff?from=1&to=7", uri))
+            .await
+            .expect(404);
+        app.get(&format!("{}/diff?from=x", uri)).await.expect(400);
+        app.get("/api/v1/routines/999/versions").await.expect(404);
+        app.put(
+            "/routines/999",
+            json!({ "name": "Push", "exercises": [{ "exercise_id": bench, "sets": 3 }] }),
+        )
+        .await
+        .expect(404);
+    }
+
+    #[actix_web::test]
+    async fn duplicate_copies_the_latest_definition() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
+        let routine = app
+            .routine("Push")
+            .exercise_with_targets(bench, 3, 8, 60)
+            .create()
+            .await;
+
+        let body = app
+            .send(
+                actix_web::test::TestRequest::post()
+                    .uri(&format!("/api/v1/routines/{}/duplicate", routine)),
+            )
+            .await
+            .expect(201)
+            .json();
+        let copy = app
+            .get(&format!("/routines/{}", body["routine_id"]))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(copy["routine_name"], "Push (copy)");
+        assert_eq!(copy["exercises"][0]["target_reps"], 8);
+
+        let body = app
+            .post(
+                &format!("/api/v1/routines/{}/duplicate", routine),
+                json!({ "name": "Push B" }),
+            )
+            .await
+            .expect(201)
+            .json();
+        let copy = app
+            .get(&format!("/routines/{}", body["routine_id"]))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(copy["routine_name"], "Push B");
+
+        for name in ["", "   ", &"x".repeat(256)] {
+            app.post(
+                &format!("/api/v1/routines/{}/duplicate", routine),
+                json!({ "name": name }),
+            )
+            .await
+            .expect(422);
         }
-    };
 
-    // Construct the response
-    let response = RoutineViewResponse {
-        routine_id: routine.routine_id,
-        routine_name: routine.routine_name,
-        routines,
-    };
+        let long = app
+            .routine(&"x".repeat(255))
+            .exercise(bench, 3)
+            .create()
+            .await;
+        let body = app
+            .post(&format!("/api/v1/routines/{}/duplicate", long), json!({}))
+            .await
+            .expect(201)
+            .json();
+        let copy = app
+            .get(&format!("/api/v1/routines/{}", body["routine_id"]))
+            .await
+            .expect(200)
+            .json();
+        let name = copy["routine_name"].as_str().unwrap();
+        assert_eq!(name.chars().count(), 255);
+        assert!(name.ends_with(" (copy)"));
+
+        app.post("/api/v1/routines/999/duplicate", json!({}))
+            .await
+            .expect(404);
+    }
 
-    info!("Retrieved details for routine {}", routine_id);
-    HttpResponse::Ok().json(response)
+    #[actix_web::test]
+    async fn delete_routine() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
+        let routine = app.routine("Push").exercise(bench, 3).create().await;
+
+        app.delete(&format!("/routines/{}", routine))
+            .await
+            .expect(200);
+        app.get(&format!("/routines/{}", routine)).await.expect(404);
+        app.delete(&format!("/routines/{}", routine))
+            .await
+            .expect(404);
+    }
 }
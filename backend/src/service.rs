@@ -0,0 +1,476 @@
+// Domain logic shared by the HTTP handlers, the CLI and the tests. Everything
+// here goes through the repositories, never the pool.
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use crate::errors::ApiError;
+use crate::markers::{MarkerValue, ReadingPolicy};
+use crate::repository::{PrRecord, RepoResult, Repositories};
+use crate::validation::validate;
+use crate::workouts::{Exercise, Set, WorkoutData};
+
+#[derive(Serialize, Debug, PartialEq, ToSchema)]
+pub(crate) enum PRValue {
+    Weight(i16),
+    OneRM(f32),
+    Volume(i32),
+    Reps(i16),
+}
+
+// Brzycki estimate, as stored in PRs.OneRM; sets of 37 reps or more count as 0
+pub(crate) fn estimated_one_rm(weight: i16, reps: i16) -> f32 {
+    if reps >= 37 {
+        return 0.0;
+    }
+    f32::from(weight) * (36.0 / (37.0 - f32::from(reps)))
+}
+
+// The records a single set would beat, keyed by record name
+pub(crate) async fn check_set(
+    repos: &Repositories,
+    exercise_id: i32,
+    weight: i16,
+    reps: i16,
+) -> RepoResult<HashMap<&'static str, PRValue>> {
+    let mut new_prs = HashMap::new();
+
+    let one_rm = estimated_one_rm(weight, reps);
+    let set_volume = i32::from(weight) * i32::from(reps);
+
+    if let Some(pr) = repos.prs.latest(exercise_id).await? {
+        if weight > pr.heaviest_weight {
+            new_prs.insert("HeaviestWeight", PRValue::Weight(weight));
+        }
+        if one_rm > pr.one_rm {
+            new_prs.insert("OneRM", PRValue::OneRM(one_rm));
+        }
+        if set_volume > pr.set_volume {
+            new_prs.insert("SetVolume", PRValue::Volume(set_volume));
+        }
+    }
+
+    // Without a record at this weight any rep count is a best
+    match repos.prs.highest_reps(exercise_id, weight).await? {
+        Some(highest) if reps <= highest => {}
+        _ => {
+            new_prs.insert("HighestReps", PRValue::Reps(reps));
+        }
+    }
+
+    Ok(new_prs)
+}
+
+// An exercise's bests within one workout, plus the most reps done at each
+// weight
+fn exercise_bests(exercise: &Exercise) -> (PrRecord, HashMap<i16, i16>) {
+    let mut pr = PrRecord {
+        heaviest_weight: 0,
+        one_rm: 0.0,
+        set_volume: 0,
+    };
+    let mut highest_reps: HashMap<i16, i16> = HashMap::new();
+
+    for set in exercise.sets.values() {
+        pr.heaviest_weight = pr.heaviest_weight.max(set.weight);
+        pr.one_rm = pr.one_rm.max(estimated_one_rm(set.weight, set.reps));
+        pr.set_volume += i32::from(set.weight) * i32::from(set.reps);
+
+        let best = highest_reps.entry(set.weight).or_insert(0);
+        *best = (*best).max(set.reps);
+    }
+    (pr, highest_reps)
+}
+
+// Records the exercise's bests for the workout; true if they beat every
+// earlier workout's heaviest weight or estimated 1RM
+#[instrument(skip(repos, exercise), fields(exercise_id = exercise.exercise_id))]
+pub(crate) async fn update_prs(
+    repos: &Repositories,
+    workout_id: i32,
+    exercise: &Exercise,
+) -> RepoResult<bool> {
+    let (pr, highest_reps) = exercise_bests(exercise);
+
+    let previous = repos
+        .prs
+        .best_excluding(exercise.exercise_id, workout_id)
+        .await?;
+    let new_record = (pr.heaviest_weight > 0 || pr.one_rm > 0.0)
+        && previous
+            .is_none_or(|(weight, one_rm)| pr.heaviest_weight > weight || pr.one_rm > one_rm);
+
+    repos
+        .prs
+        .record(workout_id, exercise.exercise_id, pr, &highest_reps)
+        .await?;
+    Ok(new_record)
+}
+
+// Runs the body's rules plus the checks that need storage, reporting
+// everything at once
+#[instrument(skip_all)]
+pub(crate) async fn validate_workout(
+    repos: &Repositories,
+    workout_data: &WorkoutData,
+) -> Result<(), ApiError> {
+    let mut v = validate(workout_data)
+        .exercises_exist(repos.exercises.as_ref())
+        .await?;
+
+    // A routine that does not exist is a problem with the body, not the URL
+    if let Some(routine_id) = workout_data.routine_id {
+        if !repos.routines.exists(routine_id).await? {
+            v.error(
+                "routine_id",
+                "not_found",
+                format!("Routine with ID {} does not exist", routine_id),
+            );
+        }
+    }
+    v.finish()
+}
+
+// Stores a workout's sets, as a new workout unless an ID is given, and
+// updates the PRs. Returns the workout's ID and the number of exercises that
+// set a PR.
+#[instrument(
+    skip_all,
+    fields(workout_id = workout_id, exercises = workout_data.exercises.len())
+)]
+pub(crate) async fn record_workout(
+    repos: &Repositories,
+    workout_data: &WorkoutData,
+    workout_id: Option<i32>,
+) -> RepoResult<(i32, u64)> {
+    let workout_id = match workout_id {
+        Some(id) => id,
+        None => {
+            repos
+                .workouts
+                .create(
+                    workout_data.start_time,
+                    workout_data.end_time,
+                    workout_data.routine_id,
+                )
+                .await?
+        }
+    };
+    tracing::Span::current().record("workout_id", workout_id);
+
+    let mut prs_set = 0;
+    for exercise in &workout_data.exercises {
+        repos.workouts.add_sets(workout_id, exercise).await?;
+        if update_prs(repos, workout_id, exercise).await? {
+            prs_set += 1;
+        }
+    }
+
+    Ok((workout_id, prs_set))
+}
+
+// Empty sets for every exercise of the routine, ready to be filled in
+pub(crate) async fn workout_template(
+    repos: &Repositories,
+    routine_id: i32,
+) -> Result<Vec<Exercise>, ApiError> {
+    if !repos.routines.exists(routine_id).await? {
+        return Err(ApiError::not_found(format!(
+            "Routine with ID {} not found",
+            routine_id
+        )));
+    }
+
+    Ok(repos
+        .routines
+        .planned_exercises(routine_id)
+        .await?
+        .into_iter()
+        .map(|planned| Exercise {
+            exercise_id: planned.exercise_id,
+            exercise_name: planned.exercise_name,
+            sets: (1..=planned.sets)
+                .map(|number| (number, Set { weight: 0, reps: 0 }))
+                .collect(),
+        })
+        .collect())
+}
+
+// Logs a reading for a regular marker, applying its reading policy. Returns
+// the new entry's ID.
+pub(crate) async fn log_marker_value(
+    repos: &Repositories,
+    marker_id: i32,
+    value: &MarkerValue,
+) -> Result<i32, ApiError> {
+    let date = value
+        .entry_date()
+        .map_err(|message| ApiError::field("date", "invalid", message))?;
+
+    let settings =
+        repos.markers.settings(marker_id).await?.ok_or_else(|| {
+            ApiError::not_found(format!("Marker with ID {} not found", marker_id))
+        })?;
+
+    if settings.formula.is_some() {
+        return Err(ApiError::Unprocessable(
+            "Values of a derived marker are computed and cannot be logged".to_string(),
+        ));
+    }
+
+    // Under the replace policy the new reading supersedes the rest of the day
+    let replace_day = settings.reading_policy == ReadingPolicy::Replace;
+    Ok(repos
+        .markers
+        .log(marker_id, date, value, replace_day)
+        .await?)
+}
+
+// False if the marker has no such entry
+pub(crate) async fn update_marker_entry(
+    repos: &Repositories,
+    marker_id: i32,
+    entry_id: i32,
+    value: &MarkerValue,
+) -> Result<bool, ApiError> {
+    let date = value
+        .entry_date()
+        .map_err(|message| ApiError::field("date", "invalid", message))?;
+
+    let settings =
+        repos.markers.settings(marker_id).await?.ok_or_else(|| {
+            ApiError::not_found(format!("Marker with ID {} not found", marker_id))
+        })?;
+
+    // An entry moved onto a day that already has readings replaces them too
+    let replace_day = settings.reading_policy == ReadingPolicy::Replace;
+    Ok(repos
+        .markers
+        .update_entry(marker_id, entry_id, date, value, replace_day)
+        .await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use serde_json::json;
+
+    use super::*;
+    use crate::routines::RoutineExercise;
+
+    fn workout(exercise_id: i32, sets: &[(i16, i16)]) -> WorkoutData {
+        let sets: serde_json::Map<String, serde_json::Value> = sets
+            .iter()
+            .enumerate()
+            .map(|(i, (weight, reps))| {
+                (
+                    (i + 1).to_string(),
+                    json!({ "weight": weight, "reps": reps }),
+                )
+            })
+            .collect();
+        serde_json::from_value(json!({
+            "start_time": "2026-01-05T18:00:00",
+            "end_time": "2026-01-05T19:00:00",
+            "exercises": [{ "exercise_id": exercise_id, "exercise_name": "", "sets": sets }],
+        }))
+        .unwrap()
+    }
+
+    async fn bench(repos: &Repositories) -> i32 {
+        repos
+            .exercises
+            .create("Bench Press", &["Chest".to_string()], "Strength")
+            .await
+            .unwrap()
+            .exercise_id
+    }
+
+    #[test]
+    fn one_rm_uses_brzycki_up_to_36_reps() {
+        assert_eq!(estimated_one_rm(100, 1), 100.0);
+        assert!((estimated_one_rm(100, 5) - 112.5).abs() < 0.01);
+        assert_eq!(estimated_one_rm(100, 37), 0.0);
+    }
+
+    #[actix_web::test]
+    async fn record_workout_counts_exercises_that_set_a_pr() {
+        let repos = Repositories::in_memory();
+        let bench = bench(&repos).await;
+
+        let mut prs_set = 0;
+        for sets in [&[(100, 5)][..], &[(80, 5)], &[(100, 6)], &[(105, 1)]] {
+            let (_, prs) = record_workout(&repos, &workout(bench, sets), None)
+                .await
+                .unwrap();
+            prs_set += prs;
+        }
+        // 80x5 beats nothing; 100x6 beats the 1RM; 105x1 the weight only
+        assert_eq!(prs_set, 3);
+
+        let latest = repos.prs.latest(bench).await.unwrap().unwrap();
+        assert_eq!(latest.heaviest_weight, 105);
+        assert_eq!(repos.prs.highest_reps(bench, 100).await.unwrap(), Some(6));
+        assert_eq!(repos.prs.highest_reps(bench, 80).await.unwrap(), Some(5));
+    }
+
+    #[actix_web::test]
+    async fn check_set_compares_with_the_latest_records() {
+        let repos = Repositories::in_memory();
+        let bench = bench(&repos).await;
+
+        let prs = check_set(&repos, bench, 100, 5).await.unwrap();
+        assert_eq!(prs.len(), 1);
+        assert_eq!(prs["HighestReps"], PRValue::Reps(5));
+
+        record_workout(&repos, &workout(bench, &[(100, 5), (90, 8)]), None)
+            .await
+            .unwrap();
+        assert!(check_set(&repos, bench, 100, 5).await.unwrap().is_empty());
+
+        let prs = check_set(&repos, bench, 90, 9).await.unwrap();
+        assert_eq!(prs["HighestReps"], PRValue::Reps(9));
+        assert!(!prs.contains_key("HeaviestWeight"));
+        assert!(!prs.contains_key("SetVolume"));
+
+        let prs = check_set(&repos, bench, 110, 6).await.unwrap();
+        assert_eq!(prs["HeaviestWeight"], PRValue::Weight(110));
+        assert!(!prs.contains_key("SetVolume")); // 660 < 500 + 720
+    }
+
+    #[actix_web::test]
+    async fn deleting_a_workout_rebuilds_its_records() {
+        let repos = Repositories::in_memory();
+        let bench = bench(&repos).await;
+
+        record_workout(&repos, &workout(bench, &[(100, 5)]), None)
+            .await
+            .unwrap();
+        let (second, _) = record_workout(&repos, &workout(bench, &[(100, 8)]), None)
+            .await
+            .unwrap();
+        assert_eq!(repos.prs.highest_reps(bench, 100).await.unwrap(), Some(8));
+
+        assert!(repos.workouts.delete(second).await.unwrap());
+        assert!(!repos.workouts.delete(second).await.unwrap());
+        assert_eq!(repos.prs.highest_reps(bench, 100).await.unwrap(), Some(5));
+        assert_eq!(check_set(&repos, bench, 100, 6).await.unwrap().len(), 3);
+    }
+
+    #[actix_web::test]
+    async fn recent_records_lists_new_bests_newest_first() {
+        let repos = Repositories::in_memory();
+        let bench = bench(&repos).await;
+
+        let mut ids = Vec::new();
+        for sets in [&[(100, 5)][..], &[(80, 5)], &[(100, 6)], &[(105, 1)]] {
+            let (id, _) = record_workout(&repos, &workout(bench, sets), None)
+                .await
+                .unwrap();
+            ids.push(id);
+        }
+
+        let records = repos.prs.recent_records(2).await.unwrap();
+        let found: Vec<i32> = records.iter().map(|r| r.workout_id).collect();
+        assert_eq!(found, vec![ids[3], ids[2]]);
+        assert_eq!(records[0].exercise_name, "Bench Press");
+        assert_eq!(repos.prs.recent_records(10).await.unwrap().len(), 3);
+    }
+
+    #[actix_web::test]
+    async fn validate_workout_checks_references() {
+        let repos = Repositories::in_memory();
+        let bench = bench(&repos).await;
+
+        let mut data = workout(bench, &[(100, 5)]);
+        validate_workout(&repos, &data).await.unwrap();
+
+        match validate_workout(&repos, &workout(999, &[(100, 5)])).await {
+            Err(ApiError::Validation(fields)) => assert_eq!(fields.len(), 1),
+            _ => panic!("expected a validation error"),
+        }
+        data.routine_id = Some(42);
+        match validate_workout(&repos, &data).await {
+            Err(ApiError::Validation(fields)) => assert_eq!(fields.len(), 1),
+            _ => panic!("expected a validation error"),
+        }
+    }
+
+    #[actix_web::test]
+    async fn template_follows_the_routine() {
+        let repos = Repositories::in_memory();
+        let bench = bench(&repos).await;
+        let routine = repos
+            .routines
+            .create(
+                "Push",
+                NaiveDate::from_ymd_opt(2026, 1, 1)
+                    .unwrap()
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap(),
+                &[RoutineExercise {
+                    exercise_id: bench,
+                    sets: 3,
+                    target_reps: None,
+                    target_weight: None,
+                }],
+            )
+            .await
+            .unwrap();
+
+        let template = workout_template(&repos, routine).await.unwrap();
+        assert_eq!(template.len(), 1);
+        assert_eq!(template[0].exercise_name, "Bench Press");
+        assert_eq!(template[0].sets.len(), 3);
+        assert!(matches!(
+            workout_template(&repos, routine + 1).await,
+            Err(ApiError::NotFound(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn replace_policy_keeps_one_reading_per_day() {
+        let repos = Repositories::in_memory();
+        let settings = serde_json::from_value(json!({ "reading_policy": "replace" })).unwrap();
+        let marker = repos
+            .markers
+            .create("Weight", "#ffffff", &settings)
+            .await
+            .unwrap();
+
+        for value in [80.0, 79.5] {
+            let value = MarkerValue {
+                value,
+                date: NaiveDate::from_ymd_opt(2026, 1, 5),
+                logged_at: None,
+                note: None,
+            };
+            log_marker_value(&repos, marker, &value).await.unwrap();
+        }
+
+        let entries = repos.markers.entries(marker, None, None).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].value, 79.5);
+
+        // Moving a reading onto that day replaces it as well
+        let other_day = MarkerValue {
+            value: 81.0,
+            date: NaiveDate::from_ymd_opt(2026, 1, 6),
+            logged_at: None,
+            note: None,
+        };
+        let moved = log_marker_value(&repos, marker, &other_day).await.unwrap();
+        let value = MarkerValue {
+            date: NaiveDate::from_ymd_opt(2026, 1, 5),
+            ..other_day
+        };
+        assert!(update_marker_entry(&repos, marker, moved, &value)
+            .await
+            .unwrap());
+
+        let entries = repos.markers.entries(marker, None, None).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].entry_id, entries[0].value), (moved, 81.0));
+    }
+}
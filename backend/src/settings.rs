@@ -0,0 +1,170 @@
+use actix_web::{delete, get, put, web, HttpResponse};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{PgPool, Row};
+use std::collections::BTreeMap;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::errors::{ApiError, ErrorBody};
+
+// Named numeric settings (e.g. height) that derived markers can reference
+#[derive(Serialize, Deserialize, ToSchema)]
+struct SettingValue {
+    value: f64,
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(list_settings, get_setting, update_setting, delete_setting),
+    tags((name = "settings", description = "Named values that derived markers can reference"))
+)]
+pub(crate) struct ApiDoc;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_settings)
+        .service(get_setting)
+        .service(update_setting)
+        .service(delete_setting);
+}
+
+fn not_found(name: &str) -> ApiError {
+    ApiError::not_found(format!("Setting '{}' not found", name))
+}
+
+#[utoipa::path(
+    tag = "settings",
+    responses((status = 200, body = BTreeMap<String, f64>, example = json!({ "height": 1.8 })))
+)]
+#[get("/settings")]
+async fn list_settings(pool: web::Data<PgPool>) -> Result<HttpResponse, ApiError> {
+    let rows = sqlx::query("SELECT Name, Value FROM Settings ORDER BY Name")
+        .fetch_all(pool.get_ref())
+        .await?;
+    let settings: BTreeMap<String, f64> = rows
+        .iter()
+        .map(|row| (row.get::<String, _>("name"), row.get::<f64, _>("value")))
+        .collect();
+    Ok(HttpResponse::Ok().json(settings))
+}
+
+#[utoipa::path(
+    tag = "settings",
+    responses(
+        (status = 200, body = SettingValue),
+        (status = 404, description = "Setting not found", body = ErrorBody)
+    )
+)]
+#[get("/settings/{name}")]
+async fn get_setting(
+    pool: web::Data<PgPool>,
+    name: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let name = name.into_inner();
+    let value = sqlx::query_scalar::<_, f64>("SELECT Value FROM Settings WHERE Name = $1")
+        .bind(&name)
+        .fetch_optional(pool.get_ref())
+        .await?
+        .ok_or_else(|| not_found(&name))?;
+    Ok(HttpResponse::Ok().json(SettingValue { value }))
+}
+
+#[utoipa::path(
+    tag = "settings",
+    summary = "Create or replace a setting",
+    responses(
+        (status = 200, body = serde_json::Value, example = json!({ "status": "updated" })),
+        (status = 400, description = "Name empty or longer than 64 characters", body = ErrorBody)
+    )
+)]
+#[put("/settings/{name}")]
+async fn update_setting(
+    pool: web::Data<PgPool>,
+    name: web::Path<String>,
+    setting: web::Json<SettingValue>,
+) -> Result<HttpResponse, ApiError> {
+    let name = name.into_inner();
+    if name.is_empty() || name.len() > 64 {
+        return Err(ApiError::bad_request(
+            "Setting name must be between 1 and 64 characters",
+        ));
+    }
+
+    sqlx::query(
+        "INSERT INTO Settings (Name, Value) VALUES ($1, $2)
+         ON CONFLICT (Name) DO UPDATE SET Value = EXCLUDED.Value",
+    )
+    .bind(&name)
+    .bind(setting.value)
+    .execute(pool.get_ref())
+    .await?;
+    info!("Set {} to {}", name, setting.value);
+    Ok(HttpResponse::Ok().json(json!({ "status": "updated" })))
+}
+
+#[utoipa::path(
+    tag = "settings",
+    responses(
+        (status = 200, body = serde_json::Value, example = json!({ "status": "deleted" })),
+        (status = 404, description = "Setting not found", body = ErrorBody)
+    )
+)]
+#[delete("/settings/{name}")]
+async fn delete_setting(
+    pool: web::Data<PgPool>,
+    name: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let name = name.into_inner();
+    let result = sqlx::query("DELETE FROM Settings WHERE Name = $1")
+        .bind(&name)
+        .execute(pool.get_ref())
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(not_found(&name));
+    }
+    info!("Deleted setting {}", name);
+    Ok(HttpResponse::Ok().json(json!({ "status": "deleted" })))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::test_support::TestApp;
+
+    #[actix_web::test]
+    async fn set_list_and_delete() {
+        let app = TestApp::new().await;
+
+        app.put("/api/v1/settings/height", json!({ "value": 1.8 }))
+            .await
+            .expect(200);
+        app.put("/api/v1/settings/age", json!({ "value": 30 }))
+            .await
+            .expect(200);
+        app.put("/api/v1/settings/height", json!({ "value": 1.81 }))
+            .await
+            .expect(200);
+        let settings = app.get("/api/v1/settings").await.expect(200).json();
+        assert_eq!(settings, json!({ "age": 30.0, "height": 1.81 }));
+        let height = app.get("/api/v1/settings/height").await.expect(200).json();
+        assert_eq!(height, json!({ "value": 1.81 }));
+        let missing = app.get("/api/v1/settings/weight").await.expect(404).json();
+        assert_eq!(missing["code"], "not_found");
+
+        app.put(
+            &format!("/api/v1/settings/{}", "x".repeat(65)),
+            json!({ "value": 1 }),
+        )
+        .await
+        .expect(400);
+        app.put("/api/v1/settings/age", json!({ "value": "old" }))
+            .await
+            .expect(400);
+
+        app.delete("/api/v1/settings/age").await.expect(200);
+        app.delete("/api/v1/settings/age").await.expect(404);
+        let settings = app.get("/api/v1/settings").await.expect(200).json();
+        assert_eq!(settings, json!({ "height": 1.81 }));
+    }
+}
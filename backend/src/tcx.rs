@@ -0,0 +1,96 @@
+// Encoder for Garmin Training Center (TCX) activity files. TCX has no notion of
+// strength sets, so each set becomes a lap whose notes carry the exercise,
+// reps and weight.
+
+use chrono::{Duration, NaiveDateTime};
+use std::fmt::Write;
+
+use crate::activity_export::Activity;
+
+const NAMESPACE: &str = "http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2";
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn time(time: NaiveDateTime) -> String {
+    time.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+pub(crate) fn encode(activity: &Activity) -> String {
+    let mut xml = String::new();
+    // Writing to a String cannot fail
+    let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(xml, r#"<TrainingCenterDatabase xmlns="{}">"#, NAMESPACE);
+    let _ = writeln!(xml, "  <Activities>");
+    let _ = writeln!(xml, r#"    <Activity Sport="Other">"#);
+    let _ = writeln!(xml, "      <Id>{}</Id>", time(activity.start));
+
+    // A workout without sets still gets the one lap the schema requires
+    let laps: Vec<(NaiveDateTime, Duration, Option<String>)> = if activity.sets.is_empty() {
+        vec![(activity.start, activity.duration(), None)]
+    } else {
+        activity
+            .sets
+            .iter()
+            .zip(activity.set_times())
+            .map(|(set, (start, length))| {
+                let notes = format!(
+                    "{}: {} reps @ {} kg",
+                    set.exercise_name, set.reps, set.weight
+                );
+                (start, length, Some(notes))
+            })
+            .collect()
+    };
+
+    for (start, length, notes) in laps {
+        let _ = writeln!(xml, r#"      <Lap StartTime="{}">"#, time(start));
+        let _ = writeln!(
+            xml,
+            "        <TotalTimeSeconds>{}</TotalTimeSeconds>",
+            length.num_milliseconds() as f64 / 1000.0
+        );
+        let _ = writeln!(xml, "        <DistanceMeters>0</DistanceMeters>");
+        let _ = writeln!(xml, "        <Calories>0</Calories>");
+        let _ = writeln!(xml, "        <Intensity>Active</Intensity>");
+        let _ = writeln!(xml, "        <TriggerMethod>Manual</TriggerMethod>");
+        if let Some(notes) = notes {
+            let _ = writeln!(xml, "        <Notes>{}</Notes>", escape(&notes));
+        }
+        let _ = writeln!(xml, "      </Lap>");
+    }
+
+    let notes = match &activity.name {
+        Some(name) => format!("Strength training: {}", name),
+        None => "Strength training".to_string(),
+    };
+    let _ = writeln!(xml, "      <Notes>{}</Notes>", escape(&notes));
+    let _ = writeln!(xml, "    </Activity>");
+    let _ = writeln!(xml, "  </Activities>");
+    let _ = writeln!(xml, "</TrainingCenterDatabase>");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activity_export::tests::{assert_golden, sample_activity};
+
+    #[test]
+    fn matches_golden_file() {
+        assert_golden(
+            "strength_workout.tcx",
+            encode(&sample_activity()).as_bytes(),
+        );
+    }
+
+    #[test]
+    fn escapes_names() {
+        assert_eq!(escape("Push & <Pull>"), "Push &amp; &lt;Pull&gt;");
+    }
+}
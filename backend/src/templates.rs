@@ -0,0 +1,1078 @@
+use actix_web::{delete, get, post, web, HttpResponse};
+use chrono::{Local, NaiveDate, NaiveDateTime, Utc, Weekday};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{PgPool, Row};
+use std::collections::{HashMap, HashSet};
+use utoipa::{OpenApi, ToSchema};
+
+use crate::errors::{ApiError, ErrorBody};
+use crate::programs::{self, ProgramCreate, ProgramSlot, ScheduleType};
+use crate::repository::postgres::insert_routine;
+use crate::routines::{self, RoutineExercise};
+use crate::validation::{plausible_date, validate, Validate, Validator, MAX_YEARS_AWAY};
+use crate::workout_import::{self, Catalog, MatchKind};
+
+// Templates describe exercises by name, muscles and type instead of by ID, so
+// they can be imported into any exercise catalog. The layout is documented by
+// schemas/template.schema.json, which is also served at /templates/schema.
+const TEMPLATE_FORMAT: &str = "strongeryou-template";
+const TEMPLATE_VERSION: u32 = 1;
+const TEMPLATE_SCHEMA: &str = include_str!("../schemas/template.schema.json");
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct TemplateDocument {
+    format: String,
+    version: u32,
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    routines: Vec<TemplateRoutine>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    program: Option<TemplateProgram>, // Program templates only
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct TemplateRoutine {
+    name: String,
+    exercises: Vec<TemplateExercise>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct TemplateExercise {
+    name: String,
+    #[serde(default)]
+    muscles_trained: Vec<String>,
+    exercise_type: String,
+    sets: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    target_reps: Option<i16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    target_weight: Option<i16>, // kg
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct TemplateProgram {
+    schedule_type: ScheduleType,
+    weeks: i16,
+    #[serde(default)]
+    deload_weeks: Vec<i16>,
+    slots: Vec<TemplateSlot>,
+}
+
+// Slots refer to routines by their index in the template's routine list
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct TemplateSlot {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, example = "Mon")]
+    weekday: Option<Weekday>,
+    routine: Option<usize>,
+}
+
+impl TemplateDocument {
+    fn kind(&self) -> &'static str {
+        if self.program.is_some() {
+            "program"
+        } else {
+            "routine"
+        }
+    }
+
+    // Each exercise once, in order of first appearance
+    fn exercises(&self) -> Vec<&TemplateExercise> {
+        let mut seen = HashSet::new();
+        self.routines
+            .iter()
+            .flat_map(|routine| &routine.exercises)
+            .filter(|exercise| seen.insert(exercise.name.as_str()))
+            .collect()
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+struct PublishRequest {
+    #[serde(default)]
+    routine_id: Option<i32>,
+    #[serde(default)]
+    program_id: Option<i32>,
+    #[serde(default)]
+    name: Option<String>, // Defaults to the routine or program name
+    #[serde(default)]
+    description: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct TemplateSummary {
+    template_id: i32,
+    kind: String,
+    name: String,
+    description: Option<String>,
+    routines: i32,
+    exercises: i32,
+    published_at: NaiveDateTime,
+}
+
+// How a template exercise maps onto this server's catalog
+#[derive(Serialize, ToSchema)]
+struct ExerciseResolution {
+    name: String,
+    exercise_id: Option<i32>, // None means it is created on import
+    exercise_name: Option<String>,
+    #[serde(rename = "match")]
+    kind: Option<MatchKind>,
+    score: Option<f64>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct TemplatePreview {
+    template_id: i32,
+    kind: String,
+    published_at: NaiveDateTime,
+    template: TemplateDocument,
+    exercises: Vec<ExerciseResolution>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct TemplateImport {
+    routine_ids: Vec<i32>,
+    program_id: Option<i32>,
+    exercises_matched: usize,
+    exercises_created: Vec<String>,
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        template_schema,
+        list_templates,
+        publish_template,
+        import_template_file,
+        preview_template,
+        download_template,
+        import_template,
+        delete_template
+    ),
+    tags((name = "templates", description = "Shareable routine and program templates"))
+)]
+pub(crate) struct ApiDoc;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    // Registered before /templates/{template_id} so "schema" is not taken for an ID
+    cfg.service(template_schema)
+        .service(list_templates)
+        .service(publish_template)
+        .service(import_template_file)
+        .service(preview_template)
+        .service(download_template)
+        .service(import_template)
+        .service(delete_template);
+}
+
+// Whether the body is a template this server can read at all; its contents
+// are checked by the Validate rules
+fn check_format(template: &TemplateDocument) -> Result<(), ApiError> {
+    if template.format != TEMPLATE_FORMAT {
+        return Err(ApiError::bad_request(format!(
+            "Not a template: format must be '{}'",
+            TEMPLATE_FORMAT
+        )));
+    }
+    if template.version > TEMPLATE_VERSION {
+        return Err(ApiError::bad_request(format!(
+            "Template version {} is newer than the supported version {}",
+            template.version, TEMPLATE_VERSION
+        )));
+    }
+    Ok(())
+}
+
+impl Validate for TemplateExercise {
+    fn rules(&self, v: &mut Validator) {
+        v.required("name", &self.name, 255);
+        v.required("exercise_type", &self.exercise_type, 255);
+        v.range("sets", self.sets, 1, routines::MAX_SETS);
+        if let Some(reps) = self.target_reps {
+            v.min("target_reps", reps, 1);
+        }
+        if let Some(weight) = self.target_weight {
+            v.min("target_weight", weight, 0);
+        }
+    }
+}
+
+impl Validate for TemplateRoutine {
+    fn rules(&self, v: &mut Validator) {
+        v.required("name", &self.name, 255);
+        v.not_empty("exercises", &self.exercises);
+        v.each("exercises", &self.exercises);
+
+        let mut names = HashSet::new();
+        for (i, exercise) in self.exercises.iter().enumerate() {
+            if !names.insert(exercise.name.to_lowercase()) {
+                v.error(
+                    &format!("exercises[{}].name", i),
+                    "duplicate",
+                    "Exercise appears more than once in the routine",
+                );
+            }
+        }
+    }
+}
+
+impl Validate for TemplateDocument {
+    fn rules(&self, v: &mut Validator) {
+        v.required("name", &self.name, 255);
+        v.not_empty("routines", &self.routines);
+        v.each("routines", &self.routines);
+
+        let Some(program) = &self.program else {
+            return;
+        };
+        v.nested("program", |v| {
+            for (i, slot) in program.slots.iter().enumerate() {
+                if let Some(routine) = slot.routine.filter(|i| *i >= self.routines.len()) {
+                    v.error(
+                        &format!("slots[{}].routine", i),
+                        "out_of_range",
+                        format!(
+                            "Routine {} is outside the template's {} routines",
+                            routine,
+                            self.routines.len()
+                        ),
+                    );
+                }
+            }
+            // Placeholder IDs and start date; only the shape of the schedule is checked
+            let placeholder = program_create(self, program, NaiveDate::MIN, |i| i as i32);
+            programs::schedule_rules(
+                v,
+                program.schedule_type,
+                program.weeks,
+                &program.deload_weeks,
+                &placeholder.slots,
+            );
+        });
+    }
+}
+
+fn program_create(
+    template: &TemplateDocument,
+    program: &TemplateProgram,
+    start_date: NaiveDate,
+    routine_id: impl Fn(usize) -> i32,
+) -> ProgramCreate {
+    ProgramCreate {
+        name: template.name.clone(),
+        schedule_type: program.schedule_type,
+        start_date,
+        weeks: program.weeks,
+        deload_weeks: program.deload_weeks.clone(),
+        slots: program
+            .slots
+            .iter()
+            .map(|slot| ProgramSlot {
+                weekday: slot.weekday,
+                routine_id: slot.routine.map(&routine_id),
+            })
+            .collect(),
+    }
+}
+
+fn resolve_exercises(catalog: &Catalog, template: &TemplateDocument) -> Vec<ExerciseResolution> {
+    let names: HashMap<i32, &str> = catalog
+        .exercises
+        .iter()
+        .map(|(id, name)| (*id, name.as_str()))
+        .collect();
+    template
+        .exercises()
+        .into_iter()
+        .map(|exercise| {
+            let matched = workout_import::match_exercise(catalog, &exercise.name);
+            ExerciseResolution {
+                name: exercise.name.clone(),
+                exercise_id: matched.as_ref().map(|m| m.0),
+                exercise_name: matched
+                    .as_ref()
+                    .and_then(|m| names.get(&m.0))
+                    .map(|name| name.to_string()),
+                kind: matched.as_ref().map(|m| m.1.clone()),
+                score: matched.map(|m| m.2),
+            }
+        })
+        .collect()
+}
+
+// The latest version of a routine, with its exercises described by name
+async fn routine_template(
+    pool: &PgPool,
+    routine_id: i32,
+) -> Result<Option<TemplateRoutine>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT v.RoutineName, e.ExerciseName, e.MusclesTrained, e.ExerciseType,
+                ve.NumberOfSets::INTEGER AS numberofsets, ve.TargetReps, ve.TargetWeight
+         FROM RoutineVersions v
+         JOIN RoutineVersionExercises ve ON ve.RoutineID = v.RoutineID AND ve.Version = v.Version
+         JOIN ExerciseList e ON ve.ExerciseID = e.ExerciseID
+         WHERE v.RoutineID = $1
+           AND v.Version = (SELECT MAX(Version) FROM RoutineVersions WHERE RoutineID = $1)
+         ORDER BY ve.Position",
+    )
+    .bind(routine_id)
+    .fetch_all(pool)
+    .await?;
+
+    let Some(first) = rows.first() else {
+        return Ok(None);
+    };
+    Ok(Some(TemplateRoutine {
+        name: first.get("routinename"),
+        exercises: rows
+            .iter()
+            .map(|row| TemplateExercise {
+                name: row.get("exercisename"),
+                muscles_trained: row.get("musclestrained"),
+                exercise_type: row.get("exercisetype"),
+                sets: row.get("numberofsets"),
+                target_reps: row.get("targetreps"),
+                target_weight: row.get("targetweight"),
+            })
+            .collect(),
+    }))
+}
+
+// Builds a template from a routine or a program; None when it does not exist
+async fn build_template(
+    pool: &PgPool,
+    request: &PublishRequest,
+) -> Result<Option<TemplateDocument>, sqlx::Error> {
+    let (name, routines, program) = if let Some(routine_id) = request.routine_id {
+        let Some(routine) = routine_template(pool, routine_id).await? else {
+            return Ok(None);
+        };
+        (routine.name.clone(), vec![routine], None)
+    } else if let Some(program_id) = request.program_id {
+        let Some(program) = programs::fetch_programs(pool, Some(program_id))
+            .await?
+            .pop()
+        else {
+            return Ok(None);
+        };
+
+        // Each routine the program uses is included once
+        let mut routine_ids: Vec<i32> = Vec::new();
+        for routine_id in program.slots.iter().filter_map(|slot| slot.routine_id) {
+            if !routine_ids.contains(&routine_id) {
+                routine_ids.push(routine_id);
+            }
+        }
+        let mut routines = Vec::new();
+        for routine_id in &routine_ids {
+            if let Some(routine) = routine_template(pool, *routine_id).await? {
+                routines.push(routine);
+            }
+        }
+
+        let slots = program
+            .slots
+            .iter()
+            .map(|slot| TemplateSlot {
+                weekday: slot.weekday,
+                routine: slot
+                    .routine_id
+                    .and_then(|id| routine_ids.iter().position(|r| *r == id)),
+            })
+            .collect();
+        (
+            program.name.clone(),
+            routines,
+            Some(TemplateProgram {
+                schedule_type: program.schedule_type,
+                weeks: program.weeks,
+                deload_weeks: program.deload_weeks.clone(),
+                slots,
+            }),
+        )
+    } else {
+        return Ok(None);
+    };
+
+    Ok(Some(TemplateDocument {
+        format: TEMPLATE_FORMAT.to_string(),
+        version: TEMPLATE_VERSION,
+        name: request.name.clone().unwrap_or(name),
+        description: request.description.clone(),
+        routines,
+        program,
+    }))
+}
+
+async fn fetch_template(
+    pool: &PgPool,
+    template_id: i32,
+) -> Result<Option<(String, NaiveDateTime, TemplateDocument)>, sqlx::Error> {
+    let Some(row) = sqlx::query(
+        "SELECT Kind, PublishedAt, Document::TEXT AS document FROM Templates WHERE TemplateID = $1",
+    )
+    .bind(template_id)
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let template =
+        serde_json::from_str(row.get("document")).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+    Ok(Some((row.get("kind"), row.get("publishedat"), template)))
+}
+
+// Creates the template's missing exercises, then its routines and program
+async fn import_into_account(
+    pool: &PgPool,
+    template: &TemplateDocument,
+    resolutions: &[ExerciseResolution],
+    start_date: NaiveDate,
+) -> Result<TemplateImport, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let mut exercise_ids: HashMap<&str, i32> = HashMap::new();
+    let mut created = Vec::new();
+    for (exercise, resolution) in template.exercises().into_iter().zip(resolutions) {
+        let id = match resolution.exercise_id {
+            Some(id) => id,
+            None => {
+                created.push(exercise.name.clone());
+                sqlx::query(
+                    "INSERT INTO ExerciseList (ExerciseName, MusclesTrained, ExerciseType)
+                     VALUES ($1, $2, $3) RETURNING ExerciseID",
+                )
+                .bind(exercise.name.trim())
+                .bind(&exercise.muscles_trained)
+                .bind(exercise.exercise_type.trim())
+                .fetch_one(&mut *tx)
+                .await?
+                .get("exerciseid")
+            }
+        };
+        exercise_ids.insert(exercise.name.as_str(), id);
+    }
+
+    let now = Utc::now().naive_utc();
+    let mut routine_ids = Vec::new();
+    for routine in &template.routines {
+        let exercises: Vec<RoutineExercise> = routine
+            .exercises
+            .iter()
+            .map(|exercise| RoutineExercise {
+                exercise_id: exercise_ids[exercise.name.as_str()],
+                sets: exercise.sets,
+                target_reps: exercise.target_reps,
+                target_weight: exercise.target_weight,
+            })
+            .collect();
+        routine_ids.push(insert_routine(&mut tx, routine.name.trim(), now, &exercises).await?);
+    }
+
+    let program_id = match &template.program {
+        Some(program) => {
+            let program = program_create(template, program, start_date, |i| routine_ids[i]);
+            Some(programs::insert_program(&mut tx, &program).await?)
+        }
+        None => None,
+    };
+
+    tx.commit().await?;
+    Ok(TemplateImport {
+        routine_ids,
+        program_id,
+        exercises_matched: resolutions.len() - created.len(),
+        exercises_created: created,
+    })
+}
+
+fn template_not_found(template_id: i32) -> ApiError {
+    ApiError::not_found(format!("Template with ID {} not found", template_id))
+}
+
+// Shared by library and file imports
+async fn import_response(
+    pool: &PgPool,
+    template: &TemplateDocument,
+    start_date: Option<NaiveDate>,
+) -> Result<HttpResponse, ApiError> {
+    check_format(template)?;
+    let mut v = validate(template);
+
+    let catalog = workout_import::load_catalog(pool).await?;
+    let resolutions = resolve_exercises(&catalog, template);
+
+    // Two template exercises matching the same catalog exercise cannot share a routine
+    let resolved: HashMap<&str, i32> = resolutions
+        .iter()
+        .filter_map(|r| Some((r.name.as_str(), r.exercise_id?)))
+        .collect();
+    for (i, routine) in template.routines.iter().enumerate() {
+        let mut seen: HashMap<i32, &str> = HashMap::new();
+        for (j, exercise) in routine.exercises.iter().enumerate() {
+            let Some(id) = resolved.get(exercise.name.as_str()) else {
+                continue;
+            };
+            if let Some(other) = seen.insert(*id, &exercise.name) {
+                v.error(
+                    &format!("routines[{}].exercises[{}].name", i, j),
+                    "duplicate",
+                    format!(
+                        "'{}' matches the same exercise as '{}'",
+                        exercise.name, other
+                    ),
+                );
+            }
+        }
+    }
+    v.finish()?;
+
+    let start_date = start_date.unwrap_or_else(|| Local::now().date_naive());
+    let result = import_into_account(pool, template, &resolutions, start_date).await?;
+    info!(
+        "Imported template {} ({} routines, {} exercises created)",
+        template.name,
+        result.routine_ids.len(),
+        result.exercises_created.len()
+    );
+    Ok(HttpResponse::Created().json(result))
+}
+
+fn start_date_param(request: &HashMap<String, String>) -> Result<Option<NaiveDate>, ApiError> {
+    request
+        .get("start_date")
+        .map(|value| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .filter(|date| plausible_date(*date))
+                .ok_or_else(|| {
+                    ApiError::bad_request(format!(
+                        "Invalid 'start_date'. Use YYYY-MM-DD within {} years of today",
+                        MAX_YEARS_AWAY
+                    ))
+                })
+        })
+        .transpose()
+}
+
+#[utoipa::path(
+    tag = "templates",
+    summary = "JSON Schema of template files",
+    responses((status = 200, content_type = "application/schema+json", body = serde_json::Value))
+)]
+#[get("/templates/schema")]
+async fn template_schema() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/schema+json")
+        .body(TEMPLATE_SCHEMA)
+}
+
+#[utoipa::path(
+    tag = "templates",
+    params(("kind" = Option<String>, Query, description = "routine or program")),
+    responses(
+        (status = 200, body = Vec<TemplateSummary>),
+        (status = 400, description = "Invalid kind", body = ErrorBody)
+    )
+)]
+#[get("/templates")]
+async fn list_templates(
+    pool: web::Data<PgPool>,
+    request: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let kind = request.get("kind");
+    if kind.is_some_and(|kind| kind != "routine" && kind != "program") {
+        return Err(ApiError::bad_request(
+            "Invalid kind. Must be 'routine' or 'program'",
+        ));
+    }
+
+    let templates: Vec<TemplateSummary> = sqlx::query(
+        "SELECT TemplateID, Kind, TemplateName, Description, PublishedAt,
+                jsonb_array_length(Document->'routines') AS routines,
+                (SELECT COALESCE(SUM(jsonb_array_length(r->'exercises')), 0)::INTEGER
+                 FROM jsonb_array_elements(Document->'routines') r) AS exercises
+         FROM Templates
+         WHERE ($1::TEXT IS NULL OR Kind = $1)
+         ORDER BY PublishedAt DESC",
+    )
+    .bind(kind)
+    .fetch_all(pool.get_ref())
+    .await?
+    .iter()
+    .map(|row| TemplateSummary {
+        template_id: row.get("templateid"),
+        kind: row.get("kind"),
+        name: row.get("templatename"),
+        description: row.get("description"),
+        routines: row.get("routines"),
+        exercises: row.get("exercises"),
+        published_at: row.get("publishedat"),
+    })
+    .collect();
+    Ok(HttpResponse::Ok().json(templates))
+}
+
+impl Validate for PublishRequest {
+    fn rules(&self, v: &mut Validator) {
+        match (self.routine_id, self.program_id) {
+            (None, None) => v.error(
+                "routine_id",
+                "required",
+                "Give either a routine_id or a program_id",
+            ),
+            (Some(_), Some(_)) => v.error(
+                "program_id",
+                "not_allowed",
+                "Give either a routine_id or a program_id, not both",
+            ),
+            _ => {}
+        }
+        if let Some(name) = &self.name {
+            v.required("name", name, 255);
+        }
+    }
+}
+
+// Publishes a routine or a program to the library
+#[utoipa::path(
+    tag = "templates",
+    summary = "Publish a routine or a program to the library",
+    responses(
+        (status = 201, body = serde_json::Value, example = json!({ "template_id": 1 })),
+        (status = 404, description = "Routine or program not found", body = ErrorBody),
+        (status = 422, description = "Neither or both of routine_id and program_id given, or the source cannot be shared", body = ErrorBody)
+    )
+)]
+#[post("/templates")]
+async fn publish_template(
+    pool: web::Data<PgPool>,
+    request: web::Json<PublishRequest>,
+) -> Result<HttpResponse, ApiError> {
+    validate(&*request).finish()?;
+
+    let template = build_template(pool.get_ref(), &request)
+        .await?
+        .ok_or_else(|| match request.routine_id {
+            Some(id) => ApiError::not_found(format!("Routine with ID {} not found", id)),
+            None => ApiError::not_found(format!(
+                "Program with ID {} not found",
+                request.program_id.unwrap_or_default()
+            )),
+        })?;
+    validate(&template).finish()?;
+
+    let document = serde_json::to_string(&template)
+        .map_err(|e| ApiError::Internal(format!("Failed to serialise template: {}", e)))?;
+    let template_id: i32 = sqlx::query_scalar(
+        "INSERT INTO Templates (Kind, TemplateName, Description, Document)
+         VALUES ($1, $2, $3, $4::JSONB) RETURNING TemplateID",
+    )
+    .bind(template.kind())
+    .bind(&template.name)
+    .bind(&template.description)
+    .bind(document)
+    .fetch_one(pool.get_ref())
+    .await?;
+    info!(
+        "Published {} template {} as {}",
+        template.kind(),
+        template.name,
+        template_id
+    );
+    Ok(HttpResponse::Created().json(json!({ "template_id": template_id })))
+}
+
+// The template and how its exercises would map onto this catalog
+#[utoipa::path(
+    tag = "templates",
+    summary = "A template and how its exercises map onto this catalog",
+    responses(
+        (status = 200, body = TemplatePreview),
+        (status = 404, description = "Template not found", body = ErrorBody)
+    )
+)]
+#[get("/templates/{template_id}")]
+async fn preview_template(
+    pool: web::Data<PgPool>,
+    template_id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let template_id = template_id.into_inner();
+    let (kind, published_at, template) = fetch_template(pool.get_ref(), template_id)
+        .await?
+        .ok_or_else(|| template_not_found(template_id))?;
+
+    let catalog = workout_import::load_catalog(pool.get_ref()).await?;
+    Ok(HttpResponse::Ok().json(TemplatePreview {
+        template_id,
+        kind,
+        published_at,
+        exercises: resolve_exercises(&catalog, &template),
+        template,
+    }))
+}
+
+#[utoipa::path(
+    tag = "templates",
+    summary = "Download a template file",
+    responses(
+        (status = 200, body = TemplateDocument),
+        (status = 404, description = "Template not found", body = ErrorBody)
+    )
+)]
+#[get("/templates/{template_id}/file")]
+async fn download_template(
+    pool: web::Data<PgPool>,
+    template_id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let template_id = template_id.into_inner();
+    let (_, _, template) = fetch_template(pool.get_ref(), template_id)
+        .await?
+        .ok_or_else(|| template_not_found(template_id))?;
+
+    let body = serde_json::to_vec_pretty(&template).map_err(|e| {
+        ApiError::Internal(format!(
+            "Failed to serialise template {}: {}",
+            template_id, e
+        ))
+    })?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"template-{}.json\"", template_id),
+        ))
+        .body(body))
+}
+
+// Imports a template from the library; programs start on ?start_date= (default today)
+#[utoipa::path(
+    tag = "templates",
+    summary = "Import a template from the library",
+    params(("start_date" = Option<NaiveDate>, Query, description = "When an imported program starts, defaults to today")),
+    responses(
+        (status = 201, body = TemplateImport),
+        (status = 400, description = "Unsupported template or invalid start date", body = ErrorBody),
+        (status = 404, description = "Template not found", body = ErrorBody),
+        (status = 422, description = "Invalid template", body = ErrorBody)
+    )
+)]
+#[post("/templates/{template_id}/import")]
+async fn import_template(
+    pool: web::Data<PgPool>,
+    template_id: web::Path<i32>,
+    request: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, ApiError> {
+    let template_id = template_id.into_inner();
+    let start_date = start_date_param(&request)?;
+    let (_, _, template) = fetch_template(pool.get_ref(), template_id)
+        .await?
+        .ok_or_else(|| template_not_found(template_id))?;
+    import_response(pool.get_ref(), &template, start_date).await
+}
+
+// Imports a template file, as downloaded from /templates/{template_id}/file
+#[utoipa::path(
+    tag = "templates",
+    summary = "Import a template file",
+    params(("start_date" = Option<NaiveDate>, Query, description = "When an imported program starts, defaults to today")),
+    request_body = TemplateDocument,
+    responses(
+        (status = 201, body = TemplateImport),
+        (status = 400, description = "Unreadable or unsupported template, or invalid start date", body = ErrorBody),
+        (status = 422, description = "Invalid template", body = ErrorBody)
+    )
+)]
+#[post("/templates/import")]
+async fn import_template_file(
+    pool: web::Data<PgPool>,
+    request: web::Query<HashMap<String, String>>,
+    body: String,
+) -> Result<HttpResponse, ApiError> {
+    let start_date = start_date_param(&request)?;
+    let template: TemplateDocument = serde_json::from_str(&body)
+        .map_err(|e| ApiError::bad_request(format!("Invalid template file: {}", e)))?;
+    import_response(pool.get_ref(), &template, start_date).await
+}
+
+#[utoipa::path(
+    tag = "templates",
+    responses(
+        (status = 200, body = serde_json::Value, example = json!({ "status": "deleted" })),
+        (status = 404, description = "Template not found", body = ErrorBody)
+    )
+)]
+#[delete("/templates/{template_id}")]
+async fn delete_template(
+    pool: web::Data<PgPool>,
+    template_id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let template_id = template_id.into_inner();
+    let result = sqlx::query("DELETE FROM Templates WHERE TemplateID = $1")
+        .bind(template_id)
+        .execute(pool.get_ref())
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(template_not_found(template_id));
+    }
+    info!("Removed template {} from the library", template_id);
+    Ok(HttpResponse::Ok().json(json!({ "status": "deleted" })))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+    use serde_json::json;
+
+    use crate::test_support::TestApp;
+
+    #[actix_web::test]
+    async fn publish_preview_and_download_a_routine() {
+        let app = TestApp::new().await;
+        let bench = app
+            .exercise("Bench Press")
+            .muscles(&["Chest", "Triceps"])
+            .exercise_type("Barbell")
+            .create()
+            .await;
+        let push = app
+            .routine("Push")
+            .exercise_with_targets(bench, 3, 5, 100)
+            .create()
+            .await;
+
+        let schema = app.get("/api/v1/templates/schema").await.expect(200);
+        assert_eq!(
+            schema.header("content-type"),
+            Some("application/schema+json")
+        );
+
+        let body = app
+            .post(
+                "/api/v1/templates",
+                json!({ "routine_id": push, "description": "Heavy day" }),
+            )
+            .await
+            .expect(201)
+            .json();
+        let template_id = body["template_id"].as_i64().unwrap();
+
+        let list = app
+            .get("/api/v1/templates?kind=routine")
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(list[0]["name"], "Push");
+        assert_eq!(list[0]["exercises"], 1);
+        let list = app
+            .get("/api/v1/templates?kind=program")
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(list, json!([]));
+        app.get("/api/v1/templates?kind=other").await.expect(400);
+
+        let preview = app
+            .get(&format!("/api/v1/templates/{}", template_id))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(preview["kind"], "routine");
+        assert_eq!(preview["exercises"][0]["exercise_id"], bench);
+        assert_eq!(preview["exercises"][0]["match"], "exact");
+
+        let file = app
+            .get(&format!("/api/v1/templates/{}/file", template_id))
+            .await
+            .expect(200);
+        assert_eq!(
+            file.header("content-disposition"),
+            Some(format!("attachment; filename=\"template-{}.json\"", template_id).as_str())
+        );
+        let document = file.json();
+        assert_eq!(document["format"], "strongeryou-template");
+        assert_eq!(
+            document["routines"][0]["exercises"][0],
+            json!({
+                "name": "Bench Press",
+                "muscles_trained": ["Chest", "Triceps"],
+                "exercise_type": "Barbell",
+                "sets": 3,
+                "target_reps": 5,
+                "target_weight": 100,
+            })
+        );
+
+        app.delete(&format!("/api/v1/templates/{}", template_id))
+            .await
+            .expect(200);
+        app.get(&format!("/api/v1/templates/{}", template_id))
+            .await
+            .expect(404);
+        app.delete(&format!("/api/v1/templates/{}", template_id))
+            .await
+            .expect(404);
+    }
+
+    #[actix_web::test]
+    async fn publish_needs_exactly_one_source() {
+        let app = TestApp::new().await;
+
+        let body = app
+            .post("/api/v1/templates", json!({}))
+            .await
+            .expect(422)
+            .json();
+        assert_eq!(body["fields"][0]["field"], "routine_id");
+        let body = app
+            .post(
+                "/api/v1/templates",
+                json!({ "routine_id": 1, "program_id": 1, "name": "" }),
+            )
+            .await
+            .expect(422)
+            .json();
+        assert_eq!(body["fields"][0]["code"], "not_allowed");
+        assert_eq!(body["fields"][1]["field"], "name");
+        app.post("/api/v1/templates", json!({ "routine_id": 999 }))
+            .await
+            .expect(404);
+    }
+
+    #[actix_web::test]
+    async fn import_a_program_from_the_library_and_from_a_file() {
+        let source = TestApp::new().await;
+        let squat = source.exercise("Squat").muscles(&["Quads"]).create().await;
+        let legs = source.routine("Legs").exercise(squat, 5).create().await;
+        let program = source
+            .post(
+                "/api/v1/programs",
+                json!({
+                    "name": "Squat Every Day",
+                    "schedule_type": "rotation",
+                    "start_date": "2026-01-05",
+                    "weeks": 4,
+                    "slots": [{ "routine_id": legs }, { "routine_id": null }],
+                }),
+            )
+            .await
+            .expect(201)
+            .json();
+        let template = source
+            .post(
+                "/api/v1/templates",
+                json!({ "program_id": program["program_id"] }),
+            )
+            .await
+            .expect(201)
+            .json();
+
+        let imported = source
+            .post(
+                &format!(
+                    "/api/v1/templates/{}/import?start_date=2026-03-02",
+                    template["template_id"]
+                ),
+                json!(null),
+            )
+            .await
+            .expect(201)
+            .json();
+        source
+            .post(
+                &format!(
+                    "/api/v1/templates/{}/import?start_date=2200-03-02",
+                    template["template_id"]
+                ),
+                json!(null),
+            )
+            .await
+            .expect(400);
+        assert_eq!(imported["exercises_matched"], 1);
+        assert_eq!(imported["exercises_created"], json!([]));
+        let copy = source
+            .get(&format!("/api/v1/programs/{}", imported["program_id"]))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(copy["start_date"], "2026-03-02");
+        assert_eq!(copy["slots"][0]["routine_id"], imported["routine_ids"][0]);
+
+        // Into an account that has never seen the exercise
+        let file = source
+            .get(&format!(
+                "/api/v1/templates/{}/file",
+                template["template_id"]
+            ))
+            .await
+            .expect(200);
+        let target = TestApp::new().await;
+        let imported = target
+            .send(
+                TestRequest::post()
+                    .uri("/api/v1/templates/import?start_date=2026-03-02")
+                    .set_payload(file.body.clone()),
+            )
+            .await
+            .expect(201)
+            .json();
+        assert_eq!(imported["exercises_created"], json!(["Squat"]));
+        assert_eq!(target.count("ExerciseList").await, 1);
+        assert_eq!(target.count("Programs").await, 1);
+
+        target
+            .send(
+                TestRequest::post()
+                    .uri("/api/v1/templates/import")
+                    .set_payload("{\"format\": \"something-else\"}"),
+            )
+            .await
+            .expect(400);
+
+        let mut invalid: serde_json::Value = serde_json::from_slice(&file.body).unwrap();
+        invalid["routines"][0]["exercises"][0]["sets"] = json!(0);
+        invalid["program"]["slots"][1]["routine"] = json!(4);
+        let body = target
+            .send(
+                TestRequest::post()
+                    .uri("/api/v1/templates/import")
+                    .set_payload(invalid.to_string()),
+            )
+            .await
+            .expect(422)
+            .json();
+        let fields: Vec<&str> = body["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["field"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            fields,
+            vec!["routines[0].exercises[0].sets", "program.slots[1].routine"]
+        );
+        invalid["version"] = json!(2);
+        target
+            .send(
+                TestRequest::post()
+                    .uri("/api/v1/templates/import")
+                    .set_payload(invalid.to_string()),
+            )
+            .await
+            .expect(400);
+        target
+            .post("/api/v1/templates/999/import", json!(null))
+            .await
+            .expect(404);
+    }
+}
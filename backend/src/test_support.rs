@@ -0,0 +1,467 @@
+// Test harness for driving the whole app against Postgres.
+//
+// Every TestApp gets a fresh schema in the database named by
+// TEST_DATABASE_URL (or DATABASE_URL, read from .env like the server does),
+// migrated from scratch and dropped when the TestApp goes out of scope, so
+// tests run in parallel without seeing each other's rows. Requests go through
+// the same middleware, shared state and routes as the server, via
+// actix_web::test. The builders insert fixtures directly, through the same
+// functions the handlers use, so a test only exercises the routes it calls.
+use actix_web::http::header::HeaderMap;
+use actix_web::http::StatusCode;
+use actix_web::{middleware, test, web, App};
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use serde_json::{json, Value};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{Connection, PgConnection, PgPool, Row};
+use std::env;
+use uuid::Uuid;
+
+use crate::config::LimitsConfig;
+use crate::limits::Limits;
+use crate::metrics::{self, Metrics};
+use crate::repository::{postgres, Repositories};
+use crate::routines::RoutineExercise;
+use crate::workouts::WorkoutData;
+use crate::{configure_app, db, request_id, service};
+
+pub struct TestApp {
+    pub pool: PgPool,
+    pub metrics: web::Data<Metrics>,
+    pub limits: web::Data<Limits>,
+    url: String,
+    schema: String,
+}
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: web::Bytes,
+}
+
+impl TestApp {
+    pub async fn new() -> TestApp {
+        dotenv::dotenv().ok();
+        let url = env::var("TEST_DATABASE_URL")
+            .or_else(|_| env::var("DATABASE_URL"))
+            .expect("set TEST_DATABASE_URL or DATABASE_URL to a Postgres database for the tests");
+        let schema = format!("test_{}", Uuid::new_v4().simple());
+
+        let mut conn = PgConnection::connect(&url)
+            .await
+            .expect("failed to connect to the test database");
+        sqlx::query(&format!("CREATE SCHEMA {}", schema))
+            .execute(&mut conn)
+            .await
+            .expect("failed to create the test schema");
+        conn.close().await.ok();
+
+        let options: PgConnectOptions = url.parse().expect("invalid test database URL");
+        let options = options.options([
+            ("search_path", schema.as_str()),
+            ("client_min_messages", "warning"),
+        ]);
+        let pool = PgPoolOptions::new()
+            .max_connections(4)
+            .connect_with(options)
+            .await
+            .expect("failed to open the test pool");
+        db::migrate(&pool)
+            .await
+            .expect("failed to migrate the test schema");
+
+        TestApp {
+            pool,
+            metrics: web::Data::new(Metrics::default()),
+            limits: web::Data::new(Limits::new(&LimitsConfig::default())),
+            url,
+            schema,
+        }
+    }
+
+    pub async fn send(&self, request: test::TestRequest) -> TestResponse {
+        let app = test::init_service(
+            App::new()
+                .wrap(middleware::from_fn(metrics::track_requests))
+                .wrap(middleware::from_fn(request_id::correlate))
+                .configure(|cfg| configure_app(cfg, &self.pool, &self.metrics, &self.limits)),
+        )
+        .await;
+        let response = test::call_service(&app, request.to_request()).await;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = test::read_body(response).await;
+        TestResponse {
+            status,
+            headers,
+            body,
+        }
+    }
+
+    pub async fn get(&self, uri: &str) -> TestResponse {
+        self.send(test::TestRequest::get().uri(uri)).await
+    }
+
+    pub async fn post(&self, uri: &str, body: Value) -> TestResponse {
+        self.send(test::TestRequest::post().uri(uri).set_json(body))
+            .await
+    }
+
+    pub async fn put(&self, uri: &str, body: Value) -> TestResponse {
+        self.send(test::TestRequest::put().uri(uri).set_json(body))
+            .await
+    }
+
+    pub async fn delete(&self, uri: &str) -> TestResponse {
+        self.send(test::TestRequest::delete().uri(uri)).await
+    }
+
+    pub async fn count(&self, table: &str) -> i64 {
+        sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+            .fetch_one(&self.pool)
+            .await
+            .expect("failed to count rows")
+    }
+
+    pub async fn user(&self) -> i32 {
+        sqlx::query_scalar("INSERT INTO Users DEFAULT VALUES RETURNING UserID")
+            .fetch_one(&self.pool)
+            .await
+            .expect("failed to insert user")
+    }
+
+    pub fn exercise(&self, name: &str) -> ExerciseBuilder<'_> {
+        ExerciseBuilder {
+            app: self,
+            name: name.to_string(),
+            muscles: vec!["Chest".to_string()],
+            exercise_type: "Regular".to_string(),
+        }
+    }
+
+    pub fn routine(&self, name: &str) -> RoutineBuilder<'_> {
+        RoutineBuilder {
+            app: self,
+            name: name.to_string(),
+            exercises: Vec::new(),
+            user: None,
+        }
+    }
+
+    pub fn workout(&self) -> WorkoutBuilder<'_> {
+        WorkoutBuilder {
+            app: self,
+            routine_id: None,
+            start: Utc::now().naive_utc() - Duration::hours(1),
+            minutes: 60,
+            exercises: Vec::new(),
+        }
+    }
+
+    pub fn marker(&self, name: &str) -> MarkerBuilder<'_> {
+        MarkerBuilder {
+            app: self,
+            name: name.to_string(),
+            color: "#FF0000".to_string(),
+            unit: None,
+            direction: "higher",
+            formula: None,
+            user: None,
+            entries: Vec::new(),
+        }
+    }
+}
+
+// Dropping happens outside any async context the test may still be in, so the
+// schema is dropped from a thread with a runtime of its own
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        let url = self.url.clone();
+        let schema = self.schema.clone();
+        let _ = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("failed to start a runtime to drop the test schema");
+            runtime.block_on(async {
+                if let Ok(mut conn) = PgConnection::connect(&url).await {
+                    // A handler that bailed out of a transaction leaves its
+                    // pooled connection idle in it until the next checkout,
+                    // still holding locks that would block the DROP
+                    let _ = sqlx::query(
+                        "SELECT pg_terminate_backend(l.pid) FROM pg_locks l \
+                         JOIN pg_class c ON c.oid = l.relation \
+                         JOIN pg_namespace n ON n.oid = c.relnamespace \
+                         WHERE n.nspname = $1 AND l.pid <> pg_backend_pid()",
+                    )
+                    .bind(&schema)
+                    .execute(&mut conn)
+                    .await;
+                    let _ = sqlx::query(&format!("DROP SCHEMA IF EXISTS {} CASCADE", schema))
+                        .execute(&mut conn)
+                        .await;
+                }
+            });
+        })
+        .join();
+    }
+}
+
+impl TestResponse {
+    // Fails with the body in the message, which usually says what went wrong
+    #[track_caller]
+    pub fn expect(self, status: u16) -> TestResponse {
+        assert_eq!(
+            self.status.as_u16(),
+            status,
+            "unexpected status, body: {}",
+            self.text()
+        );
+        self
+    }
+
+    #[track_caller]
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body)
+            .unwrap_or_else(|e| panic!("body is not JSON ({}): {}", e, self.text()))
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+}
+
+pub fn datetime(value: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+        .unwrap_or_else(|_| panic!("not a datetime: {}", value))
+}
+
+pub fn date(value: &str) -> NaiveDate {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap_or_else(|_| panic!("not a date: {}", value))
+}
+
+pub struct ExerciseBuilder<'a> {
+    app: &'a TestApp,
+    name: String,
+    muscles: Vec<String>,
+    exercise_type: String,
+}
+
+impl ExerciseBuilder<'_> {
+    pub fn muscles(mut self, muscles: &[&str]) -> Self {
+        self.muscles = muscles.iter().map(|m| m.to_string()).collect();
+        self
+    }
+
+    pub fn exercise_type(mut self, exercise_type: &str) -> Self {
+        self.exercise_type = exercise_type.to_string();
+        self
+    }
+
+    pub async fn create(self) -> i32 {
+        sqlx::query_scalar(
+            "INSERT INTO ExerciseList (ExerciseName, MusclesTrained, ExerciseType)
+             VALUES ($1, $2, $3) RETURNING ExerciseID",
+        )
+        .bind(&self.name)
+        .bind(&self.muscles)
+        .bind(&self.exercise_type)
+        .fetch_one(&self.app.pool)
+        .await
+        .expect("failed to insert exercise")
+    }
+}
+
+pub struct RoutineBuilder<'a> {
+    app: &'a TestApp,
+    name: String,
+    exercises: Vec<RoutineExercise>,
+    user: Option<i32>,
+}
+
+impl RoutineBuilder<'_> {
+    pub fn exercise(mut self, exercise_id: i32, sets: i32) -> Self {
+        self.exercises.push(RoutineExercise {
+            exercise_id,
+            sets,
+            target_reps: None,
+            target_weight: None,
+        });
+        self
+    }
+
+    pub fn exercise_with_targets(
+        mut self,
+        exercise_id: i32,
+        sets: i32,
+        target_reps: i16,
+        target_weight: i16,
+    ) -> Self {
+        self.exercises.push(RoutineExercise {
+            exercise_id,
+            sets,
+            target_reps: Some(target_reps),
+            target_weight: Some(target_weight),
+        });
+        self
+    }
+
+    pub fn user(mut self, user_id: i32) -> Self {
+        self.user = Some(user_id);
+        self
+    }
+
+    pub async fn create(self) -> i32 {
+        let mut tx = self.app.pool.begin().await.expect("failed to begin");
+        let routine_id =
+            postgres::insert_routine(&mut tx, &self.name, Utc::now().naive_utc(), &self.exercises)
+                .await
+                .expect("failed to insert routine");
+        if let Some(user_id) = self.user {
+            sqlx::query("UPDATE Routines SET UserID = $1 WHERE RoutineID = $2")
+                .bind(user_id)
+                .bind(routine_id)
+                .execute(&mut *tx)
+                .await
+                .expect("failed to assign routine to user");
+        }
+        tx.commit().await.expect("failed to commit routine");
+        routine_id
+    }
+}
+
+pub struct WorkoutBuilder<'a> {
+    app: &'a TestApp,
+    routine_id: Option<i32>,
+    start: NaiveDateTime,
+    minutes: i64,
+    exercises: Vec<(i32, Vec<(i16, i16)>)>,
+}
+
+impl WorkoutBuilder<'_> {
+    pub fn routine(mut self, routine_id: i32) -> Self {
+        self.routine_id = Some(routine_id);
+        self
+    }
+
+    // e.g. "2026-01-05T18:00:00"
+    pub fn at(mut self, start: &str) -> Self {
+        self.start = datetime(start);
+        self
+    }
+
+    pub fn minutes(mut self, minutes: i64) -> Self {
+        self.minutes = minutes;
+        self
+    }
+
+    // Adds a set; sets of the same exercise are numbered in the order added
+    pub fn set(mut self, exercise_id: i32, weight: i16, reps: i16) -> Self {
+        match self.exercises.iter_mut().find(|(id, _)| *id == exercise_id) {
+            Some((_, sets)) => sets.push((weight, reps)),
+            None => self.exercises.push((exercise_id, vec![(weight, reps)])),
+        }
+        self
+    }
+
+    // Saved as POST /workouts would, so PRs are recorded too
+    pub async fn create(self) -> i32 {
+        let data: WorkoutData = serde_json::from_value(json!({
+            "start_time": self.start,
+            "end_time": self.start + Duration::minutes(self.minutes),
+            "routine_id": self.routine_id,
+            "exercises": self.exercises.iter().map(|(exercise_id, sets)| json!({
+                "exercise_id": exercise_id,
+                "exercise_name": "",
+                "sets": sets
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (weight, reps))| {
+                        ((i + 1).to_string(), json!({ "weight": weight, "reps": reps }))
+                    })
+                    .collect::<serde_json::Map<_, _>>(),
+            })).collect::<Vec<_>>(),
+        }))
+        .expect("invalid workout fixture");
+        let repos = Repositories::postgres(&self.app.pool);
+        let (workout_id, _) = service::record_workout(&repos, &data, None)
+            .await
+            .expect("failed to save workout");
+        workout_id
+    }
+}
+
+pub struct MarkerBuilder<'a> {
+    app: &'a TestApp,
+    name: String,
+    color: String,
+    unit: Option<String>,
+    direction: &'static str,
+    formula: Option<String>,
+    user: Option<i32>,
+    entries: Vec<(NaiveDate, f64)>,
+}
+
+impl MarkerBuilder<'_> {
+    pub fn unit(mut self, unit: &str) -> Self {
+        self.unit = Some(unit.to_string());
+        self
+    }
+
+    // "higher" or "lower", whichever counts as progress
+    pub fn direction(mut self, direction: &'static str) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    pub fn formula(mut self, formula: &str) -> Self {
+        self.formula = Some(formula.to_string());
+        self
+    }
+
+    pub fn user(mut self, user_id: i32) -> Self {
+        self.user = Some(user_id);
+        self
+    }
+
+    // e.g. entry("2026-01-05", 80.5)
+    pub fn entry(mut self, date: &str, value: f64) -> Self {
+        self.entries.push((self::date(date), value));
+        self
+    }
+
+    pub async fn create(self) -> i32 {
+        let marker_id: i32 = sqlx::query(
+            "INSERT INTO MarkerList (MarkerName, Clr, Unit, Direction, Formula, UserID)
+             VALUES ($1, $2, $3, $4, $5, $6) RETURNING MarkerID",
+        )
+        .bind(&self.name)
+        .bind(&self.color)
+        .bind(&self.unit)
+        .bind(self.direction)
+        .bind(&self.formula)
+        .bind(self.user)
+        .fetch_one(&self.app.pool)
+        .await
+        .expect("failed to insert marker")
+        .get("markerid");
+
+        for (date, value) in &self.entries {
+            sqlx::query(
+                "INSERT INTO Markers (MarkerID, Value, Date, UserID) VALUES ($1, $2, $3, $4)",
+            )
+            .bind(marker_id)
+            .bind(*value as f32)
+            .bind(date)
+            .bind(self.user)
+            .execute(&self.app.pool)
+            .await
+            .expect("failed to insert marker entry");
+        }
+        marker_id
+    }
+}
@@ -0,0 +1,204 @@
+use chrono::{Datelike, Local, NaiveDate};
+
+use crate::errors::{ApiError, FieldError};
+use crate::repository::{ExerciseRepository, RoutineRepository};
+
+// Dates further from today than this are typos, and date arithmetic near the
+// far ends of what chrono accepts overflows
+pub const MAX_YEARS_AWAY: i32 = 100;
+
+pub fn plausible_date(date: NaiveDate) -> bool {
+    let year = Local::now().year();
+    (year - MAX_YEARS_AWAY..=year + MAX_YEARS_AWAY).contains(&date.year())
+}
+
+// Request bodies describe their own rules; validate() runs them and reports
+// every broken rule at once as field errors
+pub trait Validate {
+    fn rules(&self, v: &mut Validator);
+}
+
+pub fn validate<T: Validate>(body: &T) -> Validator {
+    let mut v = Validator::default();
+    body.rules(&mut v);
+    v
+}
+
+// Collects field errors. Field names are paths into the body, such as
+// "exercises[2].sets", built up by nested() and each().
+#[derive(Default)]
+pub struct Validator {
+    path: Vec<String>,
+    errors: Vec<FieldError>,
+    exercise_refs: Vec<(String, i32)>,
+    routine_refs: Vec<(String, i32)>,
+}
+
+impl Validator {
+    fn field_path(&self, field: &str) -> String {
+        let mut path = self.path.join(".");
+        if !field.is_empty() {
+            if !path.is_empty() {
+                path.push('.');
+            }
+            path.push_str(field);
+        }
+        path
+    }
+
+    pub fn error(&mut self, field: &str, code: &'static str, message: impl Into<String>) {
+        let field = self.field_path(field);
+        self.errors.push(FieldError::new(field, code, message));
+    }
+
+    pub fn check(&mut self, field: &str, ok: bool, code: &'static str, message: &str) {
+        if !ok {
+            self.error(field, code, message);
+        }
+    }
+
+    pub fn required(&mut self, field: &str, value: &str, max_len: usize) {
+        if value.trim().is_empty() {
+            self.error(field, "required", "Must not be empty");
+        } else if value.chars().count() > max_len {
+            self.error(
+                field,
+                "too_long",
+                format!("Must be at most {} characters", max_len),
+            );
+        }
+    }
+
+    pub fn present<T>(&mut self, field: &str, value: &Option<T>) {
+        if value.is_none() {
+            self.error(field, "required", "Is required");
+        }
+    }
+
+    pub fn range<N: PartialOrd + std::fmt::Display>(
+        &mut self,
+        field: &str,
+        value: N,
+        min: N,
+        max: N,
+    ) {
+        if value < min || value > max {
+            self.error(
+                field,
+                "out_of_range",
+                format!("Must be between {} and {}", min, max),
+            );
+        }
+    }
+
+    pub fn min<N: PartialOrd + std::fmt::Display>(&mut self, field: &str, value: N, min: N) {
+        if value < min {
+            self.error(field, "out_of_range", format!("Must be at least {}", min));
+        }
+    }
+
+    pub fn date(&mut self, field: &str, value: NaiveDate) {
+        if !plausible_date(value) {
+            self.error(
+                field,
+                "out_of_range",
+                format!("Must be within {} years of today", MAX_YEARS_AWAY),
+            );
+        }
+    }
+
+    pub fn not_empty<T>(&mut self, field: &str, items: &[T]) {
+        if items.is_empty() {
+            self.error(field, "required", "Must contain at least one item");
+        }
+    }
+
+    pub fn hex_color(&mut self, field: &str, value: &str) {
+        let valid = value.len() == 7
+            && value.starts_with('#')
+            && value[1..].chars().all(|c| c.is_ascii_hexdigit());
+        if !valid {
+            self.error(
+                field,
+                "invalid_format",
+                "Must be a hex color such as '#FF0000'",
+            );
+        }
+    }
+
+    // Exercise IDs are checked against storage by exercises_exist()
+    pub fn exercise_ref(&mut self, field: &str, exercise_id: i32) {
+        let field = self.field_path(field);
+        self.exercise_refs.push((field, exercise_id));
+    }
+
+    // Routine IDs are checked against storage by routines_exist()
+    pub fn routine_ref(&mut self, field: &str, routine_id: i32) {
+        let field = self.field_path(field);
+        self.routine_refs.push((field, routine_id));
+    }
+
+    pub fn nested(&mut self, field: &str, f: impl FnOnce(&mut Validator)) {
+        self.path.push(field.to_string());
+        f(self);
+        self.path.pop();
+    }
+
+    pub fn each<T: Validate>(&mut self, field: &str, items: &[T]) {
+        for (i, item) in items.iter().enumerate() {
+            self.nested(&format!("{}[{}]", field, i), |v| item.rules(v));
+        }
+    }
+
+    // Flags every exercise ID referenced by the body that does not exist
+    pub(crate) async fn exercises_exist(
+        mut self,
+        exercises: &dyn ExerciseRepository,
+    ) -> Result<Self, ApiError> {
+        if self.exercise_refs.is_empty() {
+            return Ok(self);
+        }
+
+        let ids: Vec<i32> = self.exercise_refs.iter().map(|(_, id)| *id).collect();
+        let known = exercises.existing(&ids).await?;
+        let refs = std::mem::take(&mut self.exercise_refs);
+        self.missing(refs, &known, "Exercise");
+        Ok(self)
+    }
+
+    // Flags every routine ID referenced by the body that does not exist
+    pub(crate) async fn routines_exist(
+        mut self,
+        routines: &dyn RoutineRepository,
+    ) -> Result<Self, ApiError> {
+        if self.routine_refs.is_empty() {
+            return Ok(self);
+        }
+
+        let ids: Vec<i32> = self.routine_refs.iter().map(|(_, id)| *id).collect();
+        let known = routines.existing(&ids).await?;
+        let refs = std::mem::take(&mut self.routine_refs);
+        self.missing(refs, &known, "Routine");
+        Ok(self)
+    }
+
+    fn missing(&mut self, refs: Vec<(String, i32)>, known: &[i32], kind: &str) {
+        for (field, id) in refs {
+            if !known.contains(&id) {
+                self.errors.push(FieldError::new(
+                    field,
+                    "not_found",
+                    format!("{} with ID {} does not exist", kind, id),
+                ));
+            }
+        }
+    }
+
+    pub fn finish(self) -> Result<(), ApiError> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiError::Validation(self.errors))
+        }
+    }
+}
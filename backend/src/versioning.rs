@@ -0,0 +1,80 @@
+// The API lives under /api/v1. The unversioned routes the frontend was built
+// against are still served as aliases; every response from them carries a
+// Deprecation header (RFC 9745) and a Link to the docs listing the
+// replacements. Routes added since versioning have no alias.
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue, LINK};
+use actix_web::middleware::Next;
+
+pub const PREFIX: &str = "/api/v1";
+
+pub const DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+
+// 2026-10-18, the day /api/v1 was introduced
+const DEPRECATED_SINCE: HeaderValue = HeaderValue::from_static("@1792281600");
+
+pub async fn deprecated(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let mut res = next.call(req).await?;
+    // The alias scope also answers paths no route matches; those 404s are
+    // not deprecated aliases of anything
+    if res.request().match_pattern().is_none() {
+        return Ok(res);
+    }
+    let headers = res.headers_mut();
+    headers.insert(DEPRECATION, DEPRECATED_SINCE);
+    headers.insert(
+        LINK,
+        HeaderValue::from_static("</docs/>; rel=\"deprecation\"; type=\"text/html\""),
+    );
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::TestApp;
+
+    #[actix_web::test]
+    async fn only_the_unversioned_aliases_are_deprecated() {
+        let app = TestApp::new().await;
+        let squat = app.exercise("Squat").create().await;
+
+        let legacy = app
+            .get(&format!("/exercises/volume/{}", squat))
+            .await
+            .expect(200);
+        assert_eq!(legacy.header("deprecation"), Some("@1792281600"));
+        assert!(legacy
+            .header("link")
+            .unwrap()
+            .contains("rel=\"deprecation\""));
+        // Errors from the aliases are marked too
+        let missing = app.get("/workouts/999").await.expect(404);
+        assert!(missing.header("deprecation").is_some());
+
+        let current = app
+            .get(&format!("/api/v1/exercises/{}/stats/volume", squat))
+            .await
+            .expect(200);
+        assert_eq!(current.header("deprecation"), None);
+        let health = app.get("/healthz").await.expect(200);
+        assert_eq!(health.header("deprecation"), None);
+
+        // Routes that only exist under /api/v1 have no alias, and unknown
+        // paths are not marked
+        let missing = app
+            .get(&format!("/exercises/{}/prs", squat))
+            .await
+            .expect(404);
+        assert_eq!(missing.header("deprecation"), None);
+        let missing = app.get("/settings").await.expect(404);
+        assert_eq!(missing.header("deprecation"), None);
+        app.get("/api/v1/settings").await.expect(200);
+        app.get(&format!("/api/v1/exercises/volume/{}", squat))
+            .await
+            .expect(404);
+    }
+}
@@ -0,0 +1,970 @@
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{PgPool, Row};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use tracing::instrument;
+use utoipa::{OpenApi, ToSchema};
+
+use crate::errors::{ApiError, ErrorBody};
+use crate::metrics::{Metrics, WorkoutSource};
+use crate::repository::postgres::rebuild_prs;
+
+const LB_TO_KG: f64 = 0.453_592_37;
+// Names closer than this to a catalog exercise are mapped automatically
+const FUZZY_THRESHOLD: f64 = 0.88;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum Source {
+    Strong,
+    Hevy,
+    FitNotes,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Strong => write!(f, "strong"),
+            Source::Hevy => write!(f, "hevy"),
+            Source::FitNotes => write!(f, "fitnotes"),
+        }
+    }
+}
+
+impl std::str::FromStr for Source {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "strong" => Ok(Source::Strong),
+            "hevy" => Ok(Source::Hevy),
+            "fitnotes" => Ok(Source::FitNotes),
+            _ => Err("Invalid source. Must be 'strong', 'hevy' or 'fitnotes'".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WeightUnit {
+    Kg,
+    Lb,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct ExerciseMapping {
+    source_name: String,
+    exercise_id: i32,
+}
+
+#[derive(Serialize, ToSchema)]
+struct RowIssue {
+    row: usize,
+    reason: String,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum MatchKind {
+    Alias,
+    Exact,
+    Fuzzy,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ExerciseMatch {
+    source_name: String,
+    exercise_id: Option<i32>,
+    exercise_name: Option<String>,
+    #[serde(rename = "match")]
+    kind: Option<MatchKind>,
+    score: Option<f64>,
+    sets: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+struct WorkoutImportReport {
+    source: Source,
+    dry_run: bool,
+    workouts: usize,
+    sets: usize,
+    first_workout: Option<NaiveDateTime>,
+    last_workout: Option<NaiveDateTime>,
+    duplicate_workouts: usize,
+    exercises: Vec<ExerciseMatch>,
+    unmapped: Vec<String>,
+    skipped_rows: Vec<RowIssue>,
+    errors: Vec<RowIssue>,
+}
+
+struct ImportedSet {
+    exercise_name: String,
+    weight_kg: f64,
+    reps: i16,
+}
+
+struct ImportedWorkout {
+    name: Option<String>,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    sets: Vec<ImportedSet>,
+}
+
+#[derive(Default)]
+struct ParsedExport {
+    workouts: Vec<ImportedWorkout>,
+    skipped_rows: Vec<RowIssue>,
+    errors: Vec<RowIssue>,
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        import_workouts,
+        list_exercise_mappings,
+        update_exercise_mapping,
+        delete_exercise_mapping
+    ),
+    tags((name = "imports", description = "Workout history from Strong, Hevy and FitNotes exports"))
+)]
+pub(crate) struct ApiDoc;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(import_workouts)
+        .service(list_exercise_mappings)
+        .service(update_exercise_mapping)
+        .service(delete_exercise_mapping);
+}
+
+// Lowercase words only, so "Bench Press (Barbell)" ~ "bench press barbell"
+fn normalise(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// "Bench Press (Barbell)" -> "Bench Press"
+fn base_name(name: &str) -> &str {
+    name.split('(').next().unwrap_or(name).trim()
+}
+
+// Word order insensitive, so "Squat (Barbell)" ~ "Barbell Squat"
+fn token_set(name: &str) -> String {
+    let mut words: Vec<String> = normalise(name)
+        .split(' ')
+        .map(|word| word.trim_end_matches('s').to_string())
+        .collect();
+    words.sort();
+    words.dedup();
+    words.join(" ")
+}
+
+fn detect_source(headers: &[String]) -> Option<Source> {
+    let has = |name: &str| headers.iter().any(|h| h == name);
+    if has("exercise_title") && has("start_time") {
+        Some(Source::Hevy)
+    } else if has("exercise name") && has("set order") {
+        Some(Source::Strong)
+    } else if has("exercise") && has("category") {
+        Some(Source::FitNotes)
+    } else {
+        None
+    }
+}
+
+fn parse_timestamp(field: &str) -> Option<NaiveDateTime> {
+    let field = field.trim();
+    const FORMATS: [&str; 5] = [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%d %b %Y, %H:%M", // Hevy, e.g. "30 Jan 2024, 18:09"
+        "%b %d, %Y %H:%M",
+    ];
+    FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(field, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(field, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+}
+
+// Strong writes durations as "1h 5m", "45m" or "30s"; newer exports use seconds
+fn parse_duration(field: &str) -> Option<Duration> {
+    let field = field.trim();
+    if let Ok(seconds) = field.parse::<i64>() {
+        return Some(Duration::seconds(seconds));
+    }
+    let mut total = 0i64;
+    for part in field.split_whitespace() {
+        let (number, unit) = part.split_at(part.find(|c: char| c.is_alphabetic())?);
+        let number: i64 = number.parse().ok()?;
+        total += match unit {
+            "h" => number * 3600,
+            "m" | "min" => number * 60,
+            "s" => number,
+            _ => return None,
+        };
+    }
+    Some(Duration::seconds(total))
+}
+
+fn parse_number(field: &str) -> Option<f64> {
+    let field = field.trim();
+    if field.is_empty() {
+        return None;
+    }
+    if field.contains('.') {
+        field.parse().ok()
+    } else {
+        field.replace(',', ".").parse().ok()
+    }
+}
+
+fn parse_export(
+    body: &str,
+    source: Option<Source>,
+    weight_unit: Option<WeightUnit>,
+) -> Result<(Source, ParsedExport), String> {
+    let first_line = body.lines().next().unwrap_or_default();
+    let delimiter = if first_line.contains(';') && !first_line.contains(',') {
+        b';'
+    } else {
+        b','
+    };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(body.as_bytes());
+
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| format!("Failed to read CSV header: {}", e))?
+        .iter()
+        .map(|h| h.to_lowercase())
+        .collect();
+
+    let source = match source.or_else(|| detect_source(&headers)) {
+        Some(source) => source,
+        None => {
+            return Err(
+                "Could not recognise the export format. Pass ?source=strong|hevy|fitnotes"
+                    .to_string(),
+            )
+        }
+    };
+
+    let column = |name: &str| headers.iter().position(|h| h == name);
+    let require = |name: &str| {
+        column(name).ok_or_else(|| format!("Missing '{}' column for a {} export", name, source))
+    };
+
+    // Weight column and unit, taken from the header where the export names it
+    let (weight_column, unit) = match source {
+        Source::Strong => (require("weight")?, weight_unit.unwrap_or(WeightUnit::Kg)),
+        Source::Hevy => match (column("weight_kg"), column("weight_lbs")) {
+            (Some(i), _) => (i, WeightUnit::Kg),
+            (None, Some(i)) => (i, WeightUnit::Lb),
+            (None, None) => return Err("Missing 'weight_kg' column for a hevy export".to_string()),
+        },
+        Source::FitNotes => match (column("weight (kg)"), column("weight (lbs)")) {
+            (Some(i), _) => (i, WeightUnit::Kg),
+            (None, Some(i)) => (i, WeightUnit::Lb),
+            (None, None) => {
+                return Err("Missing 'weight (kg)' column for a fitnotes export".to_string())
+            }
+        },
+    };
+
+    let (start_column, exercise_column, reps_column) = match source {
+        Source::Strong => (
+            require("date")?,
+            require("exercise name")?,
+            require("reps")?,
+        ),
+        Source::Hevy => (
+            require("start_time")?,
+            require("exercise_title")?,
+            require("reps")?,
+        ),
+        Source::FitNotes => (require("date")?, require("exercise")?, require("reps")?),
+    };
+    let name_column = match source {
+        Source::Strong => column("workout name"),
+        Source::Hevy => column("title"),
+        Source::FitNotes => None,
+    };
+    let duration_column = column("duration");
+    let end_column = column("end_time");
+
+    let mut parsed = ParsedExport::default();
+    // Rows of one workout share a start time (and name); keyed that way, so
+    // the workouts come out in chronological order
+    let mut workouts: BTreeMap<(NaiveDateTime, String), ImportedWorkout> = BTreeMap::new();
+
+    for (index, record) in reader.records().enumerate() {
+        let row = index + 2; // 1-based, after the header line
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                parsed.errors.push(RowIssue {
+                    row,
+                    reason: format!("Malformed CSV row: {}", e),
+                });
+                continue;
+            }
+        };
+        let field = |i: usize| record.get(i).unwrap_or_default();
+
+        let Some(start) = parse_timestamp(field(start_column)) else {
+            parsed.errors.push(RowIssue {
+                row,
+                reason: format!("Unrecognised date '{}'", field(start_column)),
+            });
+            continue;
+        };
+
+        let exercise_name = field(exercise_column).to_string();
+        if exercise_name.is_empty() {
+            parsed.errors.push(RowIssue {
+                row,
+                reason: "Missing exercise name".to_string(),
+            });
+            continue;
+        }
+
+        // Cardio, timed and note rows carry no reps
+        let reps = match parse_number(field(reps_column)) {
+            Some(reps) if reps >= 1.0 && reps <= f64::from(i16::MAX) => reps.round() as i16,
+            _ => {
+                parsed.skipped_rows.push(RowIssue {
+                    row,
+                    reason: "No repetitions recorded".to_string(),
+                });
+                continue;
+            }
+        };
+
+        let weight = parse_number(field(weight_column)).unwrap_or(0.0);
+        let weight_kg = match unit {
+            WeightUnit::Kg => weight,
+            WeightUnit::Lb => weight * LB_TO_KG,
+        };
+        if !(0.0..=f64::from(i16::MAX)).contains(&weight_kg) {
+            parsed.errors.push(RowIssue {
+                row,
+                reason: format!("Weight '{}' out of range", field(weight_column)),
+            });
+            continue;
+        }
+
+        let name = name_column
+            .map(|i| field(i).to_string())
+            .filter(|name| !name.is_empty());
+        let end = match source {
+            Source::Strong => duration_column
+                .and_then(|i| parse_duration(field(i)))
+                .map(|duration| start + duration),
+            Source::Hevy => end_column.and_then(|i| parse_timestamp(field(i))),
+            Source::FitNotes => None,
+        }
+        .filter(|end| *end >= start)
+        .unwrap_or(start);
+
+        workouts
+            .entry((start, name.clone().unwrap_or_default()))
+            .or_insert_with(|| ImportedWorkout {
+                name,
+                start,
+                end,
+                sets: Vec::new(),
+            })
+            .sets
+            .push(ImportedSet {
+                exercise_name,
+                weight_kg,
+                reps,
+            });
+    }
+
+    parsed.workouts = workouts.into_values().collect();
+    Ok((source, parsed))
+}
+
+pub(crate) struct Catalog {
+    pub(crate) exercises: Vec<(i32, String)>,
+    aliases: HashMap<String, i32>,
+}
+
+#[instrument(skip_all)]
+pub(crate) async fn load_catalog(pool: &PgPool) -> Result<Catalog, sqlx::Error> {
+    let exercises = sqlx::query("SELECT ExerciseID, ExerciseName FROM ExerciseList")
+        .fetch_all(pool)
+        .await?
+        .iter()
+        .map(|row| (row.get("exerciseid"), row.get("exercisename")))
+        .collect();
+
+    let aliases = sqlx::query("SELECT Alias, ExerciseID FROM ExerciseAliases")
+        .fetch_all(pool)
+        .await?
+        .iter()
+        .map(|row| (normalise(row.get("alias")), row.get("exerciseid")))
+        .collect();
+
+    Ok(Catalog { exercises, aliases })
+}
+
+pub(crate) fn match_exercise(
+    catalog: &Catalog,
+    source_name: &str,
+) -> Option<(i32, MatchKind, f64)> {
+    let name = normalise(source_name);
+    if let Some(id) = catalog.aliases.get(&name) {
+        return Some((*id, MatchKind::Alias, 1.0));
+    }
+
+    let tokens = token_set(source_name);
+    if let Some((id, _)) = catalog
+        .exercises
+        .iter()
+        .find(|(_, candidate)| normalise(candidate) == name || token_set(candidate) == tokens)
+    {
+        return Some((*id, MatchKind::Exact, 1.0));
+    }
+
+    let base = token_set(base_name(source_name));
+    let mut scored: Vec<(i32, f64)> = catalog
+        .exercises
+        .iter()
+        .map(|(id, candidate)| {
+            let full = strsim::jaro_winkler(&tokens, &token_set(candidate))
+                .max(strsim::normalized_levenshtein(&name, &normalise(candidate)));
+            // Agreeing only once equipment is ignored counts for a little less
+            let without_equipment =
+                strsim::jaro_winkler(&base, &token_set(base_name(candidate))) * 0.95;
+            (*id, full.max(without_equipment))
+        })
+        .filter(|(_, score)| *score >= FUZZY_THRESHOLD)
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    // A tie between two exercises is left for an explicit mapping
+    match scored.as_slice() {
+        [(id, score)] => Some((*id, MatchKind::Fuzzy, *score)),
+        [(id, score), (_, runner_up), ..] if score - runner_up > 1e-9 => {
+            Some((*id, MatchKind::Fuzzy, *score))
+        }
+        _ => None,
+    }
+}
+
+#[utoipa::path(
+    tag = "imports",
+    summary = "Import workouts from another app's CSV export",
+    description = "A dry run by default; pass dry_run=false to save the workouts.",
+    params(
+        ("source" = Option<String>, Query, description = "strong, hevy or fitnotes, detected from the header by default"),
+        ("weight_unit" = Option<String>, Query, description = "kg or lb, for exports that do not say"),
+        ("dry_run" = Option<bool>, Query, description = "Defaults to true"),
+        ("skip_unmapped" = Option<bool>, Query, description = "Import without sets of exercises that match nothing")
+    ),
+    request_body(content = String, content_type = "text/csv"),
+    responses(
+        (status = 200, description = "Dry run report", body = WorkoutImportReport),
+        (status = 201, description = "Workouts saved", body = WorkoutImportReport),
+        (status = 400, description = "Unreadable export or invalid parameters", body = ErrorBody),
+        (status = 422, description = "Rows with errors, or exercises that match nothing in the catalog", body = WorkoutImportReport)
+    )
+)]
+#[post("/imports/workouts")]
+async fn import_workouts(
+    pool: web::Data<PgPool>,
+    metrics: web::Data<Metrics>,
+    request: web::Query<HashMap<String, String>>,
+    body: String,
+) -> Result<HttpResponse, ApiError> {
+    let source = request
+        .get("source")
+        .map(|s| s.parse::<Source>())
+        .transpose()
+        .map_err(ApiError::bad_request)?;
+
+    let weight_unit = match request.get("weight_unit").map(|u| u.to_lowercase()) {
+        Some(u) if u == "kg" => Some(WeightUnit::Kg),
+        Some(u) if u == "lb" || u == "lbs" => Some(WeightUnit::Lb),
+        Some(_) => {
+            return Err(ApiError::bad_request(
+                "Invalid 'weight_unit'. Must be 'kg' or 'lb'",
+            ))
+        }
+        None => None,
+    };
+
+    // Defaults to a dry run: committing has to be asked for explicitly
+    let dry_run = request.get("dry_run").map(|v| v != "false").unwrap_or(true);
+    let skip_unmapped = request
+        .get("skip_unmapped")
+        .map(|v| v == "true")
+        .unwrap_or(false);
+
+    let (source, parsed) =
+        parse_export(&body, source, weight_unit).map_err(ApiError::bad_request)?;
+
+    let catalog = load_catalog(pool.get_ref()).await?;
+
+    // Resolve each distinct exercise name once
+    let mut set_counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for set in parsed.workouts.iter().flat_map(|w| &w.sets) {
+        *set_counts.entry(set.exercise_name.as_str()).or_insert(0) += 1;
+    }
+    let mut mapping: HashMap<String, i32> = HashMap::new();
+    let mut exercises = Vec::new();
+    let mut unmapped = Vec::new();
+    for (source_name, sets) in set_counts {
+        let matched = match_exercise(&catalog, source_name);
+        if let Some((id, _, _)) = &matched {
+            mapping.insert(source_name.to_string(), *id);
+        } else {
+            unmapped.push(source_name.to_string());
+        }
+        exercises.push(ExerciseMatch {
+            source_name: source_name.to_string(),
+            exercise_id: matched.as_ref().map(|(id, _, _)| *id),
+            exercise_name: matched.as_ref().and_then(|(id, _, _)| {
+                catalog
+                    .exercises
+                    .iter()
+                    .find(|(candidate, _)| candidate == id)
+                    .map(|(_, name)| name.clone())
+            }),
+            kind: matched.as_ref().map(|(_, kind, _)| kind.clone()),
+            score: matched
+                .as_ref()
+                .map(|(_, _, score)| (score * 1000.0).round() / 1000.0),
+            sets,
+        });
+    }
+
+    // Workouts already in the database are recognised by their start time
+    // and exercises, so different workouts started in the same minute are
+    // both kept
+    let key = |workout: &ImportedWorkout| {
+        let mut exercise_ids: Vec<i32> = workout
+            .sets
+            .iter()
+            .filter_map(|set| mapping.get(&set.exercise_name).copied())
+            .collect();
+        exercise_ids.sort();
+        exercise_ids.dedup();
+        (workout.start, exercise_ids)
+    };
+    let starts: Vec<NaiveDateTime> = parsed.workouts.iter().map(|w| w.start).collect();
+    let existing: HashSet<(NaiveDateTime, Vec<i32>)> = sqlx::query(
+        "SELECT w.Start,
+                ARRAY_REMOVE(ARRAY_AGG(DISTINCT wes.ExerciseID ORDER BY wes.ExerciseID), NULL)
+                    AS exercise_ids
+         FROM Workout w
+         LEFT JOIN Workout_Exercises_Sets wes ON wes.WorkoutID = w.WorkoutID
+         WHERE w.Start = ANY($1)
+         GROUP BY w.WorkoutID",
+    )
+    .bind(&starts)
+    .fetch_all(pool.get_ref())
+    .await?
+    .iter()
+    .map(|row| (row.get("start"), row.get("exercise_ids")))
+    .collect();
+
+    let workouts: Vec<&ImportedWorkout> = parsed
+        .workouts
+        .iter()
+        .filter(|w| !existing.contains(&key(w)))
+        .filter(|w| {
+            w.sets
+                .iter()
+                .any(|set| mapping.contains_key(&set.exercise_name))
+        })
+        .collect();
+
+    let report = WorkoutImportReport {
+        source,
+        dry_run,
+        workouts: workouts.len(),
+        sets: workouts
+            .iter()
+            .flat_map(|w| &w.sets)
+            .filter(|set| mapping.contains_key(&set.exercise_name))
+            .count(),
+        first_workout: workouts.iter().map(|w| w.start).min(),
+        last_workout: workouts.iter().map(|w| w.start).max(),
+        duplicate_workouts: parsed.workouts.len()
+            - parsed
+                .workouts
+                .iter()
+                .filter(|w| !existing.contains(&key(w)))
+                .count(),
+        exercises,
+        unmapped,
+        skipped_rows: parsed.skipped_rows,
+        errors: parsed.errors,
+    };
+
+    if !report.errors.is_empty() || (!report.unmapped.is_empty() && !skip_unmapped) {
+        return Ok(HttpResponse::UnprocessableEntity().json(report));
+    }
+
+    if dry_run {
+        return Ok(HttpResponse::Ok().json(report));
+    }
+
+    // Rolled back on drop if any insert fails
+    let mut tx = pool.begin().await?;
+
+    // Workout names that match a routine are linked to it
+    let routines: HashMap<String, i32> = sqlx::query("SELECT RoutineID, RoutineName FROM Routines")
+        .fetch_all(&mut *tx)
+        .await?
+        .iter()
+        .map(|row| {
+            (
+                row.get::<String, _>("routinename").to_lowercase(),
+                row.get("routineid"),
+            )
+        })
+        .collect();
+
+    for workout in &workouts {
+        let routine_id = workout
+            .name
+            .as_ref()
+            .and_then(|name| routines.get(&name.to_lowercase()));
+
+        let workout_id: i32 = sqlx::query(
+            r#"INSERT INTO Workout (start, "end", routineid, routineversion)
+                 VALUES ($1, $2, $3,
+                         (SELECT MAX(version) FROM RoutineVersions WHERE routineid = $3))
+                 RETURNING workoutid"#,
+        )
+        .bind(workout.start)
+        .bind(workout.end)
+        .bind(routine_id)
+        .fetch_one(&mut *tx)
+        .await?
+        .get("workoutid");
+
+        let sets: Vec<(i32, i16, i16)> = workout
+            .sets
+            .iter()
+            .filter_map(|set| {
+                mapping
+                    .get(&set.exercise_name)
+                    .map(|id| (*id, set.weight_kg.round() as i16, set.reps))
+            })
+            .collect();
+        let exercise_ids: Vec<i32> = sets.iter().map(|(id, _, _)| *id).collect();
+        let weights: Vec<i16> = sets.iter().map(|(_, weight, _)| *weight).collect();
+        let reps: Vec<i16> = sets.iter().map(|(_, _, reps)| *reps).collect();
+
+        let set_ids: Vec<i32> = sqlx::query(
+            r#"INSERT INTO "Set" (weight, reps)
+                 SELECT * FROM UNNEST($1::SMALLINT[], $2::SMALLINT[])
+                 RETURNING setid"#,
+        )
+        .bind(&weights)
+        .bind(&reps)
+        .fetch_all(&mut *tx)
+        .await?
+        .iter()
+        .map(|row| row.get("setid"))
+        .collect();
+
+        sqlx::query(
+            "INSERT INTO Workout_Exercises_Sets (workoutid, exerciseid, setid)
+             SELECT $1, * FROM UNNEST($2::INTEGER[], $3::INTEGER[])",
+        )
+        .bind(workout_id)
+        .bind(&exercise_ids)
+        .bind(&set_ids)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let mut affected: Vec<i32> = mapping.values().copied().collect();
+    affected.sort();
+    affected.dedup();
+    rebuild_prs(&mut tx, &affected).await?;
+    tx.commit().await?;
+
+    metrics.workouts_logged(WorkoutSource::Import, report.workouts as u64);
+    info!(
+        "Imported {} workouts with {} sets from {}",
+        report.workouts, report.sets, source
+    );
+    Ok(HttpResponse::Created().json(report))
+}
+
+#[utoipa::path(
+    tag = "imports",
+    summary = "Exercise names from other apps mapped onto the catalog",
+    responses((status = 200, body = Vec<ExerciseMapping>))
+)]
+#[get("/imports/exercise-mappings")]
+async fn list_exercise_mappings(pool: web::Data<PgPool>) -> Result<HttpResponse, ApiError> {
+    let mappings: Vec<ExerciseMapping> =
+        sqlx::query("SELECT Alias, ExerciseID FROM ExerciseAliases ORDER BY Alias")
+            .fetch_all(pool.get_ref())
+            .await?
+            .iter()
+            .map(|row| ExerciseMapping {
+                source_name: row.get("alias"),
+                exercise_id: row.get("exerciseid"),
+            })
+            .collect();
+    Ok(HttpResponse::Ok().json(mappings))
+}
+
+#[utoipa::path(
+    tag = "imports",
+    summary = "Create or replace a mapping",
+    responses(
+        (status = 200, body = serde_json::Value, example = json!({ "status": "updated" })),
+        (status = 400, description = "Empty source_name", body = ErrorBody),
+        (status = 404, description = "Exercise not found", body = ErrorBody)
+    )
+)]
+#[put("/imports/exercise-mappings")]
+async fn update_exercise_mapping(
+    pool: web::Data<PgPool>,
+    mapping: web::Json<ExerciseMapping>,
+) -> Result<HttpResponse, ApiError> {
+    if mapping.source_name.trim().is_empty() {
+        return Err(ApiError::bad_request("source_name cannot be empty"));
+    }
+
+    match sqlx::query(
+        "INSERT INTO ExerciseAliases (Alias, ExerciseID) VALUES ($1, $2)
+         ON CONFLICT (Alias) DO UPDATE SET ExerciseID = EXCLUDED.ExerciseID",
+    )
+    .bind(mapping.source_name.trim())
+    .bind(mapping.exercise_id)
+    .execute(pool.get_ref())
+    .await
+    {
+        Ok(_) => {
+            info!(
+                "Mapped '{}' to exercise {}",
+                mapping.source_name, mapping.exercise_id
+            );
+            Ok(HttpResponse::Ok().json(json!({ "status": "updated" })))
+        }
+        Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => Err(ApiError::not_found(
+            format!("Exercise with ID {} not found", mapping.exercise_id),
+        )),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[utoipa::path(
+    tag = "imports",
+    responses(
+        (status = 200, body = serde_json::Value, example = json!({ "status": "deleted" })),
+        (status = 404, description = "No mapping for that name", body = ErrorBody)
+    )
+)]
+#[delete("/imports/exercise-mappings/{source_name}")]
+async fn delete_exercise_mapping(
+    pool: web::Data<PgPool>,
+    source_name: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let source_name = source_name.into_inner();
+    let result = sqlx::query("DELETE FROM ExerciseAliases WHERE Alias = $1")
+        .bind(&source_name)
+        .execute(pool.get_ref())
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::not_found(format!(
+            "No mapping for '{}'",
+            source_name
+        )));
+    }
+    info!("Deleted exercise mapping for '{}'", source_name);
+    Ok(HttpResponse::Ok().json(json!({ "status": "deleted" })))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+    use serde_json::json;
+
+    use crate::test_support::TestApp;
+
+    const STRONG_EXPORT: &str = "\
+Date,Workout Name,Duration,Exercise Name,Set Order,Weight,Reps
+2026-01-05 18:00:00,Legs,1h 5m,Squat (Barbell),1,100,5
+2026-01-05 18:00:00,Legs,1h 5m,Squat (Barbell),2,100,4
+2026-01-05 18:00:00,Legs,1h 5m,Zercher Carry,1,60,1
+2026-01-08 18:00:00,Legs,45m,Squat (Barbell),1,105,3
+";
+
+    fn import(query: &str) -> TestRequest {
+        TestRequest::post()
+            .uri(&format!("/api/v1/imports/workouts{}", query))
+            .insert_header(("content-type", "text/csv"))
+            .set_payload(STRONG_EXPORT)
+    }
+
+    #[actix_web::test]
+    async fn strong_export_with_a_manual_mapping() {
+        let app = TestApp::new().await;
+        let squat = app.exercise("Squat").create().await;
+        let carry = app.exercise("Farmer Carry").create().await;
+        let legs = app.routine("Legs").exercise(squat, 3).create().await;
+
+        let report = app.send(import("")).await.expect(422).json();
+        assert_eq!(report["source"], "strong");
+        assert_eq!(report["dry_run"], true);
+        assert_eq!(report["unmapped"], json!(["Zercher Carry"]));
+        assert_eq!(report["exercises"][0]["exercise_id"], squat);
+
+        app.put(
+            "/api/v1/imports/exercise-mappings",
+            json!({ "source_name": "Zercher Carry", "exercise_id": carry }),
+        )
+        .await
+        .expect(200);
+        let report = app.send(import("")).await.expect(200).json();
+        assert_eq!(report["workouts"], 2);
+        assert_eq!(report["sets"], 4);
+        assert_eq!(report["exercises"][1]["match"], "alias");
+        assert_eq!(app.count("Workout").await, 0);
+
+        let report = app.send(import("?dry_run=false")).await.expect(201).json();
+        assert_eq!(report["workouts"], 2);
+        let workouts: Vec<(Option<i32>, i64)> = sqlx::query_as(
+            r#"SELECT routineid, EXTRACT(EPOCH FROM "end" - start)::BIGINT FROM Workout ORDER BY start"#,
+        )
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+        assert_eq!(workouts, vec![(Some(legs), 3900), (Some(legs), 2700)]);
+        assert_eq!(app.count("PRs").await, 3);
+        let versions = app
+            .get(&format!("/api/v1/routines/{}/versions", legs))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(versions[0]["workouts"], 2);
+
+        let report = app.send(import("?dry_run=false")).await.expect(201).json();
+        assert_eq!(report["workouts"], 0);
+        assert_eq!(report["duplicate_workouts"], 2);
+
+        // A different workout started in the same minute is not a duplicate
+        let bench = app.exercise("Bench Press").create().await;
+        let report = app
+            .send(
+                TestRequest::post()
+                    .uri("/api/v1/imports/workouts?dry_run=false")
+                    .set_payload(
+                        "Date,Workout Name,Duration,Exercise Name,Set Order,Weight,Reps\n\
+                         2026-01-05 18:00:00,Push,30m,Bench Press,1,80,5\n",
+                    ),
+            )
+            .await
+            .expect(201)
+            .json();
+        assert_eq!(report["workouts"], 1);
+        assert_eq!(report["duplicate_workouts"], 0);
+        assert_eq!(report["exercises"][0]["exercise_id"], bench);
+
+        let metrics = app.get("/metrics").await.expect(200).text();
+        assert!(metrics.contains("strongeryou_workouts_logged_total{source=\"import\"} 3\n"));
+    }
+
+    #[actix_web::test]
+    async fn unmapped_exercises_can_be_skipped() {
+        let app = TestApp::new().await;
+        app.exercise("Squat").create().await;
+
+        let report = app
+            .send(import("?dry_run=false&skip_unmapped=true"))
+            .await
+            .expect(201)
+            .json();
+        assert_eq!(report["sets"], 3);
+        assert_eq!(app.count("\"Set\"").await, 3);
+    }
+
+    #[actix_web::test]
+    async fn exercise_mappings_crud() {
+        let app = TestApp::new().await;
+        let squat = app.exercise("Squat").create().await;
+
+        app.put(
+            "/api/v1/imports/exercise-mappings",
+            json!({ "source_name": " Back Squat ", "exercise_id": squat }),
+        )
+        .await
+        .expect(200);
+        let mappings = app
+            .get("/api/v1/imports/exercise-mappings")
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(
+            mappings,
+            json!([{ "source_name": "Back Squat", "exercise_id": squat }])
+        );
+        app.put(
+            "/api/v1/imports/exercise-mappings",
+            json!({ "source_name": "Front Squat", "exercise_id": 999 }),
+        )
+        .await
+        .expect(404);
+        app.put(
+            "/api/v1/imports/exercise-mappings",
+            json!({ "source_name": " ", "exercise_id": squat }),
+        )
+        .await
+        .expect(400);
+
+        app.delete("/api/v1/imports/exercise-mappings/Back%20Squat")
+            .await
+            .expect(200);
+        app.delete("/api/v1/imports/exercise-mappings/Back%20Squat")
+            .await
+            .expect(404);
+    }
+
+    #[actix_web::test]
+    async fn rejects_unknown_formats_and_options() {
+        let app = TestApp::new().await;
+
+        app.send(import("?source=myfitnesspal")).await.expect(400);
+        app.send(import("?weight_unit=stone")).await.expect(400);
+        let response = app
+            .send(
+                TestRequest::post()
+                    .uri("/api/v1/imports/workouts")
+                    .set_payload("when,what\n2026-01-05,squat\n"),
+            )
+            .await
+            .expect(400);
+        let body = response.json();
+        assert_eq!(body["code"], "bad_request");
+        assert!(body["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("Could not recognise the export format"));
+    }
+}
@@ -1,56 +1,137 @@
-use actix_web::{get, post, put, web, HttpResponse};
+use actix_web::{delete, get, post, put, web, HttpResponse};
 use chrono::NaiveDateTime;
-use log::{error, info};
+use log::info;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use sqlx::{PgPool, Row};
 use std::collections::HashMap;
-
-#[derive(Serialize, Deserialize)]
-struct Set {
-    weight: i16,
-    reps: i16,
+use utoipa::{OpenApi, ToSchema};
+
+use crate::errors::{ApiError, ErrorBody};
+use crate::limits::Limits;
+use crate::metrics::{Metrics, WorkoutSource};
+use crate::repository::{LoggedWorkout, Repositories};
+use crate::service::{self, PRValue};
+use crate::validation::{validate, Validate, Validator};
+
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub(crate) struct Set {
+    pub(crate) weight: i16,
+    pub(crate) reps: i16,
 }
 
-#[derive(Serialize, Deserialize)]
-struct Exercise {
-    exercise_id: i32,
-    exercise_name: String,
-    sets: HashMap<i16, Set>, // set_number -> (weight, reps)
+#[derive(Serialize, Deserialize, ToSchema)]
+#[schema(as = WorkoutExercise)]
+pub(crate) struct Exercise {
+    pub(crate) exercise_id: i32,
+    pub(crate) exercise_name: String,
+    pub(crate) sets: HashMap<i16, Set>, // set_number -> (weight, reps)
 }
 
-#[derive(Serialize, Deserialize)]
-struct WorkoutData {
-    exercises: Vec<Exercise>,
-    start_time: Option<NaiveDateTime>,
-    end_time: Option<NaiveDateTime>,
-    routine_id: Option<i32>,
+#[derive(Serialize, Deserialize, ToSchema)]
+pub(crate) struct WorkoutData {
+    pub(crate) exercises: Vec<Exercise>,
+    pub(crate) start_time: Option<NaiveDateTime>,
+    pub(crate) end_time: Option<NaiveDateTime>,
+    pub(crate) routine_id: Option<i32>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 struct ValidateSetData {
     exercise_id: i32,
     weight: i16,
     reps: i16,
 }
 
-#[derive(Serialize)]
-struct WorkoutSummary {
-    workout_id: i32,
-    routine_name: String,
-    start_time: NaiveDateTime,
+#[derive(Serialize, ToSchema)]
+pub(crate) struct WorkoutSummary {
+    pub(crate) workout_id: i32,
+    // None for workouts logged without a routine, which the list used to fail
+    // on; imports are the common source of them
+    pub(crate) routine_name: Option<String>,
+    pub(crate) start_time: NaiveDateTime,
+}
+
+#[derive(Serialize, ToSchema)]
+struct WorkoutTemplate {
+    exercises: Vec<Exercise>, // In routine order
+}
+
+impl Validate for Set {
+    fn rules(&self, v: &mut Validator) {
+        v.min("weight", self.weight, 0);
+        v.min("reps", self.reps, 0);
+    }
+}
+
+impl Validate for Exercise {
+    fn rules(&self, v: &mut Validator) {
+        v.exercise_ref("exercise_id", self.exercise_id);
+
+        // Sorted so that errors come out in set order
+        let mut numbers: Vec<&i16> = self.sets.keys().collect();
+        numbers.sort();
+        for number in numbers {
+            let field = format!("sets.{}", number);
+            if *number < 1 {
+                v.error(&field, "out_of_range", "Set numbers start at 1");
+            }
+            v.nested(&field, |v| self.sets[number].rules(v));
+        }
+    }
 }
 
-#[derive(Serialize)]
-enum PRValue {
-    Weight(i16),
-    OneRM(f32),
-    Volume(i32),
-    Reps(i16),
+impl Validate for WorkoutData {
+    fn rules(&self, v: &mut Validator) {
+        // Workout.Start is NOT NULL
+        v.present("start_time", &self.start_time);
+        if let (Some(start), Some(end)) = (self.start_time, self.end_time) {
+            v.check(
+                "end_time",
+                end >= start,
+                "before_start",
+                "Must not be before start_time",
+            );
+        }
+        v.each("exercises", &self.exercises);
+    }
 }
 
+impl Validate for ValidateSetData {
+    fn rules(&self, v: &mut Validator) {
+        v.exercise_ref("exercise_id", self.exercise_id);
+        v.min("weight", self.weight, 0);
+        v.min("reps", self.reps, 0);
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        get_workout_template,
+        modify_workout,
+        finish_workout,
+        delete_workout,
+        validate_set,
+        display_workouts,
+        view_workout
+    ),
+    tags((name = "workouts", description = "Logged workouts and their sets"))
+)]
+pub(crate) struct ApiDoc;
+
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(get_workout_template)
+        .service(modify_workout)
+        .service(finish_workout)
+        .service(delete_workout)
+        .service(validate_set)
+        .service(display_workouts)
+        .service(view_workout);
+}
+
+// The unversioned routes, kept as deprecated aliases
+pub fn init_legacy_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(legacy_workout_template)
         .service(modify_workout)
         .service(finish_workout)
         .service(validate_set)
@@ -58,429 +139,556 @@ pub fn init_routes(cfg: &mut web::ServiceConfig) {
         .service(view_workout);
 }
 
+#[utoipa::path(
+    tag = "workouts",
+    summary = "Check which records a set would beat",
+    responses(
+        (status = 200, description = "New records keyed by record name", body = HashMap<String, PRValue>),
+        (status = 422, description = "Invalid set", body = ErrorBody)
+    )
+)]
 #[post("/workouts/validate")]
 async fn validate_set(
-    pool: web::Data<PgPool>,
+    repos: web::Data<Repositories>,
     set_data: web::Json<ValidateSetData>,
-) -> HttpResponse {
-    let mut new_prs: HashMap<&str, PRValue> = HashMap::new();
-
-    // Calculate 1RM using the formula
-    let one_rm = f32::from(set_data.weight) * (36.0 / (37.0 - f32::from(set_data.reps)));
-    let set_volume = i32::from(set_data.weight) * i32::from(set_data.reps);
-
-    // Check PRs
-    match sqlx::query(
-        "SELECT heaviestweight, onerm, setvolume FROM PRs 
-         WHERE exerciseid = $1 
-         ORDER BY prid DESC LIMIT 1",
-    )
-    .bind(set_data.exercise_id)
-    .fetch_optional(pool.get_ref())
-    .await
-    {
-        Ok(maybe_pr) => {
-            if let Some(pr) = maybe_pr {
-                let current_heaviest: i16 = pr.get("heaviestweight");
-                let current_one_rm: f32 = pr.get("onerm");
-                let current_volume: i32 = pr.get("setvolume");
-
-                if set_data.weight > current_heaviest {
-                    new_prs.insert("HeaviestWeight", PRValue::Weight(set_data.weight));
-                }
-                if one_rm > current_one_rm {
-                    new_prs.insert("OneRM", PRValue::OneRM(one_rm));
-                }
-                if set_volume > current_volume {
-                    new_prs.insert("SetVolume", PRValue::Volume(set_volume));
-                }
-            }
-        }
-        Err(e) => {
-            error!("Database error checking PRs: {}", e);
-            return HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to check PRs"
-            }));
-        }
-    }
-
-    // Check HighestRepsPerWeight
-    match sqlx::query(
-        "SELECT highestreps FROM HighestRepsPerWeight 
-         WHERE exerciseid = $1 AND weight = $2",
-    )
-    .bind(set_data.exercise_id)
-    .bind(set_data.weight)
-    .fetch_optional(pool.get_ref())
-    .await
-    {
-        Ok(maybe_record) => {
-            if let Some(record) = maybe_record {
-                let current_highest_reps: i16 = record.get("highestreps");
-                if set_data.reps > current_highest_reps {
-                    new_prs.insert("HighestReps", PRValue::Reps(set_data.reps));
-                }
-            } else {
-                new_prs.insert("HighestReps", PRValue::Reps(set_data.reps));
-            }
-        }
-        Err(e) => {
-            error!("Database error checking highest reps: {}", e);
-            return HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to check highest reps"
-            }));
-        }
-    }
+) -> Result<HttpResponse, ApiError> {
+    validate(&*set_data)
+        .exercises_exist(repos.exercises.as_ref())
+        .await?
+        .finish()?;
 
-    HttpResponse::Ok().json(new_prs)
+    let new_prs =
+        service::check_set(&repos, set_data.exercise_id, set_data.weight, set_data.reps).await?;
+    Ok(HttpResponse::Ok().json(new_prs))
 }
+
+#[utoipa::path(
+    tag = "workouts",
+    summary = "List workouts, newest first",
+    responses((status = 200, body = Vec<WorkoutSummary>))
+)]
 #[get("/workouts")]
-async fn display_workouts(pool: web::Data<PgPool>) -> HttpResponse {
-    match sqlx::query(
-        r#"SELECT w.workoutid, w.start, r.routinename 
-         FROM Workout w 
-         LEFT JOIN Routines r ON w.routineid = r.routineid 
-         ORDER BY w.start DESC"#,
-    )
-    .fetch_all(pool.get_ref())
-    .await
-    {
-        Ok(rows) => {
-            let workouts: Vec<WorkoutSummary> = rows
-                .iter()
-                .map(|row| WorkoutSummary {
-                    workout_id: row.get("workoutid"),
-                    routine_name: row.get("routinename"),
-                    start_time: row.get("start"),
-                })
-                .collect();
-
-            HttpResponse::Ok().json(workouts)
-        }
-        Err(e) => {
-            error!("Failed to fetch workouts: {}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to fetch workouts"
-            }))
-        }
-    }
+async fn display_workouts(repos: web::Data<Repositories>) -> Result<HttpResponse, ApiError> {
+    let workouts = repos.workouts.list(None).await?;
+    Ok(HttpResponse::Ok().json(workouts))
 }
 
+#[utoipa::path(
+    tag = "workouts",
+    summary = "Get a workout with its sets",
+    responses(
+        (status = 200, body = LoggedWorkout),
+        (status = 404, description = "Workout not found", body = ErrorBody)
+    )
+)]
 #[get("/workouts/{workout_id}")]
-async fn view_workout(pool: web::Data<PgPool>, workout_id: web::Path<i32>) -> HttpResponse {
+async fn view_workout(
+    repos: web::Data<Repositories>,
+    workout_id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
     let workout_id = workout_id.into_inner();
+    let workout = repos
+        .workouts
+        .get(workout_id)
+        .await?
+        .ok_or_else(|| workout_not_found(workout_id))?;
 
-    let workout_data = sqlx::query(
-        r#"SELECT w.routineid, r.routinename, e.exerciseid, e.exercisename, 
-           s.weight, s.reps, wes.setid
-         FROM Workout w
-         JOIN Workout_Exercises_Sets wes ON w.workoutid = wes.workoutid
-         JOIN ExerciseList e ON wes.exerciseid = e.exerciseid
-         JOIN "Set" s ON wes.setid = s.setid
-         LEFT JOIN Routines r ON w.routineid = r.routineid
-         WHERE w.workoutid = $1
-         ORDER BY e.exerciseid, s.setid"#, // Added ordering to keep sets in order
-    )
-    .bind(workout_id)
-    .fetch_all(pool.get_ref())
-    .await;
-
-    match workout_data {
-        Ok(rows) => {
-            if rows.is_empty() {
-                return HttpResponse::NotFound().json(json!({
-                    "error": format!("Workout with ID {} not found", workout_id)
-                }));
-            }
-
-            let mut exercises_map: HashMap<i32, Exercise> = HashMap::new();
-            let mut routine_id: Option<i32> = None;
-            let mut routine_name: Option<String> = None;
-
-            // Keep track of set count for each exercise
-            let mut exercise_set_counter: HashMap<i32, i16> = HashMap::new();
-
-            for row in &rows {
-                routine_id = row.try_get("routineid").ok();
-                routine_name = row.try_get("routinename").ok();
-
-                let exercise_id: i32 = row.get("exerciseid");
-                let exercise = exercises_map.entry(exercise_id).or_insert(Exercise {
-                    exercise_id,
-                    exercise_name: row.get("exercisename"),
-                    sets: HashMap::new(),
-                });
-
-                // Increment set number for this exercise
-                let set_number = exercise_set_counter.entry(exercise_id).or_insert(0);
-                *set_number += 1;
-
-                exercise.sets.insert(
-                    *set_number,
-                    Set {
-                        weight: row.get("weight"),
-                        reps: row.get("reps"),
-                    },
-                );
-            }
+    Ok(HttpResponse::Ok().json(workout))
+}
 
-            HttpResponse::Ok().json(json!({
-                "routine_id": routine_id,
-                "routine_name": routine_name,
-                "exercises": exercises_map.values().collect::<Vec<_>>()
-            }))
-        }
-        Err(e) => {
-            error!("Failed to fetch workout details: {}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": format!("Failed to fetch workout details: {}", e)
-            }))
-        }
-    }
+#[utoipa::path(
+    tag = "workouts",
+    summary = "Empty sets for every exercise of a routine",
+    responses(
+        (status = 200, body = WorkoutTemplate),
+        (status = 404, description = "Routine not found", body = ErrorBody)
+    )
+)]
+#[get("/routines/{routine_id}/workout-template")]
+async fn get_workout_template(
+    repos: web::Data<Repositories>,
+    routine_id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let exercises = service::workout_template(&repos, routine_id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(WorkoutTemplate { exercises }))
 }
 
 #[get("/workouts/template/{routine_id}")]
-async fn get_workout_template(pool: web::Data<PgPool>, routine_id: web::Path<i32>) -> HttpResponse {
-    let routine_id = routine_id.into_inner();
+async fn legacy_workout_template(
+    repos: web::Data<Repositories>,
+    routine_id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let exercises = service::workout_template(&repos, routine_id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(WorkoutTemplate { exercises }))
+}
 
-    // First verify the routine exists
-    match sqlx::query("SELECT routineid FROM Routines WHERE routineid = $1")
-        .bind(routine_id)
-        .fetch_optional(pool.get_ref())
-        .await
-    {
-        Ok(None) => {
-            return HttpResponse::NotFound().json(json!({
-                "error": format!("Routine with ID {} not found", routine_id)
-            }));
-        }
-        Err(e) => {
-            error!("Database error: {}", e);
-            return HttpResponse::InternalServerError().json(json!({
-                "error": "Internal server error"
-            }));
-        }
-        Ok(Some(_)) => {}
+fn workout_not_found(workout_id: i32) -> ApiError {
+    ApiError::not_found(format!("Workout with ID {} not found", workout_id))
+}
+
+#[utoipa::path(
+    tag = "workouts",
+    summary = "Add sets to a workout",
+    responses(
+        (status = 200, body = serde_json::Value, example = json!({ "status": "updated" })),
+        (status = 404, description = "Workout not found", body = ErrorBody),
+        (status = 413, description = "Too many exercises or sets", body = ErrorBody),
+        (status = 422, description = "Invalid workout", body = ErrorBody)
+    )
+)]
+#[put("/workouts/{workout_id}")]
+async fn modify_workout(
+    repos: web::Data<Repositories>,
+    metrics: web::Data<Metrics>,
+    limits: web::Data<Limits>,
+    workout_id: web::Path<i32>,
+    workout_data: web::Json<WorkoutData>,
+) -> Result<HttpResponse, ApiError> {
+    limits.check_workout(&workout_data)?;
+    let workout_id = workout_id.into_inner();
+    if !repos.workouts.exists(workout_id).await? {
+        return Err(workout_not_found(workout_id));
     }
 
-    match sqlx::query(
-        r#"SELECT e.exerciseid, e.exercisename, r.numberofsets 
-         FROM ExerciseList e
-         JOIN Routines_Exercises_Sets r ON e.exerciseid = r.exerciseid
-         WHERE r.routineid = $1"#,
+    service::validate_workout(&repos, &workout_data).await?;
+    let (_, prs_set) = service::record_workout(&repos, &workout_data, Some(workout_id)).await?;
+    metrics.prs_set(prs_set);
+
+    info!("Updated workout {}", workout_id);
+    Ok(HttpResponse::Ok().json(json!({ "status": "updated" })))
+}
+
+#[utoipa::path(
+    tag = "workouts",
+    summary = "Log a workout",
+    responses(
+        (status = 201, body = serde_json::Value, example = json!({ "workout_id": 1 })),
+        (status = 413, description = "Too many exercises or sets", body = ErrorBody),
+        (status = 422, description = "Invalid workout", body = ErrorBody)
     )
-    .bind(routine_id)
-    .fetch_all(pool.get_ref())
-    .await
-    {
-        Ok(rows) => {
-            let exercises: Vec<Exercise> = rows
-                .iter()
-                .map(|row| {
-                    let number_of_sets: i16 = row.get("numberofsets");
-                    let mut sets = HashMap::new();
-
-                    for set_num in 1..=number_of_sets {
-                        sets.insert(set_num, Set { weight: 0, reps: 0 });
-                    }
-
-                    Exercise {
-                        exercise_id: row.get("exerciseid"),
-                        exercise_name: row.get("exercisename"),
-                        sets,
-                    }
-                })
-                .collect();
-
-            HttpResponse::Ok().json(json!({
-                "exercises": exercises
-            }))
-        }
-        Err(e) => {
-            error!("Failed to fetch workout template: {}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to fetch workout template"
-            }))
-        }
+)]
+#[post("/workouts")]
+async fn finish_workout(
+    repos: web::Data<Repositories>,
+    metrics: web::Data<Metrics>,
+    limits: web::Data<Limits>,
+    workout_data: web::Json<WorkoutData>,
+) -> Result<HttpResponse, ApiError> {
+    limits.check_workout(&workout_data)?;
+    service::validate_workout(&repos, &workout_data).await?;
+    let (workout_id, prs_set) = service::record_workout(&repos, &workout_data, None).await?;
+    metrics.workouts_logged(WorkoutSource::Api, 1);
+    metrics.prs_set(prs_set);
+
+    info!("Created new workout {}", workout_id);
+    Ok(HttpResponse::Created().json(json!({ "workout_id": workout_id })))
+}
+
+#[utoipa::path(
+    tag = "workouts",
+    summary = "Delete a workout and its sets",
+    description = "The records of the exercises it contained are rebuilt from the remaining workouts.",
+    responses(
+        (status = 200, body = serde_json::Value, example = json!({ "status": "deleted" })),
+        (status = 404, description = "Workout not found", body = ErrorBody)
+    )
+)]
+#[delete("/workouts/{workout_id}")]
+async fn delete_workout(
+    repos: web::Data<Repositories>,
+    workout_id: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let workout_id = workout_id.into_inner();
+    if !repos.workouts.delete(workout_id).await? {
+        return Err(workout_not_found(workout_id));
     }
+
+    info!("Deleted workout {}", workout_id);
+    Ok(HttpResponse::Ok().json(json!({ "status": "deleted" })))
 }
 
-async fn update_prs(
-    pool: &PgPool,
-    workout_id: i32,
-    exercise: &Exercise,
-) -> Result<(), sqlx::Error> {
-    let mut heaviest_weight = 0i16;
-    let mut highest_volume = 0i32;
-    let mut highest_reps_map: HashMap<i16, i16> = HashMap::new();
-
-    for (_, set) in &exercise.sets {
-        if set.weight > heaviest_weight {
-            heaviest_weight = set.weight;
-        }
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
 
-        highest_volume += i32::from(set.weight) * i32::from(set.reps);
+    use crate::test_support::TestApp;
 
-        let current_highest = highest_reps_map.entry(set.weight).or_insert(0);
-        if set.reps > *current_highest {
-            *current_highest = set.reps;
-        }
+    fn workout_body(exercise_id: i32, sets: serde_json::Value) -> serde_json::Value {
+        json!({
+            "start_time": "2026-01-05T18:00:00",
+            "end_time": "2026-01-05T19:00:00",
+            "exercises": [{ "exercise_id": exercise_id, "exercise_name": "Bench Press", "sets": sets }],
+        })
     }
 
-    let one_rm = exercise
-        .sets
-        .values()
-        .map(|set| {
-            let weight = f32::from(set.weight);
-            let reps = f32::from(set.reps);
-            weight * (36.0 / (37.0 - reps))
-        })
-        .fold(0.0, f32::max);
+    #[actix_web::test]
+    async fn finish_workout_records_sets_and_prs() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
+
+        let body = app
+            .post(
+                "/workouts",
+                workout_body(
+                    bench,
+                    json!({ "1": { "weight": 100, "reps": 5 }, "2": { "weight": 90, "reps": 8 } }),
+                ),
+            )
+            .await
+            .expect(201)
+            .json();
+        let workout_id = body["workout_id"].as_i64().unwrap();
 
-    let pr_id: i32 = sqlx::query(
-        "INSERT INTO PRs (heaviestweight, onerm, setvolume, exerciseid, workoutid)
-         VALUES ($1, $2, $3, $4, $5)
-         RETURNING prid",
-    )
-    .bind(heaviest_weight)
-    .bind(one_rm)
-    .bind(highest_volume)
-    .bind(exercise.exercise_id)
-    .bind(workout_id)
-    .fetch_one(pool)
-    .await?
-    .get("prid");
-
-    for (weight, reps) in highest_reps_map {
-        sqlx::query(
-            "INSERT INTO HighestRepsPerWeight (weight, highestreps, exerciseid, prid)
-             VALUES ($1, $2, $3, $4)
-             ON CONFLICT (exerciseid, weight)
-             DO UPDATE SET highestreps = EXCLUDED.highestreps, prid = EXCLUDED.prid
-             WHERE HighestRepsPerWeight.highestreps < EXCLUDED.highestreps",
+        let pr = sqlx::query_as::<_, (i16, f32, i32)>(
+            "SELECT heaviestweight, onerm, setvolume FROM PRs WHERE workoutid = $1",
         )
-        .bind(weight)
-        .bind(reps)
-        .bind(exercise.exercise_id)
-        .bind(pr_id)
-        .execute(pool)
-        .await?;
+        .bind(workout_id as i32)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+        assert_eq!(pr.0, 100);
+        assert!((pr.1 - 112.5).abs() < 0.01); // 100 * 36 / 32
+        assert_eq!(pr.2, 100 * 5 + 90 * 8);
+
+        let best_reps: Vec<(i16, i16)> =
+            sqlx::query_as("SELECT weight, highestreps FROM HighestRepsPerWeight ORDER BY weight")
+                .fetch_all(&app.pool)
+                .await
+                .unwrap();
+        assert_eq!(best_reps, vec![(90, 8), (100, 5)]);
     }
 
-    Ok(())
-}
-
-async fn validate_routine_id(pool: &PgPool, routine_id: i32) -> Result<bool, sqlx::Error> {
-    let result = sqlx::query("SELECT routineid FROM Routines WHERE routineid = $1")
-        .bind(routine_id)
-        .fetch_optional(pool)
-        .await?;
-    Ok(result.is_some())
-}
+    #[actix_web::test]
+    async fn only_new_bests_count_as_prs_set() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
 
-async fn save_workout_data(
-    pool: &PgPool,
-    workout_data: &WorkoutData,
-    workout_id: Option<i32>,
-) -> Result<i32, sqlx::Error> {
-    // Validate routine_id if provided
-    if let Some(routine_id) = workout_data.routine_id {
-        if !validate_routine_id(pool, routine_id).await? {
-            return Err(sqlx::Error::Protocol(format!(
-                "Routine with ID {} does not exist",
-                routine_id
-            )));
+        for (weight, reps) in [(100, 5), (80, 5), (105, 3)] {
+            app.post(
+                "/workouts",
+                workout_body(bench, json!({ "1": { "weight": weight, "reps": reps } })),
+            )
+            .await
+            .expect(201);
         }
+
+        let metrics = app.get("/metrics").await.expect(200).text();
+        assert!(metrics.contains("strongeryou_workouts_logged_total{source=\"api\"} 3"));
+        assert!(metrics.contains("strongeryou_prs_set_total 2"));
     }
 
-    let workout_id = match workout_id {
-        Some(id) => id,
-        None => sqlx::query(
-            r#"INSERT INTO Workout (start, "end", routineid)
-                 VALUES ($1, $2, $3)
-                 RETURNING workoutid"#,
+    #[actix_web::test]
+    async fn higher_reps_at_a_weight_replace_the_best() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
+        app.workout().set(bench, 100, 5).create().await;
+        app.workout().set(bench, 100, 3).create().await;
+        app.workout().set(bench, 100, 7).create().await;
+
+        let reps: i16 = sqlx::query_scalar(
+            "SELECT highestreps FROM HighestRepsPerWeight WHERE exerciseid = $1 AND weight = 100",
         )
-        .bind(workout_data.start_time)
-        .bind(workout_data.end_time)
-        .bind(workout_data.routine_id)
-        .fetch_one(pool)
-        .await?
-        .get("workoutid"),
-    };
-
-    for exercise in &workout_data.exercises {
-        for (_set_number, set) in &exercise.sets {
-            let set_id: i32 = sqlx::query(
-                r#"INSERT INTO "Set" (weight, reps)
-                 VALUES ($1, $2)
-                 RETURNING setid"#,
+        .bind(bench)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+        assert_eq!(reps, 7);
+    }
+
+    #[actix_web::test]
+    async fn finish_workout_reports_every_invalid_field() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
+
+        let body = app
+            .post(
+                "/workouts",
+                json!({
+                    "start_time": "2026-01-05T18:00:00",
+                    "end_time": "2026-01-05T17:00:00",
+                    "routine_id": 999,
+                    "exercises": [
+                        { "exercise_id": bench, "exercise_name": "", "sets": { "0": { "weight": -1, "reps": 5 } } },
+                        { "exercise_id": 999, "exercise_name": "", "sets": {} },
+                    ],
+                }),
             )
-            .bind(set.weight)
-            .bind(set.reps)
-            .fetch_one(pool)
-            .await?
-            .get("setid");
-
-            sqlx::query(
-                "INSERT INTO Workout_Exercises_Sets (workoutid, exerciseid, setid)
-                 VALUES ($1, $2, $3)",
+            .await
+            .expect(422)
+            .json();
+
+        let mut fields: Vec<&str> = body["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["field"].as_str().unwrap())
+            .collect();
+        fields.sort();
+        assert_eq!(
+            fields,
+            vec![
+                "end_time",
+                "exercises[0].sets.0",
+                "exercises[0].sets.0.weight",
+                "exercises[1].exercise_id",
+                "routine_id",
+            ]
+        );
+        assert_eq!(app.count("Workout").await, 0);
+    }
+
+    #[actix_web::test]
+    async fn oversized_workouts_are_rejected() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
+
+        let sets: serde_json::Map<String, serde_json::Value> = (1..=101)
+            .map(|i| (i.to_string(), json!({ "weight": 60, "reps": 5 })))
+            .collect();
+        let body = app
+            .post("/api/v1/workouts", workout_body(bench, json!(sets)))
+            .await
+            .expect(413)
+            .json();
+        assert_eq!(body["code"], "payload_too_large");
+        assert_eq!(body["error"], "exercises[0] has more than 100 sets");
+
+        let exercise = json!({ "exercise_id": bench, "exercise_name": "Bench Press", "sets": {} });
+        let body = app
+            .post(
+                "/api/v1/workouts",
+                json!({
+                    "start_time": "2026-01-05T18:00:00",
+                    "end_time": "2026-01-05T19:00:00",
+                    "exercises": vec![exercise; 51],
+                }),
             )
-            .bind(workout_id)
-            .bind(exercise.exercise_id)
-            .bind(set_id)
-            .execute(pool)
-            .await?;
-        }
+            .await
+            .expect(413)
+            .json();
+        assert_eq!(body["error"], "A workout can have at most 50 exercises");
+        assert_eq!(app.count("Workout").await, 0);
+    }
 
-        update_prs(pool, workout_id, exercise).await?;
+    #[actix_web::test]
+    async fn set_ids_past_the_smallint_range() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
+        sqlx::query(r#"ALTER SEQUENCE "Set_setid_seq" RESTART WITH 40000"#)
+            .execute(&app.pool)
+            .await
+            .unwrap();
+
+        let workout_id = app.workout().set(bench, 100, 5).create().await;
+        let body = app
+            .get(&format!("/api/v1/workouts/{}", workout_id))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(body["exercises"][0]["sets"]["1"]["weight"], 100);
     }
 
-    Ok(workout_id)
-}
+    #[actix_web::test]
+    async fn validate_set_reports_new_prs() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
 
-#[put("/workouts/{workout_id}")]
-async fn modify_workout(
-    pool: web::Data<PgPool>,
-    workout_id: web::Path<i32>,
-    workout_data: web::Json<WorkoutData>,
-) -> HttpResponse {
-    let workout_id = workout_id.into_inner();
+        // Without history every rep count is a best
+        let body = app
+            .post(
+                "/workouts/validate",
+                json!({ "exercise_id": bench, "weight": 100, "reps": 5 }),
+            )
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(body, json!({ "HighestReps": { "Reps": 5 } }));
 
-    match save_workout_data(pool.get_ref(), &workout_data, Some(workout_id)).await {
-        Ok(_) => {
-            info!("Updated workout {}", workout_id);
-            HttpResponse::Ok().json(json!({ "status": "updated" }))
-        }
-        Err(e) => {
-            error!("Failed to update workout: {}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "error": format!("Failed to update workout: {}", e)
-            }))
-        }
+        app.workout().set(bench, 100, 5).create().await;
+
+        let body = app
+            .post(
+                "/workouts/validate",
+                json!({ "exercise_id": bench, "weight": 100, "reps": 5 }),
+            )
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(body, json!({}));
+
+        let body = app
+            .post(
+                "/workouts/validate",
+                json!({ "exercise_id": bench, "weight": 110, "reps": 6 }),
+            )
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(body["HeaviestWeight"], json!({ "Weight": 110 }));
+        assert_eq!(body["SetVolume"], json!({ "Volume": 660 }));
+        assert_eq!(body["HighestReps"], json!({ "Reps": 6 }));
+        assert!(body["OneRM"]["OneRM"].as_f64().unwrap() > 112.5);
+
+        app.post(
+            "/workouts/validate",
+            json!({ "exercise_id": 999, "weight": 100, "reps": 5 }),
+        )
+        .await
+        .expect(422);
     }
-}
 
-#[post("/workouts")]
-async fn finish_workout(
-    pool: web::Data<PgPool>,
-    workout_data: web::Json<WorkoutData>,
-) -> HttpResponse {
-    match save_workout_data(pool.get_ref(), &workout_data, None).await {
-        Ok(workout_id) => {
-            info!("Created new workout {}", workout_id);
-            HttpResponse::Created().json(json!({ "workout_id": workout_id }))
-        }
-        Err(e) => {
-            error!("Failed to create workout: {}", e);
-            if e.to_string().contains("does not exist") {
-                HttpResponse::BadRequest().json(json!({
-                    "error": e.to_string()
-                }))
-            } else {
-                HttpResponse::InternalServerError().json(json!({
-                    "error": format!("Failed to create workout: {}", e)
-                }))
-            }
-        }
+    #[actix_web::test]
+    async fn display_and_view_workouts() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
+        let squat = app.exercise("Squat").create().await;
+        let routine = app.routine("Push").exercise(bench, 2).create().await;
+        let older = app
+            .workout()
+            .at("2026-01-05T18:00:00")
+            .set(squat, 120, 5)
+            .create()
+            .await;
+        let newer = app
+            .workout()
+            .routine(routine)
+            .at("2026-01-07T18:00:00")
+            .set(bench, 100, 5)
+            .set(bench, 95, 6)
+            .create()
+            .await;
+
+        let list = app.get("/workouts").await.expect(200).json();
+        assert_eq!(
+            list,
+            json!([
+                { "workout_id": newer, "routine_name": "Push", "start_time": "2026-01-07T18:00:00" },
+                { "workout_id": older, "routine_name": null, "start_time": "2026-01-05T18:00:00" },
+            ])
+        );
+
+        let workout = app
+            .get(&format!("/workouts/{}", newer))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(workout["routine_id"], json!(routine));
+        assert_eq!(workout["routine_version"], json!(1));
+        assert_eq!(
+            workout["exercises"],
+            json!([{
+                "exercise_id": bench,
+                "exercise_name": "Bench Press",
+                "sets": { "1": { "weight": 100, "reps": 5 }, "2": { "weight": 95, "reps": 6 } },
+            }])
+        );
+
+        let body = app.get("/workouts/999").await.expect(404).json();
+        assert_eq!(body["code"], "not_found");
+        assert!(body["request_id"].is_string());
+    }
+
+    #[actix_web::test]
+    async fn modify_workout_adds_sets() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
+        let workout = app.workout().set(bench, 100, 5).create().await;
+
+        app.put(
+            &format!("/workouts/{}", workout),
+            workout_body(bench, json!({ "1": { "weight": 100, "reps": 6 } })),
+        )
+        .await
+        .expect(200);
+        assert_eq!(app.count("Workout_Exercises_Sets").await, 2);
+
+        app.put(
+            "/workouts/999",
+            workout_body(bench, json!({ "1": { "weight": 100, "reps": 6 } })),
+        )
+        .await
+        .expect(404);
+    }
+
+    #[actix_web::test]
+    async fn delete_workout_rebuilds_prs() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
+        let first = app
+            .workout()
+            .at("2026-01-05T18:00:00")
+            .set(bench, 100, 5)
+            .create()
+            .await;
+        let second = app
+            .workout()
+            .at("2026-01-07T18:00:00")
+            .set(bench, 110, 3)
+            .set(bench, 100, 8)
+            .create()
+            .await;
+
+        app.delete(&format!("/api/v1/workouts/{}", second))
+            .await
+            .expect(200);
+        app.get(&format!("/api/v1/workouts/{}", second))
+            .await
+            .expect(404);
+        app.delete(&format!("/api/v1/workouts/{}", second))
+            .await
+            .expect(404);
+        assert_eq!(app.count("Workout_Exercises_Sets").await, 1);
+        assert_eq!(app.count(r#""Set""#).await, 1);
+
+        let prs = sqlx::query_as::<_, (i32, i16)>("SELECT workoutid, heaviestweight FROM PRs")
+            .fetch_all(&app.pool)
+            .await
+            .unwrap();
+        assert_eq!(prs, vec![(first, 100)]);
+        let reps = sqlx::query_scalar::<_, i16>(
+            "SELECT highestreps FROM HighestRepsPerWeight WHERE weight = 100",
+        )
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+        assert_eq!(reps, 5);
+    }
+
+    #[actix_web::test]
+    async fn workout_template_lists_routine_sets() {
+        let app = TestApp::new().await;
+        let bench = app.exercise("Bench Press").create().await;
+        let squat = app.exercise("Squat").create().await;
+        let routine = app
+            .routine("Full body")
+            .exercise(squat, 1)
+            .exercise(bench, 2)
+            .create()
+            .await;
+
+        let body = app
+            .get(&format!("/api/v1/routines/{}/workout-template", routine))
+            .await
+            .expect(200)
+            .json();
+        let legacy = app
+            .get(&format!("/workouts/template/{}", routine))
+            .await
+            .expect(200)
+            .json();
+        assert_eq!(legacy, body);
+        assert_eq!(
+            body["exercises"],
+            json!([
+                { "exercise_id": squat, "exercise_name": "Squat", "sets": { "1": { "weight": 0, "reps": 0 } } },
+                {
+                    "exercise_id": bench,
+                    "exercise_name": "Bench Press",
+                    "sets": { "1": { "weight": 0, "reps": 0 }, "2": { "weight": 0, "reps": 0 } },
+                },
+            ])
+        );
+
+        app.get("/workouts/template/999").await.expect(404);
     }
 }
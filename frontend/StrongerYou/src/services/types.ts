@@ -14,6 +14,8 @@ export interface RoutineUpdate {
 export interface RoutineExercise {
   exercise_id: number;
   sets: number;
+  target_reps?: number | null;
+  target_weight?: number | null; // kg
 }
 
 export interface RoutineInfo {
@@ -32,12 +34,32 @@ export interface RoutineExerciseDetail {
 export interface RoutineViewResponse {
   routine_id: number;
   routine_name: string;
-  routines: ExerciseSetPair[];
+  version: number | null;
+  exercises: RoutineExerciseView[];
+  stats: RoutineStats;
 }
 
-export interface ExerciseSetPair {
+export interface RoutineExerciseView {
+  position: number; // 1-based
   exercise_id: number;
-  num_sets: number;
+  exercise_name: string;
+  muscles_trained: string[];
+  sets: number;
+  target_reps: number | null;
+  target_weight: number | null;
+}
+
+export interface RoutineStats {
+  times_performed: number;
+  last_performed: string | null;
+  average_duration_minutes: number | null;
+  volume_trend: VolumePoint[]; // One point per workout, oldest first
+}
+
+export interface VolumePoint {
+  workout_id: number;
+  date: string;
+  volume: number;
 }
 
 // Workouts Module
@@ -54,7 +76,7 @@ export interface Exercise {
 
 export interface WorkoutData {
   exercises: Exercise[];
-  start_time?: string | null;
+  start_time: string;
   end_time?: string | null;
   routine_id?: number | null;
 }
@@ -77,7 +99,7 @@ export type PRValue =
 
 export interface WorkoutSummary {
   workout_id: number;
-  routine_name: string;
+  routine_name: string | null;
   start_time: string;
 }
 
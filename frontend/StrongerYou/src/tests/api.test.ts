@@ -34,10 +34,10 @@ describe('Routines API Tests', () => {
         name: initialRoutineName,
         exercises: initialRoutineExercises
     };
-    // Backend representation for view response (matches ExerciseSetPair)
+    // Backend representation for view response (matches RoutineExerciseView)
     const viewRoutineExercises = [
-        { exercise_id: 1, num_sets: 3 },
-        { exercise_id: 5, num_sets: 4 },
+        { position: 1, exercise_id: 1, exercise_name: 'Bench Press', muscles_trained: ['Chest'], sets: 3, target_reps: null, target_weight: null },
+        { position: 2, exercise_id: 5, exercise_name: 'Flat Press (Smith Machine)', muscles_trained: ['Chest'], sets: 4, target_reps: 8, target_weight: 60 },
     ];
 
 
@@ -91,13 +91,15 @@ describe('Routines API Tests', () => {
     });
 
     // 3. View Routine Details (GET /routines/{routine_id})
-    // Rust Response: RoutineViewResponse { routine_id, routine_name, routines: Vec<ExerciseSetPair> }
+    // Rust Response: RoutineViewResponse { routine_id, routine_name, version, exercises: Vec<RoutineExerciseView>, stats }
     it('should retrieve routine details using the fetched ID', async () => {
         const mockViewRoutineId = 102; // Define an ID for this test
         const mockBackendResponse = {
             routine_id: mockViewRoutineId,
             routine_name: initialRoutineName,
-            routines: viewRoutineExercises // Using the backend structure {exercise_id, num_sets}
+            version: 1,
+            exercises: viewRoutineExercises,
+            stats: { times_performed: 0, last_performed: null, average_duration_minutes: null, volume_trend: [] }
         };
         mockedAxios.get.mockResolvedValue({ data: mockBackendResponse });
 